@@ -0,0 +1,49 @@
+use adc21::module::{PitchGeneratorType, TriggerType, MAJOR_SCALE_NOTES};
+use adc21::sequencer::{Sequencer, SequencerConfiguration};
+use adc21::sequencer_model::SequencerModel;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// The default settings, with the melody lane quantized on top of the shared scale
+fn configuration() -> SequencerConfiguration {
+    let mut config: SequencerConfiguration = SequencerModel::default().into();
+    config.melody_quantizer_scale = Some(MAJOR_SCALE_NOTES.to_vec());
+    config
+}
+
+// Cost of a tick of the whole pitch chain: melody and transposition lanes, their scales, the
+// sum and the shared quantizer
+fn pitch_chain(c: &mut Criterion) {
+    for (name, generator_type) in [
+        ("random", PitchGeneratorType::Random),
+        ("ramp", PitchGeneratorType::RampUp),
+        ("square", PitchGeneratorType::Square),
+    ] {
+        let mut config = configuration();
+        config.melody_pitch_generator_type = generator_type;
+        let mut pitch_generator = Sequencer::build_pitch_generator(&config);
+        c.bench_function(&format!("pitch chain {}", name), |b| {
+            b.iter(|| black_box(pitch_generator.tick()))
+        });
+    }
+}
+
+// Cost of a tick of the trigger chain, with some restlessness to include the drunk walk
+fn trigger_chain(c: &mut Criterion) {
+    for (name, trigger_type) in [
+        ("random", TriggerType::Random),
+        ("gate", TriggerType::Gate),
+        ("gate + random", TriggerType::GatedRandom),
+        ("gate or random", TriggerType::GateOrRandom),
+    ] {
+        let mut config = configuration();
+        config.trigger_type = trigger_type;
+        config.trigger_restlessness = 0.1;
+        let mut trigger_generator = Sequencer::build_trigger_generator(&config);
+        c.bench_function(&format!("trigger chain {}", name), |b| {
+            b.iter(|| black_box(trigger_generator.tick()))
+        });
+    }
+}
+
+criterion_group!(benches, pitch_chain, trigger_chain);
+criterion_main!(benches);
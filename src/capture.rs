@@ -0,0 +1,135 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use log::{info, warn};
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use pitch_calc::{LetterOctave, Step};
+
+const NOTE_ON_MSG: u8 = 0x90;
+const MIDI_INPUT_PORT_NAME: &str = "Nannou Generative Sequencer Input";
+
+// Records the notes played on the first MIDI input port while capturing, e.g. to seed the
+// melody step sequence with a phrase played on a keyboard
+pub struct NoteCapture {
+    notes: Arc<Mutex<Vec<LetterOctave>>>,
+    is_capturing: Arc<AtomicBool>,
+    // Notes only arrive while the connection is open, there is none without input ports
+    connection: Option<MidiInputConnection<()>>,
+}
+
+impl NoteCapture {
+    pub fn open() -> NoteCapture {
+        let notes = Arc::new(Mutex::new(Vec::new()));
+        let is_capturing = Arc::new(AtomicBool::new(false));
+        let connection = NoteCapture::connect(notes.clone(), is_capturing.clone());
+        NoteCapture {
+            notes,
+            is_capturing,
+            connection,
+        }
+    }
+
+    fn connect(
+        notes: Arc<Mutex<Vec<LetterOctave>>>,
+        is_capturing: Arc<AtomicBool>,
+    ) -> Option<MidiInputConnection<()>> {
+        // Skip sysex, timing and active sensing messages, only notes are captured
+        connect_first_input_port(MIDI_INPUT_PORT_NAME, Ignore::All, move |_, message, _| {
+            if !is_capturing.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(note) = captured_note(message) {
+                notes.lock().unwrap().push(note);
+            }
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    // Start over with an empty capture
+    pub fn start(&self) {
+        self.notes.lock().unwrap().clear();
+        self.is_capturing.store(true, Ordering::Relaxed);
+    }
+
+    // The notes played since the capture was started, in the order they were played
+    pub fn stop(&self) -> Vec<LetterOctave> {
+        self.is_capturing.store(false, Ordering::Relaxed);
+        std::mem::take(&mut *self.notes.lock().unwrap())
+    }
+}
+
+// Connect to the first available MIDI input port under the name, passing the messages the
+// filter lets through on to the callback. There is no connection without input ports.
+pub fn connect_first_input_port<F>(
+    name: &str,
+    ignore: Ignore,
+    callback: F,
+) -> Option<MidiInputConnection<()>>
+where
+    F: FnMut(u64, &[u8], &mut ()) + Send + 'static,
+{
+    let mut midi_in = match MidiInput::new(name) {
+        Ok(midi_in) => midi_in,
+        Err(e) => {
+            warn!("Failed to create MIDI input {}: {}", name, e);
+            return None;
+        }
+    };
+    midi_in.ignore(ignore);
+    let in_port = match midi_in.ports().into_iter().next() {
+        Some(in_port) => in_port,
+        None => {
+            warn!("No MIDI input ports available for {}", name);
+            return None;
+        }
+    };
+    info!(
+        "Connecting {} to MIDI input port: {}",
+        name,
+        midi_in.port_name(&in_port).unwrap()
+    );
+    midi_in
+        .connect(&in_port, name, callback, ())
+        .map_err(|e| warn!("Failed to connect {} to MIDI input port: {}", name, e))
+        .ok()
+}
+
+// The pitch of a note on message on any channel. A note on with velocity 0 ends a note.
+fn captured_note(message: &[u8]) -> Option<LetterOctave> {
+    match message {
+        [status, note, velocity] if status & 0xF0 == NOTE_ON_MSG && *velocity > 0 => {
+            Some(Step(*note as f32).to_letter_octave())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::format_step_sequence;
+
+    #[test]
+    fn captured_note_numbers_become_a_step_sequence() {
+        let messages: &[&[u8]] = &[
+            &[NOTE_ON_MSG, 60, 100],
+            &[0x80, 60, 64],
+            &[NOTE_ON_MSG | 0x01, 63, 90],
+            &[NOTE_ON_MSG, 63, 0],
+            &[0xB0, 10, 64],
+            &[NOTE_ON_MSG, 67, 1],
+            &[0xD0, 90],
+        ];
+        let notes: Vec<LetterOctave> = messages
+            .iter()
+            .filter_map(|message| captured_note(message))
+            .collect();
+
+        assert_eq!(format_step_sequence(&notes), "C4 D#4 G4");
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+
+// Bounded undo/redo stack of snapshots. Committing a changed state records the previous
+// one for undo and discards the redo steps; the oldest snapshot is dropped once the stack
+// is full.
+pub struct History<T> {
+    current: T,
+    undo: VecDeque<T>,
+    redo: Vec<T>,
+    depth: usize,
+}
+
+impl<T: Clone + PartialEq> History<T> {
+    pub fn new(initial: T, depth: usize) -> History<T> {
+        History {
+            current: initial,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth,
+        }
+    }
+
+    // Record the state after an edit, unless nothing has changed
+    pub fn commit(&mut self, state: &T) {
+        if *state == self.current {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.current, state.clone());
+        self.undo.push_back(previous);
+        if self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    // Step back to the previous state, if there is one
+    pub fn undo(&mut self) -> Option<T> {
+        let previous = self.undo.pop_back()?;
+        let current = std::mem::replace(&mut self.current, previous.clone());
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    // Step forward to the state before the last undo, if there is one
+    pub fn redo(&mut self) -> Option<T> {
+        let next = self.redo.pop()?;
+        let current = std::mem::replace(&mut self.current, next.clone());
+        self.undo.push_back(current);
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_step_through_the_committed_states_in_order() {
+        let mut history = History::new(0, 50);
+        for state in 1..=3 {
+            history.commit(&state);
+        }
+
+        assert_eq!(history.undo(), Some(2));
+        assert_eq!(history.undo(), Some(1));
+        assert_eq!(history.redo(), Some(2));
+        assert_eq!(history.undo(), Some(1));
+        assert_eq!(history.undo(), Some(0));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo(), Some(1));
+
+        // a new edit discards the redo steps
+        history.commit(&4);
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(1));
+    }
+
+    #[test]
+    fn unchanged_states_are_not_recorded() {
+        let mut history = History::new(0, 50);
+        history.commit(&0);
+        assert_eq!(history.undo(), None);
+        history.commit(&1);
+        history.commit(&1);
+        assert_eq!(history.undo(), Some(0));
+        assert_eq!(history.undo(), None);
+    }
+
+    #[test]
+    fn the_oldest_states_are_dropped_beyond_the_depth() {
+        let mut history = History::new(0, 2);
+        for state in 1..=5 {
+            history.commit(&state);
+        }
+        assert_eq!(history.undo(), Some(4));
+        assert_eq!(history.undo(), Some(3));
+        assert_eq!(history.undo(), None);
+    }
+}
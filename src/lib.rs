@@ -0,0 +1,6 @@
+// The sequencer engine without the UI, shared by the app and the benchmarks. A host embedding
+// the sequencer can depend on it as well.
+pub mod module;
+pub mod sequencer;
+pub mod sequencer_model;
+pub mod velocity;
@@ -1,112 +1,346 @@
-use std::str::FromStr;
+use std::error::Error;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
 
-use log::{info, LevelFilter};
-use module::PitchGeneratorType;
-use nannou_conrod::Color;
-use nannou_conrod::widget::*;
-use nannou_conrod::prelude::*;
+use adc21::{module, sequencer, sequencer_model};
+use capture::NoteCapture;
+use history::History;
+use log::{error, info, warn, LevelFilter};
+use midi_file::{read_step_sequence, ChordNote, CHORD_NOTE_NAMES};
+use midi_thru::MidiThru;
+use module::{Lane, PitchGeneratorType, Trigger, TriggerType};
 use nannou::prelude::*;
+use nannou_conrod::prelude::*;
 use nannou_conrod::widget::drop_down_list::Idx;
 use nannou_conrod::widget::range_slider::Edge;
+use nannou_conrod::widget::*;
+use nannou_conrod::Color;
 use pitch_calc::{Letter, LetterOctave, Step};
-use sequencer::{Sequencer, SequencerConfiguration};
+use preset_bank::PresetBank;
+use rand::Rng;
+use sequencer::{
+    actual_bpm, bars_and_beats, midi_note_number, open_midi_output, pattern_length_ticks, MidiSink,
+    ModulationTarget, MonitorMidiSink, Sequencer, SequencerConfiguration, SequencerError,
+    SequencerState,
+};
+use sequencer_model::{
+    is_humanized, melody_blend_generator_type_from_index, melody_degree_scale,
+    melody_pitch_generator_type_from_index, modulation_target_from_index, morph,
+    pitch_generator_type_from_index, quantize_range_from_band, ramp_mode_from_index, set_humanize,
+    square_interval_from_index, transposition_pitch_generator_type_from_index,
+    trigger_type_from_index, SequencerModel, COUNT_IN_NAMES, GATE_OFF_MAX_VALUE,
+    GATE_OFF_MIN_VALUE, GATE_ON_MAX_VALUE, GATE_ON_MIN_VALUE, LANE_QUANTIZER_SCALE_NAMES,
+    MELODY_BLEND_GENERATOR_TYPE_NAMES, MELODY_BLEND_WEIGHT_MAX_VALUE,
+    MELODY_BLEND_WEIGHT_MIN_VALUE, MELODY_PITCH_GENERATOR_TYPE_NAMES, MELODY_PITCH_MAX_VALUE,
+    MELODY_PITCH_MIN_VALUE, MODULATION_TARGET_NAMES, NOTE_LENGTH_NAMES, NOTE_REPEAT_NAMES,
+    PITCH_FAVOR_NAMES, PITCH_GENERATOR_TYPE_NAMES, QUANTIZER_DIRECTION_NAMES, QUANTIZER_SCALES,
+    QUANTIZER_SCALE_NAMES, QUANTIZER_STRENGTH_MAX_VALUE, QUANTIZER_STRENGTH_MIN_VALUE,
+    RAMP_MODE_NAMES, SQUARE_INTERVAL_NAMES, SWING_RESOLUTION_NAMES, TRANSPOSITION_MAX_VALUE,
+    TRANSPOSITION_MIN_VALUE, TRANSPOSITION_PITCH_GENERATOR_TYPE_NAMES, TRIGGER_ORDER_NAMES,
+    TRIGGER_PROBABILITY_MAX_VALUE, TRIGGER_PROBABILITY_MIN_VALUE, TRIGGER_TYPE_NAMES,
+    VELOCITY_CURVE_NAMES, VELOCITY_MODE_NAMES,
+};
 use simple_logger::SimpleLogger;
+use synth::{Synth, Waveform, WAVEFORM_NAMES};
 
-use crate::module::format_letter_octave;
+use crate::module::{
+    format_letter, format_letter_octave, format_step_sequence, parse_letter_octave,
+    parse_step_sequence, CHROMATIC_SCALE_NOTES,
+};
 
-mod module;
-mod sequencer;
+mod capture;
+mod history;
+mod midi_file;
+mod midi_thru;
+mod preset_bank;
+mod synth;
 
 const WIDGET_COLOR: Color = Color::Rgba(0.3, 0.3, 0.3, 1.0);
 const LABEL_COLOR: Color = Color::Rgba(1.0, 1.0, 1.0, 1.0);
 const CANVAS_COLOR: Color = color::LIGHT_PURPLE;
-const MELODY_PITCH_MIN_VALUE: LetterOctave = LetterOctave(Letter::C, 0);
-const MELODY_PITCH_MAX_VALUE: LetterOctave = LetterOctave(Letter::C, 7);
-const MELODY_MIN_PITCH_DEFAULT_VALUE: LetterOctave = LetterOctave(Letter::C, 3);
-const MELODY_MAX_PITCH_DEFAULT_VALUE: LetterOctave = LetterOctave(Letter::C, 5);
-const MELODY_PITCH_GENERATOR_TYPE_DEFAULT_VALUE: Idx = 0;
-const MELODY_PITCH_GENERATOR_CYCLE_LENGTH_DEFAULT_VALUE: f32 = 64.0;
-const TRANSPOSITION_MIN_VALUE: Step = Step(0.0);
-const TRANSPOSITION_MAX_VALUE: Step = Step(24.0);
-const TRANSPOSITION_MIN_PITCH_DEFAULT_VALUE: Step = Step(0.0);
-const TRANSPOSITION_MAX_PITCH_DEFAULT_VALUE: Step = Step(12.0);
-const TRANSPOSITION_PITCH_GENERATOR_TYPE_DEFAULT_VALUE: Idx = 1;
-const TRANSPOSITION_PITCH_GENERATOR_CYCLE_LENGTH_DEFAULT_VALUE: f32 = 128.0;
-const BPM_DEFAULT_VALUE: f32 = 120.0;
-const TRIGGER_PROBABILITY_DEFAULT_VALUE: f32 = 1.0;
-const TRIGGER_PROBABILITY_MIN_VALUE: f32 = 0.0;
-const TRIGGER_PROBABILITY_MAX_VALUE: f32 = 1.0;
-const CLOCK_DIVIDER_FACTOR_DEFAULT_VALUE: f32 = 16.0;
+const BPM_MIN_VALUE: f32 = 20.0;
+// Well below the fastest tempo the sequencer timer can keep, even with the tempo automation
+const BPM_MAX_VALUE: f32 = 300.0;
+const TEMPO_AUTOMATION_DEPTH_MIN_VALUE: f32 = 0.0;
+const TEMPO_AUTOMATION_DEPTH_MAX_VALUE: f32 = 30.0;
+const TEMPO_AUTOMATION_PERIOD_MIN_VALUE: f32 = 1.0;
+const TEMPO_AUTOMATION_PERIOD_MAX_VALUE: f32 = 64.0;
+const FILL_PHRASE_BARS_MIN_VALUE: f32 = 1.0;
+const FILL_PHRASE_BARS_MAX_VALUE: f32 = 16.0;
+const FILL_INTENSITY_MIN_VALUE: f32 = 0.0;
+const FILL_INTENSITY_MAX_VALUE: f32 = 1.0;
+const VELOCITY_LENGTH_AMOUNT_MIN_VALUE: f32 = 0.0;
+const VELOCITY_LENGTH_AMOUNT_MAX_VALUE: f32 = 1.0;
+// From straight to a dotted feel
+const SWING_MIN_VALUE: f32 = 0.5;
+const SWING_MAX_VALUE: f32 = 0.75;
+const TRIGGER_RESTLESSNESS_MIN_VALUE: f32 = 0.0;
+const TRIGGER_RESTLESSNESS_MAX_VALUE: f32 = 0.2;
+const DENSITY_DEFAULT_VALUE: f32 = 0.5;
+const DENSITY_MIN_VALUE: f32 = 0.0;
+const DENSITY_MAX_VALUE: f32 = 1.0;
+const DENSITY_SPARSE_TRIGGER_PROBABILITY: f32 = 0.2;
+const DENSITY_SPARSE_CLOCK_DIVIDER_FACTOR: f32 = 24.0;
+const DENSITY_BUSY_CLOCK_DIVIDER_FACTOR: f32 = 3.0;
 const CLOCK_DIVIDER_FACTOR_MIN_VALUE: f32 = 1.0;
 const CLOCK_DIVIDER_FACTOR_MAX_VALUE: f32 = 24.0;
+// The maximum offset is the last tick of the division
+const CLOCK_DIVIDER_OFFSET_MIN_VALUE: f32 = 0.0;
+const MELODY_DEGREE_WEIGHT_MIN_VALUE: f32 = 0.0;
+const MELODY_DEGREE_WEIGHT_MAX_VALUE: f32 = 1.0;
 const PITCH_GENERATOR_CYCLE_LENGTH_MIN_VALUE: f32 = 1.0;
 const PITCH_GENERATOR_CYCLE_LENGTH_MAX_VALUE: f32 = 128.0;
-const PITCH_GENERATOR_TYPE_NAMES: &[&str] = &["Ramp", "Square", "Random"];
-const QUANTIZER_SCALE_INDEX_DEFAULT_VALUE: Idx = 1;
-const QUANTIZER_SCALES: &[&[Letter]] = &[
-    module::CHROMATIC_SCALE_NOTES,
-    module::MAJOR_SCALE_NOTES,
-    module::MINOR_SCALE_NOTES,
-    module::MAJOR_PENTATONIC_SCALE_NOTES,
-    module::MINOR_PENTATONIC_SCALE_NOTES,
-];
-const QUANTIZER_SCALE_NAMES: &[&str] = &[
-    "Chromatic",
-    "Major",
-    "Minor",
-    "Major Pentatonic",
-    "Minor Pentatonic",
-];
+// The maximum phase is the last step of the lane's cycle
+const PHASE_MIN_VALUE: f32 = 0.0;
+// Cycle lengths are rounded up to a multiple of the selected snap, for those who prefer
+// lengths in whole bars
+const CYCLE_LENGTH_SNAP_NAMES: &[&str] = &["Cycle: any", "Cycle: x4", "Cycle: x16"];
+const CYCLE_LENGTH_SNAP_VALUES: &[u32] = &[1, 4, 16];
+const CYCLE_LENGTH_SNAP_INDEX_DEFAULT_VALUE: Idx = 0;
+const MUTATION_AMOUNT_DEFAULT_VALUE: f32 = 0.25;
+const MUTATION_AMOUNT_MIN_VALUE: f32 = 0.0;
+const MUTATION_AMOUNT_MAX_VALUE: f32 = 1.0;
+// Bars a morph from preset A to preset B takes
+const MORPH_BARS_DEFAULT_VALUE: f32 = 8.0;
+const MORPH_BARS_MIN_VALUE: f32 = 1.0;
+const MORPH_BARS_MAX_VALUE: f32 = 64.0;
+const QUANTIZER_OCTAVE_MIN_VALUE: f32 = -1.0;
+const QUANTIZER_OCTAVE_MAX_VALUE: f32 = 9.0;
+// The whole MIDI note range, where every note is quantized
+const QUANTIZE_BAND_MIN_VALUE: f32 = 0.0;
+const QUANTIZE_BAND_MAX_VALUE: f32 = 127.0;
+const TRANSPOSE_MIN_VALUE: f32 = -12.0;
+const TRANSPOSE_MAX_VALUE: f32 = 12.0;
+const OCTAVE_SCATTER_PROBABILITY_MIN_VALUE: f32 = 0.0;
+const OCTAVE_SCATTER_PROBABILITY_MAX_VALUE: f32 = 1.0;
+const OCTAVE_SCATTER_OCTAVES_MIN_VALUE: f32 = 1.0;
+const OCTAVE_SCATTER_OCTAVES_MAX_VALUE: f32 = 3.0;
+const MODULATION_MIN_VALUE: f32 = 0.0;
+const MODULATION_MAX_VALUE: f32 = 127.0;
+const MODULATION_CONTROLLER_MIN_VALUE: f32 = 0.0;
+const MODULATION_CONTROLLER_MAX_VALUE: f32 = 127.0;
+// Randomized tempos stay within a musical range
+const RANDOM_BPM_MIN_VALUE: f32 = 80.0;
+const RANDOM_BPM_MAX_VALUE: f32 = 160.0;
+const RELEASE_VELOCITY_MIN_VALUE: f32 = 0.0;
+const RELEASE_VELOCITY_MAX_VALUE: f32 = 127.0;
+const MAX_VOICES_MIN_VALUE: f32 = 1.0;
+const TIE_PROBABILITY_MIN_VALUE: f32 = 0.0;
+const TIE_PROBABILITY_MAX_VALUE: f32 = 1.0;
+const MAX_VOICES_MAX_VALUE: f32 = 16.0;
+const NOTE_DURATION_MIN_VALUE: f32 = 1.0;
+const NOTE_DURATION_MAX_VALUE: f32 = 3000.0;
+const OCTAVE_OFFSET_MIN_VALUE: i32 = -4;
+const OCTAVE_OFFSET_MAX_VALUE: i32 = 4;
+const PRESET_FILE_PATH: &str = "preset.json";
+// MIDI file the Load MIDI button reads the melody step sequence from
+const MIDI_FILE_PATH: &str = "melody.mid";
+// Live position of the generators, saved and loaded along with the preset
+const STATE_FILE_PATH: &str = "preset_state.json";
+// Folder of presets cycled through with Page Up/Page Down, unless set with the flag
+const PRESET_DIR_DEFAULT_PATH: &str = "presets";
+const PRESET_DIR_FLAG: &str = "--presets";
+const HISTORY_DEPTH: usize = 50;
+// Log the MIDI messages instead of sending them to a MIDI output port
+const MONITOR_FLAG: &str = "--monitor";
+// Log the notes of the monitored MIDI messages as frequencies in Hz instead of note names
+const HZ_FLAG: &str = "--hz";
+// Create a virtual MIDI output port for other apps to connect to, where supported
+const VIRTUAL_PORT_FLAG: &str = "--virtual-port";
+// Also play the notes on the built-in synth, on the default audio output
+const SYNTH_FLAG: &str = "--synth";
+// Open a second window with a sequencer of its own, e.g. to compare two patches
+const SECOND_WINDOW_FLAG: &str = "--second-window";
+const SYNTH_WAVEFORM_INDEX_DEFAULT_VALUE: Idx = 0;
+const CHORD_NOTE_INDEX_DEFAULT_VALUE: Idx = 0;
+// Runtime log levels cycled through with the V key, starting at Info
+const LOG_LEVELS: &[LevelFilter] = &[LevelFilter::Off, LevelFilter::Info, LevelFilter::Debug];
+const LOG_LEVEL_DEFAULT_VALUE: LevelFilter = LevelFilter::Info;
+const HELP_TEXT: &str = "\
+Keyboard shortcuts
+  Space    Play/pause the sequencer
+  R        Reset the sequencer
+  S / L    Save/load the preset file, resuming the generators where they were saved
+  PgUp/Dn  Load the previous/next preset of the preset folder
+  Up/Down  Nudge the BPM by 1
+  [ / ]    Select the previous/next quantizer scale
+  + / -    Shift the played notes up/down by an octave
+  Ctrl+Z   Undo the last change (Ctrl+Shift+Z redoes it)
+  V        Cycle the log level: off, info, or debug to also log every played note
+  H        Show/hide this help (Esc also hides it)
+  Esc      Send all notes off and all sound off (panic)
+
+Controls
+  Scale           Scale the combined pitch is quantized to
+  Strength        Share of notes that are snapped to the scale
+  Snap            Direction an off-scale note is snapped in: up, down, or to the nearest
+                  scale note (ties go up)
+  Band            Register the scale applies to, e.g. quantize the mid-range and let high
+                  notes ring free; notes outside it are played chromatic
+  Transpose       Shift the quantized notes by up to an octave, for quick key changes
+                  without changing the scale; the scale shown is no longer the key played
+  Scatter         Chance a quantized note is moved up or down by whole octaves, and the
+                  most octaves it is moved by, for register variety within the scale
+  Probability     Chance that a divided clock step plays a note
+  Restlessness    How far the probability wanders from step to step
+  Clock division  Number of clock ticks (24 per quarter note) per step
+  Offset          Clock tick within the division the steps land on, for off-beat patterns
+  Density         Sets probability and clock division together, from sparse to busy
+  XY pad          Drag to sweep probability (left to right) and clock division (long steps
+                  at the bottom, short ones at the top) at the same time
+  Trigger row     Whether steps play by chance (the probability), by a fixed pattern of
+                  played and rested steps (the gate), by chance only during the played
+                  steps of the gate, or the gate with extra notes by chance in its rests
+  Trigger order   Divide first: the trigger runs once per step, so the gate counts steps.
+                  Trigger first: it runs on every clock tick and only the tick of each step
+                  is kept, so the gate counts clock ticks and restlessness wanders faster
+  Pattern         Length of the pattern before it repeats, from the longest cycle of the
+                  melody, the transposition and the gate, in bars and beats of 4/4
+  Fill            Share of the rested steps played in the last bar of every phrase of the
+                  set number of bars, like a drum fill; 0% turns the fill off
+  Pitch chance    Play the triggered notes by a chance following their pitch: always at
+                  the favored end of the pitch range, never at the other, half in between
+  Hold on rests   Only move the melody and transposition on when a note is triggered, so
+                  that rests don't skip notes of the pitch pattern
+  Lane on/off     Switch the melody or transposition lane off: without the melody nothing
+                  is played, without the transposition the melody is played as it is
+  Melody row      Generator type, melody scale, pitch range and cycle length of the melody;
+                  type note names like C#3 or Db4 into the range boxes and press Enter
+  Steps           Melody generator playing back the notes typed into the box on the right,
+                  e.g. C4 E4 G4 C5, in a loop
+  Degrees         Melody generator playing the notes of the melody scale (or the shared
+                  scale) in the octaves of the range, each degree as often as its weight
+                  in the row below the blend row, for more control over the melodic color
+                  than random notes snapped to the scale
+  Capture         Record the notes played on the first MIDI input port, then play them back
+                  as the melody step sequence once Capture is switched off
+  Load MIDI       Play the notes of melody.mid as the melody step sequence, in the order
+                  they start; of a chord, only the top or the bottom note is kept
+  Blend row       Second melody generator played instead of the first one by chance, e.g.
+                  mostly a ramp with the occasional random note, and the share of the
+                  steps it plays; it follows the melody range and cycle length
+  Ramp mode       Whether a melody ramp jumps back down at the end of each cycle
+                  (sawtooth), or turns around and ramps back down (ping-pong)
+  Square          Whether a square lane jumps between the ends of its range, or from the
+                  minimum up by a fixed interval, the same interval whatever the range
+  Phase           Step of the cycle a ramp or square lane starts at, so that two lanes of
+                  the same cycle length do not move in lockstep
+  Transpose row   Generator type, transposition scale, range and cycle length of the
+                  transposition added to the melody
+  Progression     Transposition generator walking the chord roots of the preset's progression
+                  (I-V-vi-IV by default) in the lane scale, one chord per cycle length
+  Chord tones     Snap the transposed melody to the nearest root, third or fifth of the
+                  current chord of the progression, for consonant lines
+  Lane scales     Quantize a lane before it is summed, or leave it unquantized with Off;
+                  the sum is always quantized to the shared scale. A whole-tone
+                  transposition moves the melody in whole steps for harmonic motion
+  Note length     Shortest and longest duration of the played notes; each note is held for
+                  a random length in between, or always the same with both set equal
+  Tie             Hold a still sounding note when the next step repeats it
+  Tie chance      Chance a played note is held into the next step instead of released,
+                  for note lengths that vary by themselves
+  Repeat          Strike each played note again at the selected subdivision until its step
+                  is over, like the note repeat of a drum machine
+  Drums           Play every triggered step on the note selected next to it, e.g. a
+                  hi-hat (42 in the General MIDI drum map), in place of the pitch lanes,
+                  unshifted by the octave keys and unharmonized
+  Harmonize       Play each melody note as a chord along with the notes the typed semitones
+                  away from it, e.g. +4 +7, snapped down into the scale so that they make
+                  its triads; in mono mode the melody is played alone
+  Velocity        Fixed velocity, or velocity following the pitch through the selected curve
+  Vel -> length   Hold louder notes longer: at a coupling of 100% the note length scales
+                  with the velocity, so a note of half the velocity is held half as long
+  Humanize        Vary the velocity, timing and length of every note a little, all at once,
+                  for a less mechanical feel
+  Swing           Delay the off-beat 8ths or 16ths, from straight at 50% through a triplet
+                  feel at 67% to a dotted feel at 75%
+  Octaves         Register the quantized notes are folded into
+  MIDI row        Note off style: a note off message (0x80), or a note on with velocity 0
+                  for devices that expect it, the release velocity sent with a note off
+                  message, the MIDI channels successive notes take turns on, e.g. 1 2 3
+                  (channel 1 only by default), microtonal output, the range the note
+                  duration is kept in whatever the note length and tempo, and the number of
+                  voices the device can hold: the oldest held note is ended to make room
+                  for a new one
+  Mono            End the sounding note before the next one starts, for mono synths that
+                  glitch on overlapping notes, whatever the number of voices
+  Microtonal      Play in-between pitches as the nearest note bent by pitch bend, assuming
+                  the synth's default bend range of 2 semitones; only the notes the quantizer
+                  lets through keep their in-between pitch, so lower its strength to hear them
+  Send SPP        Send a MIDI Song Position Pointer of 0 on Reset, so that slaved sequencers
+                  and drum machines jump back to the start along with the generators
+  Synth           Waveform of the built-in synth playing along with the MIDI output, when
+                  started with --synth
+  Throttle        Protect slow MIDI devices: leave out repeated controller values and send
+                  at most 16 messages per clock tick; note offs always get through
+  MIDI thru       Pass the messages received on the first MIDI input port on to the MIDI
+                  output, merged with the generated notes
+  Modulation row  On/off, generator type, value range and cycle length of the modulation lane,
+                  plus where it is sent: a MIDI CC with the selected number, pan (CC 10),
+                  or channel pressure (aftertouch)
+  Tempo row       The BPM the timer is set to, along with the tempo it actually plays at,
+                  a little off as the time between clock ticks is rounded to whole
+                  microseconds; depth and period in bars of a slow swing of the tempo
+                  around the BPM
+  Lock seed       Play the random generators from the shown seed, the same way every time
+                  they restart, instead of from fresh randomness; New seed picks another one
+  Seed row        Seeds of the melody, transposition and trigger lanes of their own, locked
+                  and renewed one lane at a time, e.g. to keep the rhythm while the melody
+                  varies; a locked lane seed takes the place of the seed above
+  Stop            Whether stopping pauses the generators, so that Play resumes mid-pattern,
+                  or rewinds them, so that Play starts the patterns from the beginning
+  Reset           Restart all generators from the beginning
+  Panic           Silence notes stuck on the synth
+  Randomize       Pick random settings for inspiration, keeping the MIDI settings
+  Vary            Move the ranges, probability, gate, blend, strength and a note of the step
+                  sequence a little, by up to the set amount, to evolve a patch gradually
+  Morph           Move from preset A to preset B of the preset folder over the set number of
+                  bars, one step at the start of each bar: numbers crossfade, and the other
+                  settings switch half way; click again to stop
+  Count-in        One or two bars of metronome clicks on MIDI channel 10 before the notes
+                  start when Play is pressed, e.g. to get ready for recording
+  Cycle snap      Allow any cycle length, or snap the cycle lengths to multiples of 4 or 16
+  Keyboard        The octaves of the melody range, with the notes of the scale within it in
+                  green and its lowest and highest note in orange
+  Status line     The lane generators and ranges, the scale and the tempo at a glance
+  Live step       Pitch and MIDI note number of the latest step, and whether it was played
+                  as a note or a rest; red when the pitch is outside the MIDI note range and
+                  played at the nearest end of it instead; the bar underneath fills with the
+                  share of the last 16 steps played";
 
 fn main() {
     // Disable logging for all dependencies
     SimpleLogger::new()
         .with_level(LevelFilter::Off)
         .with_utc_timestamps()
-        .with_module_level("adc21", LevelFilter::Info)
+        .with_module_level("adc21", LevelFilter::Debug)
         .init()
         .unwrap();
+    // The max level filters at runtime, so it can be changed while playing
+    log::set_max_level(LOG_LEVEL_DEFAULT_VALUE);
     // Run the app
-    nannou::app(model).update(update).run();
-}
-
-#[derive(Clone)]
-pub struct SequencerModel {
-    melody_min_pitch: f32,
-    melody_max_pitch: f32,
-    melody_pitch_generator_type_index: Option<Idx>,
-    melody_cycle_length: f32,
-    transposition_min_pitch: f32,
-    transposition_max_pitch: f32,
-    transposition_pitch_generator_type_index: Option<Idx>,
-    transposition_cycle_length: f32,
-    trigger_probability: f32,
-    clock_divider_factor: f32,
-    quantizer_scale_index: Option<Idx>,
-    bpm: f32,
-}
-
-impl From<SequencerModel> for SequencerConfiguration {
-    fn from(model: SequencerModel) -> Self {
-        SequencerConfiguration {
-            melody_min_pitch: Step(model.melody_min_pitch).to_letter_octave(),
-            melody_max_pitch: Step(model.melody_max_pitch).to_letter_octave(),
-            melody_pitch_generator_type: pitch_generator_type_from_index(
-                model.melody_pitch_generator_type_index,
-            ),
-            melody_cycle_length: model.melody_cycle_length as u32,
-            transposition_min_pitch: Step(model.transposition_min_pitch).to_letter_octave(),
-            transposition_max_pitch: Step(model.transposition_max_pitch).to_letter_octave(),
-            transposition_pitch_generator_type: pitch_generator_type_from_index(
-                model.transposition_pitch_generator_type_index,
-            ),
-            transposition_cycle_length: model.transposition_cycle_length as u32,
-            trigger_probablilty: model.trigger_probability,
-            clock_divider_factor: model.clock_divider_factor as u32,
-            quantizer_scale: QUANTIZER_SCALES[model.quantizer_scale_index.unwrap()].to_vec(),
-            bpm: model.bpm,
-        }
-    }
+    nannou::app(windows).update(update_windows).run();
+}
+
+// Every window is a separate editor with a sequencer of its own, kept in the order the
+// windows were opened in. The window handlers are generic over the index of their window, as
+// nannou does not pass the window on to them, and a UI must only get the events of its own
+// window. The sequencers run on their own timers rather than a shared clock: they start
+// together at the same tempo, but each can be started, stopped and set to its own tempo
+// without holding up the other.
+struct Windows {
+    models: Vec<Model>,
+}
+
+// A morph from preset A to preset B, taking a step at the start of every bar played
+struct Morph {
+    a: SequencerModel,
+    b: SequencerModel,
+    bars: u32,
+    bars_played: u32,
+    // Bar count of the sequencer at the last step, to notice the next bar starting
+    last_bar: u32,
 }
 
 struct Model {
@@ -115,6 +349,44 @@ struct Model {
     sequencer: Sequencer,
     sequencer_model: SequencerModel,
     is_playing: bool,
+    // Pitch and trigger of the latest step, shown below the UI
+    last_step: Option<(LetterOctave, Trigger)>,
+    // Share of the recent steps that played a note, shown as a meter under the live step
+    trigger_density: f32,
+    // Notes played on a MIDI keyboard, captured into the melody step sequence
+    note_capture: NoteCapture,
+    is_capturing: bool,
+    // Note kept of the chords in a loaded MIDI file
+    chord_note_index: Option<Idx>,
+    // Messages played on a MIDI keyboard, passed on to the MIDI output while switched on
+    midi_thru: MidiThru,
+    // Built-in synth playing along with the MIDI output, when opened with the flag
+    synth: Option<Synth>,
+    synth_waveform_index: Option<Idx>,
+    show_help: bool,
+    // Macro control driving trigger probability and clock division
+    density: f32,
+    // Multiple the cycle length sliders snap to
+    cycle_length_snap_index: Option<Idx>,
+    // How far the Vary button moves the settings, from 0 to 1
+    mutation_amount: f32,
+    // Presets of the preset folder a morph goes from and to, and the bars it takes
+    morph_a_index: Option<Idx>,
+    morph_b_index: Option<Idx>,
+    morph_bars: f32,
+    morph: Option<Morph>,
+    // Note names being typed into the melody range text boxes
+    melody_min_pitch_text: Option<String>,
+    melody_max_pitch_text: Option<String>,
+    // Note names being typed into the step sequence text box
+    melody_step_sequence_text: Option<String>,
+    // Channel numbers being typed into the round-robin channels text box
+    round_robin_channels_text: Option<String>,
+    // Semitones being typed into the harmony intervals text box
+    harmony_intervals_text: Option<String>,
+    // Undo/redo snapshots of the sequencer model
+    history: History<SequencerModel>,
+    preset_bank: PresetBank,
 }
 
 // Generate unique widget IDs
@@ -122,76 +394,637 @@ widget_ids! {
     struct Ids {
         // widgets
         melody_pitch_range_slider,
+        melody_min_pitch_text_box,
+        melody_max_pitch_text_box,
+        melody_step_sequence_text_box,
+        capture_toggle,
+        load_midi_button,
+        chord_note_drop_down,
         melody_pitch_generator_type_drop_down_list,
         melody_pitch_generator_cycle_length_slider,
+        melody_quantizer_scale_drop_down,
+        melody_blend_generator_type_drop_down,
+        melody_blend_weight_slider,
+        melody_ramp_mode_drop_down,
+        square_interval_drop_down,
+        melody_degree_weight_sliders[],
+        melody_phase_slider,
+        transposition_quantizer_scale_drop_down,
         transposition_pitch_range_slider,
         transposition_pitch_generator_type_drop_down_list,
         transposition_pitch_generator_cycle_length_slider,
+        transposition_phase_slider,
         is_playing_toggle,
         reset_button,
+        panic_button,
+        randomize_button,
+        mutate_button,
+        mutation_amount_slider,
+        cycle_length_snap_drop_down,
+        count_in_drop_down,
+        preset_name_text,
+        morph_a_drop_down,
+        morph_b_drop_down,
+        morph_bars_slider,
+        morph_button,
         trigger_probability_slider,
+        trigger_restlessness_slider,
         clock_divider_factor_slider,
+        clock_divider_offset_slider,
+        trigger_type_drop_down,
+        trigger_order_drop_down,
+        gate_on_slider,
+        gate_off_slider,
         quantizer_scale_drop_down,
+        quantizer_strength_slider,
+        quantizer_direction_drop_down,
+        quantizer_octave_range_slider,
+        quantize_band_range_slider,
+        transpose_slider,
+        octave_scatter_probability_slider,
+        octave_scatter_octaves_slider,
+        note_length_min_drop_down,
+        note_length_max_drop_down,
+        note_repeat_drop_down,
+        tie_repeated_notes_toggle,
+        tie_probability_slider,
+        transposition_chord_tones_toggle,
+        velocity_mode_drop_down,
+        velocity_curve_drop_down,
+        humanize_toggle,
+        velocity_length_toggle,
+        velocity_length_amount_slider,
+        swing_slider,
+        swing_resolution_drop_down,
+        density_slider,
+        trigger_xy_pad,
+        note_off_style_toggle,
+        microtonal_toggle,
+        send_song_position_toggle,
+        midi_rate_limit_toggle,
+        midi_thru_toggle,
+        synth_waveform_drop_down,
+        stop_resets_position_toggle,
+        note_duration_range_slider,
+        max_voices_slider,
+        mono_toggle,
+        harmonize_toggle,
+        drum_mode_toggle,
+        drum_note_slider,
+        harmony_intervals_text_box,
+        release_velocity_slider,
+        round_robin_channels_text_box,
+        modulation_enabled_toggle,
+        melody_enabled_toggle,
+        transposition_enabled_toggle,
+        modulation_generator_type_drop_down_list,
+        modulation_range_slider,
+        modulation_cycle_length_slider,
+        modulation_target_drop_down,
+        modulation_controller_slider,
+        tempo_automation_depth_slider,
+        tempo_automation_period_slider,
+        fill_intensity_slider,
+        fill_phrase_bars_slider,
+        pitch_chance_toggle,
+        advance_on_trigger_only_toggle,
+        pitch_favor_drop_down,
+        pattern_length_text,
+        lock_seed_toggle,
+        seed_text,
+        bpm_text,
+        new_seed_button,
+        melody_lock_seed_toggle,
+        melody_seed_text,
+        melody_new_seed_button,
+        transposition_lock_seed_toggle,
+        transposition_seed_text,
+        transposition_new_seed_button,
+        trigger_lock_seed_toggle,
+        trigger_seed_text,
+        trigger_new_seed_button,
         // layout
         top_level_canvas,
         pitch_canvas,
+        pitch_canvas_enabled_column,
         pitch_canvas_left_column,
+        pitch_canvas_scale_column,
         pitch_canvas_middle_column,
+        pitch_canvas_min_text_column,
+        pitch_canvas_range_column,
+        pitch_canvas_max_text_column,
         pitch_canvas_right_column,
+        pitch_canvas_capture_column,
+        pitch_canvas_load_midi_column,
+        pitch_canvas_chord_note_column,
+        pitch_canvas_keyboard_column,
+        blend_canvas,
+        blend_canvas_type_column,
+        blend_canvas_weight_column,
+        blend_canvas_ramp_mode_column,
+        blend_canvas_phase_column,
+        degree_canvas,
+        degree_canvas_columns[],
         transposition_pitch_canvas,
+        transposition_pitch_canvas_enabled_column,
         transposition_pitch_canvas_left_column,
+        transposition_pitch_canvas_scale_column,
         transposition_pitch_canvas_middle_column,
         transposition_pitch_canvas_right_column,
+        transposition_pitch_canvas_phase_column,
+        transposition_pitch_canvas_chord_column,
+        transposition_pitch_canvas_square_column,
         global_canvas,
         global_canvas_left_column,
+        global_canvas_strength_column,
+        global_canvas_direction_column,
+        global_canvas_band_column,
+        global_canvas_transpose_column,
+        global_canvas_scatter_column,
+        global_canvas_scatter_octaves_column,
         global_canvas_middle_column,
+        global_canvas_restlessness_column,
         global_canvas_right_column,
+        global_canvas_offset_column,
+        trigger_canvas,
+        trigger_canvas_type_column,
+        trigger_canvas_order_column,
+        trigger_canvas_gate_on_column,
+        trigger_canvas_gate_off_column,
+        trigger_canvas_fill_intensity_column,
+        trigger_canvas_fill_phrase_column,
+        trigger_canvas_pitch_chance_column,
+        trigger_canvas_pitch_favor_column,
+        trigger_canvas_advance_column,
+        trigger_canvas_pattern_length_column,
+        note_canvas,
+        note_canvas_left_column,
+        note_canvas_length_max_column,
+        note_canvas_tie_column,
+        note_canvas_tie_probability_column,
+        note_canvas_repeat_column,
+        note_canvas_harmonize_column,
+        note_canvas_harmony_column,
+        note_canvas_drum_column,
+        note_canvas_drum_note_column,
+        note_canvas_octave_column,
+        note_canvas_density_column,
+        note_canvas_xy_pad_column,
+        velocity_canvas,
+        velocity_canvas_mode_column,
+        velocity_canvas_curve_column,
+        velocity_canvas_length_column,
+        velocity_canvas_length_amount_column,
+        velocity_canvas_humanize_column,
+        velocity_canvas_swing_column,
+        velocity_canvas_swing_resolution_column,
+        midi_canvas,
+        midi_canvas_note_off_column,
+        midi_canvas_release_velocity_column,
+        midi_canvas_channels_column,
+        midi_canvas_microtonal_column,
+        midi_canvas_song_position_column,
+        midi_canvas_rate_limit_column,
+        midi_canvas_thru_column,
+        midi_canvas_synth_column,
+        midi_canvas_note_duration_column,
+        midi_canvas_mono_column,
+        midi_canvas_right_column,
+        modulation_canvas,
+        modulation_canvas_left_column,
+        modulation_canvas_type_column,
+        modulation_canvas_middle_column,
+        modulation_canvas_right_column,
+        modulation_canvas_target_column,
+        modulation_canvas_controller_column,
+        tempo_canvas,
+        tempo_canvas_bpm_column,
+        tempo_canvas_depth_column,
+        tempo_canvas_period_column,
+        tempo_canvas_lock_seed_column,
+        tempo_canvas_seed_column,
+        tempo_canvas_new_seed_column,
+        tempo_canvas_stop_resets_column,
+        seed_canvas,
+        seed_canvas_melody_lock_column,
+        seed_canvas_melody_seed_column,
+        seed_canvas_melody_new_column,
+        seed_canvas_transposition_lock_column,
+        seed_canvas_transposition_seed_column,
+        seed_canvas_transposition_new_column,
+        seed_canvas_trigger_lock_column,
+        seed_canvas_trigger_seed_column,
+        seed_canvas_trigger_new_column,
         transport_canvas,
         transport_canvas_left_column,
-        transport_canvas_right_column
+        transport_canvas_middle_column,
+        transport_canvas_randomize_column,
+        transport_canvas_mutate_column,
+        transport_canvas_mutation_amount_column,
+        transport_canvas_snap_column,
+        transport_canvas_preset_column,
+        transport_canvas_live_column,
+        transport_canvas_count_in_column,
+        transport_canvas_right_column,
+        morph_canvas,
+        morph_canvas_a_column,
+        morph_canvas_b_column,
+        morph_canvas_bars_column,
+        morph_canvas_button_column,
+        status_canvas,
+        patch_summary_text
+    }
+}
+
+fn pitch_name(step: f32) -> String {
+    format_letter_octave(Step(step).to_letter_octave())
+}
+
+// One line summing up the patch, e.g. for screenshots of a live set. The step sequences and
+// chord progressions play their own notes, so their range is left out.
+fn patch_summary(sequencer_model: &SequencerModel) -> String {
+    let melody = if sequencer_model.melody_enabled {
+        let generator_type = melody_pitch_generator_type_from_index(
+            sequencer_model.melody_pitch_generator_type_index,
+        );
+        match generator_type {
+            PitchGeneratorType::Steps => generator_type.to_string(),
+            _ => format!(
+                "{} {}\u{2013}{}",
+                generator_type,
+                pitch_name(sequencer_model.melody_min_pitch),
+                pitch_name(sequencer_model.melody_max_pitch)
+            ),
+        }
+    } else {
+        "off".to_string()
+    };
+    let transposition = if sequencer_model.transposition_enabled {
+        let generator_type = transposition_pitch_generator_type_from_index(
+            sequencer_model.transposition_pitch_generator_type_index,
+        );
+        match generator_type {
+            PitchGeneratorType::Progression => generator_type.to_string(),
+            // the transposition range is in semitones, not notes
+            _ => format!(
+                "{} {}\u{2013}{}",
+                generator_type,
+                sequencer_model.transposition_min_pitch,
+                sequencer_model.transposition_max_pitch
+            ),
+        }
+    } else {
+        "off".to_string()
+    };
+    format!(
+        "Melody: {} / Transpose: {} / Scale: {} / {} BPM",
+        melody,
+        transposition,
+        QUANTIZER_SCALE_NAMES[sequencer_model.quantizer_scale_index.unwrap()],
+        sequencer_model.bpm.round()
+    )
+}
+
+// Parse a note name into a step within the melody pitch range
+fn parse_melody_pitch(text: &str) -> Option<f32> {
+    parse_letter_octave(text.trim())
+        .ok()
+        .map(|letter_octave| letter_octave.step())
+        .filter(|step| {
+            (MELODY_PITCH_MIN_VALUE.step()..=MELODY_PITCH_MAX_VALUE.step()).contains(step)
+        })
+}
+
+// Parse MIDI channel numbers from 1 to 16, separated by spaces or commas, e.g. "1 2 3"
+fn parse_midi_channels(text: &str) -> Option<Vec<u8>> {
+    let channels = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|channel| !channel.is_empty())
+        .map(|channel| {
+            channel
+                .parse()
+                .ok()
+                .filter(|channel| (1..=16).contains(channel))
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    if channels.is_empty() {
+        return None;
+    }
+    Some(channels)
+}
+
+// The channel list as typed, channel 1 without one
+fn format_midi_channels(channels: &[u8]) -> String {
+    if channels.is_empty() {
+        return "1".to_string();
+    }
+    channels
+        .iter()
+        .map(|channel| channel.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Parse semitone intervals of up to two octaves either way, separated by spaces or commas,
+// e.g. "+4 +7"
+fn parse_harmony_intervals(text: &str) -> Option<Vec<i32>> {
+    let intervals = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|interval| !interval.is_empty())
+        .map(|interval| {
+            interval
+                .parse()
+                .ok()
+                .filter(|interval: &i32| interval.abs() <= 24)
+        })
+        .collect::<Option<Vec<i32>>>()?;
+    if intervals.is_empty() {
+        return None;
+    }
+    Some(intervals)
+}
+
+// The intervals as typed, with their signs
+fn format_harmony_intervals(intervals: &[i32]) -> String {
+    intervals
+        .iter()
+        .map(|interval| format!("{:+}", interval))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Map the density macro to a trigger probability and clock divider factor. The
+// probability rises linearly from 20% to 100%, while the clock division falls
+// exponentially from a quarter note (24 ticks) to a 32nd note (3 ticks), so equal
+// slider moves change the step length by equal ratios.
+fn density_to_trigger_parameters(density: f32) -> (f32, f32) {
+    let probability = DENSITY_SPARSE_TRIGGER_PROBABILITY
+        + (TRIGGER_PROBABILITY_MAX_VALUE - DENSITY_SPARSE_TRIGGER_PROBABILITY) * density;
+    let clock_divider_factor = DENSITY_SPARSE_CLOCK_DIVIDER_FACTOR
+        * (DENSITY_BUSY_CLOCK_DIVIDER_FACTOR / DENSITY_SPARSE_CLOCK_DIVIDER_FACTOR).powf(density);
+    (
+        (probability * 100.0).round() / 100.0,
+        clock_divider_factor.round(),
+    )
+}
+
+// Position on the XY pad, both axes from 0 to 1, to the trigger probability (X) and clock
+// division (Y). Moving up shortens the steps, from the longest division to the shortest,
+// exponentially like the density macro.
+fn xy_pad_to_trigger_parameters(x: f32, y: f32) -> (f32, f32) {
+    let probability = TRIGGER_PROBABILITY_MIN_VALUE
+        + (TRIGGER_PROBABILITY_MAX_VALUE - TRIGGER_PROBABILITY_MIN_VALUE) * x;
+    let clock_divider_factor = CLOCK_DIVIDER_FACTOR_MAX_VALUE
+        * (CLOCK_DIVIDER_FACTOR_MIN_VALUE / CLOCK_DIVIDER_FACTOR_MAX_VALUE).powf(y);
+    (
+        (probability * 100.0).round() / 100.0,
+        clock_divider_factor.round(),
+    )
+}
+
+// The XY pad position showing the trigger probability and clock division
+fn trigger_parameters_to_xy_pad(probability: f32, clock_divider_factor: f32) -> (f32, f32) {
+    let x = (probability - TRIGGER_PROBABILITY_MIN_VALUE)
+        / (TRIGGER_PROBABILITY_MAX_VALUE - TRIGGER_PROBABILITY_MIN_VALUE);
+    let y = (clock_divider_factor / CLOCK_DIVIDER_FACTOR_MAX_VALUE).ln()
+        / (CLOCK_DIVIDER_FACTOR_MIN_VALUE / CLOCK_DIVIDER_FACTOR_MAX_VALUE).ln();
+    (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0))
+}
+
+// Pick random but valid values for the musical settings, keeping every range ordered.
+// MIDI device settings, the step sequence and the progression are kept as they are.
+fn randomize_sequencer_model<R: Rng>(
+    sequencer_model: &SequencerModel,
+    cycle_length_snap: u32,
+    rng: &mut R,
+) -> SequencerModel {
+    let (melody_min_pitch, melody_max_pitch) = random_ordered_pair(
+        rng,
+        MELODY_PITCH_MIN_VALUE.step(),
+        MELODY_PITCH_MAX_VALUE.step(),
+    );
+    let (transposition_min_pitch, transposition_max_pitch) = random_ordered_pair(
+        rng,
+        TRANSPOSITION_MIN_VALUE.step(),
+        TRANSPOSITION_MAX_VALUE.step(),
+    );
+    let (quantizer_min_octave, quantizer_max_octave) =
+        random_ordered_pair(rng, QUANTIZER_OCTAVE_MIN_VALUE, QUANTIZER_OCTAVE_MAX_VALUE);
+    let (modulation_min, modulation_max) =
+        random_ordered_pair(rng, MODULATION_MIN_VALUE, MODULATION_MAX_VALUE);
+    let melody_cycle_length = random_cycle_length(rng, cycle_length_snap);
+    let transposition_cycle_length = random_cycle_length(rng, cycle_length_snap);
+    SequencerModel {
+        melody_min_pitch,
+        melody_max_pitch,
+        melody_pitch_generator_type_index: Some(
+            rng.gen_range(0..MELODY_PITCH_GENERATOR_TYPE_NAMES.len()),
+        ),
+        melody_cycle_length,
+        melody_phase: random_phase(rng, melody_cycle_length),
+        melody_quantizer_scale_index: Some(rng.gen_range(0..LANE_QUANTIZER_SCALE_NAMES.len())),
+        melody_blend_generator_type_index: Some(
+            rng.gen_range(0..MELODY_BLEND_GENERATOR_TYPE_NAMES.len()),
+        ),
+        melody_blend_weight: rng
+            .gen_range(MELODY_BLEND_WEIGHT_MIN_VALUE..=MELODY_BLEND_WEIGHT_MAX_VALUE),
+        melody_ramp_mode_index: Some(rng.gen_range(0..RAMP_MODE_NAMES.len())),
+        transposition_min_pitch,
+        transposition_max_pitch,
+        transposition_pitch_generator_type_index: Some(
+            rng.gen_range(0..TRANSPOSITION_PITCH_GENERATOR_TYPE_NAMES.len()),
+        ),
+        transposition_cycle_length,
+        transposition_phase: random_phase(rng, transposition_cycle_length),
+        transposition_quantizer_scale_index: Some(
+            rng.gen_range(0..LANE_QUANTIZER_SCALE_NAMES.len()),
+        ),
+        // stay above the sparse end of the density macro, so there is something to hear
+        trigger_probability: (rng
+            .gen_range(DENSITY_SPARSE_TRIGGER_PROBABILITY..=TRIGGER_PROBABILITY_MAX_VALUE)
+            * 100.0)
+            .round()
+            / 100.0,
+        trigger_restlessness: rng
+            .gen_range(TRIGGER_RESTLESSNESS_MIN_VALUE..=TRIGGER_RESTLESSNESS_MAX_VALUE),
+        clock_divider_factor: rng
+            .gen_range(CLOCK_DIVIDER_FACTOR_MIN_VALUE..=CLOCK_DIVIDER_FACTOR_MAX_VALUE)
+            .round(),
+        trigger_type_index: Some(rng.gen_range(0..TRIGGER_TYPE_NAMES.len())),
+        gate_on: rng.gen_range(GATE_ON_MIN_VALUE..=GATE_ON_MAX_VALUE).round(),
+        gate_off: rng
+            .gen_range(GATE_OFF_MIN_VALUE..=GATE_OFF_MAX_VALUE)
+            .round(),
+        quantizer_scale_index: Some(rng.gen_range(0..QUANTIZER_SCALES.len())),
+        quantizer_strength: rng
+            .gen_range(QUANTIZER_STRENGTH_MIN_VALUE..=QUANTIZER_STRENGTH_MAX_VALUE),
+        quantizer_direction_index: Some(rng.gen_range(0..QUANTIZER_DIRECTION_NAMES.len())),
+        quantizer_min_octave,
+        quantizer_max_octave,
+        note_length_min_index: Some(rng.gen_range(0..NOTE_LENGTH_NAMES.len())),
+        note_length_max_index: Some(rng.gen_range(0..NOTE_LENGTH_NAMES.len())),
+        tie_repeated_notes: rng.gen(),
+        transposition_chord_tones: rng.gen(),
+        velocity_mode_index: Some(rng.gen_range(0..VELOCITY_MODE_NAMES.len())),
+        velocity_curve_index: Some(rng.gen_range(0..VELOCITY_CURVE_NAMES.len())),
+        modulation_enabled: rng.gen(),
+        modulation_generator_type_index: Some(rng.gen_range(0..PITCH_GENERATOR_TYPE_NAMES.len())),
+        modulation_min,
+        modulation_max,
+        modulation_cycle_length: random_cycle_length(rng, cycle_length_snap),
+        bpm: rng
+            .gen_range(RANDOM_BPM_MIN_VALUE..=RANDOM_BPM_MAX_VALUE)
+            .round(),
+        ..sequencer_model.clone()
+    }
+}
+
+// Two whole numbers within the bounds, the lower one first
+fn random_ordered_pair<R: Rng>(rng: &mut R, min: f32, max: f32) -> (f32, f32) {
+    let a = rng.gen_range(min..=max).round();
+    let b = rng.gen_range(min..=max).round();
+    (a.min(b), a.max(b))
+}
+
+// A cycle length within the slider range, restricted to multiples of the snap like the sliders
+fn random_cycle_length<R: Rng>(rng: &mut R, snap: u32) -> f32 {
+    let max_multiple = PITCH_GENERATOR_CYCLE_LENGTH_MAX_VALUE as u32 / snap;
+    (rng.gen_range(1..=max_multiple) * snap) as f32
+}
+
+fn snap_cycle_length(cycle_length: f32, snap: u32) -> f32 {
+    ((cycle_length.round() as u32).div_ceil(snap) * snap) as f32
+}
+
+// A step of the cycle to start at, within the cycle length
+fn random_phase<R: Rng>(rng: &mut R, cycle_length: f32) -> f32 {
+    rng.gen_range(0..cycle_length as u32) as f32
+}
+
+// Only the ramp and square generators have a phase to start their cycle at
+fn has_phase(generator_type: &PitchGeneratorType) -> bool {
+    matches!(
+        generator_type,
+        PitchGeneratorType::RampUp | PitchGeneratorType::Square
+    )
+}
+
+fn cycle_length_snap_from_index(idx: Option<Idx>) -> u32 {
+    CYCLE_LENGTH_SNAP_VALUES[idx.unwrap()]
+}
+
+fn log_sequencer_error(error: SequencerError) {
+    error!("Failed to send command to sequencer: {}", error);
+}
+
+fn windows(app: &App) -> Windows {
+    // Escape is used to dismiss the help overlay and to panic
+    app.set_exit_on_escape(false);
+
+    let mut models = vec![model::<0>(app)];
+    if std::env::args().any(|arg| arg == SECOND_WINDOW_FLAG) {
+        models.push(model::<1>(app));
+    }
+    Windows { models }
+}
+
+fn update_windows(app: &App, windows: &mut Windows, frame_update: Update) {
+    for model in windows.models.iter_mut() {
+        update(app, model, frame_update.clone());
     }
 }
 
-fn pitch_generator_type_from_index(idx: Option<Idx>) -> PitchGeneratorType {
-    PitchGeneratorType::from_str(PITCH_GENERATOR_TYPE_NAMES[idx.unwrap()]).unwrap()
+fn window_key_pressed<const WINDOW: usize>(app: &App, windows: &mut Windows, key: Key) {
+    key_pressed(app, &mut windows.models[WINDOW], key);
+}
+
+fn window_raw_event<const WINDOW: usize>(
+    app: &App,
+    windows: &mut Windows,
+    event: &nannou_conrod::RawWindowEvent,
+) {
+    raw_ui_event(app, &mut windows.models[WINDOW], event);
+}
+
+fn window_view<const WINDOW: usize>(app: &App, windows: &Windows, frame: Frame) {
+    ui_view(app, &windows.models[WINDOW], frame);
 }
 
-fn model(app: &App) -> Model {
+// The editor of a window, sending to the MIDI output port and channel of the window number
+fn model<const WINDOW: usize>(app: &App) -> Model {
     // Create a window
     let w_id = app
         .new_window()
-        .size(900, 300)
-        .key_pressed(key_pressed)
-        .raw_event(raw_ui_event)
-        .view(ui_view)
+        .title(format!("Nannou Generative Sequencer {}", WINDOW + 1))
+        .size(900, 540)
+        // The layout scales with the window, but gets cramped below the default size
+        .min_size(900, 540)
+        .key_pressed(window_key_pressed::<WINDOW>)
+        .raw_event(window_raw_event::<WINDOW>)
+        .view(window_view::<WINDOW>)
         .build()
         .unwrap();
 
     // Create the UI for our window
     let mut ui = nannou_conrod::builder(app).window(w_id).build().unwrap();
 
-    // Generate IDs for our widgets
-    let ids = Ids::new(ui.widget_id_generator());
-
-    // Create and initialize sequencer
-    let sequencer_model = SequencerModel {
-        melody_min_pitch: MELODY_MIN_PITCH_DEFAULT_VALUE.step(),
-        melody_max_pitch: MELODY_MAX_PITCH_DEFAULT_VALUE.step(),
-        melody_pitch_generator_type_index: Some(MELODY_PITCH_GENERATOR_TYPE_DEFAULT_VALUE),
-        melody_cycle_length: MELODY_PITCH_GENERATOR_CYCLE_LENGTH_DEFAULT_VALUE,
-        transposition_min_pitch: TRANSPOSITION_MIN_PITCH_DEFAULT_VALUE.step(),
-        transposition_max_pitch: TRANSPOSITION_MAX_PITCH_DEFAULT_VALUE.step(),
-        transposition_pitch_generator_type_index: Some(
-            TRANSPOSITION_PITCH_GENERATOR_TYPE_DEFAULT_VALUE,
-        ),
-        transposition_cycle_length: TRANSPOSITION_PITCH_GENERATOR_CYCLE_LENGTH_DEFAULT_VALUE,
-        trigger_probability: TRIGGER_PROBABILITY_DEFAULT_VALUE,
-        clock_divider_factor: CLOCK_DIVIDER_FACTOR_DEFAULT_VALUE,
-        quantizer_scale_index: Some(QUANTIZER_SCALE_INDEX_DEFAULT_VALUE),
-        bpm: BPM_DEFAULT_VALUE,
-    };
+    // Generate IDs for our widgets, with a degree weight slider for each degree of the
+    // largest scale
+    let mut ids = Ids::new(ui.widget_id_generator());
+    ids.degree_canvas_columns
+        .resize(CHROMATIC_SCALE_NOTES.len(), &mut ui.widget_id_generator());
+    ids.melody_degree_weight_sliders
+        .resize(CHROMATIC_SCALE_NOTES.len(), &mut ui.widget_id_generator());
+
+    // Create and initialize sequencer, the one of a second window on the next channel
+    let mut sequencer_model = SequencerModel::default();
+    if WINDOW > 0 {
+        sequencer_model.round_robin_channels = vec![WINDOW as u8 + 1];
+    }
     let is_playing = true;
-    let sequencer = Sequencer::new(sequencer_model.clone().into(), is_playing);
+    let monitor_in_hz = std::env::args().any(|arg| arg == HZ_FLAG);
+    let midi_output: Box<dyn MidiSink> = if std::env::args().any(|arg| arg == MONITOR_FLAG) {
+        info!("Monitoring MIDI messages instead of sending them");
+        Box::new(MonitorMidiSink::new(monitor_in_hz))
+    } else {
+        open_midi_output(
+            std::env::args().any(|arg| arg == VIRTUAL_PORT_FLAG),
+            WINDOW,
+            monitor_in_hz,
+        )
+        .unwrap_or_else(|e| {
+            error!("{}, monitoring MIDI messages instead", e);
+            Box::new(MonitorMidiSink::new(monitor_in_hz))
+        })
+    };
+    // There is one audio output, played by the sequencer of the first window
+    let synth = if WINDOW == 0 && std::env::args().any(|arg| arg == SYNTH_FLAG) {
+        Synth::open(Waveform::from_index(SYNTH_WAVEFORM_INDEX_DEFAULT_VALUE))
+    } else {
+        None
+    };
+    let midi_output = match &synth {
+        Some(synth) => synth.midi_sink(midi_output),
+        None => midi_output,
+    };
+    let sequencer = Sequencer::new(sequencer_model.clone().into(), is_playing, midi_output);
+    let midi_thru = MidiThru::open(sequencer.thru_sink());
+    let history_sequencer_model = sequencer_model.clone();
+
+    let preset_dir = std::env::args()
+        .skip_while(|arg| arg != PRESET_DIR_FLAG)
+        .nth(1)
+        .unwrap_or_else(|| PRESET_DIR_DEFAULT_PATH.to_string());
+    let preset_bank = match PresetBank::scan(Path::new(&preset_dir)) {
+        Ok(preset_bank) => {
+            info!("Found {} presets in: {}", preset_bank.len(), preset_dir);
+            preset_bank
+        }
+        Err(e) => {
+            warn!("Failed to scan preset folder {}: {}", preset_dir, e);
+            PresetBank::empty()
+        }
+    };
 
     Model {
         ui: ui,
@@ -199,97 +1032,932 @@ fn model(app: &App) -> Model {
         sequencer,
         sequencer_model,
         is_playing,
+        last_step: None,
+        trigger_density: 0.0,
+        note_capture: NoteCapture::open(),
+        is_capturing: false,
+        chord_note_index: Some(CHORD_NOTE_INDEX_DEFAULT_VALUE),
+        midi_thru,
+        synth,
+        synth_waveform_index: Some(SYNTH_WAVEFORM_INDEX_DEFAULT_VALUE),
+        show_help: false,
+        density: DENSITY_DEFAULT_VALUE,
+        cycle_length_snap_index: Some(CYCLE_LENGTH_SNAP_INDEX_DEFAULT_VALUE),
+        mutation_amount: MUTATION_AMOUNT_DEFAULT_VALUE,
+        morph_a_index: None,
+        morph_b_index: None,
+        morph_bars: MORPH_BARS_DEFAULT_VALUE,
+        morph: None,
+        melody_min_pitch_text: None,
+        melody_max_pitch_text: None,
+        melody_step_sequence_text: None,
+        round_robin_channels_text: None,
+        harmony_intervals_text: None,
+        history: History::new(history_sequencer_model, HISTORY_DEPTH),
+        preset_bank,
     }
 }
 
-fn key_pressed(_app: &App, model: &mut Model, key: Key) {
+fn key_pressed(app: &App, model: &mut Model, key: Key) {
+    // Leave the keyboard to widgets that capture it, e.g. text fields
+    if model
+        .ui
+        .global_input()
+        .current
+        .widget_capturing_keyboard
+        .is_some()
+    {
+        return;
+    }
+
     match key {
         Key::Space => {
             // Toggle sequencer playback
             if model.is_playing {
                 info!("Stop sequencer");
                 model.is_playing = false;
-                model.sequencer.stop()
+                model.sequencer.stop().unwrap_or_else(log_sequencer_error)
             } else {
                 info!("Start sequencer");
                 model.is_playing = true;
-                model.sequencer.start()
+                model.sequencer.start().unwrap_or_else(log_sequencer_error)
+            }
+        }
+        Key::R => reset_sequencer(&model.sequencer, &model.sequencer_model),
+        Key::S => {
+            match save_preset(&model.sequencer_model, PRESET_FILE_PATH) {
+                Ok(()) => info!("Saved preset to: {}", PRESET_FILE_PATH),
+                Err(e) => error!("Failed to save preset: {}", e),
+            }
+            match save_state(&model.sequencer, STATE_FILE_PATH) {
+                Ok(()) => info!("Saved sequencer state to: {}", STATE_FILE_PATH),
+                Err(e) => error!("Failed to save sequencer state: {}", e),
+            }
+        }
+        Key::L => match load_preset(Path::new(PRESET_FILE_PATH)) {
+            Ok(sequencer_model) => {
+                info!("Loaded preset from: {}", PRESET_FILE_PATH);
+                model.sequencer_model = sequencer_model;
+                apply_sequencer_model(&mut model.sequencer, &model.sequencer_model);
+                // Without a saved state the generators simply start from the beginning
+                match load_state(STATE_FILE_PATH) {
+                    Ok(state) => {
+                        info!("Loaded sequencer state from: {}", STATE_FILE_PATH);
+                        model
+                            .sequencer
+                            .restore(state)
+                            .unwrap_or_else(log_sequencer_error);
+                    }
+                    Err(e) => warn!("Failed to load sequencer state: {}", e),
+                }
+            }
+            Err(e) => error!("Failed to load preset: {}", e),
+        },
+        Key::PageUp | Key::PageDown => {
+            match model.preset_bank.step(key == Key::PageDown, load_preset) {
+                Some(sequencer_model) => {
+                    info!(
+                        "Loaded preset: {}",
+                        model.preset_bank.current_name().unwrap_or_default()
+                    );
+                    model.sequencer_model = sequencer_model;
+                    apply_sequencer_model(&mut model.sequencer, &model.sequencer_model);
+                }
+                None => warn!("No loadable presets in the preset folder"),
+            }
+        }
+        Key::Up | Key::Down => {
+            let delta = if key == Key::Up { 1.0 } else { -1.0 };
+            let new_value = (model.sequencer_model.bpm + delta).clamp(BPM_MIN_VALUE, BPM_MAX_VALUE);
+            if model.sequencer_model.bpm != new_value {
+                info!("Set BPM to: {}", new_value);
+                model.sequencer_model.bpm = new_value;
+                model
+                    .sequencer
+                    .set_bpm(new_value)
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+        Key::Plus | Key::Equals | Key::Minus => {
+            let delta = if key == Key::Minus { -1 } else { 1 };
+            let new_value = (model.sequencer_model.octave_offset + delta)
+                .clamp(OCTAVE_OFFSET_MIN_VALUE, OCTAVE_OFFSET_MAX_VALUE);
+            if model.sequencer_model.octave_offset != new_value {
+                info!("Set octave offset to: {}", new_value);
+                model.sequencer_model.octave_offset = new_value;
+                model
+                    .sequencer
+                    .update_octave_offset(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+        Key::V => {
+            // Step through the log levels, wrapping around after the most verbose one
+            let index = LOG_LEVELS
+                .iter()
+                .position(|level| *level == log::max_level())
+                .unwrap_or(0);
+            let new_level = LOG_LEVELS[(index + 1) % LOG_LEVELS.len()];
+            log::set_max_level(new_level);
+            info!("Set log level to: {}", new_level);
+        }
+        Key::Z if app.keys.mods.ctrl() || app.keys.mods.logo() => {
+            // Record a pending edit first, so that it is the one undone
+            model.history.commit(&model.sequencer_model);
+            let restored = if app.keys.mods.shift() {
+                info!("Redo");
+                model.history.redo()
+            } else {
+                info!("Undo");
+                model.history.undo()
+            };
+            if let Some(sequencer_model) = restored {
+                model.sequencer_model = sequencer_model;
+                apply_sequencer_model(&mut model.sequencer, &model.sequencer_model);
+            }
+        }
+        Key::H => set_show_help(model, !model.show_help),
+        Key::Escape => {
+            // Dismiss the help overlay first, panic otherwise
+            if model.show_help {
+                set_show_help(model, false);
+            } else {
+                model.sequencer.panic().unwrap_or_else(log_sequencer_error);
             }
         }
+        Key::LBracket | Key::RBracket => {
+            // Step through the quantizer scales, wrapping around at both ends
+            let scale_count = QUANTIZER_SCALES.len();
+            let index = model.sequencer_model.quantizer_scale_index.unwrap();
+            let new_index = if key == Key::RBracket {
+                (index + 1) % scale_count
+            } else {
+                (index + scale_count - 1) % scale_count
+            };
+            info!(
+                "Set pitch quantizer scale to: {}",
+                QUANTIZER_SCALE_NAMES[new_index]
+            );
+            model.sequencer_model.quantizer_scale_index = Some(new_index);
+            model
+                .sequencer
+                .update_quantizer_scale(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
         _ => (),
     }
 }
 
+fn set_show_help(model: &mut Model, show_help: bool) {
+    model.show_help = show_help;
+    // Make sure the UI is redrawn over the overlay once it is hidden
+    model.ui.needs_redraw();
+}
+
+fn reset_sequencer(sequencer: &Sequencer, sequencer_model: &SequencerModel) {
+    info!("Reset sequencer");
+    sequencer
+        .update_pitch_generator(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_trigger_generator(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_modulation(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_seed(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer.rewind().unwrap_or_else(log_sequencer_error);
+}
+
+fn apply_sequencer_model(sequencer: &mut Sequencer, sequencer_model: &SequencerModel) {
+    reset_sequencer(sequencer, sequencer_model);
+    update_sequencer_settings(sequencer, sequencer_model);
+}
+
+// The settings the sequencer plays the generated steps with, leaving the generators as
+// they are
+fn update_sequencer_settings(sequencer: &mut Sequencer, sequencer_model: &SequencerModel) {
+    sequencer
+        .update_note_length(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_note_repeat(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_tie_repeated_notes(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_tie_probability(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_octave_offset(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_note_off_style(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_release_velocity(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_channel_mode(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_microtonal(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_send_song_position(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_midi_rate_limit(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_stop_resets_position(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_note_duration_range(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_max_voices(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_mono(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_harmonizer(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_fill(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_pitch_chance(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_advance_on_trigger_only(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_velocity_length(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_humanize(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_swing(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_tempo_automation(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .update_count_in(sequencer_model.clone().into())
+        .unwrap_or_else(log_sequencer_error);
+    sequencer
+        .set_bpm(sequencer_model.bpm)
+        .unwrap_or_else(log_sequencer_error);
+}
+
+// Move the sequencer from one step of a morph to the next. The ranges, cycle lengths,
+// trigger probability and tempo change in place. When anything else changes, the
+// generators are rebuilt and carry on from the state they were in.
+fn apply_morph_step(sequencer: &mut Sequencer, from: &SequencerModel, to: &SequencerModel) {
+    let config = || -> SequencerConfiguration { to.clone().into() };
+    let in_place = SequencerModel {
+        melody_min_pitch: to.melody_min_pitch,
+        melody_max_pitch: to.melody_max_pitch,
+        melody_cycle_length: to.melody_cycle_length,
+        transposition_min_pitch: to.transposition_min_pitch,
+        transposition_max_pitch: to.transposition_max_pitch,
+        transposition_cycle_length: to.transposition_cycle_length,
+        trigger_probability: to.trigger_probability,
+        bpm: to.bpm,
+        ..from.clone()
+    };
+    if in_place != *to {
+        let state = sequencer.state();
+        sequencer
+            .update_pitch_generator(config())
+            .unwrap_or_else(log_sequencer_error);
+        sequencer
+            .update_trigger_generator(config())
+            .unwrap_or_else(log_sequencer_error);
+        sequencer
+            .update_modulation(config())
+            .unwrap_or_else(log_sequencer_error);
+        update_sequencer_settings(sequencer, to);
+        match state {
+            Ok(state) => sequencer.restore(state).unwrap_or_else(log_sequencer_error),
+            Err(e) => log_sequencer_error(e),
+        }
+        return;
+    }
+    if (from.melody_min_pitch, from.melody_max_pitch) != (to.melody_min_pitch, to.melody_max_pitch)
+    {
+        sequencer
+            .update_pitch_range(Lane::Melody, config())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    if (from.transposition_min_pitch, from.transposition_max_pitch)
+        != (to.transposition_min_pitch, to.transposition_max_pitch)
+    {
+        sequencer
+            .update_pitch_range(Lane::Transposition, config())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    if from.melody_cycle_length != to.melody_cycle_length {
+        sequencer
+            .update_cycle_length(Lane::Melody, config())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    if from.transposition_cycle_length != to.transposition_cycle_length {
+        sequencer
+            .update_cycle_length(Lane::Transposition, config())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    if from.trigger_probability != to.trigger_probability {
+        sequencer
+            .update_trigger_probability(config())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    if from.bpm != to.bpm {
+        sequencer
+            .set_bpm(to.bpm)
+            .unwrap_or_else(log_sequencer_error);
+    }
+}
+
+// Take the next step of a running morph once a new bar has started. Meant to be called
+// every frame, like the tempo automation.
+fn follow_morph(model: &mut Model) {
+    let bar = model.sequencer.bar();
+    let running = match model.morph.as_mut() {
+        Some(running) if running.last_bar != bar => running,
+        _ => return,
+    };
+    running.last_bar = bar;
+    running.bars_played += 1;
+    let t = running.bars_played as f32 / running.bars as f32;
+    let sequencer_model = morph(&running.a, &running.b, t);
+    apply_morph_step(
+        &mut model.sequencer,
+        &model.sequencer_model,
+        &sequencer_model,
+    );
+    model.sequencer_model = sequencer_model;
+    if t >= 1.0 {
+        info!("Morph done");
+        model.morph = None;
+    }
+}
+
+// The presets selected as A and B of a morph, if both load
+fn load_morph_presets(
+    preset_bank: &PresetBank,
+    a_index: Option<Idx>,
+    b_index: Option<Idx>,
+) -> Option<(SequencerModel, SequencerModel)> {
+    let load = |index: Option<Idx>| {
+        let path = preset_bank.path(index?)?;
+        load_preset(path)
+            .map_err(|e| error!("Failed to load preset {}: {}", path.display(), e))
+            .ok()
+    };
+    Some((load(a_index)?, load(b_index)?))
+}
+
+// Play the notes as the melody step sequence, e.g. captured or loaded ones
+fn set_melody_step_sequence(sequencer_model: &mut SequencerModel, notes: &[LetterOctave]) {
+    let new_value = format_step_sequence(notes);
+    info!("Set melody step sequence to: {}", new_value);
+    sequencer_model.melody_step_sequence = new_value;
+    sequencer_model.melody_pitch_generator_type_index = MELODY_PITCH_GENERATOR_TYPE_NAMES
+        .iter()
+        .position(|name| *name == PitchGeneratorType::Steps.to_string());
+}
+
+fn save_preset(sequencer_model: &SequencerModel, path: &str) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string_pretty(sequencer_model)?)?;
+    Ok(())
+}
+
+fn load_preset(path: &Path) -> Result<SequencerModel, Box<dyn Error>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn save_state(sequencer: &Sequencer, path: &str) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string_pretty(&sequencer.state()?)?)?;
+    Ok(())
+}
+
+fn load_state(path: &str) -> Result<SequencerState, Box<dyn Error>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
 fn update(_app: &App, model: &mut Model, _update: Update) {
+    // The tempo automation moves on at bar boundaries, which are checked every frame
+    model
+        .sequencer
+        .follow_tempo_automation()
+        .unwrap_or_else(log_sequencer_error);
+    follow_morph(model);
+
+    // The live step is drawn along with the UI, so only redraw when a new step came in
+    let last_step = model.sequencer.last_step();
+    if model.last_step != last_step {
+        model.last_step = last_step;
+        model.ui.needs_redraw();
+    }
+    let trigger_density = model.sequencer.trigger_density();
+    if model.trigger_density != trigger_density {
+        model.trigger_density = trigger_density;
+        model.ui.needs_redraw();
+    }
+
     // Create context for instantiating widgets
     let ui = &mut model.ui.set_widgets();
 
+    let degree_columns: Vec<_> = model
+        .ids
+        .degree_canvas_columns
+        .iter()
+        .map(|column| (*column, column_canvas()))
+        .collect();
+
     // Construct the top level layout
     widget::Canvas::new()
         .flow_down(&[
             (
                 model.ids.global_canvas,
-                widget::Canvas::new().length(60.0).flow_right(&[
+                row_canvas().flow_right(&[
                     (model.ids.global_canvas_left_column, column_canvas()),
+                    (model.ids.global_canvas_strength_column, column_canvas()),
+                    (model.ids.global_canvas_direction_column, column_canvas()),
+                    (model.ids.global_canvas_band_column, column_canvas()),
+                    (model.ids.global_canvas_transpose_column, column_canvas()),
+                    (model.ids.global_canvas_scatter_column, column_canvas()),
+                    (
+                        model.ids.global_canvas_scatter_octaves_column,
+                        column_canvas(),
+                    ),
                     (model.ids.global_canvas_middle_column, column_canvas()),
+                    (model.ids.global_canvas_restlessness_column, column_canvas()),
                     (model.ids.global_canvas_right_column, column_canvas()),
+                    (model.ids.global_canvas_offset_column, column_canvas()),
+                ]),
+            ),
+            (
+                model.ids.trigger_canvas,
+                row_canvas().flow_right(&[
+                    (model.ids.trigger_canvas_type_column, column_canvas()),
+                    (model.ids.trigger_canvas_order_column, column_canvas()),
+                    (model.ids.trigger_canvas_gate_on_column, column_canvas()),
+                    (model.ids.trigger_canvas_gate_off_column, column_canvas()),
+                    (
+                        model.ids.trigger_canvas_fill_intensity_column,
+                        column_canvas(),
+                    ),
+                    (model.ids.trigger_canvas_fill_phrase_column, column_canvas()),
+                    (
+                        model.ids.trigger_canvas_pitch_chance_column,
+                        column_canvas(),
+                    ),
+                    (model.ids.trigger_canvas_pitch_favor_column, column_canvas()),
+                    (model.ids.trigger_canvas_advance_column, column_canvas()),
+                    (
+                        model.ids.trigger_canvas_pattern_length_column,
+                        column_canvas(),
+                    ),
                 ]),
             ),
             (
                 model.ids.pitch_canvas,
-                widget::Canvas::new().length(60.0).flow_right(&[
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.pitch_canvas_enabled_column,
+                        column_canvas().length_weight(1.0),
+                    ),
                     (
                         model.ids.pitch_canvas_left_column,
                         column_canvas().length_weight(1.0),
                     ),
+                    (
+                        model.ids.pitch_canvas_scale_column,
+                        column_canvas().length_weight(1.5),
+                    ),
                     (
                         model.ids.pitch_canvas_middle_column,
-                        column_canvas().length_weight(4.0),
+                        column_canvas().length_weight(3.0).pad(0.0).flow_right(&[
+                            (
+                                model.ids.pitch_canvas_min_text_column,
+                                column_canvas().length_weight(1.0),
+                            ),
+                            (
+                                model.ids.pitch_canvas_range_column,
+                                column_canvas().length_weight(3.0),
+                            ),
+                            (
+                                model.ids.pitch_canvas_max_text_column,
+                                column_canvas().length_weight(1.0),
+                            ),
+                        ]),
                     ),
                     (
                         model.ids.pitch_canvas_right_column,
-                        column_canvas().length_weight(3.0),
+                        column_canvas().length_weight(2.5),
+                    ),
+                    (
+                        model.ids.pitch_canvas_capture_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.pitch_canvas_load_midi_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.pitch_canvas_chord_note_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.pitch_canvas_keyboard_column,
+                        column_canvas().length_weight(2.0),
                     ),
                 ]),
             ),
+            (
+                model.ids.blend_canvas,
+                row_canvas().flow_right(&[
+                    (model.ids.blend_canvas_type_column, column_canvas()),
+                    (model.ids.blend_canvas_weight_column, column_canvas()),
+                    (model.ids.blend_canvas_ramp_mode_column, column_canvas()),
+                    (model.ids.blend_canvas_phase_column, column_canvas()),
+                ]),
+            ),
+            (
+                model.ids.degree_canvas,
+                row_canvas().flow_right(&degree_columns),
+            ),
             (
                 model.ids.transposition_pitch_canvas,
-                widget::Canvas::new().length(60.0).flow_right(&[
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.transposition_pitch_canvas_enabled_column,
+                        column_canvas().length_weight(1.0),
+                    ),
                     (
                         model.ids.transposition_pitch_canvas_left_column,
                         column_canvas().length_weight(1.0),
                     ),
+                    (
+                        model.ids.transposition_pitch_canvas_scale_column,
+                        column_canvas().length_weight(1.5),
+                    ),
                     (
                         model.ids.transposition_pitch_canvas_middle_column,
-                        column_canvas().length_weight(4.0),
+                        column_canvas().length_weight(3.0),
                     ),
                     (
                         model.ids.transposition_pitch_canvas_right_column,
-                        column_canvas().length_weight(3.0),
+                        column_canvas().length_weight(2.5),
+                    ),
+                    (
+                        model.ids.transposition_pitch_canvas_phase_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transposition_pitch_canvas_chord_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transposition_pitch_canvas_square_column,
+                        column_canvas().length_weight(1.0),
                     ),
                 ]),
             ),
             (
-                model.ids.transport_canvas,
-                widget::Canvas::new().flow_right(&[
+                model.ids.note_canvas,
+                row_canvas().flow_right(&[
                     (
-                        model.ids.transport_canvas_left_column,
+                        model.ids.note_canvas_left_column,
                         column_canvas().length_weight(1.0),
                     ),
                     (
-                        model.ids.transport_canvas_right_column,
+                        model.ids.note_canvas_length_max_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.note_canvas_tie_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.note_canvas_tie_probability_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.note_canvas_repeat_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.note_canvas_harmonize_column,
                         column_canvas().length_weight(1.0),
                     ),
+                    (
+                        model.ids.note_canvas_harmony_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.note_canvas_drum_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.note_canvas_drum_note_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.note_canvas_octave_column,
+                        column_canvas().length_weight(2.0),
+                    ),
+                    (
+                        model.ids.note_canvas_density_column,
+                        column_canvas().length_weight(1.5),
+                    ),
+                    (
+                        model.ids.note_canvas_xy_pad_column,
+                        column_canvas().length_weight(1.5),
+                    ),
                 ]),
             ),
-        ])
+            (
+                model.ids.velocity_canvas,
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.velocity_canvas_mode_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.velocity_canvas_curve_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.velocity_canvas_length_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.velocity_canvas_length_amount_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.velocity_canvas_humanize_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.velocity_canvas_swing_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.velocity_canvas_swing_resolution_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                ]),
+            ),
+            (
+                model.ids.midi_canvas,
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.midi_canvas_note_off_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_release_velocity_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_channels_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_microtonal_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_song_position_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_rate_limit_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_thru_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_synth_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_note_duration_column,
+                        column_canvas().length_weight(2.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_mono_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.midi_canvas_right_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                ]),
+            ),
+            (
+                model.ids.modulation_canvas,
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.modulation_canvas_left_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.modulation_canvas_type_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.modulation_canvas_middle_column,
+                        column_canvas().length_weight(3.0),
+                    ),
+                    (
+                        model.ids.modulation_canvas_right_column,
+                        column_canvas().length_weight(2.0),
+                    ),
+                    (
+                        model.ids.modulation_canvas_target_column,
+                        column_canvas().length_weight(1.5),
+                    ),
+                    (
+                        model.ids.modulation_canvas_controller_column,
+                        column_canvas().length_weight(1.5),
+                    ),
+                ]),
+            ),
+            (
+                model.ids.tempo_canvas,
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.tempo_canvas_bpm_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.tempo_canvas_depth_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.tempo_canvas_period_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.tempo_canvas_lock_seed_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.tempo_canvas_seed_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.tempo_canvas_new_seed_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.tempo_canvas_stop_resets_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                ]),
+            ),
+            (
+                model.ids.seed_canvas,
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.seed_canvas_melody_lock_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.seed_canvas_melody_seed_column,
+                        column_canvas().length_weight(1.5),
+                    ),
+                    (
+                        model.ids.seed_canvas_melody_new_column,
+                        column_canvas().length_weight(0.5),
+                    ),
+                    (
+                        model.ids.seed_canvas_transposition_lock_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.seed_canvas_transposition_seed_column,
+                        column_canvas().length_weight(1.5),
+                    ),
+                    (
+                        model.ids.seed_canvas_transposition_new_column,
+                        column_canvas().length_weight(0.5),
+                    ),
+                    (
+                        model.ids.seed_canvas_trigger_lock_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.seed_canvas_trigger_seed_column,
+                        column_canvas().length_weight(1.5),
+                    ),
+                    (
+                        model.ids.seed_canvas_trigger_new_column,
+                        column_canvas().length_weight(0.5),
+                    ),
+                ]),
+            ),
+            (
+                model.ids.transport_canvas,
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.transport_canvas_left_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_middle_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_randomize_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_mutate_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_mutation_amount_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_snap_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_preset_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_live_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_count_in_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.transport_canvas_right_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                ]),
+            ),
+            (
+                model.ids.morph_canvas,
+                row_canvas().flow_right(&[
+                    (
+                        model.ids.morph_canvas_a_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.morph_canvas_b_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.morph_canvas_bars_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                    (
+                        model.ids.morph_canvas_button_column,
+                        column_canvas().length_weight(1.0),
+                    ),
+                ]),
+            ),
+            (model.ids.status_canvas, row_canvas().length_weight(0.5)),
+        ])
         .color(CANVAS_COLOR)
         .pad(5.0)
         .set(model.ids.top_level_canvas, ui);
 
     // Create melody pitch generator widgets
+    for melody_enabled_value in toggle(model.sequencer_model.melody_enabled)
+        .padded_wh_of(model.ids.pitch_canvas_enabled_column, 5.0)
+        .middle_of(model.ids.pitch_canvas_enabled_column)
+        .label("Melody")
+        .set(model.ids.melody_enabled_toggle, ui)
+    {
+        info!("Set melody enabled to: {}", melody_enabled_value);
+        model.sequencer_model.melody_enabled = melody_enabled_value;
+        model
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
     for melody_pitch_generator_type_value in drop_down_list(
-        PITCH_GENERATOR_TYPE_NAMES,
+        MELODY_PITCH_GENERATOR_TYPE_NAMES,
         model.sequencer_model.melody_pitch_generator_type_index,
     )
     .padded_wh_of(model.ids.pitch_canvas_left_column, 5.0)
@@ -297,14 +1965,35 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
     .set(model.ids.melody_pitch_generator_type_drop_down_list, ui)
     {
         info!(
-            "Set transposition generator type to: {}",
-            pitch_generator_type_from_index(Some(melody_pitch_generator_type_value))
+            "Set melody generator type to: {}",
+            melody_pitch_generator_type_from_index(Some(melody_pitch_generator_type_value))
         );
         model.sequencer_model.melody_pitch_generator_type_index =
             Some(melody_pitch_generator_type_value);
         model
             .sequencer
-            .update_pitch_generator(model.sequencer_model.clone().into());
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create melody quantizer scale drop-down list
+    for melody_quantizer_scale_value in drop_down_list(
+        LANE_QUANTIZER_SCALE_NAMES,
+        model.sequencer_model.melody_quantizer_scale_index,
+    )
+    .padded_wh_of(model.ids.pitch_canvas_scale_column, 5.0)
+    .middle_of(model.ids.pitch_canvas_scale_column)
+    .set(model.ids.melody_quantizer_scale_drop_down, ui)
+    {
+        model.sequencer_model.melody_quantizer_scale_index = Some(melody_quantizer_scale_value);
+        info!(
+            "Set melody quantizer scale to: {}",
+            LANE_QUANTIZER_SCALE_NAMES[melody_quantizer_scale_value]
+        );
+        model
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
     }
 
     let melody_pitch_range_label = format!(
@@ -318,8 +2007,8 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         MELODY_PITCH_MIN_VALUE.step(),
         MELODY_PITCH_MAX_VALUE.step(),
     )
-    .padded_wh_of(model.ids.pitch_canvas_middle_column, 5.0)
-    .middle_of(model.ids.pitch_canvas_middle_column)
+    .padded_wh_of(model.ids.pitch_canvas_range_column, 5.0)
+    .middle_of(model.ids.pitch_canvas_range_column)
     .label(&melody_pitch_range_label)
     .set(model.ids.melody_pitch_range_slider, ui)
     {
@@ -332,7 +2021,8 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
                     model.sequencer_model.melody_min_pitch = new_value;
                     model
                         .sequencer
-                        .update_pitch_generator(model.sequencer_model.clone().into());
+                        .update_pitch_range(Lane::Melody, model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
                 }
             }
             (Edge::End, max) => {
@@ -343,15 +2033,86 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
                     model.sequencer_model.melody_max_pitch = new_value;
                     model
                         .sequencer
-                        .update_pitch_generator(model.sequencer_model.clone().into());
+                        .update_pitch_range(Lane::Melody, model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
                 }
             }
         }
     }
 
-    // Create cycle length slider when the generator type is not random
-    if pitch_generator_type_from_index(model.sequencer_model.melody_pitch_generator_type_index)
-        != PitchGeneratorType::Random
+    // Create melody pitch range text boxes accepting note names like "C#3"
+    let melody_min_pitch_text = model
+        .melody_min_pitch_text
+        .clone()
+        .unwrap_or_else(|| pitch_name(model.sequencer_model.melody_min_pitch));
+    for event in text_box(&melody_min_pitch_text)
+        .padded_wh_of(model.ids.pitch_canvas_min_text_column, 5.0)
+        .middle_of(model.ids.pitch_canvas_min_text_column)
+        .set(model.ids.melody_min_pitch_text_box, ui)
+    {
+        match event {
+            text_box::Event::Update(text) => model.melody_min_pitch_text = Some(text),
+            text_box::Event::Enter => {
+                if let Some(text) = model.melody_min_pitch_text.take() {
+                    match parse_melody_pitch(&text) {
+                        Some(new_value) if new_value <= model.sequencer_model.melody_max_pitch => {
+                            info!("Set melody pitch range minimum to: {}", new_value);
+                            model.sequencer_model.melody_min_pitch = new_value;
+                            model
+                                .sequencer
+                                .update_pitch_range(
+                                    Lane::Melody,
+                                    model.sequencer_model.clone().into(),
+                                )
+                                .unwrap_or_else(log_sequencer_error);
+                        }
+                        _ => warn!("Invalid melody pitch range minimum: {}", text),
+                    }
+                }
+            }
+        }
+    }
+
+    let melody_max_pitch_text = model
+        .melody_max_pitch_text
+        .clone()
+        .unwrap_or_else(|| pitch_name(model.sequencer_model.melody_max_pitch));
+    for event in text_box(&melody_max_pitch_text)
+        .padded_wh_of(model.ids.pitch_canvas_max_text_column, 5.0)
+        .middle_of(model.ids.pitch_canvas_max_text_column)
+        .set(model.ids.melody_max_pitch_text_box, ui)
+    {
+        match event {
+            text_box::Event::Update(text) => model.melody_max_pitch_text = Some(text),
+            text_box::Event::Enter => {
+                if let Some(text) = model.melody_max_pitch_text.take() {
+                    match parse_melody_pitch(&text) {
+                        Some(new_value) if new_value >= model.sequencer_model.melody_min_pitch => {
+                            info!("Set melody pitch range maximum to: {}", new_value);
+                            model.sequencer_model.melody_max_pitch = new_value;
+                            model
+                                .sequencer
+                                .update_pitch_range(
+                                    Lane::Melody,
+                                    model.sequencer_model.clone().into(),
+                                )
+                                .unwrap_or_else(log_sequencer_error);
+                        }
+                        _ => warn!("Invalid melody pitch range maximum: {}", text),
+                    }
+                }
+            }
+        }
+    }
+
+    // Create cycle length slider when the generator type is neither random, a step sequence,
+    // nor scale degrees
+    let melody_pitch_generator_type = melody_pitch_generator_type_from_index(
+        model.sequencer_model.melody_pitch_generator_type_index,
+    );
+    if melody_pitch_generator_type != PitchGeneratorType::Random
+        && melody_pitch_generator_type != PitchGeneratorType::Steps
+        && melody_pitch_generator_type != PitchGeneratorType::Degrees
     {
         let melody_pitch_generator_cycle_length_label = format!(
             "Cycle length: {}",
@@ -367,43 +2128,363 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         .label(&melody_pitch_generator_cycle_length_label)
         .set(model.ids.melody_pitch_generator_cycle_length_slider, ui)
         {
-            // quick and dirty way to restrict to multiples of 16
-            let new_value = (melody_pitch_generator_cycle_length_value as u32 + 15 & !15) as f32;
+            let new_value = snap_cycle_length(
+                melody_pitch_generator_cycle_length_value,
+                cycle_length_snap_from_index(model.cycle_length_snap_index),
+            );
             // only update the sequencer when the value has changed
             if model.sequencer_model.melody_cycle_length != new_value {
                 info!("Set melody cycle length to: {}", new_value);
                 model.sequencer_model.melody_cycle_length = new_value;
                 model
                     .sequencer
-                    .update_pitch_generator(model.sequencer_model.clone().into());
+                    .update_cycle_length(Lane::Melody, model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
             }
         }
     }
 
-    // Create transposition pitch generator widgets
-    for transposition_pitch_generator_type_value in drop_down_list(
-        PITCH_GENERATOR_TYPE_NAMES,
-        model
-            .sequencer_model
-            .transposition_pitch_generator_type_index,
+    // Create step sequence text box accepting note names like "C4 E4 G4"
+    if melody_pitch_generator_type == PitchGeneratorType::Steps {
+        let melody_step_sequence_text = model
+            .melody_step_sequence_text
+            .clone()
+            .unwrap_or_else(|| model.sequencer_model.melody_step_sequence.clone());
+        for event in text_box(&melody_step_sequence_text)
+            .padded_wh_of(model.ids.pitch_canvas_right_column, 5.0)
+            .middle_of(model.ids.pitch_canvas_right_column)
+            .set(model.ids.melody_step_sequence_text_box, ui)
+        {
+            match event {
+                text_box::Event::Update(text) => model.melody_step_sequence_text = Some(text),
+                text_box::Event::Enter => {
+                    if let Some(text) = model.melody_step_sequence_text.take() {
+                        match parse_step_sequence(&text) {
+                            Ok(notes) => {
+                                let new_value = format_step_sequence(&notes);
+                                info!("Set melody step sequence to: {}", new_value);
+                                model.sequencer_model.melody_step_sequence = new_value;
+                                model
+                                    .sequencer
+                                    .update_pitch_generator(model.sequencer_model.clone().into())
+                                    .unwrap_or_else(log_sequencer_error);
+                            }
+                            Err(e) => warn!("Invalid melody step sequence: {} ({})", text, e),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Create capture toggle. Stopping the capture plays the captured notes as the melody
+    // step sequence.
+    for is_capturing_value in toggle(model.is_capturing)
+        .padded_wh_of(model.ids.pitch_canvas_capture_column, 5.0)
+        .middle_of(model.ids.pitch_canvas_capture_column)
+        .label("Capture")
+        .set(model.ids.capture_toggle, ui)
+    {
+        if is_capturing_value {
+            if model.note_capture.is_connected() {
+                info!("Start capturing notes");
+                model.is_capturing = true;
+                model.note_capture.start();
+            } else {
+                warn!("No MIDI input port to capture notes from");
+            }
+        } else {
+            info!("Stop capturing notes");
+            model.is_capturing = false;
+            let notes = model.note_capture.stop();
+            if notes.is_empty() {
+                warn!("No notes captured, keeping the melody step sequence");
+            } else {
+                set_melody_step_sequence(&mut model.sequencer_model, &notes);
+                model.melody_step_sequence_text = None;
+                model
+                    .sequencer
+                    .update_pitch_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create MIDI file widgets. Loading plays the notes of the file as the melody step
+    // sequence.
+    for _ in Button::new()
+        .padded_wh_of(model.ids.pitch_canvas_load_midi_column, 5.0)
+        .middle_of(model.ids.pitch_canvas_load_midi_column)
+        .label("Load MIDI")
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+        .set(model.ids.load_midi_button, ui)
+    {
+        let chord_note = ChordNote::from_index(model.chord_note_index.unwrap());
+        match read_step_sequence(Path::new(MIDI_FILE_PATH), chord_note) {
+            Ok(notes) if notes.is_empty() => {
+                warn!(
+                    "No notes in: {}, keeping the melody step sequence",
+                    MIDI_FILE_PATH
+                )
+            }
+            Ok(notes) => {
+                info!("Loaded MIDI file: {}", MIDI_FILE_PATH);
+                set_melody_step_sequence(&mut model.sequencer_model, &notes);
+                model.melody_step_sequence_text = None;
+                model
+                    .sequencer
+                    .update_pitch_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+            Err(e) => error!("Failed to load MIDI file: {}", e),
+        }
+    }
+
+    for chord_note_value in drop_down_list(CHORD_NOTE_NAMES, model.chord_note_index)
+        .padded_wh_of(model.ids.pitch_canvas_chord_note_column, 5.0)
+        .middle_of(model.ids.pitch_canvas_chord_note_column)
+        .set(model.ids.chord_note_drop_down, ui)
+    {
+        info!("Set chord note to: {}", CHORD_NOTE_NAMES[chord_note_value]);
+        model.chord_note_index = Some(chord_note_value);
+    }
+
+    // Create melody blend generator drop-down list
+    for melody_blend_generator_type_value in drop_down_list(
+        MELODY_BLEND_GENERATOR_TYPE_NAMES,
+        model.sequencer_model.melody_blend_generator_type_index,
     )
-    .padded_wh_of(model.ids.transposition_pitch_canvas_left_column, 5.0)
-    .middle_of(model.ids.transposition_pitch_canvas_left_column)
-    .set(
-        model.ids.transposition_pitch_generator_type_drop_down_list,
-        ui,
-    ) {
+    .padded_wh_of(model.ids.blend_canvas_type_column, 5.0)
+    .middle_of(model.ids.blend_canvas_type_column)
+    .set(model.ids.melody_blend_generator_type_drop_down, ui)
+    {
         info!(
-            "Set transposition generator type to: {}",
-            pitch_generator_type_from_index(Some(transposition_pitch_generator_type_value))
+            "Set melody blend generator type to: {}",
+            MELODY_BLEND_GENERATOR_TYPE_NAMES[melody_blend_generator_type_value]
         );
+        model.sequencer_model.melody_blend_generator_type_index =
+            Some(melody_blend_generator_type_value);
         model
-            .sequencer_model
-            .transposition_pitch_generator_type_index =
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create melody blend weight slider when a blend generator is selected
+    if melody_blend_generator_type_from_index(
+        model.sequencer_model.melody_blend_generator_type_index,
+    )
+    .is_some()
+    {
+        let melody_blend_weight_label = format!(
+            "Blend: {:.0}%",
+            model.sequencer_model.melody_blend_weight * 100.0
+        );
+        for melody_blend_weight_value in slider(
+            model.sequencer_model.melody_blend_weight,
+            MELODY_BLEND_WEIGHT_MIN_VALUE,
+            MELODY_BLEND_WEIGHT_MAX_VALUE,
+        )
+        .padded_wh_of(model.ids.blend_canvas_weight_column, 5.0)
+        .middle_of(model.ids.blend_canvas_weight_column)
+        .label(&melody_blend_weight_label)
+        .set(model.ids.melody_blend_weight_slider, ui)
+        {
+            let new_value = (melody_blend_weight_value * 100.0).round() / 100.0;
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.melody_blend_weight != new_value {
+                info!("Set melody blend weight to: {}", new_value);
+                model.sequencer_model.melody_blend_weight = new_value;
+                model
+                    .sequencer
+                    .update_pitch_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create melody ramp mode drop-down list when the melody or its blend uses a ramp
+    if melody_pitch_generator_type_from_index(
+        model.sequencer_model.melody_pitch_generator_type_index,
+    ) == PitchGeneratorType::RampUp
+        || melody_blend_generator_type_from_index(
+            model.sequencer_model.melody_blend_generator_type_index,
+        ) == Some(PitchGeneratorType::RampUp)
+    {
+        for melody_ramp_mode_value in drop_down_list(
+            RAMP_MODE_NAMES,
+            model.sequencer_model.melody_ramp_mode_index,
+        )
+        .padded_wh_of(model.ids.blend_canvas_ramp_mode_column, 5.0)
+        .middle_of(model.ids.blend_canvas_ramp_mode_column)
+        .set(model.ids.melody_ramp_mode_drop_down, ui)
+        {
+            info!(
+                "Set melody ramp mode to: {}",
+                RAMP_MODE_NAMES[melody_ramp_mode_value]
+            );
+            model.sequencer_model.melody_ramp_mode_index = Some(melody_ramp_mode_value);
+            model
+                .sequencer
+                .update_pitch_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create melody phase slider when the melody or its blend has a cycle to start into
+    let melody_cycle_length = model.sequencer_model.melody_cycle_length;
+    if melody_cycle_length > 1.0
+        && (has_phase(&melody_pitch_generator_type_from_index(
+            model.sequencer_model.melody_pitch_generator_type_index,
+        )) || melody_blend_generator_type_from_index(
+            model.sequencer_model.melody_blend_generator_type_index,
+        )
+        .is_some_and(|blend_generator_type| has_phase(&blend_generator_type)))
+    {
+        let melody_phase_label = format!("Phase: {}", model.sequencer_model.melody_phase);
+        for melody_phase_value in slider(
+            model.sequencer_model.melody_phase,
+            PHASE_MIN_VALUE,
+            melody_cycle_length - 1.0,
+        )
+        .padded_wh_of(model.ids.blend_canvas_phase_column, 5.0)
+        .middle_of(model.ids.blend_canvas_phase_column)
+        .label(&melody_phase_label)
+        .set(model.ids.melody_phase_slider, ui)
+        {
+            let new_value = melody_phase_value.round();
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.melody_phase != new_value {
+                info!("Set melody phase to: {}", new_value);
+                model.sequencer_model.melody_phase = new_value;
+                model
+                    .sequencer
+                    .update_pitch_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create scale degree weight sliders when the melody or its blend plays scale degrees,
+    // one per degree of the melody scale
+    if melody_pitch_generator_type_from_index(
+        model.sequencer_model.melody_pitch_generator_type_index,
+    ) == PitchGeneratorType::Degrees
+        || melody_blend_generator_type_from_index(
+            model.sequencer_model.melody_blend_generator_type_index,
+        ) == Some(PitchGeneratorType::Degrees)
+    {
+        let degree_scale = melody_degree_scale(&model.sequencer_model);
+        for (degree, letter) in degree_scale.into_iter().enumerate() {
+            let weight = model
+                .sequencer_model
+                .melody_degree_weights
+                .get(degree)
+                .copied()
+                .unwrap_or(0.0);
+            let column = model.ids.degree_canvas_columns[degree];
+            let melody_degree_weight_label =
+                format!("{} {:.0}%", format_letter(letter), weight * 100.0);
+            for melody_degree_weight_value in slider(
+                weight,
+                MELODY_DEGREE_WEIGHT_MIN_VALUE,
+                MELODY_DEGREE_WEIGHT_MAX_VALUE,
+            )
+            .padded_wh_of(column, 5.0)
+            .middle_of(column)
+            .label(&melody_degree_weight_label)
+            .set(model.ids.melody_degree_weight_sliders[degree], ui)
+            {
+                let new_value = (melody_degree_weight_value * 100.0).round() / 100.0;
+                // only update the sequencer when the value has changed
+                if weight != new_value {
+                    info!(
+                        "Set melody weight of degree {} to: {}",
+                        degree + 1,
+                        new_value
+                    );
+                    let weights = &mut model.sequencer_model.melody_degree_weights;
+                    // presets edited by hand may list fewer weights than there are degrees
+                    if weights.len() <= degree {
+                        weights.resize(degree + 1, 0.0);
+                    }
+                    weights[degree] = new_value;
+                    model
+                        .sequencer
+                        .update_pitch_generator(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+        }
+    }
+
+    // Create transposition pitch generator widgets
+    for transposition_enabled_value in toggle(model.sequencer_model.transposition_enabled)
+        .padded_wh_of(model.ids.transposition_pitch_canvas_enabled_column, 5.0)
+        .middle_of(model.ids.transposition_pitch_canvas_enabled_column)
+        .label("Transpose")
+        .set(model.ids.transposition_enabled_toggle, ui)
+    {
+        info!(
+            "Set transposition enabled to: {}",
+            transposition_enabled_value
+        );
+        model.sequencer_model.transposition_enabled = transposition_enabled_value;
+        model
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    for transposition_pitch_generator_type_value in drop_down_list(
+        TRANSPOSITION_PITCH_GENERATOR_TYPE_NAMES,
+        model
+            .sequencer_model
+            .transposition_pitch_generator_type_index,
+    )
+    .padded_wh_of(model.ids.transposition_pitch_canvas_left_column, 5.0)
+    .middle_of(model.ids.transposition_pitch_canvas_left_column)
+    .set(
+        model.ids.transposition_pitch_generator_type_drop_down_list,
+        ui,
+    ) {
+        info!(
+            "Set transposition generator type to: {}",
+            transposition_pitch_generator_type_from_index(Some(
+                transposition_pitch_generator_type_value
+            ))
+        );
+        model
+            .sequencer_model
+            .transposition_pitch_generator_type_index =
             Some(transposition_pitch_generator_type_value);
         model
             .sequencer
-            .update_pitch_generator(model.sequencer_model.clone().into());
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create transposition quantizer scale drop-down list
+    for transposition_quantizer_scale_value in drop_down_list(
+        LANE_QUANTIZER_SCALE_NAMES,
+        model.sequencer_model.transposition_quantizer_scale_index,
+    )
+    .padded_wh_of(model.ids.transposition_pitch_canvas_scale_column, 5.0)
+    .middle_of(model.ids.transposition_pitch_canvas_scale_column)
+    .set(model.ids.transposition_quantizer_scale_drop_down, ui)
+    {
+        model.sequencer_model.transposition_quantizer_scale_index =
+            Some(transposition_quantizer_scale_value);
+        info!(
+            "Set transposition quantizer scale to: {}",
+            LANE_QUANTIZER_SCALE_NAMES[transposition_quantizer_scale_value]
+        );
+        model
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
     }
 
     let transposition_pitch_range_label = format!(
@@ -431,7 +2512,11 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
                     model.sequencer_model.transposition_min_pitch = new_value;
                     model
                         .sequencer
-                        .update_pitch_generator(model.sequencer_model.clone().into());
+                        .update_pitch_range(
+                            Lane::Transposition,
+                            model.sequencer_model.clone().into(),
+                        )
+                        .unwrap_or_else(log_sequencer_error);
                 }
             }
             (Edge::End, max) => {
@@ -442,121 +2527,1693 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
                     model.sequencer_model.transposition_max_pitch = new_value;
                     model
                         .sequencer
-                        .update_pitch_generator(model.sequencer_model.clone().into());
+                        .update_pitch_range(
+                            Lane::Transposition,
+                            model.sequencer_model.clone().into(),
+                        )
+                        .unwrap_or_else(log_sequencer_error);
                 }
             }
         }
     }
 
     // Create cycle length slider when the generator type is not random
-    if pitch_generator_type_from_index(
+    let transposition_pitch_generator_type = transposition_pitch_generator_type_from_index(
         model
             .sequencer_model
             .transposition_pitch_generator_type_index,
-    ) != PitchGeneratorType::Random
+    );
+    if transposition_pitch_generator_type != PitchGeneratorType::Random {
+        // a progression uses the cycle length as the length of each chord
+        let transposition_pitch_generator_cycle_length_label =
+            if transposition_pitch_generator_type == PitchGeneratorType::Progression {
+                format!(
+                    "Steps per chord: {}",
+                    model.sequencer_model.transposition_cycle_length as u32
+                )
+            } else {
+                format!(
+                    "Cycle length: {}",
+                    model.sequencer_model.transposition_cycle_length as u32
+                )
+            };
+        for transposition_pitch_generator_cycle_length_value in slider(
+            model.sequencer_model.transposition_cycle_length,
+            PITCH_GENERATOR_CYCLE_LENGTH_MIN_VALUE,
+            PITCH_GENERATOR_CYCLE_LENGTH_MAX_VALUE,
+        )
+        .padded_wh_of(model.ids.transposition_pitch_canvas_right_column, 5.0)
+        .middle_of(model.ids.transposition_pitch_canvas_right_column)
+        .label(&transposition_pitch_generator_cycle_length_label)
+        .set(
+            model.ids.transposition_pitch_generator_cycle_length_slider,
+            ui,
+        ) {
+            let new_value = snap_cycle_length(
+                transposition_pitch_generator_cycle_length_value,
+                cycle_length_snap_from_index(model.cycle_length_snap_index),
+            );
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.transposition_cycle_length != new_value {
+                info!("Set transposition cycle length to: {}", new_value);
+                model.sequencer_model.transposition_cycle_length = new_value;
+                model
+                    .sequencer
+                    .update_cycle_length(Lane::Transposition, model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create transposition phase slider when the generator type has a cycle to start into
+    let transposition_cycle_length = model.sequencer_model.transposition_cycle_length;
+    if transposition_cycle_length > 1.0 && has_phase(&transposition_pitch_generator_type) {
+        let transposition_phase_label =
+            format!("Phase: {}", model.sequencer_model.transposition_phase);
+        for transposition_phase_value in slider(
+            model.sequencer_model.transposition_phase,
+            PHASE_MIN_VALUE,
+            transposition_cycle_length - 1.0,
+        )
+        .padded_wh_of(model.ids.transposition_pitch_canvas_phase_column, 5.0)
+        .middle_of(model.ids.transposition_pitch_canvas_phase_column)
+        .label(&transposition_phase_label)
+        .set(model.ids.transposition_phase_slider, ui)
+        {
+            let new_value = transposition_phase_value.round();
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.transposition_phase != new_value {
+                info!("Set transposition phase to: {}", new_value);
+                model.sequencer_model.transposition_phase = new_value;
+                model
+                    .sequencer
+                    .update_pitch_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create chord tones toggle when the transposition walks a progression
+    if transposition_pitch_generator_type == PitchGeneratorType::Progression {
+        for transposition_chord_tones_value in
+            toggle(model.sequencer_model.transposition_chord_tones)
+                .padded_wh_of(model.ids.transposition_pitch_canvas_chord_column, 5.0)
+                .middle_of(model.ids.transposition_pitch_canvas_chord_column)
+                .label("Chord tones")
+                .set(model.ids.transposition_chord_tones_toggle, ui)
+        {
+            info!(
+                "Set transposition chord tones to: {}",
+                transposition_chord_tones_value
+            );
+            model.sequencer_model.transposition_chord_tones = transposition_chord_tones_value;
+            model
+                .sequencer
+                .update_pitch_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create square interval drop-down list when a lane squares between two pitches
+    if transposition_pitch_generator_type == PitchGeneratorType::Square
+        || melody_pitch_generator_type_from_index(
+            model.sequencer_model.melody_pitch_generator_type_index,
+        ) == PitchGeneratorType::Square
+        || melody_blend_generator_type_from_index(
+            model.sequencer_model.melody_blend_generator_type_index,
+        ) == Some(PitchGeneratorType::Square)
+    {
+        for square_interval_value in drop_down_list(
+            SQUARE_INTERVAL_NAMES,
+            model.sequencer_model.square_interval_index,
+        )
+        .padded_wh_of(model.ids.transposition_pitch_canvas_square_column, 5.0)
+        .middle_of(model.ids.transposition_pitch_canvas_square_column)
+        .set(model.ids.square_interval_drop_down, ui)
+        {
+            info!(
+                "Set square interval to: {:?} semitones",
+                square_interval_from_index(Some(square_interval_value))
+            );
+            model.sequencer_model.square_interval_index = Some(square_interval_value);
+            model
+                .sequencer
+                .update_pitch_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create pitch quantizer scale drop-down list
+    for quantizer_scale_value in drop_down_list(
+        QUANTIZER_SCALE_NAMES,
+        model.sequencer_model.quantizer_scale_index,
+    )
+    .padded_wh_of(model.ids.global_canvas_left_column, 5.0)
+    .middle_of(model.ids.global_canvas_left_column)
+    .set(model.ids.quantizer_scale_drop_down, ui)
+    {
+        // Handle new drop-down list value
+        model.sequencer_model.quantizer_scale_index = Some(quantizer_scale_value);
+        info!(
+            "Set pitch quantizer scale to: {}",
+            QUANTIZER_SCALE_NAMES[quantizer_scale_value]
+        );
+        model
+            .sequencer
+            .update_quantizer_scale(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create pitch quantizer strength slider
+    let quantizer_strength_label = format!(
+        "Strength: {:.0}%",
+        model.sequencer_model.quantizer_strength * 100.0
+    );
+    for quantizer_strength_value in slider(
+        model.sequencer_model.quantizer_strength,
+        QUANTIZER_STRENGTH_MIN_VALUE,
+        QUANTIZER_STRENGTH_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.global_canvas_strength_column, 5.0)
+    .middle_of(model.ids.global_canvas_strength_column)
+    .label(&quantizer_strength_label)
+    .set(model.ids.quantizer_strength_slider, ui)
+    {
+        let new_value = (quantizer_strength_value * 100.0).round() / 100.0;
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.quantizer_strength != new_value {
+            info!("Set pitch quantizer strength to: {}", new_value);
+            model.sequencer_model.quantizer_strength = new_value;
+            model
+                .sequencer
+                .update_pitch_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create pitch quantizer direction drop-down list
+    for quantizer_direction_value in drop_down_list(
+        QUANTIZER_DIRECTION_NAMES,
+        model.sequencer_model.quantizer_direction_index,
+    )
+    .padded_wh_of(model.ids.global_canvas_direction_column, 5.0)
+    .middle_of(model.ids.global_canvas_direction_column)
+    .set(model.ids.quantizer_direction_drop_down, ui)
+    {
+        // Handle new drop-down list value
+        model.sequencer_model.quantizer_direction_index = Some(quantizer_direction_value);
+        info!(
+            "Set pitch quantizer direction to: {}",
+            QUANTIZER_DIRECTION_NAMES[quantizer_direction_value]
+        );
+        model
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create pitch quantizer band range slider, notes outside the band are not quantized
+    let quantize_band_label = match quantize_range_from_band(
+        model.sequencer_model.quantize_band_min_pitch,
+        model.sequencer_model.quantize_band_max_pitch,
+    ) {
+        Some(range) => format!(
+            "Band: {} - {}",
+            format_letter_octave(range.start().to_letter_octave()),
+            format_letter_octave(range.end().to_letter_octave()),
+        ),
+        None => "Band: all notes".to_string(),
+    };
+    for quantize_band_value in range_slider(
+        model.sequencer_model.quantize_band_min_pitch,
+        model.sequencer_model.quantize_band_max_pitch,
+        QUANTIZE_BAND_MIN_VALUE,
+        QUANTIZE_BAND_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.global_canvas_band_column, 5.0)
+    .middle_of(model.ids.global_canvas_band_column)
+    .label(&quantize_band_label)
+    .set(model.ids.quantize_band_range_slider, ui)
+    {
+        match quantize_band_value {
+            (Edge::Start, min) => {
+                let new_value = min.round();
+                // only update the sequencer when the value has changed
+                if model.sequencer_model.quantize_band_min_pitch != new_value {
+                    info!("Set pitch quantizer band minimum to: {}", new_value);
+                    model.sequencer_model.quantize_band_min_pitch = new_value;
+                    model
+                        .sequencer
+                        .update_pitch_generator(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+            (Edge::End, max) => {
+                let new_value = max.round();
+                // only update the sequencer when the value has changed
+                if model.sequencer_model.quantize_band_max_pitch != new_value {
+                    info!("Set pitch quantizer band maximum to: {}", new_value);
+                    model.sequencer_model.quantize_band_max_pitch = new_value;
+                    model
+                        .sequencer
+                        .update_pitch_generator(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+        }
+    }
+
+    // Create transpose slider, shifting the quantized notes into another key
+    let transpose_label = format!("Transpose: {:+}", model.sequencer_model.transpose);
+    for transpose_value in slider(
+        model.sequencer_model.transpose,
+        TRANSPOSE_MIN_VALUE,
+        TRANSPOSE_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.global_canvas_transpose_column, 5.0)
+    .middle_of(model.ids.global_canvas_transpose_column)
+    .label(&transpose_label)
+    .set(model.ids.transpose_slider, ui)
+    {
+        let new_value = transpose_value.round();
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.transpose != new_value {
+            info!("Set transpose to: {} semitones", new_value);
+            model.sequencer_model.transpose = new_value;
+            model
+                .sequencer
+                .update_pitch_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create octave scatter sliders, moving quantized notes by whole octaves by chance
+    let octave_scatter_probability_label = format!(
+        "Scatter: {:.0}%",
+        model.sequencer_model.octave_scatter_probability * 100.0
+    );
+    for octave_scatter_probability_value in slider(
+        model.sequencer_model.octave_scatter_probability,
+        OCTAVE_SCATTER_PROBABILITY_MIN_VALUE,
+        OCTAVE_SCATTER_PROBABILITY_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.global_canvas_scatter_column, 5.0)
+    .middle_of(model.ids.global_canvas_scatter_column)
+    .label(&octave_scatter_probability_label)
+    .set(model.ids.octave_scatter_probability_slider, ui)
+    {
+        let new_value = (octave_scatter_probability_value * 100.0).round() / 100.0;
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.octave_scatter_probability != new_value {
+            info!("Set octave scatter probability to: {}", new_value);
+            model.sequencer_model.octave_scatter_probability = new_value;
+            model
+                .sequencer
+                .update_pitch_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    let octave_scatter_octaves_label = format!(
+        "Scatter: \u{b1}{} oct",
+        model.sequencer_model.octave_scatter_octaves
+    );
+    for octave_scatter_octaves_value in slider(
+        model.sequencer_model.octave_scatter_octaves,
+        OCTAVE_SCATTER_OCTAVES_MIN_VALUE,
+        OCTAVE_SCATTER_OCTAVES_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.global_canvas_scatter_octaves_column, 5.0)
+    .middle_of(model.ids.global_canvas_scatter_octaves_column)
+    .label(&octave_scatter_octaves_label)
+    .set(model.ids.octave_scatter_octaves_slider, ui)
+    {
+        let new_value = octave_scatter_octaves_value.round();
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.octave_scatter_octaves != new_value {
+            info!("Set octave scatter range to: {} octaves", new_value);
+            model.sequencer_model.octave_scatter_octaves = new_value;
+            model
+                .sequencer
+                .update_pitch_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create trigger probability slider
+    let trigger_probability_label = format!(
+        "Probability: {:.0}%",
+        model.sequencer_model.trigger_probability * 100.0
+    );
+    for trigger_probability_value in slider(
+        model.sequencer_model.trigger_probability,
+        TRIGGER_PROBABILITY_MIN_VALUE,
+        TRIGGER_PROBABILITY_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.global_canvas_middle_column, 5.0)
+    .middle_of(model.ids.global_canvas_middle_column)
+    .label(&trigger_probability_label)
+    .set(model.ids.trigger_probability_slider, ui)
+    {
+        let new_value = (trigger_probability_value * 100.0).round() / 100.0;
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.trigger_probability != new_value {
+            info!("Set trigger probability to: {}", new_value);
+            model.sequencer_model.trigger_probability = new_value;
+            model
+                .sequencer
+                .update_trigger_probability(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create trigger restlessness slider
+    let trigger_restlessness_label = format!(
+        "Restlessness: {:.0}%",
+        model.sequencer_model.trigger_restlessness * 100.0
+    );
+    for trigger_restlessness_value in slider(
+        model.sequencer_model.trigger_restlessness,
+        TRIGGER_RESTLESSNESS_MIN_VALUE,
+        TRIGGER_RESTLESSNESS_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.global_canvas_restlessness_column, 5.0)
+    .middle_of(model.ids.global_canvas_restlessness_column)
+    .label(&trigger_restlessness_label)
+    .set(model.ids.trigger_restlessness_slider, ui)
+    {
+        let new_value = (trigger_restlessness_value * 100.0).round() / 100.0;
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.trigger_restlessness != new_value {
+            info!("Set trigger restlessness to: {}", new_value);
+            model.sequencer_model.trigger_restlessness = new_value;
+            model
+                .sequencer
+                .update_trigger_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create trigger type drop-down list
+    for trigger_type_value in
+        drop_down_list(TRIGGER_TYPE_NAMES, model.sequencer_model.trigger_type_index)
+            .padded_wh_of(model.ids.trigger_canvas_type_column, 5.0)
+            .middle_of(model.ids.trigger_canvas_type_column)
+            .set(model.ids.trigger_type_drop_down, ui)
+    {
+        info!(
+            "Set trigger type to: {}",
+            TRIGGER_TYPE_NAMES[trigger_type_value]
+        );
+        model.sequencer_model.trigger_type_index = Some(trigger_type_value);
+        model
+            .sequencer
+            .update_trigger_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create trigger order drop-down list
+    for trigger_order_value in drop_down_list(
+        TRIGGER_ORDER_NAMES,
+        model.sequencer_model.trigger_order_index,
+    )
+    .padded_wh_of(model.ids.trigger_canvas_order_column, 5.0)
+    .middle_of(model.ids.trigger_canvas_order_column)
+    .set(model.ids.trigger_order_drop_down, ui)
+    {
+        info!(
+            "Set trigger order to: {}",
+            TRIGGER_ORDER_NAMES[trigger_order_value]
+        );
+        model.sequencer_model.trigger_order_index = Some(trigger_order_value);
+        model
+            .sequencer
+            .update_trigger_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create gate sliders when the trigger type uses the gate
+    if trigger_type_from_index(model.sequencer_model.trigger_type_index) != TriggerType::Random {
+        let gate_on_label = format!("Play: {}", model.sequencer_model.gate_on as u32);
+        for gate_on_value in slider(
+            model.sequencer_model.gate_on,
+            GATE_ON_MIN_VALUE,
+            GATE_ON_MAX_VALUE,
+        )
+        .padded_wh_of(model.ids.trigger_canvas_gate_on_column, 5.0)
+        .middle_of(model.ids.trigger_canvas_gate_on_column)
+        .label(&gate_on_label)
+        .set(model.ids.gate_on_slider, ui)
+        {
+            let new_value = gate_on_value.round();
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.gate_on != new_value {
+                info!("Set gate steps played to: {}", new_value);
+                model.sequencer_model.gate_on = new_value;
+                model
+                    .sequencer
+                    .update_trigger_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+
+        let gate_off_label = format!("Rest: {}", model.sequencer_model.gate_off as u32);
+        for gate_off_value in slider(
+            model.sequencer_model.gate_off,
+            GATE_OFF_MIN_VALUE,
+            GATE_OFF_MAX_VALUE,
+        )
+        .padded_wh_of(model.ids.trigger_canvas_gate_off_column, 5.0)
+        .middle_of(model.ids.trigger_canvas_gate_off_column)
+        .label(&gate_off_label)
+        .set(model.ids.gate_off_slider, ui)
+        {
+            let new_value = gate_off_value.round();
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.gate_off != new_value {
+                info!("Set gate steps rested to: {}", new_value);
+                model.sequencer_model.gate_off = new_value;
+                model
+                    .sequencer
+                    .update_trigger_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create fill widgets
+    let fill_intensity_label =
+        format!("Fill: {:.0}%", model.sequencer_model.fill_intensity * 100.0);
+    for fill_intensity_value in slider(
+        model.sequencer_model.fill_intensity,
+        FILL_INTENSITY_MIN_VALUE,
+        FILL_INTENSITY_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.trigger_canvas_fill_intensity_column, 5.0)
+    .middle_of(model.ids.trigger_canvas_fill_intensity_column)
+    .label(&fill_intensity_label)
+    .set(model.ids.fill_intensity_slider, ui)
+    {
+        let new_value = (fill_intensity_value * 100.0).round() / 100.0;
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.fill_intensity != new_value {
+            info!("Set fill intensity to: {}", new_value);
+            model.sequencer_model.fill_intensity = new_value;
+            model
+                .sequencer
+                .update_fill(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    let fill_phrase_bars_label = format!("Phrase: {} bars", model.sequencer_model.fill_phrase_bars);
+    for fill_phrase_bars_value in slider(
+        model.sequencer_model.fill_phrase_bars,
+        FILL_PHRASE_BARS_MIN_VALUE,
+        FILL_PHRASE_BARS_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.trigger_canvas_fill_phrase_column, 5.0)
+    .middle_of(model.ids.trigger_canvas_fill_phrase_column)
+    .label(&fill_phrase_bars_label)
+    .set(model.ids.fill_phrase_bars_slider, ui)
+    {
+        let new_value = fill_phrase_bars_value.round();
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.fill_phrase_bars != new_value {
+            info!("Set fill phrase length to: {} bars", new_value);
+            model.sequencer_model.fill_phrase_bars = new_value;
+            model
+                .sequencer
+                .update_fill(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create pitch chance widgets, with the favored end shown while it is on
+    for pitch_chance_enabled_value in toggle(model.sequencer_model.pitch_chance_enabled)
+        .padded_wh_of(model.ids.trigger_canvas_pitch_chance_column, 5.0)
+        .middle_of(model.ids.trigger_canvas_pitch_chance_column)
+        .label("Pitch chance")
+        .set(model.ids.pitch_chance_toggle, ui)
+    {
+        info!("Set pitch chance to: {}", pitch_chance_enabled_value);
+        model.sequencer_model.pitch_chance_enabled = pitch_chance_enabled_value;
+        model
+            .sequencer
+            .update_pitch_chance(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    if model.sequencer_model.pitch_chance_enabled {
+        for pitch_favor_value in
+            drop_down_list(PITCH_FAVOR_NAMES, model.sequencer_model.pitch_favor_index)
+                .padded_wh_of(model.ids.trigger_canvas_pitch_favor_column, 5.0)
+                .middle_of(model.ids.trigger_canvas_pitch_favor_column)
+                .set(model.ids.pitch_favor_drop_down, ui)
+        {
+            info!(
+                "Set pitch chance to: {}",
+                PITCH_FAVOR_NAMES[pitch_favor_value]
+            );
+            model.sequencer_model.pitch_favor_index = Some(pitch_favor_value);
+            model
+                .sequencer
+                .update_pitch_chance(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    for advance_on_trigger_only_value in toggle(model.sequencer_model.advance_on_trigger_only)
+        .padded_wh_of(model.ids.trigger_canvas_advance_column, 5.0)
+        .middle_of(model.ids.trigger_canvas_advance_column)
+        .label("Hold on rests")
+        .set(model.ids.advance_on_trigger_only_toggle, ui)
+    {
+        info!(
+            "Set advance on trigger only to: {}",
+            advance_on_trigger_only_value
+        );
+        model.sequencer_model.advance_on_trigger_only = advance_on_trigger_only_value;
+        model
+            .sequencer
+            .update_advance_on_trigger_only(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Follows the cycle lengths and the clock division, as it is rebuilt every frame
+    let pattern_length =
+        bars_and_beats(pattern_length_ticks(&model.sequencer_model.clone().into()));
+    widget::Text::new(&format!("Pattern: {}", pattern_length))
+        .padded_w_of(model.ids.trigger_canvas_pattern_length_column, 5.0)
+        .middle_of(model.ids.trigger_canvas_pattern_length_column)
+        .center_justify()
+        .font_size(20)
+        .color(LABEL_COLOR)
+        .set(model.ids.pattern_length_text, ui);
+
+    // Sums up the patch along the bottom, rebuilt every frame so that it follows every change
+    widget::Text::new(&patch_summary(&model.sequencer_model))
+        .padded_w_of(model.ids.status_canvas, 5.0)
+        .middle_of(model.ids.status_canvas)
+        .center_justify()
+        .font_size(20)
+        .color(LABEL_COLOR)
+        .set(model.ids.patch_summary_text, ui);
+
+    // Create clock divider factor slider
+    let clock_divider_factor_label = format!(
+        "Clock division: {}",
+        model.sequencer_model.clock_divider_factor as u32
+    );
+    for clock_divider_factor_value in slider(
+        model.sequencer_model.clock_divider_factor,
+        CLOCK_DIVIDER_FACTOR_MIN_VALUE,
+        CLOCK_DIVIDER_FACTOR_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.global_canvas_right_column, 5.0)
+    .middle_of(model.ids.global_canvas_right_column)
+    .label(&clock_divider_factor_label)
+    .set(model.ids.clock_divider_factor_slider, ui)
+    {
+        let new_value = clock_divider_factor_value.round();
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.clock_divider_factor != new_value {
+            info!("Set clock divider factor to: {}", new_value);
+            model.sequencer_model.clock_divider_factor = new_value;
+            model
+                .sequencer
+                .update_trigger_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create clock divider offset slider when there is more than one tick per step. The
+    // offset wraps around the division, so the effective one is shown.
+    let clock_divider_factor = model.sequencer_model.clock_divider_factor;
+    if clock_divider_factor > 1.0 {
+        let clock_divider_offset =
+            model.sequencer_model.clock_divider_offset % clock_divider_factor;
+        let clock_divider_offset_label = format!("Offset: {}", clock_divider_offset);
+        for clock_divider_offset_value in slider(
+            clock_divider_offset,
+            CLOCK_DIVIDER_OFFSET_MIN_VALUE,
+            clock_divider_factor - 1.0,
+        )
+        .padded_wh_of(model.ids.global_canvas_offset_column, 5.0)
+        .middle_of(model.ids.global_canvas_offset_column)
+        .label(&clock_divider_offset_label)
+        .set(model.ids.clock_divider_offset_slider, ui)
+        {
+            let new_value = clock_divider_offset_value.round();
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.clock_divider_offset != new_value {
+                info!("Set clock divider offset to: {}", new_value);
+                model.sequencer_model.clock_divider_offset = new_value;
+                model
+                    .sequencer
+                    .update_trigger_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create density macro slider
+    let density_label = format!("Density: {:.0}%", model.density * 100.0);
+    for density_value in slider(model.density, DENSITY_MIN_VALUE, DENSITY_MAX_VALUE)
+        .padded_wh_of(model.ids.note_canvas_density_column, 5.0)
+        .middle_of(model.ids.note_canvas_density_column)
+        .label(&density_label)
+        .set(model.ids.density_slider, ui)
+    {
+        let new_value = (density_value * 100.0).round() / 100.0;
+        // only update the sequencer when the value has changed
+        if model.density != new_value {
+            let (trigger_probability, clock_divider_factor) =
+                density_to_trigger_parameters(new_value);
+            info!(
+                "Set density to: {} (probability: {}, clock division: {})",
+                new_value, trigger_probability, clock_divider_factor
+            );
+            model.density = new_value;
+            model.sequencer_model.trigger_probability = trigger_probability;
+            model.sequencer_model.clock_divider_factor = clock_divider_factor;
+            model
+                .sequencer
+                .update_trigger_generator(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create the XY pad sweeping probability and clock division at once. Dragging only
+    // updates the sequencer when a rounded value changes. The probability changes in place,
+    // the trigger generator is rebuilt only for a new clock division, and the sequencer thread
+    // installs only the latest of the trigger generators queued up in between its ticks.
+    let (pad_x, pad_y) = trigger_parameters_to_xy_pad(
+        model.sequencer_model.trigger_probability,
+        model.sequencer_model.clock_divider_factor,
+    );
+    for (x, y) in widget::XYPad::new(pad_x, 0.0, 1.0, pad_y, 0.0, 1.0)
+        .padded_wh_of(model.ids.note_canvas_xy_pad_column, 5.0)
+        .middle_of(model.ids.note_canvas_xy_pad_column)
+        .label("Probability / Division")
+        .label_font_size(14)
+        .value_font_size(12)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .line_thickness(2.0)
+        .border(0.0)
+        .set(model.ids.trigger_xy_pad, ui)
+    {
+        let (trigger_probability, clock_divider_factor) = xy_pad_to_trigger_parameters(x, y);
+        let probability_changed = model.sequencer_model.trigger_probability != trigger_probability;
+        let division_changed = model.sequencer_model.clock_divider_factor != clock_divider_factor;
+        // only update the sequencer when a value has changed
+        if probability_changed || division_changed {
+            info!(
+                "Set trigger probability to: {}, clock division to: {}",
+                trigger_probability, clock_divider_factor
+            );
+            model.sequencer_model.trigger_probability = trigger_probability;
+            model.sequencer_model.clock_divider_factor = clock_divider_factor;
+            let config = model.sequencer_model.clone().into();
+            if division_changed {
+                model
+                    .sequencer
+                    .update_trigger_generator(config)
+                    .unwrap_or_else(log_sequencer_error);
+            } else {
+                model
+                    .sequencer
+                    .update_trigger_probability(config)
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create note length drop-down lists
+    for note_length_min_value in drop_down_list(
+        NOTE_LENGTH_NAMES,
+        model.sequencer_model.note_length_min_index,
+    )
+    .padded_wh_of(model.ids.note_canvas_left_column, 5.0)
+    .middle_of(model.ids.note_canvas_left_column)
+    .set(model.ids.note_length_min_drop_down, ui)
+    {
+        model.sequencer_model.note_length_min_index = Some(note_length_min_value);
+        info!(
+            "Set note length minimum to: {}",
+            NOTE_LENGTH_NAMES[note_length_min_value]
+        );
+        model
+            .sequencer
+            .update_note_length(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    for note_length_max_value in drop_down_list(
+        NOTE_LENGTH_NAMES,
+        model.sequencer_model.note_length_max_index,
+    )
+    .padded_wh_of(model.ids.note_canvas_length_max_column, 5.0)
+    .middle_of(model.ids.note_canvas_length_max_column)
+    .set(model.ids.note_length_max_drop_down, ui)
+    {
+        model.sequencer_model.note_length_max_index = Some(note_length_max_value);
+        info!(
+            "Set note length maximum to: {}",
+            NOTE_LENGTH_NAMES[note_length_max_value]
+        );
+        model
+            .sequencer
+            .update_note_length(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create tie toggle
+    for tie_repeated_notes_value in toggle(model.sequencer_model.tie_repeated_notes)
+        .padded_wh_of(model.ids.note_canvas_tie_column, 5.0)
+        .middle_of(model.ids.note_canvas_tie_column)
+        .label("Tie")
+        .set(model.ids.tie_repeated_notes_toggle, ui)
+    {
+        info!("Set tie repeated notes to: {}", tie_repeated_notes_value);
+        model.sequencer_model.tie_repeated_notes = tie_repeated_notes_value;
+        model
+            .sequencer
+            .update_tie_repeated_notes(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create tie probability slider
+    let tie_probability_label = format!(
+        "Tie chance: {:.0}%",
+        model.sequencer_model.tie_probability * 100.0
+    );
+    for tie_probability_value in slider(
+        model.sequencer_model.tie_probability,
+        TIE_PROBABILITY_MIN_VALUE,
+        TIE_PROBABILITY_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.note_canvas_tie_probability_column, 5.0)
+    .middle_of(model.ids.note_canvas_tie_probability_column)
+    .label(&tie_probability_label)
+    .set(model.ids.tie_probability_slider, ui)
+    {
+        let new_value = (tie_probability_value * 100.0).round() / 100.0;
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.tie_probability != new_value {
+            info!("Set tie probability to: {}", new_value);
+            model.sequencer_model.tie_probability = new_value;
+            model
+                .sequencer
+                .update_tie_probability(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create note repeat drop-down list
+    for note_repeat_value in
+        drop_down_list(NOTE_REPEAT_NAMES, model.sequencer_model.note_repeat_index)
+            .padded_wh_of(model.ids.note_canvas_repeat_column, 5.0)
+            .middle_of(model.ids.note_canvas_repeat_column)
+            .set(model.ids.note_repeat_drop_down, ui)
+    {
+        model.sequencer_model.note_repeat_index = Some(note_repeat_value);
+        info!(
+            "Set note repeat to: {}",
+            NOTE_REPEAT_NAMES[note_repeat_value]
+        );
+        model
+            .sequencer
+            .update_note_repeat(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create drum mode widgets, with the drum note shown while it is on. The octave offset
+    // and the harmony follow drum mode, as they leave the drum note alone.
+    for drum_mode_value in toggle(model.sequencer_model.drum_mode)
+        .padded_wh_of(model.ids.note_canvas_drum_column, 5.0)
+        .middle_of(model.ids.note_canvas_drum_column)
+        .label("Drums")
+        .set(model.ids.drum_mode_toggle, ui)
+    {
+        info!("Set drum mode to: {}", drum_mode_value);
+        model.sequencer_model.drum_mode = drum_mode_value;
+        model
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+        model
+            .sequencer
+            .update_octave_offset(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+        model
+            .sequencer
+            .update_harmonizer(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    if model.sequencer_model.drum_mode {
+        let drum_note_label = format!(
+            "{} ({})",
+            pitch_name(model.sequencer_model.drum_note),
+            model.sequencer_model.drum_note
+        );
+        for drum_note_value in slider(model.sequencer_model.drum_note, 0.0, 127.0)
+            .padded_wh_of(model.ids.note_canvas_drum_note_column, 5.0)
+            .middle_of(model.ids.note_canvas_drum_note_column)
+            .label(&drum_note_label)
+            .set(model.ids.drum_note_slider, ui)
+        {
+            let new_value = drum_note_value.round();
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.drum_note != new_value {
+                info!("Set drum note to: {}", new_value);
+                model.sequencer_model.drum_note = new_value;
+                model
+                    .sequencer
+                    .update_pitch_generator(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create harmonize widgets, the intervals text box accepting semitones like "+4 +7"
+    for harmonize_enabled_value in toggle(model.sequencer_model.harmonize_enabled)
+        .padded_wh_of(model.ids.note_canvas_harmonize_column, 5.0)
+        .middle_of(model.ids.note_canvas_harmonize_column)
+        .label("Harmonize")
+        .set(model.ids.harmonize_toggle, ui)
+    {
+        info!("Set harmonize enabled to: {}", harmonize_enabled_value);
+        model.sequencer_model.harmonize_enabled = harmonize_enabled_value;
+        model
+            .sequencer
+            .update_harmonizer(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    let harmony_intervals_text = model
+        .harmony_intervals_text
+        .clone()
+        .unwrap_or_else(|| format_harmony_intervals(&model.sequencer_model.harmony_intervals));
+    for event in text_box(&harmony_intervals_text)
+        .padded_wh_of(model.ids.note_canvas_harmony_column, 5.0)
+        .middle_of(model.ids.note_canvas_harmony_column)
+        .set(model.ids.harmony_intervals_text_box, ui)
+    {
+        match event {
+            text_box::Event::Update(text) => model.harmony_intervals_text = Some(text),
+            text_box::Event::Enter => {
+                if let Some(text) = model.harmony_intervals_text.take() {
+                    match parse_harmony_intervals(&text) {
+                        Some(new_value) => {
+                            info!(
+                                "Set harmony intervals to: {}",
+                                format_harmony_intervals(&new_value)
+                            );
+                            model.sequencer_model.harmony_intervals = new_value;
+                            model
+                                .sequencer
+                                .update_harmonizer(model.sequencer_model.clone().into())
+                                .unwrap_or_else(log_sequencer_error);
+                        }
+                        None => warn!("Invalid harmony intervals: {}", text),
+                    }
+                }
+            }
+        }
+    }
+
+    // Create pitch quantizer octave range slider
+    let quantizer_octave_range_label = format!(
+        "Octaves: {} - {}",
+        model.sequencer_model.quantizer_min_octave as i32,
+        model.sequencer_model.quantizer_max_octave as i32
+    );
+    for quantizer_octave_range_value in range_slider(
+        model.sequencer_model.quantizer_min_octave,
+        model.sequencer_model.quantizer_max_octave,
+        QUANTIZER_OCTAVE_MIN_VALUE,
+        QUANTIZER_OCTAVE_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.note_canvas_octave_column, 5.0)
+    .middle_of(model.ids.note_canvas_octave_column)
+    .label(&quantizer_octave_range_label)
+    .set(model.ids.quantizer_octave_range_slider, ui)
+    {
+        match quantizer_octave_range_value {
+            (Edge::Start, min) => {
+                let new_value = min.round();
+                // only update the sequencer when the value has changed
+                if model.sequencer_model.quantizer_min_octave != new_value {
+                    info!("Set pitch quantizer octave range minimum to: {}", new_value);
+                    model.sequencer_model.quantizer_min_octave = new_value;
+                    model
+                        .sequencer
+                        .update_pitch_generator(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+            (Edge::End, max) => {
+                let new_value = max.round();
+                // only update the sequencer when the value has changed
+                if model.sequencer_model.quantizer_max_octave != new_value {
+                    info!("Set pitch quantizer octave range maximum to: {}", new_value);
+                    model.sequencer_model.quantizer_max_octave = new_value;
+                    model
+                        .sequencer
+                        .update_pitch_generator(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+        }
+    }
+
+    // Create velocity mode and curve drop-down lists
+    for velocity_mode_value in drop_down_list(
+        VELOCITY_MODE_NAMES,
+        model.sequencer_model.velocity_mode_index,
+    )
+    .padded_wh_of(model.ids.velocity_canvas_mode_column, 5.0)
+    .middle_of(model.ids.velocity_canvas_mode_column)
+    .set(model.ids.velocity_mode_drop_down, ui)
+    {
+        model.sequencer_model.velocity_mode_index = Some(velocity_mode_value);
+        info!(
+            "Set velocity mode to: {}",
+            VELOCITY_MODE_NAMES[velocity_mode_value]
+        );
+        model
+            .sequencer
+            .update_velocity(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    for velocity_curve_value in drop_down_list(
+        VELOCITY_CURVE_NAMES,
+        model.sequencer_model.velocity_curve_index,
+    )
+    .padded_wh_of(model.ids.velocity_canvas_curve_column, 5.0)
+    .middle_of(model.ids.velocity_canvas_curve_column)
+    .set(model.ids.velocity_curve_drop_down, ui)
+    {
+        model.sequencer_model.velocity_curve_index = Some(velocity_curve_value);
+        info!(
+            "Set velocity curve to: {}",
+            VELOCITY_CURVE_NAMES[velocity_curve_value]
+        );
+        model
+            .sequencer
+            .update_velocity(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create velocity length coupling widgets, with the amount shown while it is on
+    for velocity_length_enabled_value in toggle(model.sequencer_model.velocity_length_enabled)
+        .padded_wh_of(model.ids.velocity_canvas_length_column, 5.0)
+        .middle_of(model.ids.velocity_canvas_length_column)
+        .label("Vel -> length")
+        .set(model.ids.velocity_length_toggle, ui)
+    {
+        info!(
+            "Set velocity length coupling to: {}",
+            velocity_length_enabled_value
+        );
+        model.sequencer_model.velocity_length_enabled = velocity_length_enabled_value;
+        model
+            .sequencer
+            .update_velocity_length(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    if model.sequencer_model.velocity_length_enabled {
+        let velocity_length_amount_label = format!(
+            "Coupling: {:.0}%",
+            model.sequencer_model.velocity_length_amount * 100.0
+        );
+        for velocity_length_amount_value in slider(
+            model.sequencer_model.velocity_length_amount,
+            VELOCITY_LENGTH_AMOUNT_MIN_VALUE,
+            VELOCITY_LENGTH_AMOUNT_MAX_VALUE,
+        )
+        .padded_wh_of(model.ids.velocity_canvas_length_amount_column, 5.0)
+        .middle_of(model.ids.velocity_canvas_length_amount_column)
+        .label(&velocity_length_amount_label)
+        .set(model.ids.velocity_length_amount_slider, ui)
+        {
+            let new_value = (velocity_length_amount_value * 100.0).round() / 100.0;
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.velocity_length_amount != new_value {
+                info!("Set velocity length coupling amount to: {}", new_value);
+                model.sequencer_model.velocity_length_amount = new_value;
+                model
+                    .sequencer
+                    .update_velocity_length(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    for humanize_value in toggle(is_humanized(&model.sequencer_model))
+        .padded_wh_of(model.ids.velocity_canvas_humanize_column, 5.0)
+        .middle_of(model.ids.velocity_canvas_humanize_column)
+        .label("Humanize")
+        .set(model.ids.humanize_toggle, ui)
+    {
+        info!("Set humanize to: {}", humanize_value);
+        set_humanize(&mut model.sequencer_model, humanize_value);
+        model
+            .sequencer
+            .update_humanize(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create swing widgets
+    let swing_label = format!("Swing: {:.0}%", model.sequencer_model.swing * 100.0);
+    for swing_value in slider(
+        model.sequencer_model.swing,
+        SWING_MIN_VALUE,
+        SWING_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.velocity_canvas_swing_column, 5.0)
+    .middle_of(model.ids.velocity_canvas_swing_column)
+    .label(&swing_label)
+    .set(model.ids.swing_slider, ui)
+    {
+        let new_value = (swing_value * 100.0).round() / 100.0;
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.swing != new_value {
+            info!("Set swing to: {}", new_value);
+            model.sequencer_model.swing = new_value;
+            model
+                .sequencer
+                .update_swing(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    for swing_resolution_value in drop_down_list(
+        SWING_RESOLUTION_NAMES,
+        model.sequencer_model.swing_resolution_index,
+    )
+    .padded_wh_of(model.ids.velocity_canvas_swing_resolution_column, 5.0)
+    .middle_of(model.ids.velocity_canvas_swing_resolution_column)
+    .set(model.ids.swing_resolution_drop_down, ui)
+    {
+        model.sequencer_model.swing_resolution_index = Some(swing_resolution_value);
+        info!(
+            "Set swing resolution to: {}",
+            SWING_RESOLUTION_NAMES[swing_resolution_value]
+        );
+        model
+            .sequencer
+            .update_swing(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create MIDI settings widgets
+    let note_off_style_label = if model.sequencer_model.note_off_as_note_on {
+        "Note off: vel 0"
+    } else {
+        "Note off: 0x80"
+    };
+    for note_off_as_note_on_value in toggle(model.sequencer_model.note_off_as_note_on)
+        .padded_wh_of(model.ids.midi_canvas_note_off_column, 5.0)
+        .middle_of(model.ids.midi_canvas_note_off_column)
+        .label(note_off_style_label)
+        .set(model.ids.note_off_style_toggle, ui)
+    {
+        info!("Set note off as note on to: {}", note_off_as_note_on_value);
+        model.sequencer_model.note_off_as_note_on = note_off_as_note_on_value;
+        model
+            .sequencer
+            .update_note_off_style(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // a note on with velocity 0 has no release velocity
+    if !model.sequencer_model.note_off_as_note_on {
+        let release_velocity_label =
+            format!("Release vel: {}", model.sequencer_model.release_velocity);
+        for release_velocity_value in slider(
+            model.sequencer_model.release_velocity,
+            RELEASE_VELOCITY_MIN_VALUE,
+            RELEASE_VELOCITY_MAX_VALUE,
+        )
+        .padded_wh_of(model.ids.midi_canvas_release_velocity_column, 5.0)
+        .middle_of(model.ids.midi_canvas_release_velocity_column)
+        .label(&release_velocity_label)
+        .set(model.ids.release_velocity_slider, ui)
+        {
+            let new_value = release_velocity_value.round();
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.release_velocity != new_value {
+                info!("Set release velocity to: {}", new_value);
+                model.sequencer_model.release_velocity = new_value;
+                model
+                    .sequencer
+                    .update_release_velocity(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Create round-robin channels text box accepting channel numbers like "1 2 3"
+    let round_robin_channels_text = model
+        .round_robin_channels_text
+        .clone()
+        .unwrap_or_else(|| format_midi_channels(&model.sequencer_model.round_robin_channels));
+    for event in text_box(&round_robin_channels_text)
+        .padded_wh_of(model.ids.midi_canvas_channels_column, 5.0)
+        .middle_of(model.ids.midi_canvas_channels_column)
+        .set(model.ids.round_robin_channels_text_box, ui)
+    {
+        match event {
+            text_box::Event::Update(text) => model.round_robin_channels_text = Some(text),
+            text_box::Event::Enter => {
+                if let Some(text) = model.round_robin_channels_text.take() {
+                    match parse_midi_channels(&text) {
+                        Some(new_value) => {
+                            info!(
+                                "Set round-robin MIDI channels to: {}",
+                                format_midi_channels(&new_value)
+                            );
+                            model.sequencer_model.round_robin_channels = new_value;
+                            model
+                                .sequencer
+                                .update_channel_mode(model.sequencer_model.clone().into())
+                                .unwrap_or_else(log_sequencer_error);
+                        }
+                        None => warn!("Invalid MIDI channels: {}", text),
+                    }
+                }
+            }
+        }
+    }
+
+    for microtonal_value in toggle(model.sequencer_model.microtonal)
+        .padded_wh_of(model.ids.midi_canvas_microtonal_column, 5.0)
+        .middle_of(model.ids.midi_canvas_microtonal_column)
+        .label("Microtonal")
+        .set(model.ids.microtonal_toggle, ui)
+    {
+        info!("Set microtonal output to: {}", microtonal_value);
+        model.sequencer_model.microtonal = microtonal_value;
+        model
+            .sequencer
+            .update_microtonal(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    for send_song_position_value in toggle(model.sequencer_model.send_song_position)
+        .padded_wh_of(model.ids.midi_canvas_song_position_column, 5.0)
+        .middle_of(model.ids.midi_canvas_song_position_column)
+        .label("Send SPP")
+        .set(model.ids.send_song_position_toggle, ui)
+    {
+        info!("Set song position output to: {}", send_song_position_value);
+        model.sequencer_model.send_song_position = send_song_position_value;
+        model
+            .sequencer
+            .update_send_song_position(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    for midi_rate_limit_value in toggle(model.sequencer_model.midi_rate_limit)
+        .padded_wh_of(model.ids.midi_canvas_rate_limit_column, 5.0)
+        .middle_of(model.ids.midi_canvas_rate_limit_column)
+        .label("Throttle")
+        .set(model.ids.midi_rate_limit_toggle, ui)
+    {
+        info!("Set MIDI rate limit to: {}", midi_rate_limit_value);
+        model.sequencer_model.midi_rate_limit = midi_rate_limit_value;
+        model
+            .sequencer
+            .update_midi_rate_limit(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    for midi_thru_value in toggle(model.midi_thru.is_enabled())
+        .padded_wh_of(model.ids.midi_canvas_thru_column, 5.0)
+        .middle_of(model.ids.midi_canvas_thru_column)
+        .label("MIDI thru")
+        .set(model.ids.midi_thru_toggle, ui)
+    {
+        if midi_thru_value && !model.midi_thru.is_connected() {
+            warn!("No MIDI input port to pass thru");
+        } else {
+            info!("Set MIDI thru to: {}", midi_thru_value);
+            model.midi_thru.set_enabled(midi_thru_value);
+        }
+    }
+
+    // Create synth waveform drop-down list when the built-in synth is playing
+    if let Some(synth) = &model.synth {
+        for synth_waveform_value in drop_down_list(WAVEFORM_NAMES, model.synth_waveform_index)
+            .padded_wh_of(model.ids.midi_canvas_synth_column, 5.0)
+            .middle_of(model.ids.midi_canvas_synth_column)
+            .set(model.ids.synth_waveform_drop_down, ui)
+        {
+            info!(
+                "Set synth waveform to: {}",
+                WAVEFORM_NAMES[synth_waveform_value]
+            );
+            model.synth_waveform_index = Some(synth_waveform_value);
+            synth.set_waveform(Waveform::from_index(synth_waveform_value));
+        }
+    }
+
+    let note_duration_range_label = format!(
+        "Note duration: {} - {} ms",
+        model.sequencer_model.note_min_duration, model.sequencer_model.note_max_duration,
+    );
+    for note_duration_range_value in range_slider(
+        model.sequencer_model.note_min_duration,
+        model.sequencer_model.note_max_duration,
+        NOTE_DURATION_MIN_VALUE,
+        NOTE_DURATION_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.midi_canvas_note_duration_column, 5.0)
+    .middle_of(model.ids.midi_canvas_note_duration_column)
+    .label(&note_duration_range_label)
+    .set(model.ids.note_duration_range_slider, ui)
+    {
+        match note_duration_range_value {
+            (Edge::Start, min) => {
+                let new_value = min.round();
+                // only update the sequencer when the value has changed
+                if model.sequencer_model.note_min_duration != new_value {
+                    info!("Set note duration minimum to: {} ms", new_value);
+                    model.sequencer_model.note_min_duration = new_value;
+                    model
+                        .sequencer
+                        .update_note_duration_range(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+            (Edge::End, max) => {
+                let new_value = max.round();
+                // only update the sequencer when the value has changed
+                if model.sequencer_model.note_max_duration != new_value {
+                    info!("Set note duration maximum to: {} ms", new_value);
+                    model.sequencer_model.note_max_duration = new_value;
+                    model
+                        .sequencer
+                        .update_note_duration_range(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+        }
+    }
+
+    let max_voices_label = format!("Voices: {}", model.sequencer_model.max_voices);
+    for max_voices_value in slider(
+        model.sequencer_model.max_voices,
+        MAX_VOICES_MIN_VALUE,
+        MAX_VOICES_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.midi_canvas_right_column, 5.0)
+    .middle_of(model.ids.midi_canvas_right_column)
+    .label(&max_voices_label)
+    .set(model.ids.max_voices_slider, ui)
+    {
+        let new_value = max_voices_value.round();
+        // only update the sequencer when the value has changed
+        if model.sequencer_model.max_voices != new_value {
+            info!("Set max voices to: {}", new_value);
+            model.sequencer_model.max_voices = new_value;
+            model
+                .sequencer
+                .update_max_voices(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    for mono_value in toggle(model.sequencer_model.mono)
+        .padded_wh_of(model.ids.midi_canvas_mono_column, 5.0)
+        .middle_of(model.ids.midi_canvas_mono_column)
+        .label("Mono")
+        .set(model.ids.mono_toggle, ui)
+    {
+        info!("Set mono to: {}", mono_value);
+        model.sequencer_model.mono = mono_value;
+        model
+            .sequencer
+            .update_mono(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create modulation lane widgets
+    for modulation_enabled_value in toggle(model.sequencer_model.modulation_enabled)
+        .padded_wh_of(model.ids.modulation_canvas_left_column, 5.0)
+        .middle_of(model.ids.modulation_canvas_left_column)
+        .label("Mod")
+        .set(model.ids.modulation_enabled_toggle, ui)
+    {
+        info!("Set modulation enabled to: {}", modulation_enabled_value);
+        model.sequencer_model.modulation_enabled = modulation_enabled_value;
+        model
+            .sequencer
+            .update_modulation(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    for modulation_generator_type_value in drop_down_list(
+        PITCH_GENERATOR_TYPE_NAMES,
+        model.sequencer_model.modulation_generator_type_index,
+    )
+    .padded_wh_of(model.ids.modulation_canvas_type_column, 5.0)
+    .middle_of(model.ids.modulation_canvas_type_column)
+    .set(model.ids.modulation_generator_type_drop_down_list, ui)
+    {
+        info!(
+            "Set modulation generator type to: {}",
+            pitch_generator_type_from_index(Some(modulation_generator_type_value))
+        );
+        model.sequencer_model.modulation_generator_type_index =
+            Some(modulation_generator_type_value);
+        model
+            .sequencer
+            .update_modulation(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    let modulation_range_label = format!(
+        "Range: {} - {}",
+        model.sequencer_model.modulation_min as u8, model.sequencer_model.modulation_max as u8
+    );
+    for modulation_range_value in range_slider(
+        model.sequencer_model.modulation_min,
+        model.sequencer_model.modulation_max,
+        MODULATION_MIN_VALUE,
+        MODULATION_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.modulation_canvas_middle_column, 5.0)
+    .middle_of(model.ids.modulation_canvas_middle_column)
+    .label(&modulation_range_label)
+    .set(model.ids.modulation_range_slider, ui)
+    {
+        match modulation_range_value {
+            (Edge::Start, min) => {
+                let new_value = min.round();
+                // only update the sequencer when the value has changed
+                if model.sequencer_model.modulation_min != new_value {
+                    info!("Set modulation range minimum to: {}", new_value);
+                    model.sequencer_model.modulation_min = new_value;
+                    model
+                        .sequencer
+                        .update_modulation(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+            (Edge::End, max) => {
+                let new_value = max.round();
+                // only update the sequencer when the value has changed
+                if model.sequencer_model.modulation_max != new_value {
+                    info!("Set modulation range maximum to: {}", new_value);
+                    model.sequencer_model.modulation_max = new_value;
+                    model
+                        .sequencer
+                        .update_modulation(model.sequencer_model.clone().into())
+                        .unwrap_or_else(log_sequencer_error);
+                }
+            }
+        }
+    }
+
+    // Create cycle length slider when the generator type is not random
+    if pitch_generator_type_from_index(model.sequencer_model.modulation_generator_type_index)
+        != PitchGeneratorType::Random
     {
-        let transposition_pitch_generator_cycle_length_label = format!(
+        let modulation_cycle_length_label = format!(
             "Cycle length: {}",
-            model.sequencer_model.transposition_cycle_length as u32
+            model.sequencer_model.modulation_cycle_length as u32
         );
-        for transposition_pitch_generator_cycle_length_value in slider(
-            model.sequencer_model.transposition_cycle_length,
+        for modulation_cycle_length_value in slider(
+            model.sequencer_model.modulation_cycle_length,
             PITCH_GENERATOR_CYCLE_LENGTH_MIN_VALUE,
             PITCH_GENERATOR_CYCLE_LENGTH_MAX_VALUE,
         )
-        .padded_wh_of(model.ids.transposition_pitch_canvas_right_column, 5.0)
-        .middle_of(model.ids.transposition_pitch_canvas_right_column)
-        .label(&transposition_pitch_generator_cycle_length_label)
-        .set(
-            model.ids.transposition_pitch_generator_cycle_length_slider,
-            ui,
-        ) {
-            // quick and dirty way to restrict to multiples of 16
-            let new_value =
-                (transposition_pitch_generator_cycle_length_value as u32 + 15 & !15) as f32;
+        .padded_wh_of(model.ids.modulation_canvas_right_column, 5.0)
+        .middle_of(model.ids.modulation_canvas_right_column)
+        .label(&modulation_cycle_length_label)
+        .set(model.ids.modulation_cycle_length_slider, ui)
+        {
+            let new_value = snap_cycle_length(
+                modulation_cycle_length_value,
+                cycle_length_snap_from_index(model.cycle_length_snap_index),
+            );
             // only update the sequencer when the value has changed
-            if model.sequencer_model.transposition_cycle_length != new_value {
-                info!("Set transposition cycle length to: {}", new_value);
-                model.sequencer_model.transposition_cycle_length = new_value;
+            if model.sequencer_model.modulation_cycle_length != new_value {
+                info!("Set modulation cycle length to: {}", new_value);
+                model.sequencer_model.modulation_cycle_length = new_value;
                 model
                     .sequencer
-                    .update_pitch_generator(model.sequencer_model.clone().into());
+                    .update_modulation(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
             }
         }
     }
 
-    // Create pitch quantizer scale drop-down list
-    for quantizer_scale_value in drop_down_list(
-        QUANTIZER_SCALE_NAMES,
-        model.sequencer_model.quantizer_scale_index,
+    for modulation_target_value in drop_down_list(
+        MODULATION_TARGET_NAMES,
+        model.sequencer_model.modulation_target_index,
     )
-    .padded_wh_of(model.ids.global_canvas_left_column, 5.0)
-    .middle_of(model.ids.global_canvas_left_column)
-    .set(model.ids.quantizer_scale_drop_down, ui)
+    .padded_wh_of(model.ids.modulation_canvas_target_column, 5.0)
+    .middle_of(model.ids.modulation_canvas_target_column)
+    .set(model.ids.modulation_target_drop_down, ui)
     {
-        // Handle new drop-down list value
-        model.sequencer_model.quantizer_scale_index = Some(quantizer_scale_value);
         info!(
-            "Set pitch quantizer scale to: {}",
-            QUANTIZER_SCALE_NAMES[quantizer_scale_value]
+            "Set modulation target to: {}",
+            modulation_target_from_index(Some(modulation_target_value))
         );
+        model.sequencer_model.modulation_target_index = Some(modulation_target_value);
         model
             .sequencer
-            .update_pitch_generator(model.sequencer_model.clone().into());
+            .update_modulation(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
     }
 
-    // Create trigger probability slider
-    let trigger_probability_label = format!(
-        "Probability: {:.0}%",
-        model.sequencer_model.trigger_probability * 100.0
+    // Create controller slider when the target is a control change
+    if modulation_target_from_index(model.sequencer_model.modulation_target_index)
+        == ModulationTarget::ControlChange
+    {
+        let modulation_controller_label =
+            format!("CC: {}", model.sequencer_model.modulation_controller as u8);
+        for modulation_controller_value in slider(
+            model.sequencer_model.modulation_controller,
+            MODULATION_CONTROLLER_MIN_VALUE,
+            MODULATION_CONTROLLER_MAX_VALUE,
+        )
+        .padded_wh_of(model.ids.modulation_canvas_controller_column, 5.0)
+        .middle_of(model.ids.modulation_canvas_controller_column)
+        .label(&modulation_controller_label)
+        .set(model.ids.modulation_controller_slider, ui)
+        {
+            let new_value = modulation_controller_value.round();
+            // only update the sequencer when the value has changed
+            if model.sequencer_model.modulation_controller != new_value {
+                info!("Set modulation controller to: {}", new_value);
+                model.sequencer_model.modulation_controller = new_value;
+                model
+                    .sequencer
+                    .update_modulation(model.sequencer_model.clone().into())
+                    .unwrap_or_else(log_sequencer_error);
+            }
+        }
+    }
+
+    // Show the target tempo next to the one the rounded timer interval plays at
+    widget::Text::new(&format!(
+        "{:.1} BPM ({:.2} actual)",
+        model.sequencer.target_bpm(),
+        actual_bpm(model.sequencer.tick_interval_us())
+    ))
+    .padded_w_of(model.ids.tempo_canvas_bpm_column, 5.0)
+    .middle_of(model.ids.tempo_canvas_bpm_column)
+    .center_justify()
+    .font_size(20)
+    .color(LABEL_COLOR)
+    .set(model.ids.bpm_text, ui);
+
+    // Create tempo automation widgets
+    let tempo_automation_depth_label = format!(
+        "Tempo swing: \u{b1}{} BPM",
+        model.sequencer_model.tempo_automation_depth
     );
-    for trigger_probability_value in slider(
-        model.sequencer_model.trigger_probability,
-        TRIGGER_PROBABILITY_MIN_VALUE,
-        TRIGGER_PROBABILITY_MAX_VALUE,
+    for tempo_automation_depth_value in slider(
+        model.sequencer_model.tempo_automation_depth,
+        TEMPO_AUTOMATION_DEPTH_MIN_VALUE,
+        TEMPO_AUTOMATION_DEPTH_MAX_VALUE,
     )
-    .padded_wh_of(model.ids.global_canvas_middle_column, 5.0)
-    .middle_of(model.ids.global_canvas_middle_column)
-    .label(&trigger_probability_label)
-    .set(model.ids.trigger_probability_slider, ui)
+    .padded_wh_of(model.ids.tempo_canvas_depth_column, 5.0)
+    .middle_of(model.ids.tempo_canvas_depth_column)
+    .label(&tempo_automation_depth_label)
+    .set(model.ids.tempo_automation_depth_slider, ui)
     {
-        let new_value = (trigger_probability_value * 100.0).round() / 100.0;
+        let new_value = tempo_automation_depth_value.round();
         // only update the sequencer when the value has changed
-        if model.sequencer_model.trigger_probability != new_value {
-            info!("Set trigger probability to: {}", new_value);
-            model.sequencer_model.trigger_probability = new_value;
+        if model.sequencer_model.tempo_automation_depth != new_value {
+            info!("Set tempo automation depth to: {}", new_value);
+            model.sequencer_model.tempo_automation_depth = new_value;
             model
                 .sequencer
-                .update_trigger_generator(model.sequencer_model.clone().into());
+                .update_tempo_automation(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
         }
     }
 
-    // Create clock divider factor slider
-    let clock_divider_factor_label = format!(
-        "Clock division: {}",
-        model.sequencer_model.clock_divider_factor as u32
+    let tempo_automation_period_label = format!(
+        "Tempo period: {} bars",
+        model.sequencer_model.tempo_automation_period
     );
-    for clock_divider_factor_value in slider(
-        model.sequencer_model.clock_divider_factor,
-        CLOCK_DIVIDER_FACTOR_MIN_VALUE,
-        CLOCK_DIVIDER_FACTOR_MAX_VALUE,
+    for tempo_automation_period_value in slider(
+        model.sequencer_model.tempo_automation_period,
+        TEMPO_AUTOMATION_PERIOD_MIN_VALUE,
+        TEMPO_AUTOMATION_PERIOD_MAX_VALUE,
     )
-    .padded_wh_of(model.ids.global_canvas_right_column, 5.0)
-    .middle_of(model.ids.global_canvas_right_column)
-    .label(&clock_divider_factor_label)
-    .set(model.ids.clock_divider_factor_slider, ui)
+    .padded_wh_of(model.ids.tempo_canvas_period_column, 5.0)
+    .middle_of(model.ids.tempo_canvas_period_column)
+    .label(&tempo_automation_period_label)
+    .set(model.ids.tempo_automation_period_slider, ui)
     {
-        let new_value = clock_divider_factor_value.round();
+        let new_value = tempo_automation_period_value.round();
         // only update the sequencer when the value has changed
-        if model.sequencer_model.clock_divider_factor != new_value {
-            info!("Set clock divider factor to: {}", new_value);
-            model.sequencer_model.clock_divider_factor = new_value;
+        if model.sequencer_model.tempo_automation_period != new_value {
+            info!("Set tempo automation period to: {}", new_value);
+            model.sequencer_model.tempo_automation_period = new_value;
             model
                 .sequencer
-                .update_trigger_generator(model.sequencer_model.clone().into());
+                .update_tempo_automation(model.sequencer_model.clone().into())
+                .unwrap_or_else(log_sequencer_error);
+        }
+    }
+
+    // Create seed widgets. The generators restart to pick up a new seed.
+    for lock_seed_value in toggle(model.sequencer_model.lock_seed)
+        .padded_wh_of(model.ids.tempo_canvas_lock_seed_column, 5.0)
+        .middle_of(model.ids.tempo_canvas_lock_seed_column)
+        .label("Lock seed")
+        .set(model.ids.lock_seed_toggle, ui)
+    {
+        info!("Set lock seed to: {}", lock_seed_value);
+        model.sequencer_model.lock_seed = lock_seed_value;
+        reset_sequencer(&model.sequencer, &model.sequencer_model);
+    }
+
+    widget::Text::new(&format!("Seed: {}", model.sequencer_model.seed))
+        .padded_w_of(model.ids.tempo_canvas_seed_column, 5.0)
+        .middle_of(model.ids.tempo_canvas_seed_column)
+        .center_justify()
+        .font_size(20)
+        .color(LABEL_COLOR)
+        .set(model.ids.seed_text, ui);
+
+    for _ in Button::new()
+        .padded_wh_of(model.ids.tempo_canvas_new_seed_column, 5.0)
+        .middle_of(model.ids.tempo_canvas_new_seed_column)
+        .label("New seed")
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+        .set(model.ids.new_seed_button, ui)
+    {
+        model.sequencer_model.seed = rand::random();
+        info!("Set seed to: {}", model.sequencer_model.seed);
+        if model.sequencer_model.lock_seed {
+            reset_sequencer(&model.sequencer, &model.sequencer_model);
         }
     }
 
+    // Create lane seed widgets. The generators of the lane restart to pick up a new seed.
+    if lane_seed_widgets(
+        ui,
+        "Melody",
+        [
+            model.ids.seed_canvas_melody_lock_column,
+            model.ids.seed_canvas_melody_seed_column,
+            model.ids.seed_canvas_melody_new_column,
+        ],
+        [
+            model.ids.melody_lock_seed_toggle,
+            model.ids.melody_seed_text,
+            model.ids.melody_new_seed_button,
+        ],
+        &mut model.sequencer_model.melody_lock_seed,
+        &mut model.sequencer_model.melody_seed,
+    ) {
+        model
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    if lane_seed_widgets(
+        ui,
+        "Transpose",
+        [
+            model.ids.seed_canvas_transposition_lock_column,
+            model.ids.seed_canvas_transposition_seed_column,
+            model.ids.seed_canvas_transposition_new_column,
+        ],
+        [
+            model.ids.transposition_lock_seed_toggle,
+            model.ids.transposition_seed_text,
+            model.ids.transposition_new_seed_button,
+        ],
+        &mut model.sequencer_model.transposition_lock_seed,
+        &mut model.sequencer_model.transposition_seed,
+    ) {
+        model
+            .sequencer
+            .update_pitch_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+    if lane_seed_widgets(
+        ui,
+        "Trigger",
+        [
+            model.ids.seed_canvas_trigger_lock_column,
+            model.ids.seed_canvas_trigger_seed_column,
+            model.ids.seed_canvas_trigger_new_column,
+        ],
+        [
+            model.ids.trigger_lock_seed_toggle,
+            model.ids.trigger_seed_text,
+            model.ids.trigger_new_seed_button,
+        ],
+        &mut model.sequencer_model.trigger_lock_seed,
+        &mut model.sequencer_model.trigger_seed,
+    ) {
+        model
+            .sequencer
+            .update_trigger_generator(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create toggle choosing whether stopping rewinds the generators or pauses them
+    let stop_resets_position_label = if model.sequencer_model.stop_resets_position {
+        "Stop: rewind"
+    } else {
+        "Stop: pause"
+    };
+    for stop_resets_position_value in toggle(model.sequencer_model.stop_resets_position)
+        .padded_wh_of(model.ids.tempo_canvas_stop_resets_column, 5.0)
+        .middle_of(model.ids.tempo_canvas_stop_resets_column)
+        .label(stop_resets_position_label)
+        .set(model.ids.stop_resets_position_toggle, ui)
+    {
+        info!(
+            "Set stop resets position to: {}",
+            stop_resets_position_value
+        );
+        model.sequencer_model.stop_resets_position = stop_resets_position_value;
+        model
+            .sequencer
+            .update_stop_resets_position(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
     // Create Play/Pause toggle
     let is_playing_label = if model.is_playing { "Pause" } else { "Play" };
     for is_playing_toggle_value in Toggle::new(model.is_playing)
@@ -573,13 +4230,27 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         model.is_playing = is_playing_toggle_value;
         if model.is_playing {
             info!("Start sequencer");
-            model.sequencer.start()
+            model.sequencer.start().unwrap_or_else(log_sequencer_error)
         } else {
             info!("Stop sequencer");
-            model.sequencer.stop()
+            model.sequencer.stop().unwrap_or_else(log_sequencer_error)
         }
     }
 
+    // Create count-in drop-down list
+    for count_in_value in drop_down_list(COUNT_IN_NAMES, model.sequencer_model.count_in_index)
+        .padded_wh_of(model.ids.transport_canvas_count_in_column, 5.0)
+        .middle_of(model.ids.transport_canvas_count_in_column)
+        .set(model.ids.count_in_drop_down, ui)
+    {
+        info!("Set count-in to: {}", COUNT_IN_NAMES[count_in_value]);
+        model.sequencer_model.count_in_index = Some(count_in_value);
+        model
+            .sequencer
+            .update_count_in(model.sequencer_model.clone().into())
+            .unwrap_or_else(log_sequencer_error);
+    }
+
     // Create reset button
     for _ in Button::new()
         .padded_wh_of(model.ids.transport_canvas_left_column, 5.0)
@@ -591,16 +4262,191 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         .border(0.0)
         .set(model.ids.reset_button, ui)
     {
-        info!("Reset sequencer");
-        model
-            .sequencer
-            .update_pitch_generator(model.sequencer_model.clone().into());
-        model
-            .sequencer
-            .update_trigger_generator(model.sequencer_model.clone().into());
+        reset_sequencer(&model.sequencer, &model.sequencer_model);
+    }
+
+    // Create panic button
+    for _ in Button::new()
+        .padded_wh_of(model.ids.transport_canvas_middle_column, 5.0)
+        .middle_of(model.ids.transport_canvas_middle_column)
+        .label("Panic")
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+        .set(model.ids.panic_button, ui)
+    {
+        model.sequencer.panic().unwrap_or_else(log_sequencer_error);
+    }
+
+    // Create randomize button
+    for _ in Button::new()
+        .padded_wh_of(model.ids.transport_canvas_randomize_column, 5.0)
+        .middle_of(model.ids.transport_canvas_randomize_column)
+        .label("Randomize")
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+        .set(model.ids.randomize_button, ui)
+    {
+        info!("Randomize sequencer settings");
+        model.sequencer_model = randomize_sequencer_model(
+            &model.sequencer_model,
+            cycle_length_snap_from_index(model.cycle_length_snap_index),
+            &mut rand::thread_rng(),
+        );
+        apply_sequencer_model(&mut model.sequencer, &model.sequencer_model);
+    }
+
+    // Create variation button and amount slider
+    for _ in Button::new()
+        .padded_wh_of(model.ids.transport_canvas_mutate_column, 5.0)
+        .middle_of(model.ids.transport_canvas_mutate_column)
+        .label("Vary")
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+        .set(model.ids.mutate_button, ui)
+    {
+        info!("Vary sequencer settings");
+        model.sequencer_model = model
+            .sequencer_model
+            .mutate(&mut rand::thread_rng(), model.mutation_amount);
+        apply_sequencer_model(&mut model.sequencer, &model.sequencer_model);
+    }
+    let mutation_amount_label = format!("Amount: {:.0}%", model.mutation_amount * 100.0);
+    for mutation_amount_value in slider(
+        model.mutation_amount,
+        MUTATION_AMOUNT_MIN_VALUE,
+        MUTATION_AMOUNT_MAX_VALUE,
+    )
+    .padded_wh_of(model.ids.transport_canvas_mutation_amount_column, 5.0)
+    .middle_of(model.ids.transport_canvas_mutation_amount_column)
+    .label(&mutation_amount_label)
+    .set(model.ids.mutation_amount_slider, ui)
+    {
+        model.mutation_amount = (mutation_amount_value * 100.0).round() / 100.0;
+    }
+
+    // Create cycle length snap drop-down list. Existing cycle lengths are kept, the snap
+    // applies to the next slider move.
+    for cycle_length_snap_value in
+        drop_down_list(CYCLE_LENGTH_SNAP_NAMES, model.cycle_length_snap_index)
+            .padded_wh_of(model.ids.transport_canvas_snap_column, 5.0)
+            .middle_of(model.ids.transport_canvas_snap_column)
+            .set(model.ids.cycle_length_snap_drop_down, ui)
+    {
+        info!(
+            "Set cycle length snap to: {}",
+            CYCLE_LENGTH_SNAP_VALUES[cycle_length_snap_value]
+        );
+        model.cycle_length_snap_index = Some(cycle_length_snap_value);
+    }
+
+    // Show the name of the preset selected from the preset folder
+    let preset_name_label = match model.preset_bank.current_name() {
+        Some(name) => format!("Preset: {}", name),
+        None if model.preset_bank.is_empty() => "No presets".to_string(),
+        None => "Preset: -".to_string(),
+    };
+    widget::Text::new(&preset_name_label)
+        .padded_w_of(model.ids.transport_canvas_preset_column, 5.0)
+        .middle_of(model.ids.transport_canvas_preset_column)
+        .center_justify()
+        .font_size(20)
+        .color(LABEL_COLOR)
+        .set(model.ids.preset_name_text, ui);
+
+    // Create morph widgets, going from preset A to preset B of the preset folder
+    let preset_names = model.preset_bank.names();
+    for morph_a_value in preset_drop_down_list(&preset_names, model.morph_a_index)
+        .padded_wh_of(model.ids.morph_canvas_a_column, 5.0)
+        .middle_of(model.ids.morph_canvas_a_column)
+        .label("Morph from")
+        .set(model.ids.morph_a_drop_down, ui)
+    {
+        info!("Set morph from preset: {}", preset_names[morph_a_value]);
+        model.morph_a_index = Some(morph_a_value);
+    }
+    for morph_b_value in preset_drop_down_list(&preset_names, model.morph_b_index)
+        .padded_wh_of(model.ids.morph_canvas_b_column, 5.0)
+        .middle_of(model.ids.morph_canvas_b_column)
+        .label("Morph to")
+        .set(model.ids.morph_b_drop_down, ui)
+    {
+        info!("Set morph to preset: {}", preset_names[morph_b_value]);
+        model.morph_b_index = Some(morph_b_value);
+    }
+    let morph_bars_label = format!("Morph: {} bars", model.morph_bars);
+    for morph_bars_value in slider(model.morph_bars, MORPH_BARS_MIN_VALUE, MORPH_BARS_MAX_VALUE)
+        .padded_wh_of(model.ids.morph_canvas_bars_column, 5.0)
+        .middle_of(model.ids.morph_canvas_bars_column)
+        .label(&morph_bars_label)
+        .set(model.ids.morph_bars_slider, ui)
+    {
+        model.morph_bars = morph_bars_value.round();
+    }
+    let morph_button_label = match &model.morph {
+        Some(running) => format!("Morphing {}/{}", running.bars_played, running.bars),
+        None => "Morph".to_string(),
+    };
+    for _ in Button::new()
+        .padded_wh_of(model.ids.morph_canvas_button_column, 5.0)
+        .middle_of(model.ids.morph_canvas_button_column)
+        .label(&morph_button_label)
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+        .set(model.ids.morph_button, ui)
+    {
+        if model.morph.take().is_some() {
+            info!("Stopped morph");
+        } else if let Some((a, b)) =
+            load_morph_presets(&model.preset_bank, model.morph_a_index, model.morph_b_index)
+        {
+            info!("Morph over {} bars", model.morph_bars);
+            // Start from A, with the generators carrying on where they are
+            apply_morph_step(&mut model.sequencer, &model.sequencer_model, &a);
+            model.sequencer_model = a.clone();
+            model.morph = Some(Morph {
+                a,
+                b,
+                bars: model.morph_bars as u32,
+                bars_played: 0,
+                last_bar: model.sequencer.bar(),
+            });
+        } else {
+            warn!("Select the presets to morph from and to first");
+        }
+    }
+
+    // Record the edits for undo once the mouse is released, so that a whole slider drag
+    // is undone in one step
+    if !ui.global_input().current.mouse.buttons.left().is_down() {
+        model.history.commit(&model.sequencer_model);
     }
 }
 
+// Rows share the window height equally, so the layout follows the window size
+fn row_canvas() -> Canvas<'static> {
+    widget::Canvas::new().length_weight(1.0)
+}
+
+// Like drop_down_list, for the preset names, which are only known once the folder is read
+fn preset_drop_down_list<'a>(
+    names: &'a [String],
+    selected: Option<Idx>,
+) -> widget::DropDownList<'a, String> {
+    widget::DropDownList::new(names, selected)
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+}
+
 fn column_canvas() -> Canvas<'static> {
     widget::Canvas::new()
         .color(CANVAS_COLOR)
@@ -624,6 +4470,23 @@ fn range_slider(start: f32, end: f32, min: f32, max: f32) -> widget::RangeSlider
         .border(0.0)
 }
 
+fn toggle(value: bool) -> widget::Toggle<'static> {
+    widget::Toggle::new(value)
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+}
+
+fn text_box(text: &str) -> widget::TextBox<'_> {
+    widget::TextBox::new(text)
+        .font_size(20)
+        .center_justify()
+        .color(WIDGET_COLOR)
+        .text_color(LABEL_COLOR)
+        .border(0.0)
+}
+
 fn drop_down_list(
     items: &'static [&str],
     selected: Option<Idx>,
@@ -635,10 +4498,373 @@ fn drop_down_list(
         .border(0.0)
 }
 
+// Create the lock toggle, the seed and the new seed button of a lane with a seed of its own,
+// in its three columns. Returns whether the generators of the lane have to be rebuilt.
+fn lane_seed_widgets(
+    ui: &mut UiCell,
+    lane: &str,
+    [lock_column, seed_column, new_column]: [widget::Id; 3],
+    [lock_toggle, seed_text, new_button]: [widget::Id; 3],
+    lock_seed: &mut bool,
+    seed: &mut u64,
+) -> bool {
+    let name = lane.to_lowercase();
+    let mut changed = false;
+    for lock_seed_value in toggle(*lock_seed)
+        .padded_wh_of(lock_column, 5.0)
+        .middle_of(lock_column)
+        .label(&format!("Lock {}", name))
+        .set(lock_toggle, ui)
+    {
+        info!("Set lock {} seed to: {}", name, lock_seed_value);
+        *lock_seed = lock_seed_value;
+        changed = true;
+    }
+
+    widget::Text::new(&format!("{}: {}", lane, seed))
+        .padded_w_of(seed_column, 5.0)
+        .middle_of(seed_column)
+        .center_justify()
+        .font_size(20)
+        .color(LABEL_COLOR)
+        .set(seed_text, ui);
+
+    for _ in Button::new()
+        .padded_wh_of(new_column, 5.0)
+        .middle_of(new_column)
+        .label("New")
+        .label_font_size(20)
+        .color(WIDGET_COLOR)
+        .label_color(LABEL_COLOR)
+        .border(0.0)
+        .set(new_button, ui)
+    {
+        *seed = rand::random();
+        info!("Set {} seed to: {}", name, seed);
+        // an unlocked seed only takes effect once it is locked
+        changed |= *lock_seed;
+    }
+    changed
+}
+
 fn ui_view(app: &App, model: &Model, frame: Frame) {
-    model.ui.draw_to_frame_if_changed(app, &frame).unwrap();
+    if model.show_help {
+        // The overlay covers the UI, so the UI has to be drawn underneath it every frame
+        model.ui.draw_to_frame(app, &frame).unwrap();
+        draw_help_overlay(app, &frame);
+    } else if model.ui.draw_to_frame_if_changed(app, &frame).unwrap() {
+        // Drawn on top of the UI, so it has to be redrawn whenever the UI is
+        draw_last_step(app, model, &frame);
+        draw_trigger_density(app, model, &frame);
+        draw_keyboard(app, model, &frame);
+    }
+}
+
+// Show the latest generated note with the MIDI note number it is played at and whether it
+// was played, in the empty column of the transport row. A note outside the MIDI note range
+// is shown in red, it is played at the nearest end of the range.
+fn draw_last_step(app: &App, model: &Model, frame: &Frame) {
+    let rect = match model.ui.rect_of(model.ids.transport_canvas_live_column) {
+        Some(rect) => rect,
+        None => return,
+    };
+    let (text, is_in_range) = match model.last_step {
+        Some((pitch, trigger)) => {
+            let (note_number, is_in_range) = midi_note_number(pitch);
+            let played = if trigger == Trigger::On {
+                "note"
+            } else {
+                "rest"
+            };
+            (
+                format!(
+                    "{} ({}) {}",
+                    format_letter_octave(pitch),
+                    note_number,
+                    played
+                ),
+                is_in_range,
+            )
+        }
+        None => ("-".to_string(), true),
+    };
+    let text_color = if is_in_range {
+        nannou::color::WHITE
+    } else {
+        nannou::color::RED
+    };
+    let draw = app.draw();
+    // The same dark box as the widgets keeps the text readable on the canvas color
+    draw.rect()
+        .x_y(rect.x() as f32, rect.y() as f32)
+        .w_h(rect.w() as f32 - 10.0, rect.h() as f32 - 10.0)
+        .color(nannou::color::rgb(0.3, 0.3, 0.3));
+    draw.text(&text)
+        .x_y(rect.x() as f32, rect.y() as f32)
+        .w_h(rect.w() as f32, rect.h() as f32)
+        .font_size(20)
+        .color(text_color);
+    draw.to_frame(app, frame).unwrap();
+}
+
+// Meter along the bottom of the live step box, filling up as the rhythm gets busier
+fn draw_trigger_density(app: &App, model: &Model, frame: &Frame) {
+    let rect = match model.ui.rect_of(model.ids.transport_canvas_live_column) {
+        Some(rect) => rect,
+        None => return,
+    };
+    let full_width = rect.w() as f32 - 10.0;
+    let width = full_width * model.trigger_density;
+    let height = 4.0;
+    let draw = app.draw();
+    // Grows from the left edge of the box
+    draw.rect()
+        .x_y(
+            rect.left() as f32 + 5.0 + width / 2.0,
+            rect.bottom() as f32 + 5.0 + height / 2.0,
+        )
+        .w_h(width, height)
+        .color(nannou::color::rgb(0.4, 0.8, 0.4));
+    draw.to_frame(app, frame).unwrap();
+}
+
+// The octaves the mini keyboard shows, the ones the melody range spans
+fn keyboard_octaves(min_pitch: f32, max_pitch: f32) -> RangeInclusive<i32> {
+    let octave = |step: f32| Step(step).to_letter_octave().octave();
+    octave(min_pitch.min(max_pitch))..=octave(min_pitch.max(max_pitch))
+}
+
+// Mini piano keyboard in the melody row, showing the notes of the shared scale within the
+// melody range in green, the ends of the range in orange, and the keys outside of it dimmed
+fn draw_keyboard(app: &App, model: &Model, frame: &Frame) {
+    let rect = match model.ui.rect_of(model.ids.pitch_canvas_keyboard_column) {
+        Some(rect) => rect,
+        None => return,
+    };
+    let min_pitch = model.sequencer_model.melody_min_pitch;
+    let max_pitch = model.sequencer_model.melody_max_pitch;
+    let (low, high) = (min_pitch.min(max_pitch), min_pitch.max(max_pitch));
+    let scale: Vec<i32> = QUANTIZER_SCALES[model.sequencer_model.quantizer_scale_index.unwrap()]
+        .iter()
+        .map(|letter| (LetterOctave(*letter, 0).step() as i32).rem_euclid(12))
+        .collect();
+    let key_color = |step: f32, is_black: bool| {
+        let is_in_range = (low..=high).contains(&step);
+        if step == low || step == high {
+            nannou::color::rgb(1.0, 0.6, 0.2)
+        } else if is_in_range && scale.contains(&(step as i32).rem_euclid(12)) {
+            nannou::color::rgb(0.4, 0.8, 0.4)
+        } else {
+            match (is_in_range, is_black) {
+                (true, false) => nannou::color::rgb(1.0, 1.0, 1.0),
+                (true, true) => nannou::color::rgb(0.05, 0.05, 0.05),
+                (false, false) => nannou::color::rgb(0.6, 0.6, 0.6),
+                (false, true) => nannou::color::rgb(0.3, 0.3, 0.3),
+            }
+        }
+    };
+
+    let octaves = keyboard_octaves(min_pitch, max_pitch);
+    let octave_count = (octaves.end() - octaves.start() + 1) as f32;
+    let width = rect.w() as f32 - 10.0;
+    let height = rect.h() as f32 - 10.0;
+    let white_width = width / (7.0 * octave_count);
+    let left = rect.left() as f32 + 5.0;
+    let draw = app.draw();
+    // Semitones of the white keys, and of the black keys with the white key they follow
+    let white_keys = [0, 2, 4, 5, 7, 9, 11];
+    let black_keys = [(1, 0), (3, 1), (6, 3), (8, 4), (10, 5)];
+    for (octave_index, octave) in octaves.enumerate() {
+        let c = LetterOctave(Letter::C, octave).step();
+        let octave_left = left + octave_index as f32 * 7.0 * white_width;
+        for (index, semitone) in white_keys.iter().enumerate() {
+            draw.rect()
+                .x_y(
+                    octave_left + (index as f32 + 0.5) * white_width,
+                    rect.y() as f32,
+                )
+                // a gap between the keys
+                .w_h(white_width - 1.0, height)
+                .color(key_color(c + *semitone as f32, false));
+        }
+        // drawn on top of the white keys, over the upper part of the boundary between them
+        for (semitone, white_index) in black_keys {
+            draw.rect()
+                .x_y(
+                    octave_left + (white_index + 1) as f32 * white_width,
+                    rect.top() as f32 - 5.0 - height * 0.3,
+                )
+                .w_h(white_width * 0.6, height * 0.6)
+                .color(key_color(c + semitone as f32, true));
+        }
+    }
+    draw.to_frame(app, frame).unwrap();
+}
+
+fn draw_help_overlay(app: &App, frame: &Frame) {
+    let draw = app.draw();
+    let window_rect = frame.rect();
+    draw.rect()
+        .wh(window_rect.wh())
+        .color(nannou::color::rgba(0.0, 0.0, 0.0, 0.85));
+    draw.text(HELP_TEXT)
+        .wh(window_rect.pad(20.0).wh())
+        .left_justify()
+        .align_text_top()
+        .font_size(14)
+        .color(nannou::color::WHITE);
+    draw.to_frame(app, frame).unwrap();
 }
 
 fn raw_ui_event(app: &App, model: &mut Model, event: &nannou_conrod::RawWindowEvent) {
     model.ui.handle_raw_event(app, event);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencer::{SequencerConfiguration, MAX_BPM};
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    #[test]
+    fn density_maps_from_sparse_to_busy_trigger_parameters() {
+        assert_eq!(density_to_trigger_parameters(0.0), (0.2, 24.0));
+        assert_eq!(density_to_trigger_parameters(0.5), (0.6, 8.0));
+        assert_eq!(density_to_trigger_parameters(1.0), (1.0, 3.0));
+    }
+
+    #[test]
+    fn xy_pad_positions_map_to_probability_and_clock_division() {
+        assert_eq!(xy_pad_to_trigger_parameters(0.0, 0.0), (0.0, 24.0));
+        assert_eq!(xy_pad_to_trigger_parameters(0.5, 0.5), (0.5, 5.0));
+        assert_eq!(xy_pad_to_trigger_parameters(1.0, 1.0), (1.0, 1.0));
+        // the pad shows the current parameters where dragging would set them
+        assert_eq!(trigger_parameters_to_xy_pad(0.25, 24.0), (0.25, 0.0));
+        assert_eq!(trigger_parameters_to_xy_pad(1.0, 1.0), (1.0, 1.0));
+    }
+
+    #[test]
+    fn the_patch_summary_shows_the_lanes_scale_and_tempo() {
+        let mut sequencer_model = SequencerModel {
+            melody_min_pitch: LetterOctave(Letter::C, 3).step(),
+            melody_max_pitch: LetterOctave(Letter::C, 5).step(),
+            transposition_min_pitch: 0.0,
+            transposition_max_pitch: 12.0,
+            quantizer_scale_index: QUANTIZER_SCALE_NAMES
+                .iter()
+                .position(|name| *name == "Major"),
+            bpm: 120.0,
+            ..SequencerModel::default()
+        };
+        sequencer_model.melody_pitch_generator_type_index = MELODY_PITCH_GENERATOR_TYPE_NAMES
+            .iter()
+            .position(|name| *name == "Ramp");
+        sequencer_model.transposition_pitch_generator_type_index =
+            TRANSPOSITION_PITCH_GENERATOR_TYPE_NAMES
+                .iter()
+                .position(|name| *name == "Random");
+        assert_eq!(
+            patch_summary(&sequencer_model),
+            "Melody: Ramp C3\u{2013}C5 / Transpose: Random 0\u{2013}12 / Scale: Major / 120 BPM"
+        );
+
+        sequencer_model.melody_pitch_generator_type_index = MELODY_PITCH_GENERATOR_TYPE_NAMES
+            .iter()
+            .position(|name| *name == "Steps");
+        sequencer_model.transposition_enabled = false;
+        assert_eq!(
+            patch_summary(&sequencer_model),
+            "Melody: Steps / Transpose: off / Scale: Major / 120 BPM"
+        );
+    }
+
+    #[test]
+    fn the_keyboard_shows_the_octaves_of_the_melody_range() {
+        let step = |letter, octave| LetterOctave(letter, octave).step();
+        assert_eq!(
+            keyboard_octaves(step(Letter::C, 3), step(Letter::C, 5)),
+            3..=5
+        );
+        assert_eq!(
+            keyboard_octaves(step(Letter::B, 4), step(Letter::E, 4)),
+            4..=4
+        );
+    }
+
+    #[test]
+    fn cycle_lengths_are_rounded_up_to_the_snap() {
+        assert_eq!(snap_cycle_length(3.0, 1), 3.0);
+        assert_eq!(snap_cycle_length(3.4, 1), 3.0);
+        assert_eq!(snap_cycle_length(3.0, 4), 4.0);
+        assert_eq!(snap_cycle_length(1.0, 16), 16.0);
+        assert_eq!(snap_cycle_length(17.0, 16), 32.0);
+        assert_eq!(snap_cycle_length(128.0, 16), 128.0);
+    }
+
+    #[test]
+    fn midi_channels_are_parsed_from_a_list_of_channel_numbers() {
+        assert_eq!(parse_midi_channels("1 2 3"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_midi_channels(" 16,1, 5 "), Some(vec![16, 1, 5]));
+        assert_eq!(parse_midi_channels(""), None);
+        assert_eq!(parse_midi_channels("0 1"), None);
+        assert_eq!(parse_midi_channels("1 17"), None);
+        assert_eq!(parse_midi_channels("1 two"), None);
+        assert_eq!(format_midi_channels(&[16, 1, 5]), "16 1 5");
+        assert_eq!(format_midi_channels(&[]), "1");
+    }
+
+    #[test]
+    fn harmony_intervals_are_parsed_from_a_list_of_semitones() {
+        assert_eq!(parse_harmony_intervals("+4 +7"), Some(vec![4, 7]));
+        assert_eq!(parse_harmony_intervals("-12, 3"), Some(vec![-12, 3]));
+        assert_eq!(parse_harmony_intervals(""), None);
+        assert_eq!(parse_harmony_intervals("4 25"), None);
+        assert_eq!(parse_harmony_intervals("4 five"), None);
+        assert_eq!(format_harmony_intervals(&[4, -3]), "+4 -3");
+    }
+
+    #[test]
+    fn the_bpm_range_stays_within_the_timer_resolution() {
+        assert!(BPM_MAX_VALUE + TEMPO_AUTOMATION_DEPTH_MAX_VALUE <= MAX_BPM);
+    }
+
+    #[test]
+    fn randomized_settings_are_always_valid() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut sequencer_model = SequencerModel::default();
+        for i in 0..1000 {
+            let snap = CYCLE_LENGTH_SNAP_VALUES[i % CYCLE_LENGTH_SNAP_VALUES.len()];
+            sequencer_model = randomize_sequencer_model(&sequencer_model, snap, &mut rng);
+            assert!(sequencer_model.melody_min_pitch <= sequencer_model.melody_max_pitch);
+            assert!(MELODY_PITCH_MIN_VALUE.step() <= sequencer_model.melody_min_pitch);
+            assert!(sequencer_model.melody_max_pitch <= MELODY_PITCH_MAX_VALUE.step());
+            assert!(
+                sequencer_model.transposition_min_pitch <= sequencer_model.transposition_max_pitch
+            );
+            assert!(TRANSPOSITION_MIN_VALUE.step() <= sequencer_model.transposition_min_pitch);
+            assert!(sequencer_model.transposition_max_pitch <= TRANSPOSITION_MAX_VALUE.step());
+            assert!(sequencer_model.quantizer_min_octave <= sequencer_model.quantizer_max_octave);
+            assert!(sequencer_model.modulation_min <= sequencer_model.modulation_max);
+            for cycle_length in [
+                sequencer_model.melody_cycle_length,
+                sequencer_model.transposition_cycle_length,
+                sequencer_model.modulation_cycle_length,
+            ] {
+                assert!(cycle_length >= PITCH_GENERATOR_CYCLE_LENGTH_MIN_VALUE);
+                assert!(cycle_length <= PITCH_GENERATOR_CYCLE_LENGTH_MAX_VALUE);
+                assert_eq!(cycle_length as u32 % snap, 0);
+            }
+            assert!(sequencer_model.melody_phase < sequencer_model.melody_cycle_length);
+            assert!(
+                sequencer_model.transposition_phase < sequencer_model.transposition_cycle_length
+            );
+            assert!((RANDOM_BPM_MIN_VALUE..=RANDOM_BPM_MAX_VALUE).contains(&sequencer_model.bpm));
+            assert!(
+                (CLOCK_DIVIDER_FACTOR_MIN_VALUE..=CLOCK_DIVIDER_FACTOR_MAX_VALUE)
+                    .contains(&sequencer_model.clock_divider_factor)
+            );
+            // every index must resolve to a name, which the conversion checks
+            let _: SequencerConfiguration = sequencer_model.clone().into();
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use midly::{MidiMessage, Smf, TrackEventKind};
+use pitch_calc::{LetterOctave, Step};
+
+pub const CHORD_NOTE_NAMES: &[&str] = &["Chord: top", "Chord: bottom"];
+
+// The note kept of the notes starting together, as the step sequence plays one at a time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChordNote {
+    Top,
+    Bottom,
+}
+
+impl ChordNote {
+    pub fn from_index(idx: usize) -> ChordNote {
+        match idx {
+            0 => ChordNote::Top,
+            _ => ChordNote::Bottom,
+        }
+    }
+}
+
+// The notes of a MIDI file in the order they start, e.g. to play a melody written in a DAW
+// as the melody step sequence. Timing is left out, each note becomes one step.
+pub fn read_step_sequence(
+    path: &Path,
+    chord_note: ChordNote,
+) -> Result<Vec<LetterOctave>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    Ok(step_sequence(&Smf::parse(&bytes)?, chord_note))
+}
+
+fn step_sequence(smf: &Smf, chord_note: ChordNote) -> Vec<LetterOctave> {
+    // The tracks share the timeline, so merge their note ons by the tick they start on
+    let mut note_ons: Vec<(u32, u8)> = Vec::new();
+    for track in smf.tracks.iter() {
+        let mut tick = 0;
+        for event in track.iter() {
+            tick += event.delta.as_int();
+            // A note on with velocity 0 ends a note
+            if let TrackEventKind::Midi {
+                message: MidiMessage::NoteOn { key, vel },
+                ..
+            } = event.kind
+            {
+                if vel > 0 {
+                    note_ons.push((tick, key.as_int()));
+                }
+            }
+        }
+    }
+    note_ons.sort_by_key(|(tick, _)| *tick);
+    // Keep one note of each chord, the notes starting on the same tick
+    let mut notes: Vec<(u32, u8)> = Vec::new();
+    for (tick, note) in note_ons {
+        match notes.last_mut() {
+            Some((chord_tick, chord)) if *chord_tick == tick => {
+                *chord = match chord_note {
+                    ChordNote::Top => note.max(*chord),
+                    ChordNote::Bottom => note.min(*chord),
+                };
+            }
+            _ => notes.push((tick, note)),
+        }
+    }
+    notes
+        .into_iter()
+        .map(|(_, note)| Step(note as f32).to_letter_octave())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::format_step_sequence;
+
+    #[test]
+    fn midi_file_notes_become_a_step_sequence() {
+        // Two tracks: C4, E4, G4 and D4 on the first, C5 along with G4 on the second
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/melody.mid");
+
+        let top = read_step_sequence(&path, ChordNote::Top).unwrap();
+        assert_eq!(format_step_sequence(&top), "C4 E4 C5 D4");
+        let bottom = read_step_sequence(&path, ChordNote::Bottom).unwrap();
+        assert_eq!(format_step_sequence(&bottom), "C4 E4 G4 D4");
+    }
+}
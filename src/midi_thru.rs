@@ -0,0 +1,65 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use log::warn;
+use midir::{Ignore, MidiInputConnection};
+
+use crate::capture::connect_first_input_port;
+use crate::sequencer::MidiSink;
+
+const MIDI_THRU_PORT_NAME: &str = "Nannou Generative Sequencer Thru";
+
+// Passes the messages received on the first MIDI input port on to the MIDI output while
+// switched on, merged with the generated notes, e.g. to play a keyboard through the same
+// synth as the sequencer
+pub struct MidiThru {
+    is_enabled: Arc<AtomicBool>,
+    // Messages only arrive while the connection is open, there is none without input ports
+    connection: Option<MidiInputConnection<()>>,
+}
+
+impl MidiThru {
+    pub fn open(output: Box<dyn MidiSink>) -> MidiThru {
+        let is_enabled = Arc::new(AtomicBool::new(false));
+        let connection = MidiThru::connect(output, is_enabled.clone());
+        MidiThru {
+            is_enabled,
+            connection,
+        }
+    }
+
+    fn connect(
+        mut output: Box<dyn MidiSink>,
+        is_enabled: Arc<AtomicBool>,
+    ) -> Option<MidiInputConnection<()>> {
+        // Pass on everything from the port notes are captured from, a thru port is not supposed
+        // to filter
+        connect_first_input_port(MIDI_THRU_PORT_NAME, Ignore::None, move |_, message, _| {
+            forward(&is_enabled, output.as_mut(), message);
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, is_enabled: bool) {
+        self.is_enabled.store(is_enabled, Ordering::Relaxed);
+    }
+}
+
+// Send a received message on to the output, if MIDI thru is switched on
+fn forward(is_enabled: &AtomicBool, output: &mut dyn MidiSink, message: &[u8]) {
+    if !is_enabled.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = output.send(message) {
+        warn!("Failed to pass MIDI message thru: {}", e);
+    }
+}
@@ -1,7 +1,9 @@
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, ops::RangeInclusive, str::FromStr};
 
 use pitch_calc::*;
 use rand::prelude::*;
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
 
 pub const CHROMATIC_SCALE_NOTES: &[Letter] = &[
     Letter::C,
@@ -39,9 +41,17 @@ pub const MAJOR_PENTATONIC_SCALE_NOTES: &[Letter] =
     &[Letter::C, Letter::D, Letter::E, Letter::G, Letter::A];
 pub const MINOR_PENTATONIC_SCALE_NOTES: &[Letter] =
     &[Letter::C, Letter::Eb, Letter::F, Letter::G, Letter::Bb];
+pub const WHOLE_TONE_SCALE_NOTES: &[Letter] = &[
+    Letter::C,
+    Letter::D,
+    Letter::E,
+    Letter::Fsh,
+    Letter::Gsh,
+    Letter::Ash,
+];
 
-pub fn format_letter_octave(letter_octave: LetterOctave) -> String {
-    let letter_name = match letter_octave.letter() {
+pub fn format_letter(letter: Letter) -> &'static str {
+    match letter {
         Letter::C => "C",
         Letter::Csh => "C#",
         Letter::Db => "Db",
@@ -59,8 +69,201 @@ pub fn format_letter_octave(letter_octave: LetterOctave) -> String {
         Letter::Ash => "A#",
         Letter::Bb => "Bb",
         Letter::B => "B",
+    }
+}
+
+pub fn format_letter_octave(letter_octave: LetterOctave) -> String {
+    format!(
+        "{}{}",
+        format_letter(letter_octave.letter()),
+        letter_octave.octave()
+    )
+}
+
+// The frequency of a pitch in Hz, with A4 at 440 Hz, for output that plays frequencies
+// rather than MIDI note numbers
+pub fn letter_octave_to_hz(letter_octave: LetterOctave) -> f32 {
+    letter_octave.hz()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    InvalidLetter,
+    InvalidOctave,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ParseError::Empty => write!(f, "empty note name"),
+            ParseError::InvalidLetter => write!(f, "invalid note letter"),
+            ParseError::InvalidOctave => write!(f, "invalid octave"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse_letter_octave(s: &str) -> Result<LetterOctave, ParseError> {
+    let mut chars = s.chars();
+    let natural = chars.next().ok_or(ParseError::Empty)?;
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.chars().next() {
+        Some(c @ ('#' | 'b')) => (Some(c), &rest[1..]),
+        _ => (None, rest),
+    };
+    let letter = match (natural, accidental) {
+        ('C', None) => Letter::C,
+        ('C', Some('#')) => Letter::Csh,
+        ('D', Some('b')) => Letter::Db,
+        ('D', None) => Letter::D,
+        ('D', Some('#')) => Letter::Dsh,
+        ('E', Some('b')) => Letter::Eb,
+        ('E', None) => Letter::E,
+        ('F', None) => Letter::F,
+        ('F', Some('#')) => Letter::Fsh,
+        ('G', Some('b')) => Letter::Gb,
+        ('G', None) => Letter::G,
+        ('G', Some('#')) => Letter::Gsh,
+        ('A', Some('b')) => Letter::Ab,
+        ('A', None) => Letter::A,
+        ('A', Some('#')) => Letter::Ash,
+        ('B', Some('b')) => Letter::Bb,
+        ('B', None) => Letter::B,
+        _ => return Err(ParseError::InvalidLetter),
     };
-    format!("{}{}", letter_name, letter_octave.octave())
+    let octave = octave.parse().map_err(|_| ParseError::InvalidOctave)?;
+    Ok(LetterOctave(letter, octave))
+}
+
+// Parse whitespace separated note names, e.g. "C4 E4 G4 C5"
+pub fn parse_step_sequence(s: &str) -> Result<Vec<LetterOctave>, ParseError> {
+    let notes = s
+        .split_whitespace()
+        .map(parse_letter_octave)
+        .collect::<Result<Vec<_>, _>>()?;
+    if notes.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    Ok(notes)
+}
+
+pub fn format_step_sequence(notes: &[LetterOctave]) -> String {
+    notes
+        .iter()
+        .map(|note| format_letter_octave(*note))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Live position of a module, to resume it exactly where it left off. Composite modules
+// nest the states of their inputs.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum GeneratorState {
+    Stateless,
+    Counter(u32),
+    Position(usize),
+    Progression {
+        counter: u32,
+        chord: usize,
+    },
+    Random(RngState),
+    Drunk {
+        rng: RngState,
+        probability: f32,
+    },
+    Divider {
+        counter: u32,
+        input: Box<GeneratorState>,
+    },
+    Quantizer {
+        rng: RngState,
+        input: Box<GeneratorState>,
+    },
+    Scatter {
+        rng: RngState,
+        input: Box<GeneratorState>,
+    },
+    Adder {
+        left: Box<GeneratorState>,
+        right: Box<GeneratorState>,
+    },
+    Combinator {
+        left: Box<GeneratorState>,
+        right: Box<GeneratorState>,
+    },
+    Switch {
+        rng: RngState,
+        inputs: Vec<GeneratorState>,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RngState {
+    seed: u64,
+    draws: u64,
+}
+
+// Random number generator that keeps track of its seed and the number of words drawn,
+// so that it can be put back to any position by reseeding and jumping ahead
+pub struct SeededRng {
+    rng: Pcg32,
+    seed: u64,
+    draws: u64,
+}
+
+impl SeededRng {
+    pub fn seed_from_u64(seed: u64) -> SeededRng {
+        SeededRng {
+            rng: Pcg32::seed_from_u64(seed),
+            seed,
+            draws: 0,
+        }
+    }
+
+    pub fn from_entropy() -> SeededRng {
+        SeededRng::seed_from_u64(random())
+    }
+
+    pub fn state(&self) -> RngState {
+        RngState {
+            seed: self.seed,
+            draws: self.draws,
+        }
+    }
+
+    pub fn restore(&mut self, state: RngState) {
+        *self = SeededRng::seed_from_u64(state.seed);
+        self.rng.advance(state.draws);
+        self.draws = state.draws;
+    }
+}
+
+impl RngCore for SeededRng {
+    // Every draw goes through next_u32, so the count matches the steps of the generator
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        (high << 32) | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -81,299 +284,2614 @@ impl Trigger {
 
 pub trait TriggerModule: Send + Sync {
     fn tick(&mut self) -> Trigger;
+
+    // Whether the last tick was a step, rather than a tick in between the steps of a
+    // divided clock
+    fn is_step(&self) -> bool {
+        true
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Stateless
+    }
+
+    // States of other kinds of modules are ignored
+    fn restore(&mut self, _state: &GeneratorState) {}
+
+    // Go back to the start of the pattern, e.g. when playback is stopped. Random draws are
+    // not repeated, they carry on where they were.
+    fn reset(&mut self) {}
+
+    // Change the probability of the random draws in place, so that they carry on where they
+    // were instead of starting over in a rebuilt module. Modules without one ignore it.
+    fn set_probability(&mut self, _probability: f32) {}
 }
 
-pub struct RandomTriggerGenerator<R: Rng> {
-    rng: R,
+pub struct RandomTriggerGenerator {
+    rng: SeededRng,
     p: f32,
 }
 
-impl RandomTriggerGenerator<SmallRng> {
-    pub fn new(probability: f32) -> RandomTriggerGenerator<SmallRng> {
+impl RandomTriggerGenerator {
+    pub fn new(probability: f32, seed: u64) -> RandomTriggerGenerator {
         RandomTriggerGenerator {
-            rng: SmallRng::from_entropy(),
+            rng: SeededRng::seed_from_u64(seed),
             p: probability,
         }
     }
 }
 
-impl<R: Rng + Send + Sync> TriggerModule for RandomTriggerGenerator<R> {
+impl TriggerModule for RandomTriggerGenerator {
     fn tick(&mut self) -> Trigger {
         Trigger::from_bool(self.rng.gen_bool(self.p as f64))
     }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Random(self.rng.state())
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Random(rng) = state {
+            self.rng.restore(*rng);
+        }
+    }
+
+    fn set_probability(&mut self, probability: f32) {
+        self.p = probability;
+    }
 }
 
-pub struct ClockDivider {
-    factor: u32,
-    counter: u32,
-    input: Box<dyn TriggerModule>,
+pub struct DrunkTriggerGenerator {
+    rng: SeededRng,
+    p: f32,
+    restlessness: f32,
 }
 
-impl ClockDivider {
-    pub fn new(input: Box<dyn TriggerModule>, factor: u32) -> ClockDivider {
-        ClockDivider {
-            factor: factor,
-            counter: 0,
-            input: input,
+impl DrunkTriggerGenerator {
+    pub fn new(initial_probability: f32, restlessness: f32, seed: u64) -> DrunkTriggerGenerator {
+        DrunkTriggerGenerator {
+            rng: SeededRng::seed_from_u64(seed),
+            p: initial_probability.clamp(0.0, 1.0),
+            restlessness,
         }
     }
+
+    pub fn probability(&self) -> f32 {
+        self.p
+    }
 }
 
-impl TriggerModule for ClockDivider {
+impl TriggerModule for DrunkTriggerGenerator {
     fn tick(&mut self) -> Trigger {
-        let trigger = if self.counter % self.factor == 0 {
-            self.counter = 0;
-            self.input.tick()
+        // take a random step with the probability itself before rolling against it
+        let step = if self.rng.gen_bool(0.5) {
+            self.restlessness
         } else {
-            Trigger::Off
+            -self.restlessness
         };
-        self.counter += 1;
-        trigger
+        self.p = (self.p + step).clamp(0.0, 1.0);
+        Trigger::from_bool(self.rng.gen_bool(self.p as f64))
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Drunk {
+            rng: self.rng.state(),
+            probability: self.p,
+        }
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Drunk { rng, probability } = state {
+            self.rng.restore(*rng);
+            self.p = *probability;
+        }
+    }
+
+    // The probability wanders on from the new value
+    fn set_probability(&mut self, probability: f32) {
+        self.p = probability.clamp(0.0, 1.0);
     }
 }
 
-#[derive(PartialEq)]
-pub enum PitchGeneratorType {
-    Random,
-    RampUp,
-    Square,
+// Whether the clock divider ticks its input once per step, so that e.g. a gate counts
+// steps, or on every clock tick, keeping only the trigger of the tick the step lands on, so
+// that a gate counts clock ticks and a random walk wanders faster
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TriggerOrder {
+    DivideFirst,
+    TriggerFirst,
 }
 
-impl Display for PitchGeneratorType {
+impl Display for TriggerOrder {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
-            PitchGeneratorType::Random => write!(f, "Random"),
-            PitchGeneratorType::RampUp => write!(f, "Ramp"),
-            PitchGeneratorType::Square => write!(f, "Square"),
+            TriggerOrder::DivideFirst => write!(f, "Divide first"),
+            TriggerOrder::TriggerFirst => write!(f, "Trigger first"),
         }
     }
 }
 
-impl FromStr for PitchGeneratorType {
+impl FromStr for TriggerOrder {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "Random" => Ok(PitchGeneratorType::Random),
-            "Ramp" => Ok(PitchGeneratorType::RampUp),
-            "Square" => Ok(PitchGeneratorType::Square),
+            "Divide first" => Ok(TriggerOrder::DivideFirst),
+            "Trigger first" => Ok(TriggerOrder::TriggerFirst),
             _ => Err(()),
         }
     }
 }
 
-pub trait PitchModule: Send + Sync {
-    fn tick(&mut self) -> LetterOctave;
-}
-
-pub struct RandomPitchGenerator<R: Rng + Send + Sync> {
-    rng: R,
-    min: f32,
-    max: f32,
+pub struct ClockDivider {
+    factor: u32,
+    // Position within the division window the step lands on, e.g. for off-beat patterns
+    offset: u32,
+    order: TriggerOrder,
+    counter: u32,
+    input: Box<dyn TriggerModule>,
 }
 
-impl<R: Rng + Send + Sync> PitchModule for RandomPitchGenerator<R> {
-    fn tick(&mut self) -> LetterOctave {
-        if self.min != self.max {
-            let r: f32 = self.rng.gen_range(self.min..self.max);
-            Step(r).to_letter_octave()
-        } else {
-            Step(self.min).to_letter_octave()
+impl ClockDivider {
+    pub fn new(input: Box<dyn TriggerModule>, factor: u32, offset: u32) -> ClockDivider {
+        // a factor of 0 divides like 1, passing every tick through
+        let factor = factor.max(1);
+        ClockDivider {
+            factor,
+            offset: offset % factor,
+            order: TriggerOrder::DivideFirst,
+            counter: 0,
+            input,
         }
     }
-}
 
-impl RandomPitchGenerator<SmallRng> {
-    pub fn new(min: LetterOctave, max: LetterOctave) -> RandomPitchGenerator<SmallRng> {
-        RandomPitchGenerator {
-            rng: SmallRng::from_entropy(),
-            min: min.step(),
-            max: max.step(),
-        }
+    pub fn set_order(&mut self, order: TriggerOrder) {
+        self.order = order;
     }
 }
 
-pub struct RampPitchGenerator {
-    cycle_length: u32,
-    min: f32,
-    max: f32,
-    counter: u32,
-}
+impl TriggerModule for ClockDivider {
+    fn tick(&mut self) -> Trigger {
+        let is_step = self.counter == self.offset;
+        let trigger = match self.order {
+            TriggerOrder::DivideFirst if is_step => self.input.tick(),
+            TriggerOrder::DivideFirst => Trigger::Off,
+            TriggerOrder::TriggerFirst => {
+                let trigger = self.input.tick();
+                if is_step {
+                    trigger
+                } else {
+                    Trigger::Off
+                }
+            }
+        };
+        self.counter = (self.counter + 1) % self.factor;
+        trigger
+    }
 
-impl RampPitchGenerator {
-    pub fn new(cycle_length: u32, min: LetterOctave, max: LetterOctave) -> RampPitchGenerator {
-        RampPitchGenerator {
-            cycle_length,
-            min: min.step(),
-            max: max.step(),
-            counter: 0,
+    fn is_step(&self) -> bool {
+        self.counter == (self.offset + 1) % self.factor
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Divider {
+            counter: self.counter,
+            input: Box::new(self.input.state()),
         }
     }
-}
 
-impl PitchModule for RampPitchGenerator {
-    fn tick(&mut self) -> LetterOctave {
-        let slope = if self.cycle_length > 1 {
-            (self.max - self.min) / (self.cycle_length - 1) as f32
-        } else {
-            0.
-        };
-        let step = Step(self.min + slope * self.counter as f32);
-        let pitch = step.to_letter_octave();
-        if self.counter == self.cycle_length - 1 {
-            self.counter = 0;
-        } else {
-            self.counter += 1;
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Divider { counter, input } = state {
+            // older states counted up to the factor itself
+            self.counter = *counter % self.factor;
+            self.input.restore(input);
         }
-        pitch
+    }
+
+    fn reset(&mut self) {
+        self.counter = 0;
+        self.input.reset();
+    }
+
+    fn set_probability(&mut self, probability: f32) {
+        self.input.set_probability(probability);
     }
 }
 
-pub struct SquarePitchGenerator {
-    cycle_length: u32,
-    min: f32,
-    max: f32,
+// Deterministic rest pattern, e.g. play 3 steps and rest 1
+pub struct GateRatioTriggerGenerator {
+    on: u32,
+    off: u32,
     counter: u32,
 }
 
-impl SquarePitchGenerator {
-    pub fn new(cycle_length: u32, min: LetterOctave, max: LetterOctave) -> SquarePitchGenerator {
-        SquarePitchGenerator {
-            cycle_length,
-            min: min.step(),
-            max: max.step(),
+impl GateRatioTriggerGenerator {
+    pub fn new(on: u32, off: u32) -> GateRatioTriggerGenerator {
+        GateRatioTriggerGenerator {
+            on,
+            off,
             counter: 0,
         }
     }
 }
 
-impl PitchModule for SquarePitchGenerator {
-    fn tick(&mut self) -> LetterOctave {
-        self.counter += 1;
-        let pitch = if self.counter <= self.cycle_length / 2 {
-            Step(self.min).to_letter_octave()
-        } else {
-            if self.counter == self.cycle_length {
-                self.counter = 0;
-            }
-            Step(self.max).to_letter_octave()
-        };
-        pitch
+impl TriggerModule for GateRatioTriggerGenerator {
+    fn tick(&mut self) -> Trigger {
+        let trigger = Trigger::from_bool(self.counter < self.on);
+        // a pattern without steps stays off
+        self.counter = (self.counter + 1) % (self.on + self.off).max(1);
+        trigger
     }
-}
-
-pub struct PitchQuantizer {
-    input: Box<dyn PitchModule>,
-    enabled_notes: Vec<Letter>,
-}
 
-impl PitchQuantizer {
-    pub fn new(input: Box<dyn PitchModule>, enabled_notes: Vec<Letter>) -> PitchQuantizer {
-        PitchQuantizer {
-            input,
-            enabled_notes,
-        }
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Counter(self.counter)
     }
-}
 
-impl PitchModule for PitchQuantizer {
-    fn tick(&mut self) -> LetterOctave {
-        let unquantized = self.input.tick();
-        self.enabled_notes.sort();
-        for enabled_note in &self.enabled_notes {
-            if *enabled_note == unquantized.letter() {
-                return unquantized;
-            } else if *enabled_note > unquantized.letter() {
-                // quantize up to the next enabled note
-                let quantized = LetterOctave(enabled_note.clone(), unquantized.octave());
-                return quantized;
-            }
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Counter(counter) = state {
+            self.counter = *counter;
         }
+    }
 
-        // handle case when the unquantized note is above the highest enabled note by wrapping around
-        let quantized = LetterOctave(self.enabled_notes[0], unquantized.octave() + 1);
-        return quantized;
+    fn reset(&mut self) {
+        self.counter = 0;
     }
 }
 
-pub struct PitchAdder {
-    left: Box<dyn PitchModule>,
-    right: Box<dyn PitchModule>,
+// Fires only when both inputs fire, e.g. to intersect a fixed pattern with a random one.
+// Both inputs are ticked on every step to keep their counters in sync.
+pub struct AndTrigger {
+    left: Box<dyn TriggerModule>,
+    right: Box<dyn TriggerModule>,
 }
 
-impl PitchAdder {
-    pub fn new(left: Box<dyn PitchModule>, right: Box<dyn PitchModule>) -> PitchAdder {
-        PitchAdder { left, right }
+impl AndTrigger {
+    pub fn new(left: Box<dyn TriggerModule>, right: Box<dyn TriggerModule>) -> AndTrigger {
+        AndTrigger { left, right }
     }
 }
 
-impl PitchModule for PitchAdder {
-    fn tick(&mut self) -> LetterOctave {
-        let right_result = self.right.tick();
-        let left_result = self.left.tick();
-        let result = left_result + right_result;
-        result
+impl TriggerModule for AndTrigger {
+    fn tick(&mut self) -> Trigger {
+        let left = self.left.tick();
+        let right = self.right.tick();
+        Trigger::from_bool(left == Trigger::On && right == Trigger::On)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn state(&self) -> GeneratorState {
+        combinator_state(self.left.as_ref(), self.right.as_ref())
+    }
 
-    #[test]
-    fn square_pitch_generator_returns_symmetrical_output_when_length_is_even() {
-        let length = 4;
-        let min = Step(0.0).to_letter_octave();
-        let max = Step(10.0).to_letter_octave();
-        let mut generator = SquarePitchGenerator::new(length, min, max);
+    fn restore(&mut self, state: &GeneratorState) {
+        restore_combinator(self.left.as_mut(), self.right.as_mut(), state);
+    }
 
-        let mut actual: Vec<LetterOctave> = Vec::new();
-        for _ in 0..length * 2 {
-            actual.push(generator.tick());
-        }
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+    }
 
-        assert_eq!(actual, vec![min, min, max, max, min, min, max, max]);
+    fn set_probability(&mut self, probability: f32) {
+        self.left.set_probability(probability);
+        self.right.set_probability(probability);
     }
+}
 
-    #[test]
-    fn square_pitch_generator_returns_asymmetrical_output_when_length_is_odd() {
-        let length = 3;
-        let min = LetterOctave(Letter::C, 1);
-        let max = LetterOctave(Letter::C, 2);
-        let mut generator = SquarePitchGenerator::new(length, min, max);
+// Fires when either input fires. Both inputs are ticked on every step, even when the
+// left one already fired.
+pub struct OrTrigger {
+    left: Box<dyn TriggerModule>,
+    right: Box<dyn TriggerModule>,
+}
 
-        let mut actual: Vec<LetterOctave> = Vec::new();
-        for _ in 0..length * 2 {
-            actual.push(generator.tick());
-        }
+impl OrTrigger {
+    pub fn new(left: Box<dyn TriggerModule>, right: Box<dyn TriggerModule>) -> OrTrigger {
+        OrTrigger { left, right }
+    }
+}
 
-        assert_eq!(actual, vec![min, max, max, min, max, max]);
+impl TriggerModule for OrTrigger {
+    fn tick(&mut self) -> Trigger {
+        let left = self.left.tick();
+        let right = self.right.tick();
+        Trigger::from_bool(left == Trigger::On || right == Trigger::On)
     }
 
-    #[test]
+    fn state(&self) -> GeneratorState {
+        combinator_state(self.left.as_ref(), self.right.as_ref())
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        restore_combinator(self.left.as_mut(), self.right.as_mut(), state);
+    }
+
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+    }
+
+    fn set_probability(&mut self, probability: f32) {
+        self.left.set_probability(probability);
+        self.right.set_probability(probability);
+    }
+}
+
+fn combinator_state(left: &dyn TriggerModule, right: &dyn TriggerModule) -> GeneratorState {
+    GeneratorState::Combinator {
+        left: Box::new(left.state()),
+        right: Box::new(right.state()),
+    }
+}
+
+fn restore_combinator(
+    left: &mut dyn TriggerModule,
+    right: &mut dyn TriggerModule,
+    state: &GeneratorState,
+) {
+    if let GeneratorState::Combinator {
+        left: left_state,
+        right: right_state,
+    } = state
+    {
+        left.restore(left_state);
+        right.restore(right_state);
+    }
+}
+
+// How the divided clock steps are turned into notes: by chance, by a fixed rest pattern,
+// by chance within the rest pattern, or by the rest pattern with extra notes by chance
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TriggerType {
+    Random,
+    Gate,
+    GatedRandom,
+    GateOrRandom,
+}
+
+impl Display for TriggerType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            TriggerType::Random => write!(f, "Random"),
+            TriggerType::Gate => write!(f, "Gate"),
+            TriggerType::GatedRandom => write!(f, "Gate + Random"),
+            TriggerType::GateOrRandom => write!(f, "Gate or Random"),
+        }
+    }
+}
+
+impl FromStr for TriggerType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Random" => Ok(TriggerType::Random),
+            "Gate" => Ok(TriggerType::Gate),
+            "Gate + Random" => Ok(TriggerType::GatedRandom),
+            "Gate or Random" => Ok(TriggerType::GateOrRandom),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub enum PitchGeneratorType {
+    Random,
+    RampUp,
+    Square,
+    Steps,
+    Progression,
+    Degrees,
+}
+
+impl Display for PitchGeneratorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            PitchGeneratorType::Random => write!(f, "Random"),
+            PitchGeneratorType::RampUp => write!(f, "Ramp"),
+            PitchGeneratorType::Square => write!(f, "Square"),
+            PitchGeneratorType::Steps => write!(f, "Steps"),
+            PitchGeneratorType::Progression => write!(f, "Progression"),
+            PitchGeneratorType::Degrees => write!(f, "Degrees"),
+        }
+    }
+}
+
+impl FromStr for PitchGeneratorType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Random" => Ok(PitchGeneratorType::Random),
+            "Ramp" => Ok(PitchGeneratorType::RampUp),
+            "Square" => Ok(PitchGeneratorType::Square),
+            "Steps" => Ok(PitchGeneratorType::Steps),
+            "Progression" => Ok(PitchGeneratorType::Progression),
+            "Degrees" => Ok(PitchGeneratorType::Degrees),
+            _ => Err(()),
+        }
+    }
+}
+
+// The melody or transposition lane of the pitch generator
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Lane {
+    Melody,
+    Transposition,
+}
+
+// A parameter of the generators of a lane, changed in place
+#[derive(Clone, Debug)]
+pub enum LaneParameter {
+    Range(LetterOctave, LetterOctave),
+    CycleLength(u32),
+}
+
+// A parameter of the pitch generator, changed in place
+#[derive(Clone, Debug)]
+pub enum PitchParameter {
+    Lane(Lane, LaneParameter),
+    // Notes the shared quantizer snaps the sum of the lanes to
+    Scale(Vec<Letter>),
+    // Range the shared quantizer keeps the sum of the lanes in
+    PitchRange(Option<RangeInclusive<Step>>),
+}
+
+pub trait PitchModule: Send + Sync {
+    fn tick(&mut self) -> LetterOctave;
+
+    // The same as tick, but keeping the fraction of a semitone of the modules computing
+    // in-between pitches, for microtonal output. Rounding it gives the pitch of tick.
+    fn tick_step(&mut self) -> f32 {
+        self.tick().step()
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Stateless
+    }
+
+    // States of other kinds of modules are ignored
+    fn restore(&mut self, _state: &GeneratorState) {}
+
+    // Go back to the start of the pattern, e.g. when playback is stopped. Random draws are
+    // not repeated, they carry on where they were.
+    fn reset(&mut self) {}
+
+    // Change a parameter in place, so that the counters and random draws carry on where they
+    // were instead of starting over in a rebuilt module. The adders hand the parameters of a
+    // lane down to it, other modules pass them on to their input.
+    fn set_parameter(&mut self, _parameter: &PitchParameter) {}
+
+    // Change a parameter of the lane the module is in. Modules without it ignore it.
+    fn set_lane_parameter(&mut self, _parameter: &LaneParameter) {}
+}
+
+// Outputs the same pitch on every step, e.g. the 0 offset of a disabled transposition lane
+pub struct ConstantPitchGenerator {
+    pitch: LetterOctave,
+}
+
+impl ConstantPitchGenerator {
+    pub fn new(pitch: LetterOctave) -> ConstantPitchGenerator {
+        ConstantPitchGenerator { pitch }
+    }
+}
+
+impl PitchModule for ConstantPitchGenerator {
+    fn tick(&mut self) -> LetterOctave {
+        self.pitch
+    }
+}
+
+pub struct RandomPitchGenerator {
+    rng: SeededRng,
+    min: f32,
+    max: f32,
+}
+
+impl PitchModule for RandomPitchGenerator {
+    fn tick(&mut self) -> LetterOctave {
+        Step(self.tick_step()).to_letter_octave()
+    }
+
+    fn tick_step(&mut self) -> f32 {
+        if self.min != self.max {
+            self.rng.gen_range(self.min..self.max)
+        } else {
+            self.min
+        }
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Random(self.rng.state())
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Random(rng) = state {
+            self.rng.restore(*rng);
+        }
+    }
+
+    fn set_lane_parameter(&mut self, parameter: &LaneParameter) {
+        if let LaneParameter::Range(min, max) = parameter {
+            self.set_range(*min, *max);
+        }
+    }
+}
+
+impl RandomPitchGenerator {
+    pub fn new(min: LetterOctave, max: LetterOctave, seed: u64) -> RandomPitchGenerator {
+        let mut generator = RandomPitchGenerator {
+            rng: SeededRng::seed_from_u64(seed),
+            min: 0.0,
+            max: 0.0,
+        };
+        generator.set_range(min, max);
+        generator
+    }
+
+    pub fn set_range(&mut self, min: LetterOctave, max: LetterOctave) {
+        // Swap an inverted range instead of letting gen_range panic
+        let (min, max) = if min.step() <= max.step() {
+            (min.step(), max.step())
+        } else {
+            (max.step(), min.step())
+        };
+        self.min = min;
+        self.max = max;
+    }
+}
+
+// Picks notes straight from the degrees of a scale, each as often as its weight says, in a
+// random octave of the range. Random pitches snapped to the scale instead favour the degrees
+// above the widest gaps of the scale, and cannot be weighted.
+pub struct WeightedScalePitchGenerator {
+    notes: Vec<Letter>,
+    weights: Vec<f32>,
+    octaves: RangeInclusive<Octave>,
+    rng: SeededRng,
+}
+
+impl WeightedScalePitchGenerator {
+    // One weight per scale degree, starting at the first note of the scale. Negative and
+    // missing weights count as 0. Without any weight the first degree is always played.
+    pub fn new(
+        scale: &[Letter],
+        octaves: RangeInclusive<Octave>,
+        weights: &[f32],
+        seed: u64,
+    ) -> WeightedScalePitchGenerator {
+        assert!(!scale.is_empty(), "a scale needs at least one note");
+        let mut generator = WeightedScalePitchGenerator {
+            notes: scale.to_vec(),
+            weights: (0..scale.len())
+                .map(|degree| weights.get(degree).copied().unwrap_or(0.0))
+                .collect(),
+            octaves: 0..=0,
+            rng: SeededRng::seed_from_u64(seed),
+        };
+        generator.set_octaves(octaves);
+        generator
+    }
+
+    pub fn set_octaves(&mut self, octaves: RangeInclusive<Octave>) {
+        // Swap an inverted range instead of letting gen_range panic
+        self.octaves = if octaves.start() <= octaves.end() {
+            octaves
+        } else {
+            *octaves.end()..=*octaves.start()
+        };
+    }
+}
+
+impl PitchModule for WeightedScalePitchGenerator {
+    fn tick(&mut self) -> LetterOctave {
+        let degree = weighted_index(&mut self.rng, &self.weights);
+        let octave = self.rng.gen_range(self.octaves.clone());
+        LetterOctave(self.notes[degree], octave)
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Random(self.rng.state())
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Random(rng) = state {
+            self.rng.restore(*rng);
+        }
+    }
+
+    // The notes are played in the octaves of the range
+    fn set_lane_parameter(&mut self, parameter: &LaneParameter) {
+        if let LaneParameter::Range(min, max) = parameter {
+            self.set_octaves(min.1..=max.1);
+        }
+    }
+}
+
+// Index of a weight picked at random, each with a chance proportional to its weight.
+// Negative weights count as 0. Without any weight the first index is picked.
+fn weighted_index(rng: &mut SeededRng, weights: &[f32]) -> usize {
+    let total: f32 = weights.iter().map(|weight| weight.max(0.0)).sum();
+    if total <= 0.0 {
+        return 0;
+    }
+    let mut r = rng.gen_range(0.0..total);
+    for (index, weight) in weights.iter().enumerate() {
+        let weight = weight.max(0.0);
+        if r < weight {
+            return index;
+        }
+        r -= weight;
+    }
+    // rounding can leave r just past the last weight
+    weights.len() - 1
+}
+
+// What the ramp does at the end of a cycle: jump back to the minimum (sawtooth), or turn
+// around and ramp back down (ping-pong), taking twice as long to return to the minimum
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RampMode {
+    Sawtooth,
+    PingPong,
+}
+
+impl Display for RampMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            RampMode::Sawtooth => write!(f, "Sawtooth"),
+            RampMode::PingPong => write!(f, "Ping-pong"),
+        }
+    }
+}
+
+impl FromStr for RampMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Sawtooth" => Ok(RampMode::Sawtooth),
+            "Ping-pong" => Ok(RampMode::PingPong),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct RampPitchGenerator {
+    cycle_length: u32,
+    min: f32,
+    max: f32,
+    mode: RampMode,
+    // Step of the cycle the ramp starts at
+    phase: u32,
+    counter: u32,
+}
+
+impl RampPitchGenerator {
+    // The phase is the step of the cycle to start at, so that lanes of the same cycle length
+    // do not move in lockstep
+    pub fn new(
+        cycle_length: u32,
+        phase: u32,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> RampPitchGenerator {
+        // a cycle of 0 steps, e.g. from a preset, plays like one of a single step
+        let cycle_length = cycle_length.max(1);
+        RampPitchGenerator {
+            cycle_length,
+            min: min.step(),
+            max: max.step(),
+            mode: RampMode::Sawtooth,
+            phase: phase % cycle_length,
+            counter: phase % cycle_length,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: RampMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_range(&mut self, min: LetterOctave, max: LetterOctave) {
+        self.min = min.step();
+        self.max = max.step();
+    }
+
+    // The ramp carries on from its step of the cycle, or starts over once it is past the end
+    pub fn set_cycle_length(&mut self, cycle_length: u32) {
+        self.cycle_length = cycle_length.max(1);
+        self.phase %= self.cycle_length;
+        self.counter %= self.period();
+    }
+
+    // Ticks until the ramp is back at the minimum. The ping-pong ramp does not repeat the
+    // endpoints on the way back.
+    fn period(&self) -> u32 {
+        match self.mode {
+            RampMode::Sawtooth => self.cycle_length,
+            RampMode::PingPong => (2 * (self.cycle_length - 1)).max(1),
+        }
+    }
+}
+
+impl PitchModule for RampPitchGenerator {
+    fn tick(&mut self) -> LetterOctave {
+        Step(self.tick_step()).to_letter_octave()
+    }
+
+    fn tick_step(&mut self) -> f32 {
+        let slope = if self.cycle_length > 1 {
+            (self.max - self.min) / (self.cycle_length - 1) as f32
+        } else {
+            0.
+        };
+        // a counter restored from the other mode may be past the end of the period
+        let period = self.period();
+        let counter = self.counter % period;
+        let position = if counter < self.cycle_length {
+            counter
+        } else {
+            period - counter
+        };
+        self.counter = (counter + 1) % period;
+        self.min + slope * position as f32
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Counter(self.counter)
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Counter(counter) = state {
+            self.counter = *counter;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.counter = self.phase;
+    }
+
+    fn set_lane_parameter(&mut self, parameter: &LaneParameter) {
+        match parameter {
+            LaneParameter::Range(min, max) => self.set_range(*min, *max),
+            LaneParameter::CycleLength(cycle_length) => self.set_cycle_length(*cycle_length),
+        }
+    }
+}
+
+pub struct SquarePitchGenerator {
+    cycle_length: u32,
+    min: f32,
+    max: f32,
+    // Semitones the high value is above the low one, instead of the maximum
+    interval: Option<i32>,
+    phase: u32,
+    counter: u32,
+}
+
+impl SquarePitchGenerator {
+    // The phase is the step of the cycle to start at, like for the ramp
+    pub fn new(
+        cycle_length: u32,
+        phase: u32,
+        min: LetterOctave,
+        max: LetterOctave,
+    ) -> SquarePitchGenerator {
+        SquarePitchGenerator {
+            cycle_length,
+            min: min.step(),
+            max: max.step(),
+            interval: None,
+            phase: phase % cycle_length.max(1),
+            counter: phase % cycle_length.max(1),
+        }
+    }
+
+    // Jump by a fixed interval above the minimum, e.g. 12 for octaves, whatever the range
+    pub fn set_interval(&mut self, interval: Option<i32>) {
+        self.interval = interval;
+    }
+
+    pub fn set_range(&mut self, min: LetterOctave, max: LetterOctave) {
+        self.min = min.step();
+        self.max = max.step();
+    }
+
+    // Like the ramp, the square carries on from its step of the cycle
+    pub fn set_cycle_length(&mut self, cycle_length: u32) {
+        self.cycle_length = cycle_length;
+        self.phase %= cycle_length.max(1);
+        self.counter %= cycle_length.max(1);
+    }
+
+    fn high(&self) -> f32 {
+        match self.interval {
+            Some(interval) => self.min + interval as f32,
+            None => self.max,
+        }
+    }
+}
+
+impl PitchModule for SquarePitchGenerator {
+    fn tick(&mut self) -> LetterOctave {
+        self.counter += 1;
+        let pitch = if self.counter <= self.cycle_length / 2 {
+            Step(self.min).to_letter_octave()
+        } else {
+            if self.counter == self.cycle_length {
+                self.counter = 0;
+            }
+            Step(self.high()).to_letter_octave()
+        };
+        pitch
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Counter(self.counter)
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        // a counter saved with a longer cycle would otherwise never wrap around
+        if let GeneratorState::Counter(counter) = state {
+            self.counter = *counter % self.cycle_length.max(1);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.counter = self.phase;
+    }
+
+    fn set_lane_parameter(&mut self, parameter: &LaneParameter) {
+        match parameter {
+            LaneParameter::Range(min, max) => self.set_range(*min, *max),
+            LaneParameter::CycleLength(cycle_length) => self.set_cycle_length(*cycle_length),
+        }
+    }
+}
+
+// Plays back a fixed sequence of notes in a loop, like a row of a step sequencer
+pub struct StepSequencePitchGenerator {
+    notes: Vec<LetterOctave>,
+    position: usize,
+}
+
+impl StepSequencePitchGenerator {
+    pub fn new(notes: Vec<LetterOctave>) -> StepSequencePitchGenerator {
+        assert!(!notes.is_empty(), "a step sequence needs at least one note");
+        StepSequencePitchGenerator { notes, position: 0 }
+    }
+}
+
+impl PitchModule for StepSequencePitchGenerator {
+    fn tick(&mut self) -> LetterOctave {
+        let pitch = self.notes[self.position];
+        self.position = (self.position + 1) % self.notes.len();
+        pitch
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Position(self.position)
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        // the sequence may have been edited to fewer notes since
+        if let GeneratorState::Position(position) = state {
+            self.position = position % self.notes.len();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+    }
+}
+
+// Walks a chord progression given as scale degrees (0 is the tonic), outputting the
+// root of the current chord as a transposition in semitones, e.g. I-V-vi-IV is [0, 4, 5, 3]
+pub struct ProgressionPitchGenerator {
+    roots: Vec<f32>,
+    // Pitch classes (0 is C) of the root, third and fifth of each chord, stacked in the scale
+    chord_tones: Vec<[i32; 3]>,
+    steps_per_chord: u32,
+    counter: u32,
+    chord: usize,
+}
+
+impl ProgressionPitchGenerator {
+    pub fn new(
+        degrees: &[i32],
+        steps_per_chord: u32,
+        scale: &[Letter],
+    ) -> ProgressionPitchGenerator {
+        assert!(
+            !degrees.is_empty(),
+            "a progression needs at least one chord"
+        );
+        let tonic = LetterOctave(Letter::C, 0).step();
+        let note = |degree: i32| scale[degree.rem_euclid(scale.len() as i32) as usize];
+        let roots = degrees
+            .iter()
+            .map(|degree| {
+                let octave = degree.div_euclid(scale.len() as i32);
+                LetterOctave(note(*degree), 0).step() - tonic + (12 * octave) as f32
+            })
+            .collect();
+        let chord_tones = degrees
+            .iter()
+            .map(|degree| {
+                [0, 2, 4]
+                    .map(|third| (LetterOctave(note(degree + third), 0).step() - tonic) as i32 % 12)
+            })
+            .collect();
+        ProgressionPitchGenerator {
+            roots,
+            chord_tones,
+            steps_per_chord: steps_per_chord.max(1),
+            counter: 0,
+            chord: 0,
+        }
+    }
+
+    // The current chord lasts for the new number of steps, counting the ones it has played
+    pub fn set_steps_per_chord(&mut self, steps_per_chord: u32) {
+        self.steps_per_chord = steps_per_chord.max(1);
+        self.counter %= self.steps_per_chord;
+    }
+}
+
+impl PitchModule for ProgressionPitchGenerator {
+    fn tick(&mut self) -> LetterOctave {
+        let pitch = Step(self.roots[self.chord]).to_letter_octave();
+        self.counter += 1;
+        if self.counter == self.steps_per_chord {
+            self.counter = 0;
+            self.chord = (self.chord + 1) % self.roots.len();
+        }
+        pitch
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Progression {
+            counter: self.counter,
+            chord: self.chord,
+        }
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Progression { counter, chord } = state {
+            self.counter = *counter;
+            self.chord = chord % self.roots.len();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.counter = 0;
+        self.chord = 0;
+    }
+
+    // The cycle length sets how many steps each chord lasts
+    fn set_lane_parameter(&mut self, parameter: &LaneParameter) {
+        if let LaneParameter::CycleLength(steps_per_chord) = parameter {
+            self.set_steps_per_chord(*steps_per_chord);
+        }
+    }
+}
+
+// Adds the chord roots of a progression to the melody like a PitchAdder, then snaps the sum
+// to the nearest tone of the current chord, so the melody always lands on the root, third
+// or fifth. Ties are broken upwards.
+pub struct ChordTonePitchAdder {
+    melody: Box<dyn PitchModule>,
+    progression: ProgressionPitchGenerator,
+}
+
+impl ChordTonePitchAdder {
+    pub fn new(
+        melody: Box<dyn PitchModule>,
+        progression: ProgressionPitchGenerator,
+    ) -> ChordTonePitchAdder {
+        ChordTonePitchAdder {
+            melody,
+            progression,
+        }
+    }
+}
+
+impl PitchModule for ChordTonePitchAdder {
+    fn tick(&mut self) -> LetterOctave {
+        // the chord the progression is about to output
+        let chord_tones = self.progression.chord_tones[self.progression.chord];
+        let sum = self.melody.tick() + self.progression.tick();
+        let tonic = LetterOctave(Letter::C, 0).step() as i32;
+        let step = sum.step().round() as i32;
+        // a chord tone is never more than a fifth away
+        let snapped = (0..=6)
+            .flat_map(|distance| [step + distance, step - distance])
+            .find(|candidate| chord_tones.contains(&(candidate - tonic).rem_euclid(12)))
+            .unwrap_or(step);
+        Step(snapped as f32).to_letter_octave()
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Adder {
+            left: Box::new(self.melody.state()),
+            right: Box::new(self.progression.state()),
+        }
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Adder { left, right } = state {
+            self.melody.restore(left);
+            self.progression.restore(right);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.melody.reset();
+        self.progression.reset();
+    }
+
+    fn set_parameter(&mut self, parameter: &PitchParameter) {
+        match parameter {
+            PitchParameter::Lane(Lane::Melody, parameter) => {
+                self.melody.set_lane_parameter(parameter)
+            }
+            PitchParameter::Lane(Lane::Transposition, parameter) => {
+                self.progression.set_lane_parameter(parameter)
+            }
+            _ => (),
+        }
+    }
+}
+
+// Which enabled note an off-scale note snaps to. Nearest breaks ties upwards.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum QuantizeDirection {
+    Up,
+    Down,
+    Nearest,
+}
+
+impl Display for QuantizeDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            QuantizeDirection::Up => write!(f, "Up"),
+            QuantizeDirection::Down => write!(f, "Down"),
+            QuantizeDirection::Nearest => write!(f, "Nearest"),
+        }
+    }
+}
+
+impl FromStr for QuantizeDirection {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Up" => Ok(QuantizeDirection::Up),
+            "Down" => Ok(QuantizeDirection::Down),
+            "Nearest" => Ok(QuantizeDirection::Nearest),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct PitchQuantizer {
+    input: Box<dyn PitchModule>,
+    enabled_notes: Vec<Letter>,
+    direction: QuantizeDirection,
+    strength: f32,
+    rng: SeededRng,
+    allowed_octaves: RangeInclusive<i32>,
+    // Only notes in this range are quantized, the others pass through chromatic
+    quantize_range: Option<RangeInclusive<Step>>,
+    // Range the input is generated in; a note quantized out of it is snapped the other way
+    pitch_range: Option<RangeInclusive<Step>>,
+    // A bypassed quantizer only folds the octaves, e.g. of notes already snapped to chord tones
+    bypass: bool,
+}
+
+impl PitchQuantizer {
+    pub fn new(input: Box<dyn PitchModule>, enabled_notes: Vec<Letter>) -> PitchQuantizer {
+        PitchQuantizer::with_strength(input, enabled_notes, 1.0, random())
+    }
+
+    pub fn with_strength(
+        input: Box<dyn PitchModule>,
+        enabled_notes: Vec<Letter>,
+        strength: f32,
+        seed: u64,
+    ) -> PitchQuantizer {
+        PitchQuantizer {
+            input,
+            enabled_notes,
+            direction: QuantizeDirection::Up,
+            strength: strength.clamp(0.0, 1.0),
+            rng: SeededRng::seed_from_u64(seed),
+            allowed_octaves: i32::MIN..=i32::MAX,
+            quantize_range: None,
+            pitch_range: None,
+            bypass: false,
+        }
+    }
+
+    pub fn set_allowed_octaves(&mut self, allowed_octaves: RangeInclusive<i32>) {
+        // Swap an inverted range, which would leave no octave to fold into
+        let (start, end) = allowed_octaves.into_inner();
+        self.allowed_octaves = start.min(end)..=start.max(end);
+    }
+
+    pub fn set_quantize_range(&mut self, quantize_range: Option<RangeInclusive<Step>>) {
+        self.quantize_range = quantize_range;
+    }
+
+    pub fn set_pitch_range(&mut self, pitch_range: Option<RangeInclusive<Step>>) {
+        self.pitch_range = pitch_range;
+    }
+
+    pub fn set_direction(&mut self, direction: QuantizeDirection) {
+        self.direction = direction;
+    }
+
+    pub fn set_bypass(&mut self, bypass: bool) {
+        self.bypass = bypass;
+    }
+
+    fn is_quantized(&mut self, step: f32) -> bool {
+        if self.bypass {
+            return false;
+        }
+        // the strength is only rolled for the notes in the quantize range
+        let in_range = self
+            .quantize_range
+            .as_ref()
+            .is_none_or(|range| range.contains(&Step(step)));
+        // let some notes through unquantized as chromatic passing tones
+        in_range && (self.strength >= 1.0 || self.rng.gen_bool(self.strength as f64))
+    }
+
+    fn fold_octave(&self, pitch: LetterOctave) -> LetterOctave {
+        if self.allowed_octaves.contains(&pitch.octave()) {
+            return pitch;
+        }
+        // wrap the octave around so that it lands inside the allowed range
+        let lowest = *self.allowed_octaves.start();
+        let octave_count = self.allowed_octaves.end() - lowest + 1;
+        let octave = lowest + (pitch.octave() - lowest).rem_euclid(octave_count);
+        LetterOctave(pitch.letter(), octave)
+    }
+
+    fn quantize(&mut self, unquantized: LetterOctave) -> LetterOctave {
+        self.enabled_notes.sort();
+        let quantized = match self.direction {
+            QuantizeDirection::Up => self.quantize_up(unquantized),
+            QuantizeDirection::Down => self.quantize_down(unquantized),
+            QuantizeDirection::Nearest => {
+                let up = self.quantize_up(unquantized);
+                let down = self.quantize_down(unquantized);
+                if up.step() - unquantized.step() <= unquantized.step() - down.step() {
+                    up
+                } else {
+                    down
+                }
+            }
+        };
+        self.keep_in_pitch_range(unquantized, quantized)
+    }
+
+    // Snap a note quantized past an end of the pitch range, e.g. wrapped around to the
+    // octave above, to the enabled note on the other side of the input instead, as long as
+    // that one is in the range
+    fn keep_in_pitch_range(
+        &self,
+        unquantized: LetterOctave,
+        quantized: LetterOctave,
+    ) -> LetterOctave {
+        let range = match &self.pitch_range {
+            Some(range) => range,
+            None => return quantized,
+        };
+        let in_range = |pitch: LetterOctave| range.contains(&pitch.to_step());
+        if in_range(quantized) {
+            return quantized;
+        }
+        let other = if quantized.step() > unquantized.step() {
+            self.quantize_down(unquantized)
+        } else {
+            self.quantize_up(unquantized)
+        };
+        if in_range(other) {
+            other
+        } else {
+            quantized
+        }
+    }
+
+    fn quantize_down(&self, unquantized: LetterOctave) -> LetterOctave {
+        // compare semitones, as the scales spell some notes as flats and the input as sharps
+        let semitone = |letter: Letter| LetterOctave(letter, 0).step();
+        for enabled_note in self.enabled_notes.iter().rev() {
+            if semitone(*enabled_note) <= semitone(unquantized.letter()) {
+                return LetterOctave(*enabled_note, unquantized.octave());
+            }
+        }
+
+        // handle case when the unquantized note is below the lowest enabled note by wrapping around
+        LetterOctave(
+            *self.enabled_notes.last().unwrap(),
+            unquantized.octave() - 1,
+        )
+    }
+
+    fn quantize_up(&self, unquantized: LetterOctave) -> LetterOctave {
+        // compare semitones, as the scales spell some notes as flats and the input as sharps
+        let semitone = |letter: Letter| LetterOctave(letter, 0).step();
+        for enabled_note in &self.enabled_notes {
+            if semitone(*enabled_note) >= semitone(unquantized.letter()) {
+                return LetterOctave(*enabled_note, unquantized.octave());
+            }
+        }
+
+        // handle case when the unquantized note is above the highest enabled note by wrapping around
+        LetterOctave(self.enabled_notes[0], unquantized.octave() + 1)
+    }
+}
+
+impl PitchModule for PitchQuantizer {
+    fn tick(&mut self) -> LetterOctave {
+        let unquantized = self.input.tick();
+        let pitch = if self.is_quantized(unquantized.step()) {
+            self.quantize(unquantized)
+        } else {
+            unquantized
+        };
+        self.fold_octave(pitch)
+    }
+
+    fn tick_step(&mut self) -> f32 {
+        let unquantized = self.input.tick_step();
+        let nearest = Step(unquantized).to_letter_octave();
+        if self.is_quantized(unquantized) {
+            let quantized = self.quantize(nearest);
+            self.fold_octave(quantized).step()
+        } else {
+            // the passing tones keep their fraction of a semitone
+            self.fold_octave(nearest).step() + (unquantized - nearest.step())
+        }
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Quantizer {
+            rng: self.rng.state(),
+            input: Box::new(self.input.state()),
+        }
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Quantizer { rng, input } = state {
+            self.rng.restore(*rng);
+            self.input.restore(input);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.input.reset();
+    }
+
+    fn set_parameter(&mut self, parameter: &PitchParameter) {
+        match parameter {
+            PitchParameter::Scale(enabled_notes) => self.enabled_notes = enabled_notes.clone(),
+            PitchParameter::PitchRange(pitch_range) => self.pitch_range = pitch_range.clone(),
+            parameter => self.input.set_parameter(parameter),
+        }
+    }
+
+    // A quantizer of a lane keeps the lane in its range, unless the lane plays its own notes
+    fn set_lane_parameter(&mut self, parameter: &LaneParameter) {
+        if let (LaneParameter::Range(min, max), Some(_)) = (parameter, &self.pitch_range) {
+            let (low, high) = if min.step() <= max.step() {
+                (min, max)
+            } else {
+                (max, min)
+            };
+            self.pitch_range = Some(low.to_step()..=high.to_step());
+        }
+        self.input.set_lane_parameter(parameter);
+    }
+}
+
+// Moves a note of its input up or down by up to the set number of octaves by chance, for
+// register variety that keeps the notes in their scale and the line mostly in its shape
+pub struct OctaveScatterPitchModule {
+    input: Box<dyn PitchModule>,
+    probability: f32,
+    max_octaves: u32,
+    rng: SeededRng,
+}
+
+impl OctaveScatterPitchModule {
+    pub fn new(
+        input: Box<dyn PitchModule>,
+        probability: f32,
+        max_octaves: u32,
+        seed: u64,
+    ) -> OctaveScatterPitchModule {
+        OctaveScatterPitchModule {
+            input,
+            probability,
+            max_octaves,
+            rng: SeededRng::seed_from_u64(seed),
+        }
+    }
+
+    // Semitones the note of this step is moved by
+    fn scatter(&mut self) -> f32 {
+        if self.probability <= 0.0 || self.max_octaves == 0 {
+            return 0.0;
+        }
+        if self.rng.gen::<f32>() >= self.probability {
+            return 0.0;
+        }
+        let octaves = self.rng.gen_range(1..=self.max_octaves) as f32;
+        let direction = if self.rng.gen::<bool>() { 1.0 } else { -1.0 };
+        direction * octaves * 12.0
+    }
+}
+
+impl PitchModule for OctaveScatterPitchModule {
+    fn tick(&mut self) -> LetterOctave {
+        let pitch = self.input.tick();
+        Step(pitch.step() + self.scatter()).to_letter_octave()
+    }
+
+    fn tick_step(&mut self) -> f32 {
+        self.input.tick_step() + self.scatter()
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Scatter {
+            rng: self.rng.state(),
+            input: Box::new(self.input.state()),
+        }
+    }
+
+    // States saved without the scatter are passed on to the input
+    fn restore(&mut self, state: &GeneratorState) {
+        match state {
+            GeneratorState::Scatter { rng, input } => {
+                self.rng.restore(*rng);
+                self.input.restore(input);
+            }
+            state => self.input.restore(state),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.input.reset();
+    }
+
+    fn set_parameter(&mut self, parameter: &PitchParameter) {
+        self.input.set_parameter(parameter);
+    }
+}
+
+// Shifts its input by a number of semitones, e.g. to move the quantized output to another
+// key. The shifted notes can leave the scale the input was quantized to.
+pub struct TransposePitchModule {
+    input: Box<dyn PitchModule>,
+    semitones: i32,
+}
+
+impl TransposePitchModule {
+    pub fn new(input: Box<dyn PitchModule>, semitones: i32) -> TransposePitchModule {
+        TransposePitchModule { input, semitones }
+    }
+}
+
+impl PitchModule for TransposePitchModule {
+    fn tick(&mut self) -> LetterOctave {
+        Step(self.input.tick().step() + self.semitones as f32).to_letter_octave()
+    }
+
+    fn tick_step(&mut self) -> f32 {
+        self.input.tick_step() + self.semitones as f32
+    }
+
+    // The shift has no state of its own, so states saved without it restore the same
+    fn state(&self) -> GeneratorState {
+        self.input.state()
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        self.input.restore(state);
+    }
+
+    fn reset(&mut self) {
+        self.input.reset();
+    }
+
+    fn set_parameter(&mut self, parameter: &PitchParameter) {
+        self.input.set_parameter(parameter);
+    }
+}
+
+pub struct PitchAdder {
+    left: Box<dyn PitchModule>,
+    right: Box<dyn PitchModule>,
+}
+
+impl PitchAdder {
+    pub fn new(left: Box<dyn PitchModule>, right: Box<dyn PitchModule>) -> PitchAdder {
+        PitchAdder { left, right }
+    }
+}
+
+impl PitchModule for PitchAdder {
+    fn tick(&mut self) -> LetterOctave {
+        let right_result = self.right.tick();
+        let left_result = self.left.tick();
+        let result = left_result + right_result;
+        result
+    }
+
+    fn tick_step(&mut self) -> f32 {
+        let right_result = self.right.tick_step();
+        let left_result = self.left.tick_step();
+        left_result + right_result
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Adder {
+            left: Box::new(self.left.state()),
+            right: Box::new(self.right.state()),
+        }
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Adder { left, right } = state {
+            self.left.restore(left);
+            self.right.restore(right);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+    }
+
+    // The melody is added on the left, the transposition on the right
+    fn set_parameter(&mut self, parameter: &PitchParameter) {
+        match parameter {
+            PitchParameter::Lane(Lane::Melody, parameter) => {
+                self.left.set_lane_parameter(parameter)
+            }
+            PitchParameter::Lane(Lane::Transposition, parameter) => {
+                self.right.set_lane_parameter(parameter)
+            }
+            _ => (),
+        }
+    }
+}
+
+// Plays one of its inputs on each step, picked by chance according to the weights, e.g.
+// mostly a ramp with the occasional random note. All inputs are ticked on every step, so
+// the ones not picked keep their place in the cycle.
+pub struct SwitchPitchGenerator {
+    inputs: Vec<(Box<dyn PitchModule>, f32)>,
+    rng: SeededRng,
+}
+
+impl SwitchPitchGenerator {
+    // Negative weights count as 0. Without any weight the first input is always played.
+    pub fn new(inputs: Vec<(Box<dyn PitchModule>, f32)>, seed: u64) -> SwitchPitchGenerator {
+        assert!(!inputs.is_empty(), "switch needs at least one input");
+        SwitchPitchGenerator {
+            inputs,
+            rng: SeededRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl SwitchPitchGenerator {
+    // Index of the input played on this step
+    fn pick(&mut self) -> usize {
+        let weights: Vec<f32> = self.inputs.iter().map(|(_, weight)| *weight).collect();
+        weighted_index(&mut self.rng, &weights)
+    }
+}
+
+impl PitchModule for SwitchPitchGenerator {
+    fn tick(&mut self) -> LetterOctave {
+        let pitches: Vec<LetterOctave> = self
+            .inputs
+            .iter_mut()
+            .map(|(generator, _)| generator.tick())
+            .collect();
+        pitches[self.pick()]
+    }
+
+    fn tick_step(&mut self) -> f32 {
+        let steps: Vec<f32> = self
+            .inputs
+            .iter_mut()
+            .map(|(generator, _)| generator.tick_step())
+            .collect();
+        steps[self.pick()]
+    }
+
+    fn state(&self) -> GeneratorState {
+        GeneratorState::Switch {
+            rng: self.rng.state(),
+            inputs: self
+                .inputs
+                .iter()
+                .map(|(generator, _)| generator.state())
+                .collect(),
+        }
+    }
+
+    fn restore(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Switch { rng, inputs } = state {
+            self.rng.restore(*rng);
+            for ((generator, _), input) in self.inputs.iter_mut().zip(inputs) {
+                generator.restore(input);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for (generator, _) in self.inputs.iter_mut() {
+            generator.reset();
+        }
+    }
+
+    fn set_lane_parameter(&mut self, parameter: &LaneParameter) {
+        for (generator, _) in self.inputs.iter_mut() {
+            generator.set_lane_parameter(parameter);
+        }
+    }
+}
+
+// Plays each note as a chord of it and the notes the intervals away from it, in semitones,
+// like the chord memory of a synth. The added notes are snapped down into the scale, so that
+// a major third and fifth make the triads of the scale, e.g. D-F-A for a D in C major.
+pub struct Harmonizer {
+    intervals: Vec<i32>,
+    scale: Vec<Letter>,
+}
+
+impl Harmonizer {
+    pub fn new(intervals: Vec<i32>, scale: Vec<Letter>) -> Harmonizer {
+        Harmonizer { intervals, scale }
+    }
+
+    // The note followed by the notes added to it, in the order of the intervals. Intervals
+    // snapped onto a note already in the chord don't play it twice.
+    pub fn chord(&self, root: LetterOctave) -> Vec<LetterOctave> {
+        let mut chord = vec![root];
+        for interval in &self.intervals {
+            let note = self.snap_to_scale(root.step() + *interval as f32);
+            if !chord.contains(&note) {
+                chord.push(note);
+            }
+        }
+        chord
+    }
+
+    // The nearest note of the scale at or below the step, the step itself without a scale
+    fn snap_to_scale(&self, step: f32) -> LetterOctave {
+        // compare semitones, as the scales spell some notes as flats
+        let semitone = |step: f32| step.rem_euclid(12.0);
+        let in_scale = |step: f32| {
+            self.scale
+                .iter()
+                .any(|letter| semitone(LetterOctave(*letter, 0).step()) == semitone(step))
+        };
+        let snapped = (0..12)
+            .map(|down| step - down as f32)
+            .find(|step| in_scale(*step))
+            .unwrap_or(step);
+        Step(snapped).to_letter_octave()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct PitchSequence {
+        pitches: Vec<LetterOctave>,
+        index: usize,
+    }
+
+    impl PitchSequence {
+        fn new(pitches: Vec<LetterOctave>) -> PitchSequence {
+            PitchSequence { pitches, index: 0 }
+        }
+    }
+
+    impl PitchModule for PitchSequence {
+        fn tick(&mut self) -> LetterOctave {
+            let pitch = self.pitches[self.index];
+            self.index = (self.index + 1) % self.pitches.len();
+            pitch
+        }
+    }
+
+    fn chromatic_octave(octave: i32) -> Vec<LetterOctave> {
+        CHROMATIC_SCALE_NOTES
+            .iter()
+            .map(|letter| LetterOctave(*letter, octave))
+            .collect()
+    }
+
+    #[test]
+    fn square_pitch_generator_returns_symmetrical_output_when_length_is_even() {
+        let length = 4;
+        let min = Step(0.0).to_letter_octave();
+        let max = Step(10.0).to_letter_octave();
+        let mut generator = SquarePitchGenerator::new(length, 0, min, max);
+
+        let mut actual: Vec<LetterOctave> = Vec::new();
+        for _ in 0..length * 2 {
+            actual.push(generator.tick());
+        }
+
+        assert_eq!(actual, vec![min, min, max, max, min, min, max, max]);
+    }
+
+    #[test]
+    fn square_pitch_generator_returns_asymmetrical_output_when_length_is_odd() {
+        let length = 3;
+        let min = LetterOctave(Letter::C, 1);
+        let max = LetterOctave(Letter::C, 2);
+        let mut generator = SquarePitchGenerator::new(length, 0, min, max);
+
+        let mut actual: Vec<LetterOctave> = Vec::new();
+        for _ in 0..length * 2 {
+            actual.push(generator.tick());
+        }
+
+        assert_eq!(actual, vec![min, max, max, min, max, max]);
+    }
+
+    #[test]
+    fn square_pitch_generator_with_an_interval_jumps_that_far_above_the_minimum() {
+        let length = 4;
+        let min = LetterOctave(Letter::E, 2);
+        // the maximum is ignored
+        let max = LetterOctave(Letter::C, 3);
+        let mut generator = SquarePitchGenerator::new(length, 0, min, max);
+        generator.set_interval(Some(12));
+
+        let high = Step(min.step() + 12.0).to_letter_octave();
+        for _ in 0..3 {
+            let actual: Vec<LetterOctave> = (0..length).map(|_| generator.tick()).collect();
+            assert_eq!(actual, vec![min, min, high, high]);
+        }
+    }
+
+    #[test]
     fn ramp_generator_returns_stepped_output_including_min_max_values() {
         let length = 4;
         let min = LetterOctave(Letter::C, 1);
         let max = LetterOctave(Letter::C, 2);
-        let mut generator = RampPitchGenerator::new(length, min, max);
+        let mut generator = RampPitchGenerator::new(length, 0, min, max);
+
+        let mut actual: Vec<LetterOctave> = Vec::new();
+        for _ in 0..length * 2 {
+            actual.push(generator.tick());
+        }
+
+        assert_eq!(
+            actual,
+            vec![
+                LetterOctave(Letter::C, 1),
+                LetterOctave(Letter::E, 1),
+                LetterOctave(Letter::Gsh, 1),
+                LetterOctave(Letter::C, 2),
+                LetterOctave(Letter::C, 1),
+                LetterOctave(Letter::E, 1),
+                LetterOctave(Letter::Gsh, 1),
+                LetterOctave(Letter::C, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn phase_offset_rotates_the_ramp_and_square_cycles() {
+        let min = LetterOctave(Letter::C, 1);
+        let max = LetterOctave(Letter::C, 2);
+        let mut ramp = RampPitchGenerator::new(4, 0, min, max);
+        let in_phase: Vec<LetterOctave> = (0..8).map(|_| ramp.tick()).collect();
+        let mut ramp = RampPitchGenerator::new(4, 1, min, max);
+        let offset: Vec<LetterOctave> = (0..8).map(|_| ramp.tick()).collect();
+
+        assert_eq!(format_step_sequence(&in_phase), "C1 E1 G#1 C2 C1 E1 G#1 C2");
+        assert_eq!(format_step_sequence(&offset), "E1 G#1 C2 C1 E1 G#1 C2 C1");
+        // the phase wraps around the cycle length
+        let mut ramp = RampPitchGenerator::new(4, 5, min, max);
+        assert_eq!(ramp.tick(), offset[0]);
+
+        let mut square = SquarePitchGenerator::new(4, 1, min, max);
+        let actual: Vec<LetterOctave> = (0..8).map(|_| square.tick()).collect();
+        assert_eq!(actual, vec![min, max, max, min, min, max, max, min]);
+        let mut square = SquarePitchGenerator::new(4, 5, min, max);
+        assert_eq!(square.tick(), actual[0]);
+    }
+
+    #[test]
+    fn square_pitch_generator_wraps_a_counter_restored_past_its_cycle() {
+        let min = LetterOctave(Letter::C, 1);
+        let max = LetterOctave(Letter::C, 2);
+        let mut generator = SquarePitchGenerator::new(4, 0, min, max);
+        generator.restore(&GeneratorState::Counter(6));
+
+        let actual: Vec<LetterOctave> = (0..8).map(|_| generator.tick()).collect();
+        assert_eq!(actual, vec![max, max, min, min, max, max, min, min]);
+    }
+
+    #[test]
+    fn ping_pong_ramp_generator_turns_around_at_min_and_max() {
+        let length = 4;
+        let mut generator = RampPitchGenerator::new(
+            length,
+            0,
+            LetterOctave(Letter::C, 1),
+            LetterOctave(Letter::C, 2),
+        );
+        generator.set_mode(RampMode::PingPong);
+
+        // two full cycles up and down
+        let actual: Vec<LetterOctave> = (0..12).map(|_| generator.tick()).collect();
+
+        assert_eq!(
+            format_step_sequence(&actual),
+            "C1 E1 G#1 C2 G#1 E1 C1 E1 G#1 C2 G#1 E1"
+        );
+    }
+
+    #[test]
+    fn ramp_generator_with_a_zero_step_cycle_stays_at_min() {
+        for mode in [RampMode::Sawtooth, RampMode::PingPong] {
+            let mut generator = RampPitchGenerator::new(
+                0,
+                3,
+                LetterOctave(Letter::C, 1),
+                LetterOctave(Letter::C, 2),
+            );
+            generator.set_mode(mode);
+
+            assert!((0..4).all(|_| generator.tick() == LetterOctave(Letter::C, 1)));
+        }
+    }
+
+    #[test]
+    fn ping_pong_ramp_generator_with_a_single_step_cycle_stays_at_min() {
+        let mut generator =
+            RampPitchGenerator::new(1, 0, LetterOctave(Letter::C, 1), LetterOctave(Letter::C, 2));
+        generator.set_mode(RampMode::PingPong);
+
+        assert!((0..4).all(|_| generator.tick() == LetterOctave(Letter::C, 1)));
+    }
+
+    #[test]
+    fn chord_tone_pitch_adder_lands_on_the_nearest_tone_of_a_c_major_triad() {
+        let mut adder = ChordTonePitchAdder::new(
+            Box::new(PitchSequence::new(chromatic_octave(4))),
+            ProgressionPitchGenerator::new(&[0], 1, MAJOR_SCALE_NOTES),
+        );
+
+        let actual: Vec<LetterOctave> = (0..12).map(|_| adder.tick()).collect();
+
+        assert!(actual
+            .iter()
+            .all(|pitch| [Letter::C, Letter::E, Letter::G].contains(&pitch.letter())));
+        // D and A# are halfway between two chord tones and go up
+        assert_eq!(
+            format_step_sequence(&actual),
+            "C4 C4 E4 E4 E4 E4 G4 G4 G4 G4 C5 C5"
+        );
+    }
+
+    #[test]
+    fn chord_tone_pitch_adder_follows_the_chords_of_the_progression() {
+        // I-V in C major: C E G, then G B D
+        let mut adder = ChordTonePitchAdder::new(
+            Box::new(PitchSequence::new(vec![LetterOctave(Letter::F, 3)])),
+            ProgressionPitchGenerator::new(&[0, 4], 1, MAJOR_SCALE_NOTES),
+        );
+
+        let actual: Vec<LetterOctave> = (0..4).map(|_| adder.tick()).collect();
+
+        // F3 snaps to E3 on the I chord, F3 raised by a fifth is C4, which snaps to B3 on
+        // the V chord
+        assert_eq!(format_step_sequence(&actual), "E3 B3 E3 B3");
+    }
+
+    #[test]
+    fn drunk_trigger_generator_keeps_probability_in_range_and_is_deterministic() {
+        let mut first = DrunkTriggerGenerator::new(0.5, 0.3, 42);
+        let mut second = DrunkTriggerGenerator::new(0.5, 0.3, 42);
+
+        for _ in 0..1000 {
+            assert_eq!(first.tick(), second.tick());
+            assert_eq!(first.probability(), second.probability());
+            assert!((0.0..=1.0).contains(&first.probability()));
+        }
+    }
+
+    #[test]
+    fn pitch_quantizer_never_quantizes_at_zero_strength() {
+        let input = chromatic_octave(3);
+        let mut quantizer = PitchQuantizer::with_strength(
+            Box::new(PitchSequence::new(input.clone())),
+            MAJOR_SCALE_NOTES.to_vec(),
+            0.0,
+            7,
+        );
 
-        let mut actual: Vec<LetterOctave> = Vec::new();
-        for _ in 0..length * 2 {
-            actual.push(generator.tick());
+        let actual: Vec<LetterOctave> = input.iter().map(|_| quantizer.tick()).collect();
+
+        assert_eq!(actual, input);
+    }
+
+    #[test]
+    fn pitch_quantizer_always_quantizes_at_full_strength() {
+        let input = chromatic_octave(3);
+        let mut quantizer = PitchQuantizer::with_strength(
+            Box::new(PitchSequence::new(input.clone())),
+            MAJOR_SCALE_NOTES.to_vec(),
+            1.0,
+            7,
+        );
+
+        for _ in 0..input.len() * 4 {
+            assert!(MAJOR_SCALE_NOTES.contains(&quantizer.tick().letter()));
+        }
+    }
+
+    #[test]
+    fn parse_letter_octave_is_the_inverse_of_format_letter_octave() {
+        for step in 0..128 {
+            let letter_octave = Step(step as f32).to_letter_octave();
+            assert_eq!(
+                parse_letter_octave(&format_letter_octave(letter_octave)),
+                Ok(letter_octave)
+            );
+        }
+        for letter in [Letter::Db, Letter::Eb, Letter::Gb, Letter::Ab, Letter::Bb] {
+            let letter_octave = LetterOctave(letter, 3);
+            assert_eq!(
+                parse_letter_octave(&format_letter_octave(letter_octave)),
+                Ok(letter_octave)
+            );
         }
+    }
+
+    #[test]
+    fn parse_letter_octave_accepts_sharps_flats_and_negative_octaves() {
+        assert_eq!(parse_letter_octave("C#4"), Ok(LetterOctave(Letter::Csh, 4)));
+        assert_eq!(parse_letter_octave("Db2"), Ok(LetterOctave(Letter::Db, 2)));
+        assert_eq!(parse_letter_octave("G-1"), Ok(LetterOctave(Letter::G, -1)));
+        assert_eq!(
+            parse_letter_octave("Bb-1"),
+            Ok(LetterOctave(Letter::Bb, -1))
+        );
+    }
+
+    #[test]
+    fn parse_letter_octave_rejects_invalid_input() {
+        assert_eq!(parse_letter_octave(""), Err(ParseError::Empty));
+        assert_eq!(parse_letter_octave("H9"), Err(ParseError::InvalidLetter));
+        assert_eq!(parse_letter_octave("E#3"), Err(ParseError::InvalidLetter));
+        assert_eq!(parse_letter_octave("c3"), Err(ParseError::InvalidLetter));
+        assert_eq!(parse_letter_octave("C"), Err(ParseError::InvalidOctave));
+        assert_eq!(parse_letter_octave("C#"), Err(ParseError::InvalidOctave));
+        assert_eq!(parse_letter_octave("C3x"), Err(ParseError::InvalidOctave));
+    }
+
+    #[test]
+    fn letter_octaves_are_converted_to_hz_from_a4_at_440() {
+        assert!((letter_octave_to_hz(LetterOctave(Letter::A, 4)) - 440.0).abs() < 0.001);
+        assert!((letter_octave_to_hz(LetterOctave(Letter::A, 3)) - 220.0).abs() < 0.001);
+        assert!((letter_octave_to_hz(LetterOctave(Letter::C, 4)) - 261.63).abs() < 0.01);
+    }
+
+    #[test]
+    fn pitch_quantizer_only_quantizes_inside_the_quantize_range() {
+        let input = vec![
+            LetterOctave(Letter::Csh, 3),
+            LetterOctave(Letter::Csh, 4),
+            LetterOctave(Letter::Fsh, 4),
+            LetterOctave(Letter::Csh, 5),
+            LetterOctave(Letter::Fsh, 6),
+        ];
+        let mut quantizer = PitchQuantizer::new(
+            Box::new(PitchSequence::new(input)),
+            MAJOR_SCALE_NOTES.to_vec(),
+        );
+        quantizer.set_quantize_range(Some(
+            LetterOctave(Letter::C, 4).to_step()..=LetterOctave(Letter::C, 5).to_step(),
+        ));
+
+        let actual: Vec<LetterOctave> = (0..5).map(|_| quantizer.tick()).collect();
 
         assert_eq!(
             actual,
             vec![
+                LetterOctave(Letter::Csh, 3),
+                LetterOctave(Letter::D, 4),
+                LetterOctave(Letter::G, 4),
+                LetterOctave(Letter::Csh, 5),
+                LetterOctave(Letter::Fsh, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn pitch_quantizer_keeps_notes_near_the_range_ceiling_in_range() {
+        let input = vec![LetterOctave(Letter::A, 4), LetterOctave(Letter::A, 4)];
+        let quantize = |pitch_range| {
+            let mut quantizer = PitchQuantizer::new(
+                Box::new(PitchSequence::new(input.clone())),
+                vec![Letter::C, Letter::E, Letter::G],
+            );
+            quantizer.set_pitch_range(pitch_range);
+            quantizer.tick()
+        };
+
+        // A4 is above the highest enabled note, so it wraps around to the C an octave higher
+        assert_eq!(quantize(None), LetterOctave(Letter::C, 5));
+        // unless that is above the range, then it snaps down to the G below the ceiling
+        let pitch_range =
+            LetterOctave(Letter::C, 4).to_step()..=LetterOctave(Letter::B, 4).to_step();
+        assert_eq!(quantize(Some(pitch_range)), LetterOctave(Letter::G, 4));
+    }
+
+    #[test]
+    fn octave_scatter_moves_notes_by_chance_within_the_octave_range() {
+        let mut scatter = OctaveScatterPitchModule::new(
+            Box::new(ConstantPitchGenerator::new(LetterOctave(Letter::E, 4))),
+            0.25,
+            2,
+            7,
+        );
+        let offsets: Vec<f32> = (0..1000)
+            .map(|_| scatter.tick().step() - LetterOctave(Letter::E, 4).step())
+            .collect();
+
+        let scattered = offsets.iter().filter(|offset| **offset != 0.0).count();
+        assert!((200..=300).contains(&scattered));
+        // whole octaves, both ways, and no further than the range
+        assert!(offsets.iter().all(|offset| offset % 12.0 == 0.0));
+        assert!(offsets.iter().all(|offset| offset.abs() <= 24.0));
+        for offset in [-24.0, -12.0, 12.0, 24.0] {
+            assert!(offsets.contains(&offset));
+        }
+
+        let mut unscattered = OctaveScatterPitchModule::new(
+            Box::new(ConstantPitchGenerator::new(LetterOctave(Letter::E, 4))),
+            0.0,
+            2,
+            7,
+        );
+        assert!((0..100).all(|_| unscattered.tick() == LetterOctave(Letter::E, 4)));
+    }
+
+    #[test]
+    fn pitch_quantizer_folds_octaves_into_the_allowed_range() {
+        let mut quantizer = PitchQuantizer::new(
+            Box::new(PitchSequence::new(vec![
+                LetterOctave(Letter::C, 6),
+                LetterOctave(Letter::Csh, 5),
                 LetterOctave(Letter::C, 1),
-                LetterOctave(Letter::E, 1),
-                LetterOctave(Letter::Gsh, 1),
-                LetterOctave(Letter::C, 2),
-                LetterOctave(Letter::C, 1),
-                LetterOctave(Letter::E, 1),
-                LetterOctave(Letter::Gsh, 1),
-                LetterOctave(Letter::C, 2)
+                LetterOctave(Letter::E, 3),
+            ])),
+            MAJOR_SCALE_NOTES.to_vec(),
+        );
+        quantizer.set_allowed_octaves(3..=4);
+
+        let actual: Vec<LetterOctave> = (0..4).map(|_| quantizer.tick()).collect();
+
+        assert_eq!(
+            actual,
+            vec![
+                LetterOctave(Letter::C, 4),
+                LetterOctave(Letter::D, 3),
+                LetterOctave(Letter::C, 3),
+                LetterOctave(Letter::E, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn pitch_quantizer_swaps_an_inverted_octave_range() {
+        let input = vec![LetterOctave(Letter::C, 6), LetterOctave(Letter::E, 1)];
+        for (high, low) in [(4, 3), (3, 2)] {
+            let mut quantizer = PitchQuantizer::new(
+                Box::new(PitchSequence::new(input.clone())),
+                MAJOR_SCALE_NOTES.to_vec(),
+            );
+            quantizer.set_allowed_octaves(RangeInclusive::new(high, low));
+
+            for _ in 0..input.len() {
+                let octave = quantizer.tick().octave();
+                assert!((low..=high).contains(&octave), "{}", octave);
+            }
+        }
+    }
+
+    #[test]
+    fn random_pitch_generator_swaps_an_inverted_range() {
+        let min = LetterOctave(Letter::C, 3);
+        let max = LetterOctave(Letter::C, 5);
+        let mut generator = RandomPitchGenerator::new(max, min, random());
+        for _ in 0..1000 {
+            let step = generator.tick().step();
+            assert!(min.step() <= step && step <= max.step());
+        }
+    }
+
+    #[test]
+    fn step_sequence_pitch_generator_cycles_through_the_notes_in_order() {
+        let notes = parse_step_sequence("C4 E4 G4").unwrap();
+        let mut generator = StepSequencePitchGenerator::new(notes.clone());
+        let pitches: Vec<LetterOctave> = (0..7).map(|_| generator.tick()).collect();
+        assert_eq!(
+            pitches,
+            vec![notes[0], notes[1], notes[2], notes[0], notes[1], notes[2], notes[0]]
+        );
+        assert_eq!(format_step_sequence(&notes), "C4 E4 G4");
+    }
+
+    #[test]
+    fn parse_step_sequence_rejects_empty_and_invalid_input() {
+        assert_eq!(parse_step_sequence("  "), Err(ParseError::Empty));
+        assert_eq!(parse_step_sequence("C4 H4"), Err(ParseError::InvalidLetter));
+    }
+
+    #[test]
+    fn progression_pitch_generator_changes_chord_on_step_boundaries() {
+        let mut generator = ProgressionPitchGenerator::new(&[0, 4, 5, 3], 2, MAJOR_SCALE_NOTES);
+        let roots: Vec<f32> = (0..10).map(|_| generator.tick().step()).collect();
+        assert_eq!(
+            roots,
+            vec![0.0, 0.0, 7.0, 7.0, 9.0, 9.0, 5.0, 5.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn progression_pitch_generator_wraps_degrees_into_other_octaves() {
+        let mut generator = ProgressionPitchGenerator::new(&[7, -1], 1, MAJOR_SCALE_NOTES);
+        assert_eq!(generator.tick().step(), 12.0);
+        assert_eq!(generator.tick().step(), -1.0);
+    }
+
+    #[test]
+    fn restored_generators_continue_exactly_where_they_were_saved() {
+        let build_pitch = || {
+            PitchQuantizer::with_strength(
+                Box::new(PitchAdder::new(
+                    Box::new(RandomPitchGenerator::new(
+                        LetterOctave(Letter::C, 3),
+                        LetterOctave(Letter::C, 5),
+                        random(),
+                    )),
+                    Box::new(RampPitchGenerator::new(
+                        16,
+                        0,
+                        LetterOctave(Letter::C, -1),
+                        LetterOctave(Letter::C, 0),
+                    )),
+                )),
+                MAJOR_SCALE_NOTES.to_vec(),
+                0.5,
+                random(),
+            )
+        };
+        let build_trigger = || {
+            ClockDivider::new(
+                Box::new(DrunkTriggerGenerator::new(0.5, 0.1, random())),
+                3,
+                0,
+            )
+        };
+        let mut pitch = build_pitch();
+        let mut trigger = build_trigger();
+        for _ in 0..37 {
+            pitch.tick();
+            trigger.tick();
+        }
+
+        let saved = serde_json::to_string(&(pitch.state(), trigger.state())).unwrap();
+        let (pitch_state, trigger_state): (GeneratorState, GeneratorState) =
+            serde_json::from_str(&saved).unwrap();
+        let mut restored_pitch = build_pitch();
+        let mut restored_trigger = build_trigger();
+        restored_pitch.restore(&pitch_state);
+        restored_trigger.restore(&trigger_state);
+
+        for _ in 0..100 {
+            assert_eq!(restored_pitch.tick(), pitch.tick());
+            assert_eq!(restored_trigger.tick(), trigger.tick());
+        }
+    }
+
+    #[test]
+    fn clock_divider_offset_shifts_the_steps_within_the_division() {
+        let on_ticks = |offset| {
+            let mut divider =
+                ClockDivider::new(Box::new(GateRatioTriggerGenerator::new(1, 0)), 4, offset);
+            (0..12)
+                .filter(|_| {
+                    let trigger = divider.tick();
+                    assert_eq!(divider.is_step(), trigger == Trigger::On);
+                    trigger == Trigger::On
+                })
+                .collect::<Vec<u32>>()
+        };
+
+        assert_eq!(on_ticks(0), vec![0, 4, 8]);
+        assert_eq!(on_ticks(2), vec![2, 6, 10]);
+        // the offset wraps around the division
+        assert_eq!(on_ticks(5), vec![1, 5, 9]);
+    }
+
+    #[test]
+    fn clock_divider_with_a_factor_of_zero_passes_every_tick_through() {
+        let mut divider = ClockDivider::new(Box::new(GateRatioTriggerGenerator::new(1, 0)), 0, 3);
+        for _ in 0..4 {
+            assert_eq!(divider.tick(), Trigger::On);
+            assert!(divider.is_step());
+        }
+    }
+
+    #[test]
+    fn the_trigger_order_decides_whether_the_input_ticks_per_step_or_per_clock_tick() {
+        let steps = |input: Box<dyn TriggerModule>, order| {
+            let mut divider = ClockDivider::new(input, 3, 0);
+            divider.set_order(order);
+            (0..24)
+                .map(|_| divider.tick())
+                .step_by(3)
+                .collect::<Vec<Trigger>>()
+        };
+
+        // a gate of 2 + 1 plays two steps and rests one, but counting clock ticks it is on
+        // at the start of every step of 3 ticks
+        let gate = || Box::new(GateRatioTriggerGenerator::new(2, 1));
+        let mut gate_steps = [Trigger::On, Trigger::On, Trigger::Off].repeat(3);
+        gate_steps.truncate(8);
+        assert_eq!(steps(gate(), TriggerOrder::DivideFirst), gate_steps);
+        assert_eq!(
+            steps(gate(), TriggerOrder::TriggerFirst),
+            vec![Trigger::On; 8]
+        );
+
+        // the same seed rolls once per step, or keeps every third roll of the clock ticks
+        let random = || Box::new(RandomTriggerGenerator::new(0.5, 7));
+        let mut rolls = RandomTriggerGenerator::new(0.5, 7);
+        let rolls: Vec<Trigger> = (0..24).map(|_| rolls.tick()).collect();
+        let divide_first = steps(random(), TriggerOrder::DivideFirst);
+        let trigger_first = steps(random(), TriggerOrder::TriggerFirst);
+        assert_eq!(divide_first, rolls[..8]);
+        assert_eq!(
+            trigger_first,
+            rolls.iter().copied().step_by(3).collect::<Vec<Trigger>>()
+        );
+        assert_ne!(divide_first, trigger_first);
+    }
+
+    #[test]
+    fn changing_the_probability_in_place_keeps_the_random_stream_going() {
+        let mut divider = ClockDivider::new(Box::new(RandomTriggerGenerator::new(0.3, 7)), 2, 0);
+        for _ in 0..100 {
+            divider.tick();
+        }
+        let state = divider.state();
+        divider.set_probability(0.7);
+        let continued: Vec<Trigger> = (0..100).map(|_| divider.tick()).collect();
+
+        // the same draws as a module of the new probability carrying on from the same state
+        let mut carried_on = ClockDivider::new(Box::new(RandomTriggerGenerator::new(0.7, 7)), 2, 0);
+        carried_on.restore(&state);
+        let expected: Vec<Trigger> = (0..100).map(|_| carried_on.tick()).collect();
+        assert_eq!(continued, expected);
+        assert!(continued.contains(&Trigger::On));
+
+        // while a rebuilt module starts the draws over
+        let mut rebuilt = ClockDivider::new(Box::new(RandomTriggerGenerator::new(0.7, 7)), 2, 0);
+        let restarted: Vec<Trigger> = (0..100).map(|_| rebuilt.tick()).collect();
+        assert_ne!(continued, restarted);
+    }
+
+    #[test]
+    fn changing_a_lane_parameter_in_place_keeps_its_counter_and_random_draws() {
+        let low = LetterOctave(Letter::C, 3);
+        let high = LetterOctave(Letter::C, 5);
+        // the random draws carry on in the new range
+        let mut random = RandomPitchGenerator::new(low, high, 7);
+        let mut carried_on = RandomPitchGenerator::new(low, LetterOctave(Letter::C, 4), 7);
+        for _ in 0..5 {
+            random.tick();
+            carried_on.tick();
+        }
+        random.set_lane_parameter(&LaneParameter::Range(low, LetterOctave(Letter::C, 4)));
+        for _ in 0..20 {
+            assert_eq!(random.tick_step(), carried_on.tick_step());
+        }
+
+        // the ramp carries on from its step of the cycle at the new slope
+        let mut ramp = RampPitchGenerator::new(3, 0, low, LetterOctave(Letter::C, 4));
+        assert_eq!(ramp.tick_step(), low.step());
+        assert_eq!(ramp.tick_step(), low.step() + 6.0);
+        ramp.set_lane_parameter(&LaneParameter::CycleLength(5));
+        assert_eq!(ramp.tick_step(), low.step() + 6.0);
+        assert_eq!(ramp.tick_step(), low.step() + 9.0);
+        // a cycle of 0 steps plays like one of a single step
+        for mode in [RampMode::Sawtooth, RampMode::PingPong] {
+            ramp.set_mode(mode);
+            ramp.set_lane_parameter(&LaneParameter::CycleLength(0));
+            assert_eq!(ramp.tick_step(), low.step());
+        }
+    }
+
+    #[test]
+    fn the_adder_hands_a_lane_parameter_to_its_lane_only() {
+        let melody = LetterOctave(Letter::C, 4);
+        let transposition = LetterOctave(Letter::C, 0);
+        let mut adder = PitchAdder::new(
+            Box::new(RandomPitchGenerator::new(melody, melody, 1)),
+            Box::new(RandomPitchGenerator::new(transposition, transposition, 2)),
+        );
+        assert_eq!(adder.tick_step(), melody.step() + transposition.step());
+
+        let d4 = LetterOctave(Letter::D, 4);
+        adder.set_parameter(&PitchParameter::Lane(
+            Lane::Melody,
+            LaneParameter::Range(d4, d4),
+        ));
+        assert_eq!(adder.tick_step(), d4.step() + transposition.step());
+
+        let e0 = LetterOctave(Letter::E, 0);
+        adder.set_parameter(&PitchParameter::Lane(
+            Lane::Transposition,
+            LaneParameter::Range(e0, e0),
+        ));
+        assert_eq!(adder.tick_step(), d4.step() + e0.step());
+    }
+
+    #[test]
+    fn harmonizing_a_note_adds_the_intervals_snapped_into_the_scale() {
+        let harmonizer = Harmonizer::new(vec![4, 7], MAJOR_SCALE_NOTES.to_vec());
+
+        assert_eq!(
+            harmonizer.chord(LetterOctave(Letter::C, 4)),
+            vec![
+                LetterOctave(Letter::C, 4),
+                LetterOctave(Letter::E, 4),
+                LetterOctave(Letter::G, 4)
+            ]
+        );
+        // the major third of D leaves the scale, so it is snapped down to the minor third
+        assert_eq!(
+            harmonizer.chord(LetterOctave(Letter::D, 4)),
+            vec![
+                LetterOctave(Letter::D, 4),
+                LetterOctave(Letter::F, 4),
+                LetterOctave(Letter::A, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn harmonizing_a_note_plays_each_note_of_the_chord_once() {
+        // both thirds of E snap to G, and the octave of the unison is a note of its own
+        let harmonizer = Harmonizer::new(vec![0, 3, 4, 12], MAJOR_SCALE_NOTES.to_vec());
+
+        assert_eq!(
+            harmonizer.chord(LetterOctave(Letter::E, 4)),
+            vec![
+                LetterOctave(Letter::E, 4),
+                LetterOctave(Letter::G, 4),
+                LetterOctave(Letter::E, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn gate_ratio_trigger_generator_plays_and_rests_in_a_fixed_pattern() {
+        let mut generator = GateRatioTriggerGenerator::new(3, 1);
+
+        let actual: Vec<Trigger> = (0..12).map(|_| generator.tick()).collect();
+
+        let cycle = [Trigger::On, Trigger::On, Trigger::On, Trigger::Off];
+        assert_eq!(actual, cycle.repeat(3));
+    }
+
+    #[test]
+    fn gate_ratio_trigger_generator_handles_patterns_without_rests_or_notes() {
+        let mut always = GateRatioTriggerGenerator::new(2, 0);
+        assert!((0..5).all(|_| always.tick() == Trigger::On));
+        let mut never = GateRatioTriggerGenerator::new(0, 0);
+        assert!((0..5).all(|_| never.tick() == Trigger::Off));
+    }
+
+    // left:  On Off On Off On Off
+    // right: On On Off On On Off
+    fn combinator_inputs() -> (Box<dyn TriggerModule>, Box<dyn TriggerModule>) {
+        (
+            Box::new(GateRatioTriggerGenerator::new(1, 1)),
+            Box::new(GateRatioTriggerGenerator::new(2, 1)),
+        )
+    }
+
+    #[test]
+    fn and_trigger_fires_only_when_both_inputs_fire() {
+        let (left, right) = combinator_inputs();
+        let mut trigger = AndTrigger::new(left, right);
+
+        let actual: Vec<Trigger> = (0..6).map(|_| trigger.tick()).collect();
+
+        assert_eq!(
+            actual,
+            vec![
+                Trigger::On,
+                Trigger::Off,
+                Trigger::Off,
+                Trigger::Off,
+                Trigger::On,
+                Trigger::Off
+            ]
+        );
+    }
+
+    #[test]
+    fn or_trigger_fires_when_either_input_fires() {
+        let (left, right) = combinator_inputs();
+        let mut trigger = OrTrigger::new(left, right);
+
+        let actual: Vec<Trigger> = (0..6).map(|_| trigger.tick()).collect();
+
+        assert_eq!(
+            actual,
+            vec![
+                Trigger::On,
+                Trigger::On,
+                Trigger::On,
+                Trigger::On,
+                Trigger::On,
+                Trigger::Off
             ]
         );
     }
+
+    #[test]
+    fn trigger_combinators_restore_the_state_of_both_inputs() {
+        let build = || {
+            OrTrigger::new(
+                Box::new(GateRatioTriggerGenerator::new(1, 2)),
+                Box::new(GateRatioTriggerGenerator::new(1, 4)),
+            )
+        };
+        let mut trigger = build();
+        for _ in 0..7 {
+            trigger.tick();
+        }
+        let mut restored = build();
+        restored.restore(&trigger.state());
+
+        for _ in 0..20 {
+            assert_eq!(restored.tick(), trigger.tick());
+        }
+    }
+
+    #[test]
+    fn switch_pitch_generator_picks_inputs_according_to_the_weights() {
+        let mut generator = SwitchPitchGenerator::new(
+            vec![
+                (
+                    Box::new(PitchSequence::new(vec![LetterOctave(Letter::C, 4)])),
+                    3.0,
+                ),
+                (
+                    Box::new(PitchSequence::new(vec![LetterOctave(Letter::G, 4)])),
+                    1.0,
+                ),
+            ],
+            42,
+        );
+
+        let ticks = 4000;
+        let c4_count = (0..ticks)
+            .filter(|_| generator.tick() == LetterOctave(Letter::C, 4))
+            .count();
+
+        let c4_share = c4_count as f32 / ticks as f32;
+        assert!((c4_share - 0.75).abs() < 0.03, "C4 share was {}", c4_share);
+    }
+
+    #[test]
+    fn switch_pitch_generator_keeps_inputs_that_are_not_picked_in_step() {
+        let sequence = vec![
+            LetterOctave(Letter::C, 4),
+            LetterOctave(Letter::D, 4),
+            LetterOctave(Letter::E, 4),
+        ];
+        let mut generator = SwitchPitchGenerator::new(
+            vec![
+                (Box::new(PitchSequence::new(sequence.clone())), 1.0),
+                (
+                    Box::new(PitchSequence::new(vec![LetterOctave(Letter::G, 4)])),
+                    1.0,
+                ),
+            ],
+            7,
+        );
+
+        for i in 0..30 {
+            let pitch = generator.tick();
+            if pitch != LetterOctave(Letter::G, 4) {
+                assert_eq!(pitch, sequence[i % sequence.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn switch_pitch_generator_without_weights_plays_the_first_input() {
+        let mut generator = SwitchPitchGenerator::new(
+            vec![
+                (
+                    Box::new(PitchSequence::new(vec![LetterOctave(Letter::C, 4)])),
+                    0.0,
+                ),
+                (
+                    Box::new(PitchSequence::new(vec![LetterOctave(Letter::G, 4)])),
+                    -1.0,
+                ),
+            ],
+            42,
+        );
+
+        assert!((0..10).all(|_| generator.tick() == LetterOctave(Letter::C, 4)));
+    }
+
+    #[test]
+    fn weighted_scale_degrees_are_played_as_often_as_weighted_in_the_octave_range() {
+        // C three times as often as E, F never, G as often as E
+        let mut generator = WeightedScalePitchGenerator::new(
+            MAJOR_SCALE_NOTES,
+            3..=4,
+            &[3.0, 0.0, 1.0, 0.0, 1.0],
+            42,
+        );
+
+        let ticks = 5000;
+        let pitches: Vec<LetterOctave> = (0..ticks).map(|_| generator.tick()).collect();
+        let share = |letter: Letter| {
+            pitches.iter().filter(|pitch| pitch.0 == letter).count() as f32 / ticks as f32
+        };
+
+        assert!(
+            (share(Letter::C) - 0.6).abs() < 0.03,
+            "C share was {}",
+            share(Letter::C)
+        );
+        assert!(
+            (share(Letter::E) - 0.2).abs() < 0.03,
+            "E share was {}",
+            share(Letter::E)
+        );
+        assert!(
+            (share(Letter::G) - 0.2).abs() < 0.03,
+            "G share was {}",
+            share(Letter::G)
+        );
+        assert!(pitches.iter().all(|pitch| pitch.1 == 3 || pitch.1 == 4));
+        assert!(pitches.iter().any(|pitch| pitch.1 == 3));
+        assert!(pitches.iter().any(|pitch| pitch.1 == 4));
+    }
+
+    fn quantize_with(
+        direction: QuantizeDirection,
+        scale: &[Letter],
+        input: LetterOctave,
+    ) -> LetterOctave {
+        let mut quantizer =
+            PitchQuantizer::new(Box::new(PitchSequence::new(vec![input])), scale.to_vec());
+        quantizer.set_direction(direction);
+        quantizer.tick()
+    }
+
+    #[test]
+    fn quantizer_snaps_up_to_the_next_enabled_note() {
+        let up = |input| quantize_with(QuantizeDirection::Up, MAJOR_PENTATONIC_SCALE_NOTES, input);
+        assert_eq!(up(LetterOctave(Letter::F, 4)), LetterOctave(Letter::G, 4));
+        assert_eq!(up(LetterOctave(Letter::B, 4)), LetterOctave(Letter::C, 5));
+        // A# is the Bb of the minor scale, and B wraps around to the C above it
+        let minor_up = |input| quantize_with(QuantizeDirection::Up, MINOR_SCALE_NOTES, input);
+        assert_eq!(
+            minor_up(LetterOctave(Letter::Ash, 4)).step(),
+            LetterOctave(Letter::Bb, 4).step()
+        );
+        assert_eq!(
+            minor_up(LetterOctave(Letter::E, 4)),
+            LetterOctave(Letter::F, 4)
+        );
+        assert_eq!(
+            minor_up(LetterOctave(Letter::B, 4)),
+            LetterOctave(Letter::C, 5)
+        );
+        // an Eb input stays on the D# of a sharp-spelled scale instead of skipping past it
+        assert_eq!(
+            quantize_with(
+                QuantizeDirection::Up,
+                &[Letter::C, Letter::Dsh, Letter::G],
+                LetterOctave(Letter::Eb, 4)
+            )
+            .step(),
+            LetterOctave(Letter::Dsh, 4).step()
+        );
+    }
+
+    #[test]
+    fn quantizer_snaps_down_to_the_previous_enabled_note() {
+        let down =
+            |input| quantize_with(QuantizeDirection::Down, MAJOR_PENTATONIC_SCALE_NOTES, input);
+        assert_eq!(down(LetterOctave(Letter::F, 4)), LetterOctave(Letter::E, 4));
+        assert_eq!(down(LetterOctave(Letter::B, 4)), LetterOctave(Letter::A, 4));
+        // D# is the Eb of the minor pentatonic scale, not the C below it
+        assert_eq!(
+            quantize_with(
+                QuantizeDirection::Down,
+                MINOR_PENTATONIC_SCALE_NOTES,
+                LetterOctave(Letter::Dsh, 4)
+            )
+            .step(),
+            LetterOctave(Letter::Eb, 4).step()
+        );
+        // G# is the Ab of the minor scale, and E drops to its Eb
+        let minor_down = |input| quantize_with(QuantizeDirection::Down, MINOR_SCALE_NOTES, input);
+        assert_eq!(
+            minor_down(LetterOctave(Letter::Gsh, 4)).step(),
+            LetterOctave(Letter::Ab, 4).step()
+        );
+        assert_eq!(
+            minor_down(LetterOctave(Letter::E, 4)).step(),
+            LetterOctave(Letter::Eb, 4).step()
+        );
+        // below the lowest enabled note wraps around to the octave below
+        assert_eq!(
+            quantize_with(
+                QuantizeDirection::Down,
+                &[Letter::D, Letter::A],
+                LetterOctave(Letter::C, 4)
+            ),
+            LetterOctave(Letter::A, 3)
+        );
+    }
+
+    #[test]
+    fn quantizer_snaps_to_the_nearest_enabled_note_breaking_ties_upwards() {
+        let nearest = |input| {
+            quantize_with(
+                QuantizeDirection::Nearest,
+                MAJOR_PENTATONIC_SCALE_NOTES,
+                input,
+            )
+        };
+        // F is one semitone above E and two below G
+        assert_eq!(
+            nearest(LetterOctave(Letter::F, 4)),
+            LetterOctave(Letter::E, 4)
+        );
+        // B is one semitone below C and two above A
+        assert_eq!(
+            nearest(LetterOctave(Letter::B, 4)),
+            LetterOctave(Letter::C, 5)
+        );
+        // C# is one semitone from both C and D
+        assert_eq!(
+            nearest(LetterOctave(Letter::Csh, 4)),
+            LetterOctave(Letter::D, 4)
+        );
+    }
 }
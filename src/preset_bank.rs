@@ -0,0 +1,176 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+const PRESET_EXTENSION: &str = "json";
+
+// The presets of a folder, in file name order. Nothing is selected until the first step,
+// which selects the first preset going forwards and the last one going backwards.
+pub struct PresetBank {
+    paths: Vec<PathBuf>,
+    index: Option<usize>,
+}
+
+impl PresetBank {
+    pub fn new(mut paths: Vec<PathBuf>) -> PresetBank {
+        paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        PresetBank { paths, index: None }
+    }
+
+    pub fn scan(dir: &Path) -> io::Result<PresetBank> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == PRESET_EXTENSION) {
+                paths.push(path);
+            }
+        }
+        Ok(PresetBank::new(paths))
+    }
+
+    pub fn empty() -> PresetBank {
+        PresetBank::new(Vec::new())
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    // File name of the selected preset, without the extension
+    pub fn current_name(&self) -> Option<String> {
+        preset_name(&self.paths[self.index?])
+    }
+
+    // File names of all presets, without the extension, in order
+    pub fn names(&self) -> Vec<String> {
+        self.paths
+            .iter()
+            .map(|path| preset_name(path).unwrap_or_default())
+            .collect()
+    }
+
+    pub fn path(&self, index: usize) -> Option<&Path> {
+        self.paths.get(index).map(PathBuf::as_path)
+    }
+
+    pub fn select_next(&mut self) -> Option<&Path> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = match self.index {
+            Some(index) => (index + 1) % self.len(),
+            None => 0,
+        };
+        self.index = Some(index);
+        Some(&self.paths[index])
+    }
+
+    pub fn select_previous(&mut self) -> Option<&Path> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = match self.index {
+            Some(index) => (index + self.len() - 1) % self.len(),
+            None => self.len() - 1,
+        };
+        self.index = Some(index);
+        Some(&self.paths[index])
+    }
+
+    // Step through the presets until one loads, skipping the ones that fail with a warning.
+    // Gives up after a full round without a loadable preset.
+    pub fn step<T, E: Display>(
+        &mut self,
+        forward: bool,
+        load: impl Fn(&Path) -> Result<T, E>,
+    ) -> Option<T> {
+        for _ in 0..self.len() {
+            let path = if forward {
+                self.select_next()?
+            } else {
+                self.select_previous()?
+            };
+            match load(path) {
+                Ok(preset) => return Some(preset),
+                Err(e) => warn!("Skipping preset {}: {}", path.display(), e),
+            }
+        }
+        None
+    }
+}
+
+fn preset_name(path: &Path) -> Option<String> {
+    Some(path.file_stem()?.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank(names: &[&str]) -> PresetBank {
+        PresetBank::new(names.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn next_and_previous_wrap_around_the_sorted_presets() {
+        let mut bank = bank(&["presets/c.json", "presets/a.json", "presets/b.json"]);
+        assert_eq!(bank.current_name(), None);
+
+        let names: Vec<String> = (0..4)
+            .map(|_| {
+                bank.select_next();
+                bank.current_name().unwrap()
+            })
+            .collect();
+        assert_eq!(names, ["a", "b", "c", "a"]);
+
+        bank.select_previous();
+        assert_eq!(bank.current_name().unwrap(), "c");
+        bank.select_previous();
+        assert_eq!(bank.current_name().unwrap(), "b");
+        assert_eq!(bank.names(), ["a", "b", "c"]);
+        assert_eq!(bank.path(2), Some(Path::new("presets/c.json")));
+    }
+
+    #[test]
+    fn previous_starts_at_the_last_preset() {
+        let mut bank = bank(&["a.json", "b.json"]);
+        assert_eq!(bank.select_previous(), Some(Path::new("b.json")));
+    }
+
+    #[test]
+    fn an_empty_bank_selects_nothing() {
+        let mut bank = PresetBank::empty();
+        assert_eq!(bank.select_next(), None);
+        assert_eq!(bank.select_previous(), None);
+        assert_eq!(bank.current_name(), None);
+        assert_eq!(bank.step(true, |_| Ok::<(), String>(())), None);
+    }
+
+    #[test]
+    fn presets_that_fail_to_load_are_skipped() {
+        let mut bank = bank(&["a.json", "b.json", "c.json"]);
+        let load = |path: &Path| {
+            if path == Path::new("b.json") {
+                Err("invalid preset")
+            } else {
+                Ok(path.to_path_buf())
+            }
+        };
+
+        assert_eq!(bank.step(true, load), Some(PathBuf::from("a.json")));
+        assert_eq!(bank.step(true, load), Some(PathBuf::from("c.json")));
+        assert_eq!(bank.step(false, load), Some(PathBuf::from("a.json")));
+
+        // a round without any loadable preset gives up
+        assert_eq!(bank.step(true, |_| Err::<(), _>("invalid preset")), None);
+    }
+}
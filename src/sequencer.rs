@@ -1,235 +1,4745 @@
-use std::{sync::mpsc, thread::sleep};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    ops::RangeInclusive,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc, Arc, Mutex, Weak,
+    },
+};
 
 use chrono::Duration;
-use log::info;
-use pitch_calc::{Letter, LetterOctave};
-use timer::Timer;
+use log::{debug, error, info, warn};
+use pitch_calc::{Letter, LetterOctave, Step};
+use rand::{random, Rng, RngCore};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use timer::{Guard, Timer};
 
-use midir::MidiOutputConnection;
+use midir::{MidiOutput, MidiOutputConnection, SendError};
 
 use crate::module::{
-    format_letter_octave, ClockDivider, PitchAdder, PitchGeneratorType, PitchModule,
-    PitchQuantizer, RampPitchGenerator, RandomPitchGenerator, RandomTriggerGenerator,
-    SquarePitchGenerator, Trigger, TriggerModule,
+    format_letter_octave, letter_octave_to_hz, AndTrigger, ChordTonePitchAdder, ClockDivider,
+    ConstantPitchGenerator, DrunkTriggerGenerator, GateRatioTriggerGenerator, GeneratorState,
+    Harmonizer, Lane, LaneParameter, OctaveScatterPitchModule, OrTrigger, PitchAdder,
+    PitchGeneratorType, PitchModule, PitchParameter, PitchQuantizer, ProgressionPitchGenerator,
+    QuantizeDirection, RampMode, RampPitchGenerator, RandomPitchGenerator, RandomTriggerGenerator,
+    SeededRng, SquarePitchGenerator, StepSequencePitchGenerator, SwitchPitchGenerator,
+    TransposePitchModule, Trigger, TriggerModule, TriggerOrder, TriggerType,
+    WeightedScalePitchGenerator,
 };
+use crate::velocity::{VelocityCurve, VelocityMode, VelocityShaper};
 
 const TICKS_PER_QUARTER_NOTE: u32 = 24;
+// Bars are counted in 4/4
+const BEATS_PER_BAR: u32 = 4;
+const TICKS_PER_BAR: u32 = TICKS_PER_QUARTER_NOTE * BEATS_PER_BAR;
+// A MIDI beat of the song position pointer is a 16th note
+const TICKS_PER_MIDI_BEAT: u32 = TICKS_PER_QUARTER_NOTE / 4;
+// The fastest tempo the timer can keep, where a clock tick lasts 1 ms, the shortest interval
+// it schedules
+pub const MAX_BPM: f32 = 60_000.0 / TICKS_PER_QUARTER_NOTE as f32;
+const STEPS_PER_OCTAVE: i32 = 12;
+const MAX_MIDI_NOTE: f32 = 127.0;
+const NOTE_ON_MSG: u8 = 0x90;
+const NOTE_OFF_MSG: u8 = 0x80;
+const CONTROL_CHANGE_MSG: u8 = 0xB0;
+const CHANNEL_PRESSURE_MSG: u8 = 0xD0;
+const PITCH_BEND_MSG: u8 = 0xE0;
+const PITCH_BEND_CENTER: u16 = 0x2000;
+// The default pitch bend range of most synths, which have to be left at it for microtonal
+// pitches to be in tune
+const PITCH_BEND_RANGE_SEMITONES: f32 = 2.0;
+const SONG_POSITION_POINTER_MSG: u8 = 0xF2;
+// The song position pointer is a 14 bit value
+const MAX_SONG_POSITION: u16 = 0x3FFF;
+const PAN_CONTROLLER: u8 = 0x0A;
+const ALL_SOUND_OFF_CONTROLLER: u8 = 0x78;
+const ALL_NOTES_OFF_CONTROLLER: u8 = 0x7B;
+const VELOCITY: u8 = 0x64;
+// The release velocity for devices that do not respond to it, as recommended by the MIDI spec
+const RELEASE_VELOCITY: u8 = 0x40;
+// The count-in clicks are sent on the General MIDI drum channel 10, away from the melody,
+// as a high wood block on the first beat of each bar and a low one on the others
+const METRONOME_NOTE_ON_MSG: u8 = 0x99;
+const METRONOME_NOTE_OFF_MSG: u8 = 0x89;
+const METRONOME_ACCENT_NOTE: u8 = 76;
+const METRONOME_NOTE: u8 = 77;
+// Each part of the sequencer draws its seeds from its own stream of a locked seed
+const PITCH_SEED_STREAM: u64 = 0;
+const TRIGGER_SEED_STREAM: u64 = 1;
+const MODULATION_SEED_STREAM: u64 = 2;
+const ENGINE_SEED_STREAM: u64 = 3;
+const TRANSPOSITION_SEED_STREAM: u64 = 4;
+// Steps the trigger density is averaged over, a bar of 16th notes
+const TRIGGER_DENSITY_WINDOW: usize = 16;
+const MIDI_PORT_NAME: &str = "Nannou Generative Sequencer";
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NoteLength {
+    ThirtySecond,
+    Sixteenth,
+    Eighth,
+    Quarter,
+}
+
+impl NoteLength {
+    pub fn to_ticks(&self, ppqn: u32) -> u32 {
+        let ticks = match *self {
+            NoteLength::ThirtySecond => ppqn / 8,
+            NoteLength::Sixteenth => ppqn / 4,
+            NoteLength::Eighth => ppqn / 2,
+            NoteLength::Quarter => ppqn,
+        };
+        // a note must last at least one tick to be audible
+        ticks.max(1)
+    }
+}
+
+impl Display for NoteLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            NoteLength::ThirtySecond => write!(f, "1/32"),
+            NoteLength::Sixteenth => write!(f, "1/16"),
+            NoteLength::Eighth => write!(f, "1/8"),
+            NoteLength::Quarter => write!(f, "1/4"),
+        }
+    }
+}
+
+impl FromStr for NoteLength {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1/32" => Ok(NoteLength::ThirtySecond),
+            "1/16" => Ok(NoteLength::Sixteenth),
+            "1/8" => Ok(NoteLength::Eighth),
+            "1/4" => Ok(NoteLength::Quarter),
+            _ => Err(()),
+        }
+    }
+}
+
+// How a note is ended, since some devices only understand a note on with velocity 0. Only
+// the note off message carries a release velocity.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NoteOffStyle {
+    Status80,
+    NoteOnVelocity0,
+}
+
+impl NoteOffStyle {
+    fn message(&self, channel: u8, note: u8, release_velocity: u8) -> [u8; 3] {
+        match *self {
+            NoteOffStyle::Status80 => [NOTE_OFF_MSG | channel, note, release_velocity],
+            NoteOffStyle::NoteOnVelocity0 => [NOTE_ON_MSG | channel, note, 0],
+        }
+    }
+}
+
+// The MIDI channels (0 to 15) the notes are sent on: always the first channel, or the next
+// channel of the list for every played note, so successive notes hit different synth
+// patches. The modulation lane stays on the first channel.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ChannelMode {
+    Fixed,
+    RoundRobin(Vec<u8>),
+}
+
+impl ChannelMode {
+    fn channels(&self) -> &[u8] {
+        match self {
+            ChannelMode::RoundRobin(channels) if !channels.is_empty() => channels,
+            _ => &[0],
+        }
+    }
+}
+
+// Where the modulation lane is sent: a control change on the selected controller, the pan
+// controller, or channel pressure (aftertouch)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ModulationTarget {
+    ControlChange,
+    Pan,
+    Aftertouch,
+}
+
+impl Display for ModulationTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ModulationTarget::ControlChange => write!(f, "CC"),
+            ModulationTarget::Pan => write!(f, "Pan"),
+            ModulationTarget::Aftertouch => write!(f, "Aftertouch"),
+        }
+    }
+}
+
+impl FromStr for ModulationTarget {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CC" => Ok(ModulationTarget::ControlChange),
+            "Pan" => Ok(ModulationTarget::Pan),
+            "Aftertouch" => Ok(ModulationTarget::Aftertouch),
+            _ => Err(()),
+        }
+    }
+}
+
+// Which end of the pitch range a note is more likely to be played at, with the pitch chance
+// rule on
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PitchFavor {
+    High,
+    Low,
+}
+
+impl Display for PitchFavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            PitchFavor::High => write!(f, "Favor high"),
+            PitchFavor::Low => write!(f, "Favor low"),
+        }
+    }
+}
+
+impl FromStr for PitchFavor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Favor high" => Ok(PitchFavor::High),
+            "Favor low" => Ok(PitchFavor::Low),
+            _ => Err(()),
+        }
+    }
+}
 
 pub struct SequencerConfiguration {
+    // A disabled melody plays no notes, a disabled transposition leaves the melody as it is
+    pub melody_enabled: bool,
+    pub transposition_enabled: bool,
+    // Drum mode: every triggered step plays this note, e.g. a hi-hat, in place of the pitch
+    // lanes; None plays the pitch lanes
+    pub drum_note: Option<LetterOctave>,
     pub melody_min_pitch: LetterOctave,
     pub melody_max_pitch: LetterOctave,
     pub melody_pitch_generator_type: PitchGeneratorType,
     pub melody_cycle_length: u32,
+    // Step of the cycle the ramp and square generators start at
+    pub melody_phase: u32,
+    pub melody_step_sequence: Vec<LetterOctave>,
+    // Weights of the scale degrees played by the degrees generator, starting at the first
+    // note of the scale
+    pub melody_degree_weights: Vec<f32>,
+    pub melody_ramp_mode: RampMode,
+    // Semitones between the low and high value of the square lanes, None to jump between
+    // the minimum and maximum of the range
+    pub square_interval: Option<i32>,
+    // None leaves the lane unquantized before it is summed
+    pub melody_quantizer_scale: Option<Vec<Letter>>,
+    // Second melody generator played instead of the first one by chance, None plays only
+    // the first one
+    pub melody_blend_generator_type: Option<PitchGeneratorType>,
+    // Share of the steps played by the blend generator, from 0 to 1
+    pub melody_blend_weight: f32,
     pub transposition_min_pitch: LetterOctave,
     pub transposition_max_pitch: LetterOctave,
     pub transposition_pitch_generator_type: PitchGeneratorType,
     pub transposition_cycle_length: u32,
+    pub transposition_phase: u32,
+    pub transposition_quantizer_scale: Option<Vec<Letter>>,
+    // Scale degrees of the chord roots, 0 being the tonic
+    pub transposition_progression: Vec<i32>,
+    // Snap the melody to the nearest tone of the current chord of the progression
+    pub transposition_chord_tones: bool,
     pub trigger_probablilty: f32,
+    pub trigger_restlessness: f32,
+    pub trigger_type: TriggerType,
+    // Steps played and rested by the gate trigger types, or clock ticks when the trigger comes
+    // before the clock divider
+    pub gate_on: u32,
+    pub gate_off: u32,
     pub clock_divider_factor: u32,
+    // Clock tick within the division the steps land on
+    pub clock_divider_offset: u32,
+    pub trigger_order: TriggerOrder,
     pub quantizer_scale: Vec<Letter>,
+    pub quantizer_strength: f32,
+    pub quantizer_direction: QuantizeDirection,
+    pub allowed_octaves: RangeInclusive<i32>,
+    // Register the shared scale applies to, notes outside it stay chromatic
+    pub quantize_range: Option<RangeInclusive<Step>>,
+    // Semitones the quantized notes are shifted by, for key changes without changing the
+    // scale; the shifted notes can leave the scale
+    pub transpose: i32,
+    // Chance a quantized note is moved up or down by up to the set number of octaves
+    pub octave_scatter_probability: f32,
+    pub octave_scatter_octaves: u32,
+    // Each note is held for a random length between these two
+    pub note_length_min: NoteLength,
+    pub note_length_max: NoteLength,
+    // Subdivision a played note is struck again at until its step is over, None to strike
+    // it once
+    pub note_repeat: Option<NoteLength>,
+    // Bounds of the played note duration in milliseconds, whatever the note length and tempo
+    pub note_min_duration_ms: f32,
+    pub note_max_duration_ms: f32,
+    pub tie_repeated_notes: bool,
+    // Chance a played note is tied into the next step instead of being released
+    pub tie_probability: f32,
+    pub octave_offset: i32,
+    pub note_off_style: NoteOffStyle,
+    pub channel_mode: ChannelMode,
+    // Play the fraction of a semitone of in-between pitches as a pitch bend before each note
+    pub microtonal: bool,
+    // How fast a note is released, for synths responding to the note off velocity
+    pub release_velocity: u8,
+    // Notes held at once, the oldest is stolen when a new note would exceed it
+    pub max_voices: usize,
+    // End the sounding note before the next one starts, for mono synths that glitch on
+    // overlapping notes, whatever the number of voices
+    pub mono: bool,
+    // Semitones of the notes played along with each melody note, snapped into the shared
+    // scale; None plays the melody notes alone
+    pub harmony_intervals: Option<Vec<i32>>,
+    pub pitch_history_length: usize,
+    pub velocity_mode: VelocityMode,
+    pub velocity_curve: VelocityCurve,
+    // How much louder notes are held longer: at 1 the note length scales with the velocity,
+    // at 0 the velocity has no effect on it
+    pub velocity_length: f32,
+    pub modulation_enabled: bool,
+    pub modulation_generator_type: PitchGeneratorType,
+    pub modulation_min: u8,
+    pub modulation_max: u8,
+    pub modulation_cycle_length: u32,
+    pub modulation_target: ModulationTarget,
+    pub modulation_controller: u8,
     pub bpm: f32,
+    pub tempo_automation: TempoAutomation,
+    // Bars of metronome clicks before the notes start when playback is started, 0 for none
+    pub count_in_bars: u32,
+    pub fill: Fill,
+    // Couple the rhythm to the melody: the triggered notes are only played by a chance that
+    // grows towards the favored end of the pitch range; None plays all of them
+    pub pitch_chance: Option<PitchFavor>,
+    // Only move the pitch generators on when a note is triggered, so that rests don't skip
+    // positions of the pitch pattern
+    pub advance_on_trigger_only: bool,
+    pub humanize: Humanize,
+    pub swing: Swing,
+    // Send a song position pointer when the sequencer is reset, so that slaved devices
+    // follow it back to the start
+    pub send_song_position: bool,
+    // Rewind the generators to the start of their patterns when playback is stopped, so
+    // that it starts fresh instead of resuming mid-pattern
+    pub stop_resets_position: bool,
+    // Most messages sent per tick, with unchanged controller values left out, to protect
+    // slow MIDI devices; None sends every message
+    pub midi_rate_limit: Option<usize>,
+    // Seed of all random modules, so that they play the same every time they are built.
+    // None seeds them with fresh randomness instead.
+    pub seed: Option<u64>,
+    // Seeds of the melody, transposition and trigger lanes of their own, taking the place of
+    // the seed above for their lane, so that a lane can be locked while another one varies
+    pub melody_seed: Option<u64>,
+    pub transposition_seed: Option<u64>,
+    pub trigger_seed: Option<u64>,
+}
+
+// Slow sine wave swinging the tempo around the set BPM, e.g. a depth of 10 BPM over a period
+// of 16 bars breathes between 110 and 130 BPM at 120 BPM
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TempoAutomation {
+    pub depth: f32,
+    pub period_bars: u32,
+}
+
+impl TempoAutomation {
+    pub fn is_enabled(&self) -> bool {
+        self.depth > 0.0 && self.period_bars > 0
+    }
+
+    // Tempo at the start of the given bar
+    pub fn bpm(&self, bpm: f32, bar: u32) -> f32 {
+        if !self.is_enabled() {
+            return bpm;
+        }
+        let phase = (bar % self.period_bars) as f32 / self.period_bars as f32;
+        // keep the tempo positive however deep the swing
+        (bpm + self.depth * (std::f32::consts::TAU * phase).sin()).max(1.0)
+    }
+}
+
+// Busier playing in the last bar of every phrase, like a drum fill: the steps the trigger
+// rests are played by chance, e.g. with an intensity of 0.5 half of them over 4-bar phrases
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Fill {
+    pub phrase_bars: u32,
+    pub intensity: f32,
+}
+
+impl Fill {
+    pub fn is_enabled(&self) -> bool {
+        self.intensity > 0.0 && self.phrase_bars > 0
+    }
+
+    // Whether the given bar is the last one of its phrase
+    pub fn is_fill_bar(&self, bar: u32) -> bool {
+        self.is_enabled() && bar % self.phrase_bars == self.phrase_bars - 1
+    }
+}
+
+// The chance a triggered note is played at, from its position in the range the melody and
+// transposition add up to: at the favored end it is always played, at the other end never
+#[derive(Clone, PartialEq, Debug)]
+pub struct PitchChance {
+    pub favor: PitchFavor,
+    pub pitch_range: RangeInclusive<f32>,
+}
+
+impl PitchChance {
+    pub fn probability(&self, step: f32) -> f32 {
+        let (low, high) = (*self.pitch_range.start(), *self.pitch_range.end());
+        let position = if high > low {
+            ((step - low) / (high - low)).clamp(0.0, 1.0)
+        } else {
+            // a range of a single pitch is in the middle of itself
+            0.5
+        };
+        match self.favor {
+            PitchFavor::High => position,
+            PitchFavor::Low => 1.0 - position,
+        }
+    }
+}
+
+// Random deviations that make the playing less mechanical: each note's velocity is moved by
+// up to the velocity jitter either way, the note is delayed by up to the timing jitter, and
+// its length is scaled by up to the length jitter either way, e.g. 0.2 for 80-120%
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Humanize {
+    pub velocity_jitter: u8,
+    pub timing_jitter_ticks: u32,
+    pub length_jitter: f32,
+}
+
+impl Humanize {
+    pub fn is_enabled(&self) -> bool {
+        self.velocity_jitter > 0 || self.timing_jitter_ticks > 0 || self.length_jitter > 0.0
+    }
+}
+
+// Delays the second step of every pair of steps at the resolution, e.g. with 8th-note swing
+// the off-beat 8th of each beat. The amount is the share of the pair the first step lasts:
+// 0.5 plays straight, 0.67 a triplet feel, and 0.75 a dotted feel.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Swing {
+    pub amount: f32,
+    pub resolution: NoteLength,
+}
+
+impl Swing {
+    // Ticks a note starting at the given position in the bar is held back by
+    pub fn delay_ticks(&self, position_ticks: u32) -> u32 {
+        let step_ticks = self.resolution.to_ticks(TICKS_PER_QUARTER_NOTE);
+        if position_ticks % (2 * step_ticks) != step_ticks {
+            return 0;
+        }
+        ((self.amount - 0.5) * 2.0 * step_ticks as f32)
+            .round()
+            .max(0.0) as u32
+    }
+}
+
+// Seeds for the random modules being built. A locked seed hands out the same seeds in the
+// same order every time, so rebuilt modules reproduce their output.
+struct SeedSource(Option<Pcg32>);
+
+impl SeedSource {
+    // The stream selects one of the generator's independent sequences, so that the same
+    // seed on a neighbouring stream doesn't line up with a neighbouring seed
+    fn new(seed: Option<u64>, stream: u64) -> SeedSource {
+        SeedSource(seed.map(|seed| Pcg32::new(seed, stream)))
+    }
+
+    fn next(&mut self) -> u64 {
+        match &mut self.0 {
+            Some(rng) => rng.next_u64(),
+            None => random(),
+        }
+    }
+
+    // The next seed of a lane with a seed of its own, if it has one. The seed is drawn from
+    // this source either way, so that the seeds of the other modules stay put.
+    fn next_for_lane(&mut self, lane: &mut Option<SeedSource>) -> u64 {
+        let seed = self.next();
+        match lane {
+            Some(lane) => lane.next(),
+            None => seed,
+        }
+    }
+}
+
+// The settings a lane builds its generator from. Lanes without some of the options leave
+// them empty.
+#[derive(Clone, Copy)]
+struct LaneParameters<'a> {
+    cycle_length: u32,
+    phase: u32,
+    min: LetterOctave,
+    max: LetterOctave,
+    ramp_mode: RampMode,
+    square_interval: Option<i32>,
+    step_sequence: &'a [LetterOctave],
+    progression: &'a [i32],
+    scale: &'a [Letter],
+    degree_weights: &'a [f32],
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SequencerError {
+    Disconnected,
+}
+
+impl Display for SequencerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            SequencerError::Disconnected => write!(f, "sequencer thread is not running"),
+        }
+    }
+}
+
+impl std::error::Error for SequencerError {}
+
+// The MIDI output could not be opened, with the reason given by the MIDI backend
+#[derive(Debug, PartialEq)]
+pub struct MidiOutputError(String);
+
+impl Display for MidiOutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to open MIDI output: {}", self.0)
+    }
+}
+
+impl std::error::Error for MidiOutputError {}
+
+impl<T> From<mpsc::SendError<T>> for SequencerError {
+    fn from(_: mpsc::SendError<T>) -> Self {
+        SequencerError::Disconnected
+    }
+}
+
+// Live position of the generators, to resume a performance exactly where it left off
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SequencerState {
+    pitch_generator: GeneratorState,
+    trigger_generator: GeneratorState,
+    modulation: Option<GeneratorState>,
 }
 
 enum SequencerCommand {
     Start,
     Stop,
+    Panic,
     SetPitchGenerator(Box<dyn PitchModule>),
+    SetMelodyRange(LetterOctave, LetterOctave),
+    SetTranspositionRange(LetterOctave, LetterOctave),
+    SetCycleLength(Lane, u32),
+    SetQuantizerScale(Vec<Letter>),
+    SetQuantizerPitchRange(Option<RangeInclusive<Step>>),
+    SetMelodyEnabled(bool),
     SetTriggerGenerator(Box<dyn TriggerModule>),
+    SetNoteLength(RangeInclusive<u32>),
+    SetNoteRepeat(Option<u32>),
+    SetNoteDurationRange(RangeInclusive<f32>),
+    SetTickDuration(f32),
+    SetTieRepeatedNotes(bool),
+    SetTieProbability(f32),
+    SetTriggerProbability(f32),
+    SetOctaveOffset(i32),
+    SetNoteOffStyle(NoteOffStyle),
+    SetReleaseVelocity(u8),
+    SetChannelMode(ChannelMode),
+    SetMicrotonal(bool),
+    SetMaxVoices(usize),
+    SetMono(bool),
+    SetHarmonizer(Option<Harmonizer>),
+    SetVelocity(VelocityShaper),
+    SetVelocityLength(f32),
+    SetModulation(Option<ModulationLane>),
+    SetCountIn(u32),
+    SetFill(Fill),
+    SetPitchChance(Option<PitchChance>),
+    SetAdvanceOnTriggerOnly(bool),
+    SetHumanize(Humanize),
+    SetSwing(Swing),
+    SetSendSongPosition(bool),
+    SetMidiRateLimit(Option<usize>),
+    SetStopResetsPosition(bool),
+    Rewind,
+    SetSeed(Option<u64>),
+    RestoreState(SequencerState),
 }
 
 pub struct Sequencer {
     sender: mpsc::Sender<SequencerCommand>,
-    _timer: Timer,
+    // The timer callback owns the thread, so it is torn down when the callback is dropped
+    thread: Weak<Mutex<SequencerThread>>,
+    pitch_history: Arc<Mutex<PitchHistory>>,
+    last_step: Arc<Mutex<Option<(LetterOctave, Trigger)>>>,
+    trigger_density: Arc<Mutex<TriggerDensity>>,
+    timer: Timer,
+    guard: Guard,
+    bpm: f32,
+    // Tempo the timer is scheduled at, the BPM moved by the tempo automation
+    scheduled_bpm: f32,
+    tempo_automation: TempoAutomation,
+    // Bars played so far, counted by the sequencer thread
+    bar: Arc<AtomicU32>,
+    last_bar: u32,
 }
 
 impl Sequencer {
-    pub fn new(config: SequencerConfiguration, is_playing: bool) -> Sequencer {
+    pub fn new(
+        config: SequencerConfiguration,
+        is_playing: bool,
+        midi_output: Box<dyn MidiSink>,
+    ) -> Sequencer {
         // Create async communication channel to the sequencer thread
         let (tx, rx) = mpsc::channel();
-        let mut thread = SequencerThread::new(
-            rx,
-            Sequencer::build_pitch_generator(&config),
-            Sequencer::build_trigger_generator(&config),
-            is_playing,
-        );
+        let engine = SequencerEngine::new(&config, is_playing);
+        let pitch_history = engine.pitch_history.clone();
+        let last_step = engine.last_step.clone();
+        let trigger_density = engine.trigger_density.clone();
+        let bar = engine.bar.clone();
+        let mut thread = SequencerThread::new(rx, engine, midi_output);
+        thread.rate_limiter.set_limit(config.midi_rate_limit);
 
         // Schedule the sequencer thread
         let timer = Timer::new();
-        let guard = timer.schedule_repeating(
-            Duration::milliseconds((60_000.0 / config.bpm / TICKS_PER_QUARTER_NOTE as f32) as i64),
-            move || thread.tick(),
-        );
-        guard.ignore();
+        let thread = Arc::new(Mutex::new(thread));
+        let bpm = clamp_bpm(config.bpm);
+        let guard = Sequencer::schedule(&timer, thread.clone(), bpm);
 
         Sequencer {
             sender: tx,
-            _timer: timer,
+            thread: Arc::downgrade(&thread),
+            pitch_history,
+            last_step,
+            trigger_density,
+            timer,
+            guard,
+            bpm,
+            scheduled_bpm: bpm,
+            tempo_automation: config.tempo_automation,
+            bar,
+            last_bar: 0,
+        }
+    }
+
+    pub fn state(&self) -> Result<SequencerState, SequencerError> {
+        let thread = self.thread.upgrade().ok_or(SequencerError::Disconnected)?;
+        let state = thread.lock().unwrap().engine.state();
+        Ok(state)
+    }
+
+    // The state is restored after any queued generator updates, so it can be sent right
+    // after the configuration it was saved with
+    pub fn restore(&self, state: SequencerState) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::RestoreState(state))?;
+        Ok(())
+    }
+
+    // Pitches of the most recently played notes, oldest first. Not used by the app itself,
+    // it is meant for embedders building their own visualizations.
+    pub fn pitch_history(&self) -> Vec<LetterOctave> {
+        self.pitch_history.lock().unwrap().to_vec()
+    }
+
+    // Pitch and trigger of the latest step, including the ones without a note. It only
+    // changes once per step, not on the clock ticks in between.
+    pub fn last_step(&self) -> Option<(LetterOctave, Trigger)> {
+        *self.last_step.lock().unwrap()
+    }
+
+    // Share of the recent steps that played a note, from 0 to 1
+    pub fn trigger_density(&self) -> f32 {
+        self.trigger_density.lock().unwrap().density()
+    }
+
+    // A MIDI sink sending messages from outside the sequencer, e.g. MIDI thru, on the MIDI
+    // output of the sequencer, merged with the generated ones
+    pub fn thru_sink(&self) -> Box<dyn MidiSink> {
+        Box::new(ThruMidiSink {
+            thread: self.thread.clone(),
+        })
+    }
+
+    fn schedule(timer: &Timer, thread: Arc<Mutex<SequencerThread>>, bpm: f32) -> Guard {
+        timer.schedule_repeating(Duration::microseconds(timer_interval_us(bpm)), move || {
+            thread.lock().unwrap().tick()
+        })
+    }
+
+    pub fn set_bpm(&mut self, bpm: f32) -> Result<(), SequencerError> {
+        self.bpm = clamp_bpm(bpm);
+        self.reschedule()
+    }
+
+    pub fn update_tempo_automation(
+        &mut self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.tempo_automation = config.tempo_automation;
+        self.reschedule()
+    }
+
+    // Tempo the timer is meant to run at, including the tempo automation
+    pub fn target_bpm(&self) -> f32 {
+        self.scheduled_bpm
+    }
+
+    // Whole microseconds between the clock ticks as scheduled, which the actual tempo
+    // follows from
+    pub fn tick_interval_us(&self) -> i64 {
+        timer_interval_us(self.scheduled_bpm)
+    }
+
+    // Bars played so far, counted from the last rewind
+    pub fn bar(&self) -> u32 {
+        self.bar.load(Ordering::Relaxed)
+    }
+
+    // Move the tempo along the automation once a new bar has started. Meant to be called
+    // regularly, e.g. once per frame.
+    pub fn follow_tempo_automation(&mut self) -> Result<(), SequencerError> {
+        let bar = self.bar.load(Ordering::Relaxed);
+        if bar == self.last_bar {
+            return Ok(());
+        }
+        self.last_bar = bar;
+        if !self.tempo_automation.is_enabled() {
+            return Ok(());
         }
+        self.reschedule()
+    }
+
+    fn reschedule(&mut self) -> Result<(), SequencerError> {
+        let thread = self.thread.upgrade().ok_or(SequencerError::Disconnected)?;
+        let bpm = clamp_bpm(self.tempo_automation.bpm(self.bpm, self.last_bar));
+        self.scheduled_bpm = bpm;
+        // Replacing the guard cancels the schedule using the previous tempo
+        self.guard = Sequencer::schedule(&self.timer, thread, bpm);
+        // Let the note durations follow the new tempo
+        self.sender
+            .send(SequencerCommand::SetTickDuration(tick_duration_ms(bpm)))?;
+        Ok(())
     }
 
-    pub fn start(&self) {
+    pub fn start(&self) -> Result<(), SequencerError> {
         info!("Start");
-        self.sender.send(SequencerCommand::Start).unwrap();
+        self.sender.send(SequencerCommand::Start)?;
+        Ok(())
     }
 
-    pub fn stop(&self) {
+    pub fn stop(&self) -> Result<(), SequencerError> {
         info!("Stop");
-        self.sender.send(SequencerCommand::Stop).unwrap();
+        self.sender.send(SequencerCommand::Stop)?;
+        Ok(())
     }
 
-    fn build_pitch_generator(config: &SequencerConfiguration) -> Box<dyn PitchModule> {
-        let melody_pitch_generator: Box<dyn PitchModule> = match config.melody_pitch_generator_type
-        {
-            PitchGeneratorType::Random => Box::new(RandomPitchGenerator::new(
-                config.melody_min_pitch,
-                config.melody_max_pitch,
-            )),
-            PitchGeneratorType::RampUp => Box::new(RampPitchGenerator::new(
-                config.melody_cycle_length as u32,
-                config.melody_min_pitch,
-                config.melody_max_pitch,
+    fn build_generator(
+        generator_type: &PitchGeneratorType,
+        lane: &LaneParameters,
+        seed: u64,
+    ) -> Box<dyn PitchModule> {
+        let LaneParameters {
+            cycle_length,
+            phase,
+            min,
+            max,
+            ramp_mode,
+            square_interval,
+            step_sequence,
+            progression,
+            scale,
+            degree_weights,
+        } = *lane;
+        match generator_type {
+            PitchGeneratorType::Random => Box::new(RandomPitchGenerator::new(min, max, seed)),
+            PitchGeneratorType::RampUp => {
+                let mut generator = RampPitchGenerator::new(cycle_length, phase, min, max);
+                generator.set_mode(ramp_mode);
+                Box::new(generator)
+            }
+            PitchGeneratorType::Square => {
+                let mut generator = SquarePitchGenerator::new(cycle_length, phase, min, max);
+                generator.set_interval(square_interval);
+                Box::new(generator)
+            }
+            PitchGeneratorType::Steps => {
+                Box::new(StepSequencePitchGenerator::new(step_sequence.to_vec()))
+            }
+            // the cycle length sets how many steps each chord lasts
+            PitchGeneratorType::Progression => Box::new(ProgressionPitchGenerator::new(
+                progression,
+                cycle_length,
+                scale,
             )),
-            PitchGeneratorType::Square => Box::new(SquarePitchGenerator::new(
-                config.melody_cycle_length as u32,
-                config.melody_min_pitch,
-                config.melody_max_pitch,
+            // notes of the scale degrees, in the octaves of the range
+            PitchGeneratorType::Degrees => Box::new(WeightedScalePitchGenerator::new(
+                scale,
+                min.1..=max.1,
+                degree_weights,
+                seed,
             )),
+        }
+    }
+
+    // Quantize a lane to its own scale, keeping it in the range it is generated in, or pass it
+    // through untouched without one
+    fn quantize_lane(
+        generator: Box<dyn PitchModule>,
+        scale: Option<Vec<Letter>>,
+        pitch_range: Option<RangeInclusive<Step>>,
+    ) -> Box<dyn PitchModule> {
+        match scale {
+            Some(scale) => {
+                let mut quantizer = PitchQuantizer::new(generator, scale);
+                quantizer.set_pitch_range(pitch_range);
+                Box::new(quantizer)
+            }
+            None => generator,
+        }
+    }
+
+    // The range the pitches of a lane are generated in, or None for the step sequences and
+    // chord progressions, which play their own notes whatever the range
+    fn lane_pitch_range(
+        generator_types: &[&PitchGeneratorType],
+        min_pitch: LetterOctave,
+        max_pitch: LetterOctave,
+    ) -> Option<RangeInclusive<Step>> {
+        let plays_own_notes = generator_types.iter().any(|generator_type| {
+            matches!(
+                generator_type,
+                PitchGeneratorType::Steps | PitchGeneratorType::Progression
+            )
+        });
+        // the generators swap an inverted range
+        let (low, high) = if min_pitch.step() <= max_pitch.step() {
+            (min_pitch, max_pitch)
+        } else {
+            (max_pitch, min_pitch)
         };
-        let transposition_pitch_generator: Box<dyn PitchModule> =
-            match config.transposition_pitch_generator_type {
-                PitchGeneratorType::Random => Box::new(RandomPitchGenerator::new(
-                    config.transposition_min_pitch,
-                    config.transposition_max_pitch,
-                )),
-                PitchGeneratorType::RampUp => Box::new(RampPitchGenerator::new(
-                    config.transposition_cycle_length as u32,
-                    config.transposition_min_pitch,
-                    config.transposition_max_pitch,
-                )),
-                PitchGeneratorType::Square => Box::new(SquarePitchGenerator::new(
-                    config.transposition_cycle_length as u32,
-                    config.transposition_min_pitch,
-                    config.transposition_max_pitch,
-                )),
-            };
+        (!plays_own_notes).then(|| low.to_step()..=high.to_step())
+    }
 
-        Box::new(PitchQuantizer::new(
+    // The melody and transposition lanes are each quantized to their own scale (if any),
+    // then summed, and the sum is quantized to the shared scale and folded into the
+    // allowed octaves. Lane scales of different keys layer into polytonal lines, while
+    // the shared scale decides the notes that are actually played. A disabled lane is
+    // replaced by a constant 0 that is never ticked; the engine silences a disabled melody.
+    // In drum mode the whole chain is replaced by the drum note.
+    pub fn build_pitch_generator(config: &SequencerConfiguration) -> Box<dyn PitchModule> {
+        if let Some(drum_note) = config.drum_note {
+            return Box::new(ConstantPitchGenerator::new(drum_note));
+        }
+        let mut seeds = SeedSource::new(config.seed, PITCH_SEED_STREAM);
+        let mut melody_seeds = config
+            .melody_seed
+            .map(|seed| SeedSource::new(Some(seed), PITCH_SEED_STREAM));
+        let mut transposition_seeds = config
+            .transposition_seed
+            .map(|seed| SeedSource::new(Some(seed), TRANSPOSITION_SEED_STREAM));
+        // scale degrees follow the lane scale, or the shared scale without one
+        let degree_scale = config
+            .melody_quantizer_scale
+            .as_ref()
+            .unwrap_or(&config.quantizer_scale);
+        let melody_lane = LaneParameters {
+            cycle_length: config.melody_cycle_length,
+            phase: config.melody_phase,
+            min: config.melody_min_pitch,
+            max: config.melody_max_pitch,
+            ramp_mode: config.melody_ramp_mode,
+            square_interval: config.square_interval,
+            step_sequence: &config.melody_step_sequence,
+            // only the transposition lane offers chord progressions
+            progression: &[],
+            scale: degree_scale,
+            degree_weights: &config.melody_degree_weights,
+        };
+        let build_melody_generator =
+            |generator_type, seed| Sequencer::build_generator(generator_type, &melody_lane, seed);
+        let mut melody_pitch_generator = build_melody_generator(
+            &config.melody_pitch_generator_type,
+            seeds.next_for_lane(&mut melody_seeds),
+        );
+        if let Some(blend_generator_type) = &config.melody_blend_generator_type {
+            melody_pitch_generator = Box::new(SwitchPitchGenerator::new(
+                vec![
+                    (melody_pitch_generator, 1.0 - config.melody_blend_weight),
+                    (
+                        build_melody_generator(
+                            blend_generator_type,
+                            seeds.next_for_lane(&mut melody_seeds),
+                        ),
+                        config.melody_blend_weight,
+                    ),
+                ],
+                seeds.next_for_lane(&mut melody_seeds),
+            ));
+        }
+        // the disabled melody is still built, so that the seeds of the other modules stay put
+        let melody_pitch_generator = if config.melody_enabled {
+            Sequencer::quantize_lane(
+                melody_pitch_generator,
+                config.melody_quantizer_scale.clone(),
+                Sequencer::melody_pitch_range(config),
+            )
+        } else {
+            Box::new(ConstantPitchGenerator::new(Step(0.0).to_letter_octave()))
+        };
+        // chord roots follow the lane scale, or the shared scale without one
+        let progression_scale = config
+            .transposition_quantizer_scale
+            .as_ref()
+            .unwrap_or(&config.quantizer_scale);
+        let snaps_to_chord_tones = config.transposition_enabled
+            && config.transposition_chord_tones
+            && config.transposition_pitch_generator_type == PitchGeneratorType::Progression;
+        let sum: Box<dyn PitchModule> = if !config.transposition_enabled {
+            Box::new(PitchAdder::new(
+                melody_pitch_generator,
+                Box::new(ConstantPitchGenerator::new(Step(0.0).to_letter_octave())),
+            ))
+        } else if snaps_to_chord_tones {
+            // the chord roots are already in the lane scale, so the lane is not quantized
+            Box::new(ChordTonePitchAdder::new(
+                melody_pitch_generator,
+                ProgressionPitchGenerator::new(
+                    &config.transposition_progression,
+                    config.transposition_cycle_length,
+                    progression_scale,
+                ),
+            ))
+        } else {
+            let transposition_pitch_generator = Sequencer::quantize_lane(
+                Sequencer::build_generator(
+                    &config.transposition_pitch_generator_type,
+                    &LaneParameters {
+                        cycle_length: config.transposition_cycle_length,
+                        phase: config.transposition_phase,
+                        min: config.transposition_min_pitch,
+                        max: config.transposition_max_pitch,
+                        // only the melody lane offers ping-pong ramps and step sequences
+                        ramp_mode: RampMode::Sawtooth,
+                        square_interval: config.square_interval,
+                        step_sequence: &[],
+                        progression: &config.transposition_progression,
+                        scale: progression_scale,
+                        // only the melody lane offers weighted scale degrees
+                        degree_weights: &[],
+                    },
+                    seeds.next_for_lane(&mut transposition_seeds),
+                ),
+                config.transposition_quantizer_scale.clone(),
+                Sequencer::transposition_pitch_range(config),
+            );
             Box::new(PitchAdder::new(
                 melody_pitch_generator,
                 transposition_pitch_generator,
-            )),
+            ))
+        };
+
+        let mut quantizer = PitchQuantizer::with_strength(
+            sum,
             config.quantizer_scale.clone(),
+            config.quantizer_strength,
+            seeds.next(),
+        );
+        quantizer.set_direction(config.quantizer_direction);
+        // Quantizing a chord tone could move it off the chord, so it is only folded
+        quantizer.set_bypass(snaps_to_chord_tones);
+        // Keep transposed notes within the allowed register
+        quantizer.set_allowed_octaves(config.allowed_octaves.clone());
+        quantizer.set_quantize_range(config.quantize_range.clone());
+        quantizer.set_pitch_range(Sequencer::quantizer_pitch_range(config));
+        // Scattered notes keep their scale, so the scatter comes after the quantizer
+        let scatter = OctaveScatterPitchModule::new(
+            Box::new(quantizer),
+            config.octave_scatter_probability,
+            config.octave_scatter_octaves,
+            seeds.next(),
+        );
+        Box::new(TransposePitchModule::new(
+            Box::new(scatter),
+            config.transpose,
         ))
     }
 
-    fn build_trigger_generator(config: &SequencerConfiguration) -> Box<dyn TriggerModule> {
-        Box::new(ClockDivider::new(
-            Box::new(RandomTriggerGenerator::new(config.trigger_probablilty)),
-            config.clock_divider_factor,
-        ))
+    fn melody_pitch_range(config: &SequencerConfiguration) -> Option<RangeInclusive<Step>> {
+        let mut generator_types = vec![&config.melody_pitch_generator_type];
+        generator_types.extend(&config.melody_blend_generator_type);
+        Sequencer::lane_pitch_range(
+            &generator_types,
+            config.melody_min_pitch,
+            config.melody_max_pitch,
+        )
     }
 
-    pub fn update_pitch_generator(&self, config: SequencerConfiguration) {
-        self.sender
-            .send(SequencerCommand::SetPitchGenerator(
-                Sequencer::build_pitch_generator(&config),
-            ))
-            .unwrap();
+    fn transposition_pitch_range(config: &SequencerConfiguration) -> Option<RangeInclusive<Step>> {
+        Sequencer::lane_pitch_range(
+            &[&config.transposition_pitch_generator_type],
+            config.transposition_min_pitch,
+            config.transposition_max_pitch,
+        )
     }
 
-    pub fn update_trigger_generator(&self, config: SequencerConfiguration) {
-        self.sender
-            .send(SequencerCommand::SetTriggerGenerator(
-                Sequencer::build_trigger_generator(&config),
-            ))
-            .unwrap();
+    // The sum spans the ranges of both lanes added up, or the melody's alone without the
+    // transposition
+    fn quantizer_pitch_range(config: &SequencerConfiguration) -> Option<RangeInclusive<Step>> {
+        let melody_pitch_range = Sequencer::melody_pitch_range(config);
+        if !config.transposition_enabled {
+            return melody_pitch_range;
+        }
+        melody_pitch_range
+            .zip(Sequencer::transposition_pitch_range(config))
+            .map(|(melody, transposition)| {
+                Step(melody.start().0 + transposition.start().0)
+                    ..=Step(melody.end().0 + transposition.end().0)
+            })
     }
-}
 
-struct SequencerThread {
-    receiver: mpsc::Receiver<SequencerCommand>,
-    pitch_generator: Box<dyn PitchModule>,
-    trigger_generator: Box<dyn TriggerModule>,
-    midi_output_conn: MidiOutputConnection,
-    is_playing: bool,
-}
+    // Every pitch the melody and transposition lanes can add up to
+    fn summed_pitch_range(config: &SequencerConfiguration) -> RangeInclusive<f32> {
+        let low = config.melody_min_pitch.step() + config.transposition_min_pitch.step();
+        let high = config.melody_max_pitch.step() + config.transposition_max_pitch.step();
+        low..=high
+    }
 
-impl SequencerThread {
-    fn new(
-        receiver: mpsc::Receiver<SequencerCommand>,
-        pitch_generator: Box<dyn PitchModule>,
-        trigger_generator: Box<dyn TriggerModule>,
-        is_playing: bool,
-    ) -> SequencerThread {
-        // Create MIDI output
-        let midi_out = midir::MidiOutput::new("Nannou Generative Sequencer").unwrap();
-        info!("Available MIDI output ports:");
-        for (i, p) in midi_out.ports().iter().enumerate() {
-            info!("\t{}: {}", i, midi_out.port_name(p).unwrap());
-        }
-        // Connect to the first available MIDI output port (IAC Bus 1)
-        let out_port = &midi_out.ports()[0];
-        info!("Connecting to {}", midi_out.port_name(out_port).unwrap());
-        let out_conn = midi_out
-            .connect(out_port, "Nannou Generative Sequencer")
-            .unwrap();
+    fn build_pitch_chance(config: &SequencerConfiguration) -> Option<PitchChance> {
+        config.pitch_chance.map(|favor| PitchChance {
+            favor,
+            pitch_range: Sequencer::summed_pitch_range(config),
+        })
+    }
 
-        SequencerThread {
-            receiver,
-            pitch_generator,
-            trigger_generator,
-            midi_output_conn: out_conn,
-            is_playing: is_playing,
+    fn build_harmonizer(config: &SequencerConfiguration) -> Option<Harmonizer> {
+        config
+            .harmony_intervals
+            .as_ref()
+            .map(|intervals| Harmonizer::new(intervals.clone(), config.quantizer_scale.clone()))
+    }
+
+    fn build_velocity(config: &SequencerConfiguration) -> VelocityShaper {
+        // Pitch follow spans every note the melody and transposition lanes can add up to
+        let (low, high) = Sequencer::summed_pitch_range(config).into_inner();
+        VelocityShaper {
+            mode: config.velocity_mode,
+            curve: config.velocity_curve,
+            pitch_range: low.clamp(0.0, 127.0) as u8..=high.clamp(0.0, 127.0) as u8,
         }
     }
 
-    fn tick(&mut self) {
-        // Process all pending commands
-        for command in self.receiver.try_iter() {
-            match command {
-                SequencerCommand::Start => {
-                    if !self.is_playing {
-                        self.is_playing = true
-                    }
-                }
-                SequencerCommand::Stop => {
-                    if self.is_playing {
-                        self.is_playing = false
-                    }
-                }
-                SequencerCommand::SetPitchGenerator(pg) => {
-                    self.pitch_generator = pg;
-                }
-                SequencerCommand::SetTriggerGenerator(tg) => {
-                    self.trigger_generator = tg;
-                }
-            };
+    fn build_modulation(config: &SequencerConfiguration) -> Option<ModulationLane> {
+        if !config.modulation_enabled {
+            return None;
         }
+        let mut seeds = SeedSource::new(config.seed, MODULATION_SEED_STREAM);
+        Some(ModulationLane {
+            generator: Sequencer::build_generator(
+                &config.modulation_generator_type,
+                &LaneParameters {
+                    cycle_length: config.modulation_cycle_length,
+                    phase: 0,
+                    min: Step(config.modulation_min as f32).to_letter_octave(),
+                    max: Step(config.modulation_max as f32).to_letter_octave(),
+                    ramp_mode: RampMode::Sawtooth,
+                    // the modulation squares between its value range
+                    square_interval: None,
+                    step_sequence: &[],
+                    progression: &[],
+                    scale: &[],
+                    degree_weights: &[],
+                },
+                seeds.next(),
+            ),
+            target: config.modulation_target,
+            controller: config.modulation_controller,
+        })
+    }
 
-        // Play note
-        if self.is_playing {
-            let pitch = self.pitch_generator.tick();
-            match self.trigger_generator.tick() {
-                Trigger::On => {
-                    const NOTE_ON_MSG: u8 = 0x90;
-                    const NOTE_OFF_MSG: u8 = 0x80;
-                    const VELOCITY: u8 = 0x64;
+    pub fn build_trigger_generator(config: &SequencerConfiguration) -> Box<dyn TriggerModule> {
+        let mut seeds = SeedSource::new(config.trigger_seed.or(config.seed), TRIGGER_SEED_STREAM);
+        // Let the probability wander only when some restlessness is set
+        let random_trigger_generator: Box<dyn TriggerModule> = if config.trigger_restlessness > 0.0
+        {
+            Box::new(DrunkTriggerGenerator::new(
+                config.trigger_probablilty,
+                config.trigger_restlessness,
+                seeds.next(),
+            ))
+        } else {
+            Box::new(RandomTriggerGenerator::new(
+                config.trigger_probablilty,
+                seeds.next(),
+            ))
+        };
+        let gate = Box::new(GateRatioTriggerGenerator::new(
+            config.gate_on,
+            config.gate_off,
+        ));
+        let trigger_generator: Box<dyn TriggerModule> = match config.trigger_type {
+            TriggerType::Random => random_trigger_generator,
+            TriggerType::Gate => gate,
+            TriggerType::GatedRandom => Box::new(AndTrigger::new(gate, random_trigger_generator)),
+            TriggerType::GateOrRandom => Box::new(OrTrigger::new(gate, random_trigger_generator)),
+        };
+        let mut clock_divider = ClockDivider::new(
+            trigger_generator,
+            config.clock_divider_factor,
+            config.clock_divider_offset,
+        );
+        clock_divider.set_order(config.trigger_order);
+        Box::new(clock_divider)
+    }
 
-                    // Play the generated MIDI note
-                    let note = pitch.step() as u8;
+    pub fn panic(&self) -> Result<(), SequencerError> {
+        info!("Panic");
+        self.sender.send(SequencerCommand::Panic)?;
+        Ok(())
+    }
 
-                    info!("Play note: {}", format_letter_octave(pitch));
+    // Start the song position over, e.g. along with resetting the generators
+    pub fn rewind(&self) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::Rewind)?;
+        Ok(())
+    }
 
-                    self.midi_output_conn
-                        .send(&[NOTE_ON_MSG, note, VELOCITY])
-                        .unwrap();
-                    sleep(core::time::Duration::from_millis(5));
-                    self.midi_output_conn
-                        .send(&[NOTE_OFF_MSG, note, VELOCITY])
-                        .unwrap();
-                }
-                Trigger::Off => (),
+    // Change the probability of the trigger generator in place, so that its random draws
+    // carry on instead of starting over, e.g. while a slider is dragged
+    pub fn update_trigger_probability(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetTriggerProbability(
+            config.trigger_probablilty,
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_pitch_generator(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetPitchGenerator(
+            Sequencer::build_pitch_generator(&config),
+        ))?;
+        self.sender
+            .send(SequencerCommand::SetMelodyEnabled(config.melody_enabled))?;
+        // The pitch chance and the pitch follow velocity depend on the pitch ranges, so keep
+        // them in sync
+        self.sender.send(SequencerCommand::SetPitchChance(
+            Sequencer::build_pitch_chance(&config),
+        ))?;
+        self.update_velocity(config)
+    }
+
+    // Change the pitch range of a lane in place, so that the counters and random draws of its
+    // generators carry on instead of starting over, e.g. while a slider is dragged
+    pub fn update_pitch_range(
+        &self,
+        lane: Lane,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        let command = match lane {
+            Lane::Melody => {
+                SequencerCommand::SetMelodyRange(config.melody_min_pitch, config.melody_max_pitch)
             }
-        }
+            Lane::Transposition => SequencerCommand::SetTranspositionRange(
+                config.transposition_min_pitch,
+                config.transposition_max_pitch,
+            ),
+        };
+        self.sender.send(command)?;
+        self.sender.send(SequencerCommand::SetQuantizerPitchRange(
+            Sequencer::quantizer_pitch_range(&config),
+        ))?;
+        self.sender.send(SequencerCommand::SetPitchChance(
+            Sequencer::build_pitch_chance(&config),
+        ))?;
+        self.update_velocity(config)
+    }
+
+    // Change the cycle length of a lane in place, like the pitch range
+    pub fn update_cycle_length(
+        &self,
+        lane: Lane,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        let cycle_length = match lane {
+            Lane::Melody => config.melody_cycle_length,
+            Lane::Transposition => config.transposition_cycle_length,
+        };
+        self.sender
+            .send(SequencerCommand::SetCycleLength(lane, cycle_length))?;
+        Ok(())
+    }
+
+    // Change the shared scale in place, like the pitch range. Scale degrees and chord roots
+    // following the shared scale are built from it, so then the pitch generator is rebuilt.
+    pub fn update_quantizer_scale(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        let plays_shared_degrees = config.melody_quantizer_scale.is_none()
+            && (config.melody_pitch_generator_type == PitchGeneratorType::Degrees
+                || config.melody_blend_generator_type == Some(PitchGeneratorType::Degrees));
+        let plays_shared_chords = config.transposition_quantizer_scale.is_none()
+            && config.transposition_pitch_generator_type == PitchGeneratorType::Progression;
+        self.sender.send(SequencerCommand::SetHarmonizer(
+            Sequencer::build_harmonizer(&config),
+        ))?;
+        if plays_shared_degrees || plays_shared_chords {
+            return self.update_pitch_generator(config);
+        }
+        self.sender
+            .send(SequencerCommand::SetQuantizerScale(config.quantizer_scale))?;
+        Ok(())
+    }
+
+    pub fn update_pitch_chance(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetPitchChance(
+            Sequencer::build_pitch_chance(&config),
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_advance_on_trigger_only(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetAdvanceOnTriggerOnly(
+            config.advance_on_trigger_only,
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_velocity(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetVelocity(Sequencer::build_velocity(
+                &config,
+            )))?;
+        Ok(())
+    }
+
+    pub fn update_velocity_length(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetVelocityLength(config.velocity_length))?;
+        Ok(())
+    }
+
+    pub fn update_trigger_generator(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetTriggerGenerator(
+            Sequencer::build_trigger_generator(&config),
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_modulation(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetModulation(
+            Sequencer::build_modulation(&config),
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_note_length(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetNoteLength(note_length_ticks(&config)))?;
+        Ok(())
+    }
+
+    pub fn update_note_repeat(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetNoteRepeat(note_repeat_ticks(&config)))?;
+        Ok(())
+    }
+
+    pub fn update_note_duration_range(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetNoteDurationRange(note_duration_range(
+                &config,
+            )))?;
+        Ok(())
+    }
+
+    pub fn update_octave_offset(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetOctaveOffset(config.octave_offset))?;
+        Ok(())
+    }
+
+    pub fn update_note_off_style(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetNoteOffStyle(config.note_off_style))?;
+        Ok(())
+    }
+
+    pub fn update_release_velocity(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetReleaseVelocity(
+            config.release_velocity,
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_channel_mode(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetChannelMode(config.channel_mode))?;
+        Ok(())
+    }
+
+    pub fn update_microtonal(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetMicrotonal(config.microtonal))?;
+        Ok(())
+    }
+
+    pub fn update_max_voices(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetMaxVoices(config.max_voices))?;
+        Ok(())
+    }
+
+    pub fn update_mono(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetMono(config.mono))?;
+        Ok(())
+    }
+
+    pub fn update_harmonizer(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetHarmonizer(
+            Sequencer::build_harmonizer(&config),
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_count_in(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetCountIn(config.count_in_bars))?;
+        Ok(())
+    }
+
+    pub fn update_seed(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetSeed(config.seed))?;
+        Ok(())
+    }
+
+    pub fn update_fill(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetFill(config.fill))?;
+        Ok(())
+    }
+
+    pub fn update_humanize(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetHumanize(config.humanize))?;
+        Ok(())
+    }
+
+    pub fn update_swing(&self, config: SequencerConfiguration) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetSwing(config.swing))?;
+        Ok(())
+    }
+
+    pub fn update_midi_rate_limit(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetMidiRateLimit(config.midi_rate_limit))?;
+        Ok(())
+    }
+
+    pub fn update_send_song_position(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetSendSongPosition(
+            config.send_song_position,
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_stop_resets_position(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetStopResetsPosition(
+            config.stop_resets_position,
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_tie_repeated_notes(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender.send(SequencerCommand::SetTieRepeatedNotes(
+            config.tie_repeated_notes,
+        ))?;
+        Ok(())
+    }
+
+    pub fn update_tie_probability(
+        &self,
+        config: SequencerConfiguration,
+    ) -> Result<(), SequencerError> {
+        self.sender
+            .send(SequencerCommand::SetTieProbability(config.tie_probability))?;
+        Ok(())
+    }
+}
+
+// The timer callback owns the sequencer thread and drops it on the timer thread, whenever
+// the cancelled schedule gets cleaned up. So stop and silence the thread right here instead,
+// and close the MIDI connection while the thread is locked, so that a tick still in flight
+// cannot play a note after the notes have been flushed.
+impl Drop for Sequencer {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.upgrade() {
+            // a poisoned lock means the thread panicked mid-tick, so it is silenced anyway
+            if let Ok(mut thread) = thread.lock() {
+                thread.engine.set_playing(false);
+                thread.engine.panic();
+                thread.flush();
+                thread.midi_output = Box::new(ClosedMidiSink);
+            }
+        }
+        // Dropping the guard afterwards cancels the schedule
+    }
+}
+
+// The note length range in ticks, with the bounds swapped if the maximum is the shorter one
+fn note_length_ticks(config: &SequencerConfiguration) -> RangeInclusive<u32> {
+    let min = config.note_length_min.to_ticks(TICKS_PER_QUARTER_NOTE);
+    let max = config.note_length_max.to_ticks(TICKS_PER_QUARTER_NOTE);
+    min.min(max)..=min.max(max)
+}
+
+// A note length scaled by the velocity by the coupling amount, e.g. at an amount of 1 a note
+// of velocity 64 is held about half as long as one of velocity 127
+fn velocity_scaled_ticks(ticks: f32, velocity: u8, amount: f32) -> f32 {
+    ticks * (1.0 - amount + amount * velocity as f32 / 127.0)
+}
+
+fn note_repeat_ticks(config: &SequencerConfiguration) -> Option<u32> {
+    config
+        .note_repeat
+        .map(|note_repeat| note_repeat.to_ticks(TICKS_PER_QUARTER_NOTE))
+}
+
+// The bounds are put in order, as the sliders or a preset can set the minimum above the maximum
+fn note_duration_range(config: &SequencerConfiguration) -> RangeInclusive<f32> {
+    let (min, max) = (config.note_min_duration_ms, config.note_max_duration_ms);
+    min.min(max)..=min.max(max)
+}
+
+fn tick_duration_ms(bpm: f32) -> f32 {
+    60_000.0 / bpm / TICKS_PER_QUARTER_NOTE as f32
+}
+
+// Above the maximum the interval would round down to 0 ms, and the timer would run busy
+fn clamp_bpm(bpm: f32) -> f32 {
+    if bpm > MAX_BPM {
+        warn!(
+            "BPM {} is too fast for the timer, clamped to {}",
+            bpm, MAX_BPM
+        );
+        MAX_BPM
+    } else {
+        bpm
+    }
+}
+
+// Whole microseconds between the timer ticks, at least a millisecond. The timer schedules
+// each tick an interval after the previous one, so the rounding error of the interval adds
+// up: at 130 BPM, whole milliseconds drift by 2.3 s over 10,000 ticks (under 4 minutes),
+// microseconds by 2.3 ms.
+fn timer_interval_us(bpm: f32) -> i64 {
+    ((tick_duration_ms(bpm) * 1000.0).round() as i64).max(1000)
+}
+
+// The tempo the timer actually plays at with the rounded interval between the clock ticks,
+// slightly off the target BPM
+pub fn actual_bpm(interval_us: i64) -> f32 {
+    60_000_000.0 / (interval_us as f32 * TICKS_PER_QUARTER_NOTE as f32)
+}
+
+pub trait MidiSink: Send {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError>;
+}
+
+impl MidiSink for MidiOutputConnection {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        MidiOutputConnection::send(self, message)
+    }
+}
+
+// Drops the MIDI messages of a sequencer that has been shut down
+struct ClosedMidiSink;
+
+impl MidiSink for ClosedMidiSink {
+    fn send(&mut self, _message: &[u8]) -> Result<(), SendError> {
+        Ok(())
+    }
+}
+
+// Logs the MIDI messages instead of sending them, for running without a synth
+pub struct MonitorMidiSink {
+    // Show the notes as frequencies in Hz instead of note names
+    in_hz: bool,
+}
+
+impl MonitorMidiSink {
+    pub fn new(in_hz: bool) -> MonitorMidiSink {
+        MonitorMidiSink { in_hz }
+    }
+}
+
+impl MidiSink for MonitorMidiSink {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        info!("MIDI: {}", format_midi_message(message, self.in_hz));
+        Ok(())
+    }
+}
+
+// Sends straight to the MIDI output of the sequencer thread, in between its ticks. The
+// messages are dropped once the sequencer has been shut down.
+struct ThruMidiSink {
+    thread: Weak<Mutex<SequencerThread>>,
+}
+
+impl MidiSink for ThruMidiSink {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        match self.thread.upgrade() {
+            Some(thread) => thread.lock().unwrap().midi_output.send(message),
+            None => Ok(()),
+        }
+    }
+}
+
+// Describe a MIDI message as human-readable text, e.g. "Note On C4 vel 100", or
+// "Note On 261.63 Hz vel 100" with the notes in Hz
+fn format_midi_message(message: &[u8], in_hz: bool) -> String {
+    let format_note = |note: u8| {
+        let letter_octave = Step(note as f32).to_letter_octave();
+        if in_hz {
+            format!("{:.2} Hz", letter_octave_to_hz(letter_octave))
+        } else {
+            format_letter_octave(letter_octave)
+        }
+    };
+    // The low nibble of the status byte is the channel, which is not shown
+    match message {
+        [status, note, velocity] if status & 0xF0 == NOTE_ON_MSG => {
+            format!("Note On {} vel {}", format_note(*note), velocity)
+        }
+        [status, note, velocity] if status & 0xF0 == NOTE_OFF_MSG => {
+            format!("Note Off {} vel {}", format_note(*note), velocity)
+        }
+        [status, controller, value] if status & 0xF0 == CONTROL_CHANGE_MSG => {
+            format!("Control Change {} value {}", controller, value)
+        }
+        [status, value] if status & 0xF0 == CHANNEL_PRESSURE_MSG => {
+            format!("Channel Pressure {}", value)
+        }
+        [SONG_POSITION_POINTER_MSG, lsb, msb] => {
+            format!("Song Position {}", (*msb as u16) << 7 | *lsb as u16)
+        }
+        // shown relative to the center, where the pitch is not bent
+        [status, lsb, msb] if status & 0xF0 == PITCH_BEND_MSG => {
+            let bend = ((*msb as u16) << 7 | *lsb as u16) as i32 - PITCH_BEND_CENTER as i32;
+            format!("Pitch Bend {:+}", bend)
+        }
+        _ => format!("{:02X?}", message),
+    }
+}
+
+// Name of the MIDI output of a sequencer, numbered from the second one on
+fn midi_port_name(port_index: usize) -> String {
+    match port_index {
+        0 => MIDI_PORT_NAME.to_string(),
+        _ => format!("{} {}", MIDI_PORT_NAME, port_index + 1),
+    }
+}
+
+// Open the MIDI output, either as a virtual port other apps can connect to, or connected
+// to the existing port of the index, e.g. 1 for the sequencer of a second window. Without
+// that many ports the first one is used, and without any the messages are monitored, with
+// the notes in Hz if set.
+pub fn open_midi_output(
+    virtual_port: bool,
+    port_index: usize,
+    monitor_in_hz: bool,
+) -> Result<Box<dyn MidiSink>, MidiOutputError> {
+    let port_name = midi_port_name(port_index);
+    // Create MIDI output
+    let mut midi_out = MidiOutput::new(&port_name).map_err(|e| MidiOutputError(e.to_string()))?;
+    if virtual_port {
+        match create_virtual_output(midi_out, &port_name) {
+            Ok(connection) => {
+                info!("Created virtual MIDI output port: {}", port_name);
+                return Ok(Box::new(connection));
+            }
+            Err(output) => {
+                warn!("Falling back to connecting to an existing MIDI output port");
+                midi_out = output;
+            }
+        }
+    }
+    info!("Available MIDI output ports:");
+    for (i, p) in midi_out.ports().iter().enumerate() {
+        let name = midi_out
+            .port_name(p)
+            .map_err(|e| MidiOutputError(e.to_string()))?;
+        info!("\t{}: {}", i, name);
+    }
+    // Fall back to monitoring the messages when there is nothing to connect to
+    if midi_out.ports().is_empty() {
+        warn!("No MIDI output ports available, monitoring MIDI messages instead");
+        return Ok(Box::new(MonitorMidiSink::new(monitor_in_hz)));
+    }
+    // Connect to the MIDI output port of the index, the first one being IAC Bus 1
+    let ports = midi_out.ports();
+    let out_port = ports.get(port_index).unwrap_or_else(|| {
+        warn!(
+            "No MIDI output port {}, connecting to the first one instead",
+            port_index
+        );
+        &ports[0]
+    });
+    let out_port_name = midi_out
+        .port_name(out_port)
+        .map_err(|e| MidiOutputError(e.to_string()))?;
+    info!("Connecting to existing MIDI output port: {}", out_port_name);
+    let connection = midi_out
+        .connect(out_port, &port_name)
+        .map_err(|e| MidiOutputError(e.to_string()))?;
+    Ok(Box::new(connection))
+}
+
+#[cfg(unix)]
+fn create_virtual_output(
+    midi_out: MidiOutput,
+    port_name: &str,
+) -> Result<MidiOutputConnection, MidiOutput> {
+    use midir::os::unix::VirtualOutput;
+
+    midi_out.create_virtual(port_name).map_err(|e| {
+        warn!("Failed to create virtual MIDI output port: {}", e);
+        e.into_inner()
+    })
+}
+
+// Windows has no virtual MIDI ports, the output is handed back to connect to a port instead
+#[cfg(not(unix))]
+fn create_virtual_output(
+    midi_out: MidiOutput,
+    _port_name: &str,
+) -> Result<MidiOutputConnection, MidiOutput> {
+    warn!("Virtual MIDI output ports are not supported on this platform");
+    Err(midi_out)
+}
+
+struct ModulationLane {
+    generator: Box<dyn PitchModule>,
+    target: ModulationTarget,
+    // Only used by the control change target
+    controller: u8,
+}
+
+impl ModulationLane {
+    fn message(&self, value: u8) -> Vec<u8> {
+        match self.target {
+            ModulationTarget::ControlChange => vec![CONTROL_CHANGE_MSG, self.controller, value],
+            ModulationTarget::Pan => vec![CONTROL_CHANGE_MSG, PAN_CONTROLLER, value],
+            ModulationTarget::Aftertouch => vec![CHANNEL_PRESSURE_MSG, value],
+        }
+    }
+}
+
+// The nearest MIDI note of a fractional step, and the 14 bit pitch bend value bending it
+// the rest of the way, e.g. 60.5 is note 61 bent down a quarter of the bend range. Steps
+// outside the MIDI note range are clamped to the lowest or highest note, not wrapped around.
+fn microtonal_note(step: f32) -> (u8, u16) {
+    let step = step.clamp(0.0, MAX_MIDI_NOTE);
+    let note = step.round();
+    let bend = (step - note) / PITCH_BEND_RANGE_SEMITONES * PITCH_BEND_CENTER as f32;
+    let bend = (PITCH_BEND_CENTER as f32 + bend)
+        .round()
+        .clamp(0.0, 0x3FFF as f32);
+    (note as u8, bend as u16)
+}
+
+// Ticks until the pattern repeats: the longest cycle of the melody, the transposition and
+// the gate, rounded up to whole steps of the clock divider. Random generators do not
+// repeat, so their cycle length is taken as it is.
+pub fn pattern_length_ticks(config: &SequencerConfiguration) -> u32 {
+    let melody = match config.melody_pitch_generator_type {
+        PitchGeneratorType::Steps => config.melody_step_sequence.len() as u32,
+        _ => config.melody_cycle_length,
+    };
+    // the progression plays one chord per cycle length
+    let transposition = match config.transposition_pitch_generator_type {
+        PitchGeneratorType::Progression => {
+            config.transposition_cycle_length * config.transposition_progression.len() as u32
+        }
+        _ => config.transposition_cycle_length,
+    };
+    let step_ticks = config.clock_divider_factor.max(1);
+    let gate_length = config.gate_on + config.gate_off;
+    let gate = match (config.trigger_type, config.trigger_order) {
+        (TriggerType::Random, _) => 0,
+        (_, TriggerOrder::DivideFirst) => gate_length * step_ticks,
+        // a gate counting clock ticks lines up with the steps again after both have cycled
+        (_, TriggerOrder::TriggerFirst) => least_common_multiple(gate_length, step_ticks),
+    };
+    let longest = melody.max(transposition).max(gate).max(1);
+    longest.div_ceil(step_ticks) * step_ticks
+}
+
+fn least_common_multiple(a: u32, b: u32) -> u32 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    match x {
+        0 => 0,
+        gcd => a / gcd * b,
+    }
+}
+
+// A length in whole bars and beats of 4/4, plus the ticks left over
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BarsAndBeats {
+    pub bars: u32,
+    pub beats: u32,
+    pub ticks: u32,
+}
+
+pub fn bars_and_beats(ticks: u32) -> BarsAndBeats {
+    BarsAndBeats {
+        bars: ticks / TICKS_PER_BAR,
+        beats: ticks % TICKS_PER_BAR / TICKS_PER_QUARTER_NOTE,
+        ticks: ticks % TICKS_PER_QUARTER_NOTE,
+    }
+}
+
+impl Display for BarsAndBeats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let plural = |count: u32| if count == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} bar{} {} beat{}",
+            self.bars,
+            plural(self.bars),
+            self.beats,
+            plural(self.beats)
+        )?;
+        if self.ticks > 0 {
+            write!(f, " +{} tick{}", self.ticks, plural(self.ticks))?;
+        }
+        Ok(())
+    }
+}
+
+// The MIDI note number a pitch is played at, clamped to the MIDI note range, and whether the
+// pitch was within the range
+pub fn midi_note_number(pitch: LetterOctave) -> (u8, bool) {
+    let step = pitch.step();
+    (
+        step.clamp(0.0, MAX_MIDI_NOTE) as u8,
+        (0.0..=MAX_MIDI_NOTE).contains(&step),
+    )
+}
+
+// The MIDI beats (16th notes) elapsed by the given clock tick, as far as a song position
+// pointer reaches
+fn midi_beats(ticks: u32) -> u16 {
+    (ticks / TICKS_PER_MIDI_BEAT).min(MAX_SONG_POSITION as u32) as u16
+}
+
+// Song position pointer message with the 14 bit position split into two 7 bit bytes, least
+// significant first
+fn song_position_pointer(midi_beats: u16) -> [u8; 3] {
+    [
+        SONG_POSITION_POINTER_MSG,
+        (midi_beats & 0x7F) as u8,
+        (midi_beats >> 7 & 0x7F) as u8,
+    ]
+}
+
+// Map the generated pitch step onto the range of a MIDI control change value
+fn modulation_value(pitch: LetterOctave) -> u8 {
+    pitch.step().round().clamp(0.0, 127.0) as u8
+}
+
+// Fixed-size ring buffer of the most recent values, oldest first. The histories are shared
+// with the sequencer thread behind a mutex rather than a lock-free queue: the thread holds the
+// lock only to push one value per step or note and readers only to copy the buffer out, so
+// contention stays negligible.
+struct RingBuffer<T> {
+    values: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T: Copy> RingBuffer<T> {
+    fn new(capacity: usize) -> RingBuffer<T> {
+        RingBuffer {
+            values: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, value: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        self.values.iter().copied().collect()
+    }
+}
+
+// Pitches of the most recently played notes
+type PitchHistory = RingBuffer<LetterOctave>;
+
+// Triggers of the most recent steps, for their rolling average
+type TriggerDensity = RingBuffer<Trigger>;
+
+impl TriggerDensity {
+    // No steps yet count as silence
+    fn density(&self) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let played = self
+            .values
+            .iter()
+            .filter(|trigger| **trigger == Trigger::On)
+            .count();
+        played as f32 / self.values.len() as f32
+    }
+}
+
+struct PendingNoteOff {
+    channel: u8,
+    note: u8,
+    remaining_ticks: u32,
+    // Held until the next step while playing, then released after a note length from there
+    tied: bool,
+}
+
+// A note held back by the swing or the timing jitter, played once the delay has elapsed
+struct DelayedNote {
+    note: u8,
+    bend: u16,
+    remaining_ticks: u32,
+}
+
+// A played note struck again every interval until the next step
+struct RepeatingNote {
+    channel: u8,
+    note: u8,
+    interval_ticks: u32,
+    remaining_ticks: u32,
+}
+
+// A MIDI message, e.g. a note on as [0x90, note, velocity]
+pub type MidiEvent = Vec<u8>;
+
+// The musical logic of the sequencer, advanced by one clock tick (24 per quarter note) at a
+// time. The Sequencer drives it from its timer, but a host can just as well drive it from
+// its own clock, e.g. an audio callback, and send the returned MIDI events itself.
+pub struct SequencerEngine {
+    pitch_generator: Box<dyn PitchModule>,
+    trigger_generator: Box<dyn TriggerModule>,
+    // Without the melody the steps are rested
+    melody_enabled: bool,
+    note_length_ticks: RangeInclusive<u32>,
+    note_length_rng: SeededRng,
+    note_repeat_ticks: Option<u32>,
+    // One per note of the chord played on the current step
+    repeating_notes: Vec<RepeatingNote>,
+    note_duration_ms: RangeInclusive<f32>,
+    tick_duration_ms: f32,
+    tie_repeated_notes: bool,
+    tie_probability: f32,
+    tie_rng: SeededRng,
+    octave_offset: i32,
+    note_off_style: NoteOffStyle,
+    release_velocity: u8,
+    channel_mode: ChannelMode,
+    microtonal: bool,
+    // Position in the channel list of the next played note
+    channel_index: usize,
+    max_voices: usize,
+    mono: bool,
+    harmonizer: Option<Harmonizer>,
+    velocity: VelocityShaper,
+    velocity_length: f32,
+    pitch_history: Arc<Mutex<PitchHistory>>,
+    last_step: Arc<Mutex<Option<(LetterOctave, Trigger)>>>,
+    trigger_density: Arc<Mutex<TriggerDensity>>,
+    pending_note_offs: Vec<PendingNoteOff>,
+    modulation: Option<ModulationLane>,
+    ticks_in_bar: u32,
+    bar: Arc<AtomicU32>,
+    count_in_bars: u32,
+    // Ticks counted in so far, None once the count-in is over
+    count_in_ticks: Option<u32>,
+    // Click still sounding, ended on the next tick
+    metronome_note: Option<u8>,
+    fill: Fill,
+    fill_rng: SeededRng,
+    pitch_chance: Option<PitchChance>,
+    pitch_chance_rng: SeededRng,
+    advance_on_trigger_only: bool,
+    // Latest generated pitch, held on the rests while the pitch only advances on triggers
+    held_step: f32,
+    send_song_position: bool,
+    stop_resets_position: bool,
+    humanize: Humanize,
+    humanize_rng: SeededRng,
+    delayed_notes: Vec<DelayedNote>,
+    swing: Swing,
+    is_playing: bool,
+    // MIDI messages generated since the last tick was handed out
+    events: Vec<MidiEvent>,
+}
+
+impl SequencerEngine {
+    pub fn new(config: &SequencerConfiguration, is_playing: bool) -> SequencerEngine {
+        let mut engine = SequencerEngine::with_generators(
+            Sequencer::build_pitch_generator(config),
+            Sequencer::build_trigger_generator(config),
+            note_length_ticks(config),
+            is_playing,
+        );
+        engine.modulation = Sequencer::build_modulation(config);
+        engine.melody_enabled = config.melody_enabled;
+        engine.note_repeat_ticks = note_repeat_ticks(config);
+        engine.tie_repeated_notes = config.tie_repeated_notes;
+        engine.tie_probability = config.tie_probability;
+        engine.octave_offset = config.octave_offset;
+        engine.note_off_style = config.note_off_style;
+        engine.release_velocity = config.release_velocity;
+        engine.channel_mode = config.channel_mode.clone();
+        engine.microtonal = config.microtonal;
+        engine.max_voices = config.max_voices;
+        engine.mono = config.mono;
+        engine.harmonizer = Sequencer::build_harmonizer(config);
+        engine.velocity = Sequencer::build_velocity(config);
+        engine.velocity_length = config.velocity_length;
+        engine.note_duration_ms = note_duration_range(config);
+        engine.tick_duration_ms = tick_duration_ms(config.bpm);
+        engine.count_in_bars = config.count_in_bars;
+        engine.fill = config.fill;
+        engine.pitch_chance = Sequencer::build_pitch_chance(config);
+        engine.advance_on_trigger_only = config.advance_on_trigger_only;
+        engine.humanize = config.humanize;
+        engine.swing = config.swing;
+        engine.send_song_position = config.send_song_position;
+        engine.stop_resets_position = config.stop_resets_position;
+        engine.reseed(config.seed);
+        engine.pitch_history = Arc::new(Mutex::new(PitchHistory::new(config.pitch_history_length)));
+        engine
+    }
+
+    fn with_generators(
+        pitch_generator: Box<dyn PitchModule>,
+        trigger_generator: Box<dyn TriggerModule>,
+        note_length_ticks: RangeInclusive<u32>,
+        is_playing: bool,
+    ) -> SequencerEngine {
+        SequencerEngine {
+            pitch_generator,
+            trigger_generator,
+            melody_enabled: true,
+            note_length_ticks,
+            note_length_rng: SeededRng::from_entropy(),
+            note_repeat_ticks: None,
+            repeating_notes: Vec::new(),
+            // notes keep their length until a duration range and tempo are set
+            note_duration_ms: 0.0..=f32::INFINITY,
+            tick_duration_ms: 1.0,
+            tie_repeated_notes: false,
+            tie_probability: 0.0,
+            tie_rng: SeededRng::from_entropy(),
+            octave_offset: 0,
+            note_off_style: NoteOffStyle::Status80,
+            release_velocity: RELEASE_VELOCITY,
+            channel_mode: ChannelMode::Fixed,
+            microtonal: false,
+            channel_index: 0,
+            max_voices: usize::MAX,
+            mono: false,
+            harmonizer: None,
+            velocity: VelocityShaper::default(),
+            velocity_length: 0.0,
+            pitch_history: Arc::new(Mutex::new(PitchHistory::new(0))),
+            last_step: Arc::new(Mutex::new(None)),
+            trigger_density: Arc::new(Mutex::new(TriggerDensity::new(TRIGGER_DENSITY_WINDOW))),
+            pending_note_offs: Vec::new(),
+            modulation: None,
+            ticks_in_bar: 0,
+            bar: Arc::new(AtomicU32::new(0)),
+            count_in_bars: 0,
+            count_in_ticks: None,
+            metronome_note: None,
+            fill: Fill {
+                phrase_bars: 0,
+                intensity: 0.0,
+            },
+            fill_rng: SeededRng::from_entropy(),
+            pitch_chance: None,
+            pitch_chance_rng: SeededRng::from_entropy(),
+            advance_on_trigger_only: false,
+            held_step: 0.0,
+            send_song_position: false,
+            stop_resets_position: false,
+            humanize: Humanize {
+                velocity_jitter: 0,
+                timing_jitter_ticks: 0,
+                length_jitter: 0.0,
+            },
+            humanize_rng: SeededRng::from_entropy(),
+            delayed_notes: Vec::new(),
+            swing: Swing {
+                amount: 0.5,
+                resolution: NoteLength::Eighth,
+            },
+            is_playing,
+            events: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) {
+        self.events.push(message.to_vec());
+    }
+
+    // Restart the note lengths, fills, humanization, pitch chance and ties from the seed, or
+    // from fresh randomness without one
+    fn reseed(&mut self, seed: Option<u64>) {
+        let mut seeds = SeedSource::new(seed, ENGINE_SEED_STREAM);
+        self.note_length_rng = SeededRng::seed_from_u64(seeds.next());
+        self.fill_rng = SeededRng::seed_from_u64(seeds.next());
+        self.humanize_rng = SeededRng::seed_from_u64(seeds.next());
+        self.pitch_chance_rng = SeededRng::seed_from_u64(seeds.next());
+        self.tie_rng = SeededRng::seed_from_u64(seeds.next());
+    }
+
+    // Whether the note struck now is tied into the next step, by the tie probability
+    fn is_tied(&mut self) -> bool {
+        self.tie_probability > 0.0 && self.tie_rng.gen::<f32>() < self.tie_probability
+    }
+
+    // Carry the tied notes into the step starting now, held for at least a note length more
+    fn carry_tied_notes(&mut self) {
+        for index in 0..self.pending_note_offs.len() {
+            if !self.pending_note_offs[index].tied {
+                continue;
+            }
+            let velocity = self.velocity.velocity(self.pending_note_offs[index].note);
+            let remaining_ticks = self.note_off_ticks(velocity);
+            let pending = &mut self.pending_note_offs[index];
+            pending.tied = false;
+            pending.remaining_ticks = pending.remaining_ticks.max(remaining_ticks);
+        }
+    }
+
+    // The release velocity is a data byte, so it stops at 127
+    fn note_off_message(&self, channel: u8, note: u8) -> [u8; 3] {
+        self.note_off_style
+            .message(channel, note, self.release_velocity.min(127))
+    }
+
+    // The channel of the next played note, moving on through the list in round-robin mode
+    fn next_channel(&mut self) -> u8 {
+        let channels = self.channel_mode.channels();
+        let channel = channels[self.channel_index % channels.len()];
+        self.channel_index = (self.channel_index + 1) % channels.len();
+        channel
+    }
+
+    // Starting playback counts in first, if a count-in is set
+    pub fn set_playing(&mut self, is_playing: bool) {
+        // Notes held back when playback stops are dropped, not played on the next start
+        if !is_playing {
+            self.delayed_notes.clear();
+            if self.is_playing && self.microtonal {
+                self.center_pitch_bend();
+            }
+            if self.is_playing && self.stop_resets_position {
+                self.reset_position();
+            }
+        }
+        // Count in when playback starts, but not when it is already playing
+        self.count_in_ticks = if is_playing && !self.is_playing {
+            Some(0)
+        } else {
+            None
+        };
+        self.is_playing = is_playing;
+    }
+
+    // Silence everything on the channel, including notes stuck on the receiving synth. The
+    // messages are handed out along with the ones of the next tick.
+    pub fn panic(&mut self) {
+        self.pending_note_offs.clear();
+        self.delayed_notes.clear();
+        self.repeating_notes.clear();
+        for channel in self.channel_mode.channels().to_vec() {
+            self.send(&[CONTROL_CHANGE_MSG | channel, ALL_NOTES_OFF_CONTROLLER, 0]);
+            self.send(&[CONTROL_CHANGE_MSG | channel, ALL_SOUND_OFF_CONTROLLER, 0]);
+        }
+        self.center_pitch_bend();
+    }
+
+    // Switching microtonal output off leaves the channels at their last bend otherwise
+    pub fn set_microtonal(&mut self, microtonal: bool) {
+        if self.microtonal && !microtonal {
+            self.center_pitch_bend();
+        }
+        self.microtonal = microtonal;
+    }
+
+    // Undo the bend of the last microtonal note, so that the channels play in tune again
+    fn center_pitch_bend(&mut self) {
+        for channel in self.channel_mode.channels().to_vec() {
+            self.send(&[
+                PITCH_BEND_MSG | channel,
+                (PITCH_BEND_CENTER & 0x7F) as u8,
+                (PITCH_BEND_CENTER >> 7) as u8,
+            ]);
+        }
+    }
+
+    // Go back to the start of the first bar, sending the new song position if song position
+    // output is on. The message is handed out along with the ones of the next tick.
+    pub fn rewind(&mut self) {
+        self.ticks_in_bar = 0;
+        self.bar.store(0, Ordering::Relaxed);
+        if self.send_song_position {
+            self.send(&song_position_pointer(midi_beats(self.position_ticks())));
+        }
+    }
+
+    // Rewind the generators to the start of their patterns and the bars to the first one
+    fn reset_position(&mut self) {
+        self.pitch_generator.reset();
+        self.trigger_generator.reset();
+        if let Some(modulation) = &mut self.modulation {
+            modulation.generator.reset();
+        }
+        self.repeating_notes.clear();
+        self.rewind();
+    }
+
+    // Clock ticks since the start of the first bar
+    fn position_ticks(&self) -> u32 {
+        self.bar.load(Ordering::Relaxed) * TICKS_PER_BAR + self.ticks_in_bar
+    }
+
+    // Ticks until the note off, with a random note length from the note length range, scaled
+    // by the velocity if coupled to it, and clamped to the note duration range, so notes
+    // neither run into each other nor become inaudibly short
+    fn note_off_ticks(&mut self, velocity: u8) -> u32 {
+        let length_ticks = self
+            .note_length_rng
+            .gen_range(self.note_length_ticks.clone());
+        let jitter = self.humanize.length_jitter;
+        let length_ticks = if jitter > 0.0 {
+            length_ticks as f32 * (1.0 + self.humanize_rng.gen_range(-jitter..=jitter))
+        } else {
+            length_ticks as f32
+        };
+        let length_ticks = velocity_scaled_ticks(length_ticks, velocity, self.velocity_length);
+        let duration_ms = (length_ticks * self.tick_duration_ms)
+            .clamp(*self.note_duration_ms.start(), *self.note_duration_ms.end());
+        // a note must last at least one tick to be audible
+        ((duration_ms / self.tick_duration_ms).round() as u32).max(1)
+    }
+
+    // The velocity of the note, moved by up to the velocity jitter either way
+    fn note_velocity(&mut self, note: u8) -> u8 {
+        let velocity = self.velocity.velocity(note);
+        let jitter = self.humanize.velocity_jitter as i32;
+        if jitter == 0 {
+            return velocity;
+        }
+        (velocity as i32 + self.humanize_rng.gen_range(-jitter..=jitter)).clamp(1, 127) as u8
+    }
+
+    // Ticks the next note is held back by: the swing of its position in the bar, plus up to
+    // the timing jitter
+    fn note_delay_ticks(&mut self) -> u32 {
+        let swing_ticks = self.swing.delay_ticks(self.ticks_in_bar);
+        match self.humanize.timing_jitter_ticks {
+            0 => swing_ticks,
+            jitter => swing_ticks + self.humanize_rng.gen_range(0..=jitter),
+        }
+    }
+
+    // Strike a repeating note again, ending the previous strike if it is still sounding.
+    // Each strike ends by the next one at the latest.
+    fn restrike(&mut self, channel: u8, note: u8, interval_ticks: u32) {
+        let held = self
+            .pending_note_offs
+            .iter()
+            .position(|pending| pending.channel == channel && pending.note == note);
+        if let Some(index) = held {
+            self.pending_note_offs.remove(index);
+            self.send(&self.note_off_message(channel, note));
+        }
+        let velocity = self.note_velocity(note);
+        self.send(&[NOTE_ON_MSG | channel, note, velocity]);
+        let remaining_ticks = self.note_off_ticks(velocity).min(interval_ticks);
+        self.pending_note_offs.push(PendingNoteOff {
+            channel,
+            note,
+            remaining_ticks,
+            tied: false,
+        });
+    }
+
+    // Play a MIDI note with its pitch bend, tied to or ending a still sounding instance of it
+    fn play_note(&mut self, note: u8, bend: u16) {
+        let held = self
+            .pending_note_offs
+            .iter()
+            .position(|pending| pending.note == note);
+        match held {
+            Some(index) if self.tie_repeated_notes => {
+                // Keep holding the sounding note instead of retriggering it
+                let velocity = self.velocity.velocity(note);
+                let remaining_ticks = self.note_off_ticks(velocity);
+                self.pending_note_offs[index].remaining_ticks = remaining_ticks;
+            }
+            _ => {
+                // End a still sounding instance of the same note first, on
+                // whichever channel it was played
+                if let Some(index) = held {
+                    let held = self.pending_note_offs.remove(index);
+                    self.send(&self.note_off_message(held.channel, note));
+                }
+
+                // Steal the oldest held notes to make room for the new one, or all of them
+                // in mono mode
+                let max_voices = if self.mono { 1 } else { self.max_voices };
+                while !self.pending_note_offs.is_empty()
+                    && self.pending_note_offs.len() >= max_voices
+                {
+                    let oldest = self.pending_note_offs.remove(0);
+                    self.send(&self.note_off_message(oldest.channel, oldest.note));
+                }
+
+                let channel = self.next_channel();
+                if self.microtonal {
+                    // bent before every note, so the next one starts in tune
+                    self.send(&[
+                        PITCH_BEND_MSG | channel,
+                        (bend & 0x7F) as u8,
+                        (bend >> 7) as u8,
+                    ]);
+                }
+                let velocity = self.note_velocity(note);
+                self.send(&[NOTE_ON_MSG | channel, note, velocity]);
+                let mut remaining_ticks = self.note_off_ticks(velocity);
+                if let Some(interval_ticks) = self.note_repeat_ticks {
+                    remaining_ticks = remaining_ticks.min(interval_ticks);
+                    self.repeating_notes.push(RepeatingNote {
+                        channel,
+                        note,
+                        interval_ticks,
+                        remaining_ticks: interval_ticks,
+                    });
+                }
+                let tied = self.is_tied();
+                self.pending_note_offs.push(PendingNoteOff {
+                    channel,
+                    note,
+                    remaining_ticks,
+                    tied,
+                });
+            }
+        }
+    }
+
+    // Whether the tick belongs to the count-in, clicking on every beat of it
+    fn count_in(&mut self) -> bool {
+        let ticks = match self.count_in_ticks {
+            Some(ticks) if ticks < self.count_in_bars * TICKS_PER_BAR => ticks,
+            _ => {
+                self.count_in_ticks = None;
+                return false;
+            }
+        };
+        if ticks % TICKS_PER_QUARTER_NOTE == 0 {
+            let note = if ticks % TICKS_PER_BAR == 0 {
+                METRONOME_ACCENT_NOTE
+            } else {
+                METRONOME_NOTE
+            };
+            self.send(&[METRONOME_NOTE_ON_MSG, note, VELOCITY]);
+            self.metronome_note = Some(note);
+        }
+        self.count_in_ticks = Some(ticks + 1);
+        true
+    }
+
+    fn state(&self) -> SequencerState {
+        SequencerState {
+            pitch_generator: self.pitch_generator.state(),
+            trigger_generator: self.trigger_generator.state(),
+            modulation: self
+                .modulation
+                .as_ref()
+                .map(|modulation| modulation.generator.state()),
+        }
+    }
+
+    fn restore(&mut self, state: &SequencerState) {
+        self.pitch_generator.restore(&state.pitch_generator);
+        self.trigger_generator.restore(&state.trigger_generator);
+        if let (Some(modulation), Some(modulation_state)) =
+            (&mut self.modulation, &state.modulation)
+        {
+            modulation.generator.restore(modulation_state);
+        }
+    }
+
+    // Advance by one clock tick, returning the MIDI messages to send, in order
+    pub fn tick(&mut self) -> Vec<MidiEvent> {
+        // Release notes whose length has elapsed, even when stopped. Tied notes are held
+        // while playing, until the next step carries them on.
+        let is_playing = self.is_playing;
+        let mut released = Vec::new();
+        self.pending_note_offs.retain_mut(|pending| {
+            if pending.tied && is_playing && pending.remaining_ticks == 1 {
+                return true;
+            }
+            pending.remaining_ticks -= 1;
+            if pending.remaining_ticks == 0 {
+                released.push((pending.channel, pending.note));
+            }
+            pending.remaining_ticks > 0
+        });
+        for (channel, note) in released {
+            self.send(&self.note_off_message(channel, note));
+        }
+        if let Some(note) = self.metronome_note.take() {
+            self.send(&[METRONOME_NOTE_OFF_MSG, note, 0]);
+        }
+
+        // Play note, once the count-in is over
+        if self.is_playing && !self.count_in() {
+            let mut trigger = self.trigger_generator.tick();
+
+            // Play the rested steps by chance during the fill bar, then go back to the trigger
+            if trigger == Trigger::Off
+                && self.trigger_generator.is_step()
+                && self.fill.is_fill_bar(self.bar.load(Ordering::Relaxed))
+                && self.fill_rng.gen::<f32>() < self.fill.intensity
+            {
+                trigger = Trigger::On;
+            }
+            // Rests leave the pitch pattern where it is when it only advances on triggers
+            if trigger == Trigger::On || !self.advance_on_trigger_only {
+                self.held_step = if self.microtonal {
+                    self.pitch_generator.tick_step()
+                } else {
+                    self.pitch_generator.tick().step()
+                };
+            }
+            let step = self.held_step;
+            // Play the triggered note by the chance of its pitch, before the octave shift
+            if let Some(pitch_chance) = &self.pitch_chance {
+                if trigger == Trigger::On
+                    && self.pitch_chance_rng.gen::<f32>() >= pitch_chance.probability(step)
+                {
+                    trigger = Trigger::Off;
+                }
+            }
+            if !self.melody_enabled {
+                trigger = Trigger::Off;
+            }
+
+            // Shift the generated pitch by whole octaves, staying within the MIDI note range
+            let step = step + (STEPS_PER_OCTAVE * self.octave_offset) as f32;
+            // the live step shows the pitch before the clamping, so that clamped notes stand out
+            let generated_pitch = Step(step).to_letter_octave();
+            let step = step.clamp(0.0, MAX_MIDI_NOTE);
+            let pitch = Step(step).to_letter_octave();
+
+            if self.trigger_generator.is_step() {
+                *self.last_step.lock().unwrap() = Some((generated_pitch, trigger));
+                self.trigger_density.lock().unwrap().push(trigger);
+                // the note repeat lasts until the end of the step
+                self.repeating_notes.clear();
+                self.carry_tied_notes();
+            }
+
+            // Strike the notes of the current step again at the note repeat interval
+            let mut restruck = Vec::new();
+            for repeating in self.repeating_notes.iter_mut() {
+                repeating.remaining_ticks -= 1;
+                if repeating.remaining_ticks == 0 {
+                    repeating.remaining_ticks = repeating.interval_ticks;
+                    restruck.push((repeating.channel, repeating.note, repeating.interval_ticks));
+                }
+            }
+            for (channel, note, interval_ticks) in restruck {
+                self.restrike(channel, note, interval_ticks);
+            }
+
+            // Play the notes held back by the swing or the timing jitter whose delay has elapsed
+            for delayed in self.delayed_notes.iter_mut() {
+                delayed.remaining_ticks -= 1;
+            }
+            while let Some(index) = self
+                .delayed_notes
+                .iter()
+                .position(|delayed| delayed.remaining_ticks == 0)
+            {
+                let delayed = self.delayed_notes.remove(index);
+                self.play_note(delayed.note, delayed.bend);
+            }
+
+            match trigger {
+                Trigger::On => {
+                    // Logged at debug level, as it floods the console at high tempos
+                    debug!("Play note: {}", format_letter_octave(pitch));
+                    self.pitch_history.lock().unwrap().push(pitch);
+
+                    // Play the generated MIDI note, the nearest one of a microtonal pitch, along
+                    // with its harmony, all bent alike. A mono synth plays the melody alone.
+                    let chord = match &self.harmonizer {
+                        Some(harmonizer) if !self.mono => harmonizer.chord(pitch),
+                        _ => vec![pitch],
+                    };
+                    // Hold the notes back by the swing and the timing jitter, if any
+                    let delay_ticks = self.note_delay_ticks();
+                    for chord_pitch in chord {
+                        let chord_step =
+                            (step + chord_pitch.step() - pitch.step()).clamp(0.0, MAX_MIDI_NOTE);
+                        let (note, bend) = microtonal_note(chord_step);
+                        match delay_ticks {
+                            0 => self.play_note(note, bend),
+                            delay_ticks => self.delayed_notes.push(DelayedNote {
+                                note,
+                                bend,
+                                remaining_ticks: delay_ticks,
+                            }),
+                        }
+                    }
+                }
+                Trigger::Off => (),
+            }
+
+            // Send the modulation value
+            if let Some(modulation) = &mut self.modulation {
+                let value = modulation_value(modulation.generator.tick());
+                self.events.push(modulation.message(value));
+            }
+
+            // Count the bars for the tempo automation and the fill
+            self.ticks_in_bar += 1;
+            if self.ticks_in_bar == TICKS_PER_BAR {
+                self.ticks_in_bar = 0;
+                self.bar.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        std::mem::take(&mut self.events)
+    }
+}
+
+// Thins out the messages for slow MIDI devices: a controller or aftertouch value is not sent
+// again while it is unchanged, and at most the limit of messages is sent per tick. Messages
+// that end notes are always sent, so that no note is left hanging, except for the note offs
+// of dropped notes.
+#[derive(Default)]
+struct MidiRateLimiter {
+    limit: Option<usize>,
+    // Last value sent per status byte and controller
+    last_values: HashMap<(u8, u8), u8>,
+    // Channel and note number of the note ons that were dropped
+    dropped_notes: HashSet<(u8, u8)>,
+}
+
+impl MidiRateLimiter {
+    fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+        self.reset();
+    }
+
+    // Send every value again, e.g. after a panic reset the receiving synth
+    fn reset(&mut self) {
+        self.last_values.clear();
+        self.dropped_notes.clear();
+    }
+
+    fn limit<'a>(&mut self, events: &'a [MidiEvent]) -> Vec<&'a MidiEvent> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return events.iter().collect(),
+        };
+        let mut sent = 0;
+        let mut limited = Vec::new();
+        for event in events {
+            let note = channel_note(event);
+            if ends_notes(event) {
+                if !note.is_some_and(|note| self.dropped_notes.remove(&note)) {
+                    limited.push(event);
+                }
+                continue;
+            }
+            let controller_value = controller_value(event);
+            if let Some((controller, value)) = controller_value {
+                if self.last_values.get(&controller) == Some(&value) {
+                    continue;
+                }
+            }
+            if sent == limit {
+                self.dropped_notes.extend(note);
+                continue;
+            }
+            if let Some((controller, value)) = controller_value {
+                self.last_values.insert(controller, value);
+            }
+            if let Some(note) = note {
+                self.dropped_notes.remove(&note);
+            }
+            sent += 1;
+            limited.push(event);
+        }
+        limited
+    }
+}
+
+// Note offs, including note ons without velocity, and the all notes and sound off controllers
+fn ends_notes(message: &[u8]) -> bool {
+    match message {
+        [status, _, velocity] if status & 0xF0 == NOTE_ON_MSG => *velocity == 0,
+        [status, _, _] if status & 0xF0 == NOTE_OFF_MSG => true,
+        [status, controller, _] if status & 0xF0 == CONTROL_CHANGE_MSG => {
+            *controller == ALL_NOTES_OFF_CONTROLLER || *controller == ALL_SOUND_OFF_CONTROLLER
+        }
+        _ => false,
+    }
+}
+
+// The channel and note number of a note on or note off message
+fn channel_note(message: &[u8]) -> Option<(u8, u8)> {
+    match message {
+        [status, note, _] if matches!(status & 0xF0, NOTE_ON_MSG | NOTE_OFF_MSG) => {
+            Some((status & 0x0F, *note))
+        }
+        _ => None,
+    }
+}
+
+// The controller, keyed by status byte and controller number, and the value of a control
+// change or channel pressure message
+fn controller_value(message: &[u8]) -> Option<((u8, u8), u8)> {
+    match message {
+        [status, controller, value] if status & 0xF0 == CONTROL_CHANGE_MSG => {
+            Some(((*status, *controller), *value))
+        }
+        [status, value] if status & 0xF0 == CHANNEL_PRESSURE_MSG => Some(((*status, 0), *value)),
+        _ => None,
+    }
+}
+
+// Feeds the commands of the Sequencer to the engine and sends the MIDI events of each tick
+// to the MIDI output
+struct SequencerThread {
+    receiver: mpsc::Receiver<SequencerCommand>,
+    engine: SequencerEngine,
+    midi_output: Box<dyn MidiSink>,
+    // Whether the last MIDI write failed, so that a failing output is only reported once
+    midi_output_failed: bool,
+    rate_limiter: MidiRateLimiter,
+}
+
+impl SequencerThread {
+    fn new(
+        receiver: mpsc::Receiver<SequencerCommand>,
+        engine: SequencerEngine,
+        midi_output: Box<dyn MidiSink>,
+    ) -> SequencerThread {
+        SequencerThread {
+            receiver,
+            engine,
+            midi_output,
+            midi_output_failed: false,
+            rate_limiter: MidiRateLimiter::default(),
+        }
+    }
+
+    fn send(&mut self, events: &[MidiEvent]) {
+        // A failed write, e.g. to an unplugged device, is reported and the sequencer carries
+        // on, so that it plays again once the device is back
+        for event in self.rate_limiter.limit(events) {
+            match self.midi_output.send(event) {
+                Ok(()) => self.midi_output_failed = false,
+                Err(e) => {
+                    if !self.midi_output_failed {
+                        error!("Failed to send MIDI message: {}", e);
+                    }
+                    self.midi_output_failed = true;
+                }
+            }
+        }
+    }
+
+    // Send the messages the engine generated outside of a tick, e.g. by a panic
+    fn flush(&mut self) {
+        let events = std::mem::take(&mut self.engine.events);
+        self.send(&events);
+    }
+
+    fn tick(&mut self) {
+        // Process all pending commands, coalescing them so that only the latest of each kind
+        // takes effect. Starts and stops are kept in order instead, with repeats collapsed,
+        // so that a stop followed by a start still stops first.
+        let mut transport: Vec<bool> = Vec::new();
+        let mut panic = false;
+        let mut rewind = false;
+        let mut pitch_generator = None;
+        let mut trigger_generator = None;
+        // Changed in place after the generators are replaced, so that a generator replaced in
+        // the same tick gets the changes too
+        let mut pitch_parameters = Vec::new();
+        let mut trigger_probability = None;
+        let mut state = None;
+        for command in self.receiver.try_iter() {
+            match command {
+                SequencerCommand::Start => {
+                    if transport.last() != Some(&true) {
+                        transport.push(true);
+                    }
+                }
+                SequencerCommand::Stop => {
+                    if transport.last() != Some(&false) {
+                        transport.push(false);
+                    }
+                }
+                SequencerCommand::Panic => {
+                    panic = true;
+                }
+                SequencerCommand::Rewind => {
+                    rewind = true;
+                }
+                SequencerCommand::SetPitchGenerator(pg) => {
+                    pitch_generator = Some(pg);
+                }
+                SequencerCommand::SetMelodyRange(min, max) => {
+                    pitch_parameters.push(PitchParameter::Lane(
+                        Lane::Melody,
+                        LaneParameter::Range(min, max),
+                    ));
+                }
+                SequencerCommand::SetTranspositionRange(min, max) => {
+                    pitch_parameters.push(PitchParameter::Lane(
+                        Lane::Transposition,
+                        LaneParameter::Range(min, max),
+                    ));
+                }
+                SequencerCommand::SetCycleLength(lane, cycle_length) => {
+                    pitch_parameters.push(PitchParameter::Lane(
+                        lane,
+                        LaneParameter::CycleLength(cycle_length),
+                    ));
+                }
+                SequencerCommand::SetQuantizerScale(scale) => {
+                    pitch_parameters.push(PitchParameter::Scale(scale));
+                }
+                SequencerCommand::SetQuantizerPitchRange(pitch_range) => {
+                    pitch_parameters.push(PitchParameter::PitchRange(pitch_range));
+                }
+                SequencerCommand::SetMelodyEnabled(melody_enabled) => {
+                    self.engine.melody_enabled = melody_enabled;
+                }
+                SequencerCommand::SetTriggerGenerator(tg) => {
+                    trigger_generator = Some(tg);
+                }
+                SequencerCommand::SetNoteLength(ticks) => {
+                    self.engine.note_length_ticks = ticks;
+                }
+                SequencerCommand::SetNoteRepeat(ticks) => {
+                    self.engine.note_repeat_ticks = ticks;
+                }
+                SequencerCommand::SetNoteDurationRange(note_duration_ms) => {
+                    self.engine.note_duration_ms = note_duration_ms;
+                }
+                SequencerCommand::SetTickDuration(tick_duration_ms) => {
+                    self.engine.tick_duration_ms = tick_duration_ms;
+                }
+                SequencerCommand::SetTieRepeatedNotes(tie_repeated_notes) => {
+                    self.engine.tie_repeated_notes = tie_repeated_notes;
+                }
+                SequencerCommand::SetTieProbability(tie_probability) => {
+                    self.engine.tie_probability = tie_probability;
+                }
+                SequencerCommand::SetTriggerProbability(probability) => {
+                    trigger_probability = Some(probability);
+                }
+                SequencerCommand::SetOctaveOffset(octave_offset) => {
+                    self.engine.octave_offset = octave_offset;
+                }
+                SequencerCommand::SetNoteOffStyle(note_off_style) => {
+                    self.engine.note_off_style = note_off_style;
+                }
+                SequencerCommand::SetReleaseVelocity(release_velocity) => {
+                    self.engine.release_velocity = release_velocity;
+                }
+                SequencerCommand::SetChannelMode(channel_mode) => {
+                    self.engine.channel_mode = channel_mode;
+                }
+                SequencerCommand::SetMicrotonal(microtonal) => {
+                    self.engine.set_microtonal(microtonal);
+                }
+                SequencerCommand::SetMaxVoices(max_voices) => {
+                    self.engine.max_voices = max_voices;
+                }
+                SequencerCommand::SetMono(mono) => {
+                    self.engine.mono = mono;
+                }
+                SequencerCommand::SetHarmonizer(harmonizer) => {
+                    self.engine.harmonizer = harmonizer;
+                }
+                SequencerCommand::SetVelocity(velocity) => {
+                    self.engine.velocity = velocity;
+                }
+                SequencerCommand::SetModulation(modulation) => {
+                    self.engine.modulation = modulation;
+                }
+                SequencerCommand::SetCountIn(count_in_bars) => {
+                    self.engine.count_in_bars = count_in_bars;
+                }
+                SequencerCommand::SetFill(fill) => {
+                    self.engine.fill = fill;
+                }
+                SequencerCommand::SetPitchChance(pitch_chance) => {
+                    self.engine.pitch_chance = pitch_chance;
+                }
+                SequencerCommand::SetAdvanceOnTriggerOnly(advance_on_trigger_only) => {
+                    self.engine.advance_on_trigger_only = advance_on_trigger_only;
+                }
+                SequencerCommand::SetVelocityLength(velocity_length) => {
+                    self.engine.velocity_length = velocity_length;
+                }
+                SequencerCommand::SetHumanize(humanize) => {
+                    self.engine.humanize = humanize;
+                }
+                SequencerCommand::SetSwing(swing) => {
+                    self.engine.swing = swing;
+                }
+                SequencerCommand::SetSendSongPosition(send_song_position) => {
+                    self.engine.send_song_position = send_song_position;
+                }
+                SequencerCommand::SetMidiRateLimit(limit) => {
+                    self.rate_limiter.set_limit(limit);
+                }
+                SequencerCommand::SetStopResetsPosition(stop_resets_position) => {
+                    self.engine.stop_resets_position = stop_resets_position;
+                }
+                SequencerCommand::SetSeed(seed) => {
+                    self.engine.reseed(seed);
+                }
+                SequencerCommand::RestoreState(s) => {
+                    state = Some(s);
+                }
+            };
+        }
+        for is_playing in transport {
+            self.engine.set_playing(is_playing);
+        }
+        if panic {
+            self.engine.panic();
+            self.rate_limiter.reset();
+        }
+        if rewind {
+            self.engine.rewind();
+            self.rate_limiter.reset();
+        }
+        if let Some(pg) = pitch_generator {
+            self.engine.pitch_generator = pg;
+        }
+        if let Some(tg) = trigger_generator {
+            self.engine.trigger_generator = tg;
+        }
+        for parameter in pitch_parameters.iter() {
+            self.engine.pitch_generator.set_parameter(parameter);
+        }
+        if let Some(probability) = trigger_probability {
+            self.engine.trigger_generator.set_probability(probability);
+        }
+        if let Some(state) = state {
+            self.engine.restore(&state);
+        }
+
+        let events = self.engine.tick();
+        self.send(&events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::{
+        CHROMATIC_SCALE_NOTES, MAJOR_PENTATONIC_SCALE_NOTES, MAJOR_SCALE_NOTES, MINOR_SCALE_NOTES,
+        WHOLE_TONE_SCALE_NOTES,
+    };
+    use crate::sequencer_model::{
+        SequencerModel, MELODY_PITCH_GENERATOR_TYPE_NAMES, NOTE_LENGTH_NAMES,
+    };
+
+    #[derive(Clone, Default)]
+    struct MockMidiSink {
+        messages: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl MockMidiSink {
+        fn messages(&self) -> Vec<Vec<u8>> {
+            self.messages.lock().unwrap().clone()
+        }
+    }
+
+    impl MidiSink for MockMidiSink {
+        fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+            self.messages.lock().unwrap().push(message.to_vec());
+            Ok(())
+        }
+    }
+
+    struct ConstantPitch(LetterOctave);
+
+    impl PitchModule for ConstantPitch {
+        fn tick(&mut self) -> LetterOctave {
+            self.0
+        }
+    }
+
+    struct ConstantTrigger(Trigger);
+
+    impl TriggerModule for ConstantTrigger {
+        fn tick(&mut self) -> Trigger {
+            self.0
+        }
+    }
+
+    fn sequencer_thread(
+        midi_output: &MockMidiSink,
+    ) -> (mpsc::Sender<SequencerCommand>, SequencerThread) {
+        let (tx, rx) = mpsc::channel();
+        let engine = SequencerEngine::with_generators(
+            Box::new(ConstantPitch(LetterOctave(Letter::C, 4))),
+            Box::new(ConstantTrigger(Trigger::On)),
+            1..=1,
+            true,
+        );
+        let thread = SequencerThread::new(rx, engine, Box::new(midi_output.clone()));
+        (tx, thread)
+    }
+
+    #[test]
+    fn thru_messages_are_merged_with_the_generated_ones() {
+        let midi_output = MockMidiSink::default();
+        let (_tx, thread) = sequencer_thread(&midi_output);
+        let thread = Arc::new(Mutex::new(thread));
+        let mut thru = ThruMidiSink {
+            thread: Arc::downgrade(&thread),
+        };
+        let note = LetterOctave(Letter::C, 4).step() as u8;
+        let thru_note_on = vec![NOTE_ON_MSG | 0x01, 64, 90];
+
+        thread.lock().unwrap().tick();
+        thru.send(&thru_note_on).unwrap();
+        thread.lock().unwrap().tick();
+
+        let note_ons: Vec<Vec<u8>> = midi_output
+            .messages()
+            .into_iter()
+            .filter(|message| message[0] & 0xF0 == NOTE_ON_MSG)
+            .collect();
+        assert_eq!(
+            note_ons,
+            vec![
+                vec![NOTE_ON_MSG, note, VELOCITY],
+                thru_note_on.clone(),
+                vec![NOTE_ON_MSG, note, VELOCITY],
+            ]
+        );
+
+        // thru messages are dropped once the sequencer is gone
+        drop(thread);
+        thru.send(&thru_note_on).unwrap();
+        assert_eq!(
+            midi_output
+                .messages()
+                .iter()
+                .filter(|message| **message == thru_note_on)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn only_the_latest_queued_pitch_generator_is_installed() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        for letter in [Letter::D, Letter::E, Letter::F] {
+            tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+                ConstantPitch(LetterOctave(letter, 4)),
+            )))
+            .unwrap();
+        }
+
+        thread.tick();
+
+        let note = LetterOctave(Letter::F, 4).step() as u8;
+        assert_eq!(
+            midi_output.messages(),
+            vec![vec![NOTE_ON_MSG, note, VELOCITY]]
+        );
+    }
+
+    #[test]
+    fn no_melody_notes_are_played_during_the_count_in() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        thread.engine.is_playing = false;
+        tx.send(SequencerCommand::SetCountIn(1)).unwrap();
+        tx.send(SequencerCommand::Start).unwrap();
+
+        for _ in 0..TICKS_PER_BAR {
+            thread.tick();
+        }
+
+        let messages = midi_output.messages();
+        assert!(messages.iter().all(|message| message[0] != NOTE_ON_MSG));
+        let clicks: Vec<u8> = messages
+            .iter()
+            .filter(|message| message[0] == METRONOME_NOTE_ON_MSG)
+            .map(|message| message[1])
+            .collect();
+        assert_eq!(
+            clicks,
+            vec![
+                METRONOME_ACCENT_NOTE,
+                METRONOME_NOTE,
+                METRONOME_NOTE,
+                METRONOME_NOTE
+            ]
+        );
+
+        // the melody starts right after the count-in
+        thread.tick();
+        let note = LetterOctave(Letter::C, 4).step() as u8;
+        assert!(midi_output
+            .messages()
+            .contains(&vec![NOTE_ON_MSG, note, VELOCITY]));
+    }
+
+    #[test]
+    fn the_fill_plays_the_rested_steps_only_in_the_last_bar_of_each_phrase() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        thread.engine.trigger_generator = Box::new(ClockDivider::new(
+            Box::new(ConstantTrigger(Trigger::Off)),
+            6,
+            0,
+        ));
+        tx.send(SequencerCommand::SetFill(Fill {
+            phrase_bars: 4,
+            intensity: 1.0,
+        }))
+        .unwrap();
+
+        let mut notes_per_bar = Vec::new();
+        for _ in 0..8 {
+            let played_before = midi_output.messages().len();
+            for _ in 0..TICKS_PER_BAR {
+                thread.tick();
+            }
+            let notes = midi_output.messages()[played_before..]
+                .iter()
+                .filter(|message| message[0] == NOTE_ON_MSG)
+                .count();
+            notes_per_bar.push(notes);
+        }
+
+        // every step of the clock divider plays during the fill
+        let steps_per_bar = (TICKS_PER_BAR / 6) as usize;
+        assert_eq!(
+            notes_per_bar,
+            vec![0, 0, 0, steps_per_bar, 0, 0, 0, steps_per_bar]
+        );
+    }
+
+    #[test]
+    fn the_pitch_chance_plays_the_favored_end_of_the_range_and_rests_the_other() {
+        let pitch_chance = |favor| PitchChance {
+            favor,
+            pitch_range: 48.0..=72.0,
+        };
+        assert_eq!(pitch_chance(PitchFavor::High).probability(72.0), 1.0);
+        assert_eq!(pitch_chance(PitchFavor::High).probability(48.0), 0.0);
+        assert_eq!(pitch_chance(PitchFavor::High).probability(66.0), 0.75);
+        assert_eq!(pitch_chance(PitchFavor::Low).probability(48.0), 1.0);
+        assert_eq!(pitch_chance(PitchFavor::Low).probability(66.0), 0.25);
+        // outside the range counts as its nearest end
+        assert_eq!(pitch_chance(PitchFavor::High).probability(80.0), 1.0);
+
+        let notes_played = |pitch: LetterOctave, favor| {
+            let mut engine = SequencerEngine::with_generators(
+                Box::new(ConstantPitch(pitch)),
+                Box::new(ConstantTrigger(Trigger::On)),
+                1..=1,
+                true,
+            );
+            engine.pitch_chance = Some(pitch_chance(favor));
+            engine.reseed(Some(7));
+            (0..100)
+                .flat_map(|_| engine.tick())
+                .filter(|message| message[0] == NOTE_ON_MSG)
+                .count()
+        };
+        let (low, middle, high) = (
+            LetterOctave(Letter::C, 3),
+            LetterOctave(Letter::C, 4),
+            LetterOctave(Letter::C, 5),
+        );
+        assert_eq!(notes_played(high, PitchFavor::High), 100);
+        assert_eq!(notes_played(low, PitchFavor::High), 0);
+        assert_eq!(notes_played(low, PitchFavor::Low), 100);
+        assert_eq!(notes_played(high, PitchFavor::Low), 0);
+        // halfway up the range, about half of the notes are played
+        assert!((30..=70).contains(&notes_played(middle, PitchFavor::High)));
+    }
+
+    #[test]
+    fn fills_are_off_without_intensity_or_phrase_length() {
+        let fill = Fill {
+            phrase_bars: 4,
+            intensity: 0.0,
+        };
+        assert!(!(0..8).any(|bar| fill.is_fill_bar(bar)));
+        let fill = Fill {
+            phrase_bars: 0,
+            intensity: 1.0,
+        };
+        assert!(!(0..8).any(|bar| fill.is_fill_bar(bar)));
+        let fill = Fill {
+            phrase_bars: 1,
+            intensity: 1.0,
+        };
+        assert!((0..8).all(|bar| fill.is_fill_bar(bar)));
+    }
+
+    #[test]
+    fn a_locked_seed_reproduces_the_output_of_rebuilt_generators() {
+        let mut model = SequencerModel {
+            melody_pitch_generator_type_index: Some(
+                MELODY_PITCH_GENERATOR_TYPE_NAMES
+                    .iter()
+                    .position(|name| *name == "Random")
+                    .unwrap(),
+            ),
+            trigger_probability: 0.5,
+            trigger_restlessness: 0.1,
+            quantizer_strength: 0.5,
+            note_length_max_index: Some(NOTE_LENGTH_NAMES.len() - 1),
+            modulation_enabled: true,
+            fill_intensity: 0.5,
+            lock_seed: true,
+            seed: 1234,
+            ..SequencerModel::default()
+        };
+        let play = |model: &SequencerModel| {
+            let config: SequencerConfiguration = model.clone().into();
+            let mut engine = SequencerEngine::new(&config, true);
+            (0..TICKS_PER_BAR * 8)
+                .flat_map(|_| engine.tick())
+                .collect::<Vec<MidiEvent>>()
+        };
+
+        let first = play(&model);
+        assert!(first.iter().any(|message| message[0] == NOTE_ON_MSG));
+        assert_eq!(play(&model), first);
+
+        model.seed = 4321;
+        assert_ne!(play(&model), first);
+    }
+
+    #[test]
+    fn drum_mode_plays_every_triggered_step_on_the_drum_note() {
+        let model = SequencerModel {
+            melody_pitch_generator_type_index: Some(
+                MELODY_PITCH_GENERATOR_TYPE_NAMES
+                    .iter()
+                    .position(|name| *name == "Random")
+                    .unwrap(),
+            ),
+            trigger_probability: 0.5,
+            // neither shifts nor harmonizes the drum note
+            octave_offset: 1,
+            harmonize_enabled: true,
+            drum_mode: true,
+            drum_note: 42.0,
+            ..SequencerModel::default()
+        };
+        let config: SequencerConfiguration = model.into();
+        let mut engine = SequencerEngine::new(&config, true);
+
+        let notes: Vec<u8> = (0..TICKS_PER_BAR * 4)
+            .flat_map(|_| engine.tick())
+            .filter(|message| message[0] == NOTE_ON_MSG)
+            .map(|message| message[1])
+            .collect();
+
+        assert!(notes.len() > 1);
+        assert!(notes.iter().all(|note| *note == 42), "{:?}", notes);
+    }
+
+    #[test]
+    fn a_lane_seed_only_changes_the_output_of_its_own_lane() {
+        let model = SequencerModel {
+            melody_pitch_generator_type_index: Some(
+                MELODY_PITCH_GENERATOR_TYPE_NAMES
+                    .iter()
+                    .position(|name| *name == "Random")
+                    .unwrap(),
+            ),
+            trigger_probability: 0.5,
+            lock_seed: true,
+            seed: 1234,
+            melody_lock_seed: true,
+            melody_seed: 1,
+            trigger_lock_seed: true,
+            trigger_seed: 1,
+            ..SequencerModel::default()
+        };
+        let pitches = |model: &SequencerModel| {
+            let mut generator = Sequencer::build_pitch_generator(&model.clone().into());
+            (0..64).map(|_| generator.tick_step()).collect::<Vec<f32>>()
+        };
+        let triggers = |model: &SequencerModel| {
+            let mut generator = Sequencer::build_trigger_generator(&model.clone().into());
+            (0..TICKS_PER_BAR * 4)
+                .map(|_| generator.tick())
+                .collect::<Vec<Trigger>>()
+        };
+
+        let trigger_reseeded = SequencerModel {
+            trigger_seed: 2,
+            ..model.clone()
+        };
+        assert_eq!(pitches(&trigger_reseeded), pitches(&model));
+        assert_ne!(triggers(&trigger_reseeded), triggers(&model));
+
+        let melody_reseeded = SequencerModel {
+            melody_seed: 2,
+            ..model.clone()
+        };
+        assert_ne!(pitches(&melody_reseeded), pitches(&model));
+        assert_eq!(triggers(&melody_reseeded), triggers(&model));
+    }
+
+    #[test]
+    fn a_seed_on_the_next_stream_differs_from_the_next_seed() {
+        for seed in 0..100 {
+            let mut trigger_seeds = SeedSource::new(Some(seed), TRIGGER_SEED_STREAM);
+            let mut pitch_seeds = SeedSource::new(Some(seed + 1), PITCH_SEED_STREAM);
+            assert_ne!(trigger_seeds.next(), pitch_seeds.next(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn an_engine_driven_by_hand_plays_like_the_timer_driven_thread() {
+        let engine = || {
+            SequencerEngine::with_generators(
+                Box::new(RampPitchGenerator::new(
+                    8,
+                    0,
+                    LetterOctave(Letter::C, 3),
+                    LetterOctave(Letter::C, 5),
+                )),
+                Box::new(ClockDivider::new(
+                    Box::new(DrunkTriggerGenerator::new(0.5, 0.1, 42)),
+                    6,
+                    0,
+                )),
+                2..=2,
+                true,
+            )
+        };
+        let midi_output = MockMidiSink::default();
+        let (_tx, rx) = mpsc::channel();
+        let mut thread = SequencerThread::new(rx, engine(), Box::new(midi_output.clone()));
+        let mut engine = engine();
+
+        let mut events = Vec::new();
+        for _ in 0..TICKS_PER_BAR * 4 {
+            // the timer calls the thread, a host calls the engine
+            thread.tick();
+            events.extend(engine.tick());
+        }
+
+        assert!(!events.is_empty());
+        assert_eq!(events, midi_output.messages());
+    }
+
+    #[test]
+    fn start_returns_error_when_sequencer_thread_is_gone() {
+        let (tx, rx) = mpsc::channel();
+        let timer = Timer::new();
+        let guard = timer.schedule_with_delay(Duration::hours(1), || ());
+        let sequencer = Sequencer {
+            sender: tx,
+            thread: Weak::new(),
+            pitch_history: Arc::new(Mutex::new(PitchHistory::new(0))),
+            last_step: Arc::new(Mutex::new(None)),
+            trigger_density: Arc::new(Mutex::new(TriggerDensity::new(0))),
+            timer,
+            guard,
+            bpm: 120.0,
+            scheduled_bpm: 120.0,
+            tempo_automation: TempoAutomation {
+                depth: 0.0,
+                period_bars: 0,
+            },
+            bar: Arc::new(AtomicU32::new(0)),
+            last_bar: 0,
+        };
+        drop(rx);
+
+        assert_eq!(sequencer.start(), Err(SequencerError::Disconnected));
+    }
+
+    #[test]
+    fn dropping_the_sequencer_flushes_the_notes_and_closes_the_midi_output() {
+        let midi_output = MockMidiSink::default();
+        let sequencer = Sequencer::new(
+            SequencerConfiguration::default(),
+            false,
+            Box::new(midi_output.clone()),
+        );
+
+        drop(sequencer);
+
+        // nothing was playing, so only the panic messages were sent
+        assert_eq!(
+            midi_output.messages(),
+            vec![
+                vec![CONTROL_CHANGE_MSG, ALL_NOTES_OFF_CONTROLLER, 0],
+                vec![CONTROL_CHANGE_MSG, ALL_SOUND_OFF_CONTROLLER, 0],
+                vec![PITCH_BEND_MSG, 0x00, 0x40],
+            ]
+        );
+        // only the test still holds on to the sink
+        assert_eq!(Arc::strong_count(&midi_output.messages), 1);
+    }
+
+    #[test]
+    fn only_the_latest_queued_command_of_each_kind_takes_effect() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+            ConstantPitch(LetterOctave(Letter::D, 4)),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetTriggerGenerator(Box::new(
+            ConstantTrigger(Trigger::Off),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::Stop).unwrap();
+        tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+            ConstantPitch(LetterOctave(Letter::F, 4)),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetTriggerGenerator(Box::new(
+            ConstantTrigger(Trigger::On),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::Start).unwrap();
+
+        thread.tick();
+
+        let note = LetterOctave(Letter::F, 4).step() as u8;
+        assert!(thread.engine.is_playing);
+        assert_eq!(
+            midi_output.messages(),
+            vec![vec![NOTE_ON_MSG, note, VELOCITY]]
+        );
+    }
+
+    #[test]
+    fn consecutive_start_and_stop_collapse_into_the_final_state() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::Start).unwrap();
+        tx.send(SequencerCommand::Stop).unwrap();
+
+        thread.tick();
+
+        assert!(!thread.engine.is_playing);
+        assert!(midi_output.messages().is_empty());
+    }
+
+    #[test]
+    fn repeated_notes_are_tied_instead_of_retriggered() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetNoteLength(2..=2)).unwrap();
+        tx.send(SequencerCommand::SetTieRepeatedNotes(true))
+            .unwrap();
+
+        for _ in 0..3 {
+            thread.tick();
+        }
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![vec![NOTE_ON_MSG, 60, VELOCITY]]
+        );
+    }
+
+    #[test]
+    fn notes_are_tied_into_the_next_step_by_the_tie_probability() {
+        let mut engine = SequencerEngine::with_generators(
+            Box::new(ConstantPitch(LetterOctave(Letter::C, 4))),
+            Box::new(ClockDivider::new(
+                Box::new(ConstantTrigger(Trigger::On)),
+                4,
+                0,
+            )),
+            1..=1,
+            true,
+        );
+        engine.tie_probability = 0.3;
+        engine.reseed(Some(7));
+
+        // a released note ends within its step, a tied one is still sounding when the next
+        // step starts
+        let mut note_ons = 0;
+        let mut ties = 0;
+        let mut is_sounding = false;
+        for _ in 0..4000 {
+            let was_sounding = is_sounding;
+            for message in engine.tick() {
+                if message[0] == NOTE_ON_MSG {
+                    note_ons += 1;
+                    if was_sounding {
+                        ties += 1;
+                    }
+                    is_sounding = true;
+                } else if message[0] == NOTE_OFF_MSG {
+                    is_sounding = false;
+                }
+            }
+        }
+        assert_eq!(note_ons, 1000);
+        assert!((250..=350).contains(&ties));
+    }
+
+    #[test]
+    fn note_offs_carry_the_configured_release_velocity() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetReleaseVelocity(0x10)).unwrap();
+
+        thread.tick();
+        thread.engine.is_playing = false;
+        thread.tick();
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+                vec![NOTE_OFF_MSG, 60, 0x10],
+            ]
+        );
+
+        // the note on style has no release velocity
+        tx.send(SequencerCommand::SetNoteOffStyle(
+            NoteOffStyle::NoteOnVelocity0,
+        ))
+        .unwrap();
+        tx.send(SequencerCommand::Start).unwrap();
+        thread.tick();
+        thread.engine.is_playing = false;
+        thread.tick();
+        assert_eq!(midi_output.messages()[3], vec![NOTE_ON_MSG, 60, 0]);
+
+        tx.send(SequencerCommand::SetNoteOffStyle(NoteOffStyle::Status80))
+            .unwrap();
+        tx.send(SequencerCommand::SetReleaseVelocity(200)).unwrap();
+        tx.send(SequencerCommand::Start).unwrap();
+        thread.tick();
+        thread.engine.is_playing = false;
+        thread.tick();
+        assert_eq!(midi_output.messages()[5], vec![NOTE_OFF_MSG, 60, 127]);
+    }
+
+    #[test]
+    fn round_robin_notes_alternate_channels_and_end_on_their_channel() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetChannelMode(ChannelMode::RoundRobin(
+            vec![0, 2],
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetNoteLength(3..=3)).unwrap();
+
+        for _ in 0..3 {
+            thread.tick();
+        }
+        thread.engine.is_playing = false;
+        for _ in 0..3 {
+            thread.tick();
+        }
+
+        // the repeated note is retriggered on the next channel, ending the held one on its own
+        assert_eq!(
+            midi_output.messages(),
+            vec![
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+                vec![NOTE_OFF_MSG, 60, RELEASE_VELOCITY],
+                vec![NOTE_ON_MSG | 2, 60, VELOCITY],
+                vec![NOTE_OFF_MSG | 2, 60, RELEASE_VELOCITY],
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+                vec![NOTE_OFF_MSG, 60, RELEASE_VELOCITY],
+            ]
+        );
+
+        // a panic silences every channel of the list
+        tx.send(SequencerCommand::Panic).unwrap();
+        thread.tick();
+        let channels: Vec<u8> = midi_output.messages()[6..]
+            .iter()
+            .map(|message| message[0] & 0x0F)
+            .collect();
+        assert_eq!(channels, vec![0, 0, 2, 2, 0, 2]);
+    }
+
+    #[test]
+    fn fractional_steps_are_split_into_the_nearest_note_and_a_pitch_bend() {
+        assert_eq!(microtonal_note(60.0), (60, PITCH_BEND_CENTER));
+        // a quarter of the two semitone bend range down from the rounded up note
+        assert_eq!(microtonal_note(60.5), (61, PITCH_BEND_CENTER - 0x800));
+        assert_eq!(microtonal_note(60.25), (60, PITCH_BEND_CENTER + 0x400));
+        assert_eq!(microtonal_note(59.75), (60, PITCH_BEND_CENTER - 0x400));
+        // out of range steps play the nearest end of the range unbent
+        assert_eq!(microtonal_note(-5.0), (0, PITCH_BEND_CENTER));
+        assert_eq!(microtonal_note(200.0), (127, PITCH_BEND_CENTER));
+    }
+
+    #[test]
+    fn out_of_range_steps_are_clamped_to_the_lowest_and_highest_midi_note() {
+        for microtonal in [false, true] {
+            for (step, note) in [(-5.0, 0), (200.0, 127)] {
+                let midi_output = MockMidiSink::default();
+                let (tx, mut thread) = sequencer_thread(&midi_output);
+                tx.send(SequencerCommand::SetPitchGenerator(Box::new(ConstantStep(
+                    step,
+                ))))
+                .unwrap();
+                tx.send(SequencerCommand::SetMicrotonal(microtonal))
+                    .unwrap();
+
+                thread.tick();
+
+                // microtonal notes come with their pitch bend
+                let notes: Vec<Vec<u8>> = midi_output
+                    .messages()
+                    .into_iter()
+                    .filter(|message| message[0] == NOTE_ON_MSG)
+                    .collect();
+                assert_eq!(notes, vec![vec![NOTE_ON_MSG, note, VELOCITY]]);
+            }
+        }
+    }
+
+    struct ConstantStep(f32);
+
+    impl PitchModule for ConstantStep {
+        fn tick(&mut self) -> LetterOctave {
+            Step(self.0).to_letter_octave()
+        }
+
+        fn tick_step(&mut self) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn stopping_rewinds_the_generators_only_when_stop_resets_the_position() {
+        let notes_after_restart = |stop_resets_position: bool| {
+            let mut engine = SequencerEngine::with_generators(
+                Box::new(StepSequencePitchGenerator::new(vec![
+                    LetterOctave(Letter::C, 4),
+                    LetterOctave(Letter::E, 4),
+                    LetterOctave(Letter::G, 4),
+                ])),
+                Box::new(GateRatioTriggerGenerator::new(3, 1)),
+                1..=1,
+                true,
+            );
+            engine.stop_resets_position = stop_resets_position;
+            for _ in 0..2 {
+                engine.tick();
+            }
+            engine.set_playing(false);
+            engine.set_playing(true);
+            (0..3)
+                .flat_map(|_| engine.tick())
+                .filter(|message| message[0] == NOTE_ON_MSG)
+                .map(|message| message[1])
+                .collect::<Vec<u8>>()
+        };
+
+        // resuming plays the third note, rests on the gate's rest, and carries on mid-sequence
+        assert_eq!(notes_after_restart(false), vec![67, 64]);
+        assert_eq!(notes_after_restart(true), vec![60, 64, 67]);
+    }
+
+    #[test]
+    fn a_stop_and_start_in_the_same_tick_still_resets_the_position() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+            StepSequencePitchGenerator::new(vec![
+                LetterOctave(Letter::C, 4),
+                LetterOctave(Letter::E, 4),
+                LetterOctave(Letter::G, 4),
+            ]),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetNoteLength(1..=1)).unwrap();
+        tx.send(SequencerCommand::SetStopResetsPosition(true))
+            .unwrap();
+        for _ in 0..2 {
+            thread.tick();
+        }
+
+        tx.send(SequencerCommand::Stop).unwrap();
+        tx.send(SequencerCommand::Start).unwrap();
+        thread.tick();
+
+        let notes: Vec<u8> = midi_output
+            .messages()
+            .iter()
+            .filter(|message| message[0] == NOTE_ON_MSG)
+            .map(|message| message[1])
+            .collect();
+        assert_eq!(notes, vec![60, 64, 60]);
+    }
+
+    #[test]
+    fn ticks_are_counted_in_bars_and_beats_of_4_4() {
+        assert_eq!(
+            bars_and_beats(0),
+            BarsAndBeats {
+                bars: 0,
+                beats: 0,
+                ticks: 0
+            }
+        );
+        assert_eq!(
+            bars_and_beats(TICKS_PER_BAR * 2 + TICKS_PER_QUARTER_NOTE * 3 + 6),
+            BarsAndBeats {
+                bars: 2,
+                beats: 3,
+                ticks: 6
+            }
+        );
+        assert_eq!(bars_and_beats(TICKS_PER_BAR).to_string(), "1 bar 0 beats");
+        assert_eq!(
+            bars_and_beats(TICKS_PER_BAR * 4 + TICKS_PER_QUARTER_NOTE).to_string(),
+            "4 bars 1 beat"
+        );
+        assert_eq!(bars_and_beats(13).to_string(), "0 bars 0 beats +13 ticks");
+    }
+
+    #[test]
+    fn the_pattern_lasts_as_long_as_its_longest_cycle_in_whole_steps() {
+        let mut config: SequencerConfiguration = SequencerModel::default().into();
+        config.melody_pitch_generator_type = PitchGeneratorType::Random;
+        config.melody_cycle_length = 100;
+        config.transposition_pitch_generator_type = PitchGeneratorType::Square;
+        config.transposition_cycle_length = 64;
+        config.trigger_type = TriggerType::Random;
+        config.clock_divider_factor = 6;
+        // 100 ticks are rounded up to 17 steps of 6 ticks
+        assert_eq!(pattern_length_ticks(&config), 102);
+
+        // a gate of 3 + 5 steps of 24 ticks outlasts the cycles
+        config.trigger_type = TriggerType::Gate;
+        config.gate_on = 3;
+        config.gate_off = 5;
+        config.clock_divider_factor = 24;
+        assert_eq!(pattern_length_ticks(&config), 2 * TICKS_PER_BAR);
+
+        // counting clock ticks, the gate of 8 ticks lines up with the steps every 24 ticks
+        config.trigger_order = TriggerOrder::TriggerFirst;
+        config.melody_cycle_length = 16;
+        config.transposition_cycle_length = 16;
+        assert_eq!(pattern_length_ticks(&config), TICKS_PER_QUARTER_NOTE);
+    }
+
+    #[test]
+    fn song_positions_are_sent_in_16th_notes_split_into_7_bit_bytes() {
+        assert_eq!(song_position_pointer(0), [SONG_POSITION_POINTER_MSG, 0, 0]);
+        // 200 = 1 * 128 + 72
+        assert_eq!(
+            song_position_pointer(200),
+            [SONG_POSITION_POINTER_MSG, 72, 1]
+        );
+        assert_eq!(
+            song_position_pointer(MAX_SONG_POSITION),
+            [SONG_POSITION_POINTER_MSG, 0x7F, 0x7F]
+        );
+
+        // four bars of 4/4 are 64 16th notes
+        assert_eq!(midi_beats(4 * TICKS_PER_BAR), 64);
+        assert_eq!(midi_beats(TICKS_PER_MIDI_BEAT - 1), 0);
+        assert_eq!(midi_beats(u32::MAX), MAX_SONG_POSITION);
+        assert_eq!(
+            format_midi_message(&song_position_pointer(200), false),
+            "Song Position 200"
+        );
+    }
+
+    #[test]
+    fn rewinding_sends_the_start_position_only_when_song_position_output_is_on() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        for _ in 0..TICKS_PER_BAR + 5 {
+            thread.tick();
+        }
+        tx.send(SequencerCommand::Rewind).unwrap();
+        thread.tick();
+        assert_eq!(thread.engine.position_ticks(), 1);
+        assert!(!midi_output
+            .messages()
+            .iter()
+            .any(|message| message[0] == SONG_POSITION_POINTER_MSG));
+
+        tx.send(SequencerCommand::SetSendSongPosition(true))
+            .unwrap();
+        tx.send(SequencerCommand::Rewind).unwrap();
+        thread.tick();
+        let song_positions: Vec<Vec<u8>> = midi_output
+            .messages()
+            .into_iter()
+            .filter(|message| message[0] == SONG_POSITION_POINTER_MSG)
+            .collect();
+        assert_eq!(song_positions, vec![vec![SONG_POSITION_POINTER_MSG, 0, 0]]);
+    }
+
+    #[test]
+    fn microtonal_notes_are_bent_before_they_are_played() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        thread.engine.pitch_generator = Box::new(ConstantStep(60.5));
+
+        // rounded to the nearest note without microtonal output
+        thread.tick();
+        tx.send(SequencerCommand::SetMicrotonal(true)).unwrap();
+        thread.tick();
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![
+                vec![NOTE_ON_MSG, 61, VELOCITY],
+                vec![NOTE_OFF_MSG, 61, RELEASE_VELOCITY],
+                vec![PITCH_BEND_MSG, 0x00, 0x30],
+                vec![NOTE_ON_MSG, 61, VELOCITY],
+            ]
+        );
+    }
+
+    #[test]
+    fn the_pitch_bend_is_centered_when_microtonal_output_is_switched_off_or_stopped() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        thread.engine.pitch_generator = Box::new(ConstantStep(60.5));
+        let centered_count = || {
+            midi_output
+                .messages()
+                .iter()
+                .filter(|message| **message == vec![PITCH_BEND_MSG, 0x00, 0x40])
+                .count()
+        };
+        tx.send(SequencerCommand::SetMicrotonal(true)).unwrap();
+        thread.tick();
+        tx.send(SequencerCommand::SetMicrotonal(false)).unwrap();
+        thread.tick();
+        assert_eq!(centered_count(), 1);
+
+        tx.send(SequencerCommand::SetMicrotonal(true)).unwrap();
+        thread.tick();
+        tx.send(SequencerCommand::Stop).unwrap();
+        thread.tick();
+        assert_eq!(centered_count(), 2);
+    }
+
+    #[test]
+    fn repeated_notes_are_retriggered_without_tie() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetNoteLength(2..=2)).unwrap();
+
+        for _ in 0..2 {
+            thread.tick();
+        }
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+                vec![NOTE_OFF_MSG, 60, RELEASE_VELOCITY],
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+            ]
+        );
+    }
+
+    #[test]
+    fn humanized_notes_are_delayed_and_accented_within_the_jitter() {
+        let mut delays = Vec::new();
+        let mut velocities = Vec::new();
+        for seed in 0..50 {
+            let mut engine = SequencerEngine::with_generators(
+                Box::new(ConstantPitch(LetterOctave(Letter::C, 4))),
+                Box::new(ConstantTrigger(Trigger::On)),
+                1..=1,
+                true,
+            );
+            engine.humanize = Humanize {
+                velocity_jitter: 10,
+                timing_jitter_ticks: 3,
+                length_jitter: 0.0,
+            };
+            engine.reseed(Some(seed));
+
+            // a single step is played, then the trigger rests
+            let mut ticks = vec![engine.tick()];
+            engine.trigger_generator = Box::new(ConstantTrigger(Trigger::Off));
+            ticks.extend((0..4).map(|_| engine.tick()));
+
+            let (delay, note_on) = ticks
+                .iter()
+                .enumerate()
+                .find_map(|(delay, events)| {
+                    let note_on = events.iter().find(|message| message[0] == NOTE_ON_MSG)?;
+                    Some((delay, note_on.clone()))
+                })
+                .unwrap();
+            delays.push(delay);
+            velocities.push(note_on[2]);
+        }
+
+        assert!(delays.iter().all(|delay| *delay <= 3));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+        assert!(velocities
+            .iter()
+            .all(|velocity| (VELOCITY - 10..=VELOCITY + 10).contains(velocity)));
+        assert!(velocities.iter().any(|velocity| *velocity != VELOCITY));
+    }
+
+    #[test]
+    fn swing_delays_the_second_step_of_each_pair_at_its_resolution() {
+        let swing = |resolution| Swing {
+            amount: 0.75,
+            resolution,
+        };
+        let delays = |swing: Swing| {
+            (0..TICKS_PER_QUARTER_NOTE * 2)
+                .filter(|ticks| ticks % 6 == 0)
+                .map(|ticks| swing.delay_ticks(ticks))
+                .collect::<Vec<u32>>()
+        };
+        // at 24 PPQN the off-beat 8th is the 12th tick of the beat, the off-beat 16th the 6th
+        // and 18th, each delayed by half of its step at 75%
+        assert_eq!(
+            delays(swing(NoteLength::Eighth)),
+            vec![0, 0, 6, 0, 0, 0, 6, 0]
+        );
+        assert_eq!(
+            delays(swing(NoteLength::Sixteenth)),
+            vec![0, 3, 0, 3, 0, 3, 0, 3]
+        );
+
+        let note_on_ticks = |swing: Swing| {
+            let mut engine = SequencerEngine::with_generators(
+                Box::new(ConstantPitch(LetterOctave(Letter::C, 4))),
+                Box::new(ClockDivider::new(
+                    Box::new(ConstantTrigger(Trigger::On)),
+                    6,
+                    0,
+                )),
+                1..=1,
+                true,
+            );
+            engine.swing = swing;
+            (0..TICKS_PER_QUARTER_NOTE)
+                .flat_map(|tick| {
+                    engine
+                        .tick()
+                        .into_iter()
+                        .filter(|message| message[0] == NOTE_ON_MSG)
+                        .map(move |_| tick)
+                })
+                .collect::<Vec<u32>>()
+        };
+        // 16th steps, with the 8th-note swing moving the off-beat 8th onto the last 16th
+        assert_eq!(note_on_ticks(swing(NoteLength::Eighth)), vec![0, 6, 18, 18]);
+        assert_eq!(
+            note_on_ticks(swing(NoteLength::Sixteenth)),
+            vec![0, 9, 12, 21]
+        );
+        let straight = Swing {
+            amount: 0.5,
+            ..swing(NoteLength::Eighth)
+        };
+        assert_eq!(note_on_ticks(straight), vec![0, 6, 12, 18]);
+    }
+
+    #[test]
+    fn lanes_without_a_scale_pass_pitches_through_untouched() {
+        let pitch = LetterOctave(Letter::Csh, 4);
+        let mut lane = Sequencer::quantize_lane(Box::new(ConstantPitch(pitch)), None, None);
+        for _ in 0..3 {
+            assert_eq!(lane.tick(), pitch);
+        }
+
+        let mut lane = Sequencer::quantize_lane(
+            Box::new(ConstantPitch(pitch)),
+            Some(MAJOR_SCALE_NOTES.to_vec()),
+            None,
+        );
+        assert_eq!(lane.tick(), LetterOctave(Letter::D, 4));
+    }
+
+    #[test]
+    fn the_transpose_shifts_every_played_note_after_quantization() {
+        let notes = |transpose: f32| {
+            let model = SequencerModel {
+                melody_pitch_generator_type_index: Some(
+                    MELODY_PITCH_GENERATOR_TYPE_NAMES
+                        .iter()
+                        .position(|name| *name == "Random")
+                        .unwrap(),
+                ),
+                transpose,
+                lock_seed: true,
+                seed: 1234,
+                ..SequencerModel::default()
+            };
+            let config: SequencerConfiguration = model.into();
+            let mut engine = SequencerEngine::new(&config, true);
+            (0..TICKS_PER_BAR * 4)
+                .flat_map(|_| engine.tick())
+                .filter(|message| message[0] == NOTE_ON_MSG)
+                .map(|message| message[1])
+                .collect::<Vec<u8>>()
+        };
+
+        let untransposed = notes(0.0);
+        assert!(!untransposed.is_empty());
+        assert_eq!(
+            notes(2.0),
+            untransposed
+                .iter()
+                .map(|note| note + 2)
+                .collect::<Vec<u8>>()
+        );
+    }
+
+    #[test]
+    fn pitch_parameters_changed_in_place_play_like_a_rebuilt_generator_carrying_on() {
+        let mut config: SequencerConfiguration = SequencerModel::default().into();
+        config.melody_enabled = true;
+        config.melody_pitch_generator_type = PitchGeneratorType::Random;
+        config.melody_quantizer_scale = Some(MAJOR_SCALE_NOTES.to_vec());
+        config.transposition_enabled = true;
+        config.transposition_pitch_generator_type = PitchGeneratorType::RampUp;
+        config.transposition_cycle_length = 8;
+        config.quantizer_strength = 0.5;
+        let mut pitch_generator = Sequencer::build_pitch_generator(&config);
+        for _ in 0..5 {
+            pitch_generator.tick();
+        }
+        let state = pitch_generator.state();
+
+        config.melody_min_pitch = LetterOctave(Letter::G, 3);
+        config.melody_max_pitch = LetterOctave(Letter::G, 5);
+        config.transposition_cycle_length = 12;
+        config.quantizer_scale = WHOLE_TONE_SCALE_NOTES.to_vec();
+        // the parameters the in-place updates send
+        for parameter in [
+            PitchParameter::Lane(
+                Lane::Melody,
+                LaneParameter::Range(config.melody_min_pitch, config.melody_max_pitch),
+            ),
+            PitchParameter::PitchRange(Sequencer::quantizer_pitch_range(&config)),
+            PitchParameter::Lane(Lane::Transposition, LaneParameter::CycleLength(12)),
+            PitchParameter::Scale(config.quantizer_scale.clone()),
+        ] {
+            pitch_generator.set_parameter(&parameter);
+        }
+        let mut rebuilt = Sequencer::build_pitch_generator(&config);
+        rebuilt.restore(&state);
+
+        let continued: Vec<f32> = (0..100).map(|_| pitch_generator.tick_step()).collect();
+        let expected: Vec<f32> = (0..100).map(|_| rebuilt.tick_step()).collect();
+        assert_eq!(continued, expected);
+    }
+
+    #[test]
+    fn the_transposition_lane_snaps_to_its_own_scale_before_it_is_added() {
+        let mut config: SequencerConfiguration = SequencerModel::default().into();
+        config.melody_pitch_generator_type = PitchGeneratorType::Steps;
+        config.melody_step_sequence = vec![LetterOctave(Letter::C, 4)];
+        config.transposition_pitch_generator_type = PitchGeneratorType::Random;
+        config.transposition_quantizer_scale = Some(WHOLE_TONE_SCALE_NOTES.to_vec());
+        // the shared scale lets every note through
+        config.quantizer_scale = CHROMATIC_SCALE_NOTES.to_vec();
+        let mut pitch_generator = Sequencer::build_pitch_generator(&config);
+
+        for _ in 0..100 {
+            // whole tones above C4 stay in the whole-tone scale of C
+            let pitch = pitch_generator.tick();
+            assert!(
+                WHOLE_TONE_SCALE_NOTES.contains(&pitch.letter()),
+                "{:?}",
+                pitch
+            );
+        }
+    }
+
+    #[test]
+    fn chord_tones_are_not_moved_off_the_chord_by_the_shared_scale() {
+        let mut config: SequencerConfiguration = SequencerModel::default().into();
+        config.melody_pitch_generator_type = PitchGeneratorType::Steps;
+        config.melody_step_sequence = vec![LetterOctave(Letter::E, 4)];
+        // the C minor triad of the lane scale, against a shared scale without its E flat
+        config.transposition_pitch_generator_type = PitchGeneratorType::Progression;
+        config.transposition_progression = vec![0];
+        config.transposition_quantizer_scale = Some(MINOR_SCALE_NOTES.to_vec());
+        config.transposition_chord_tones = true;
+        config.quantizer_scale = MAJOR_PENTATONIC_SCALE_NOTES.to_vec();
+        let mut pitch_generator = Sequencer::build_pitch_generator(&config);
+
+        assert_eq!(
+            pitch_generator.tick().step(),
+            LetterOctave(Letter::Eb, 4).step()
+        );
+    }
+
+    #[test]
+    fn a_disabled_melody_silences_the_lanes_and_a_disabled_transposition_adds_nothing() {
+        let notes = |melody_enabled: bool, transposition_enabled: bool| {
+            let mut config: SequencerConfiguration = SequencerModel::default().into();
+            config.melody_enabled = melody_enabled;
+            config.transposition_enabled = transposition_enabled;
+            config.melody_pitch_generator_type = PitchGeneratorType::Steps;
+            config.melody_step_sequence =
+                vec![LetterOctave(Letter::C, 4), LetterOctave(Letter::E, 4)];
+            // a fifth up on every step
+            config.transposition_pitch_generator_type = PitchGeneratorType::Square;
+            config.transposition_min_pitch = Step(7.0).to_letter_octave();
+            config.transposition_max_pitch = Step(7.0).to_letter_octave();
+            config.quantizer_scale = CHROMATIC_SCALE_NOTES.to_vec();
+            config.trigger_probablilty = 1.0;
+            config.trigger_restlessness = 0.0;
+            // the pitch lanes move on every tick, so every tick is a step
+            config.clock_divider_factor = 1;
+            let mut engine = SequencerEngine::new(&config, true);
+            let mut notes = (0..TICKS_PER_BAR)
+                .flat_map(|_| engine.tick())
+                .filter(|message| message[0] == NOTE_ON_MSG)
+                .map(|message| message[1])
+                .collect::<Vec<u8>>();
+            notes.sort_unstable();
+            notes.dedup();
+            notes
+        };
+
+        assert_eq!(notes(true, true), vec![67, 71]);
+        assert_eq!(notes(true, false), vec![60, 64]);
+        assert!(notes(false, true).is_empty());
+        assert!(notes(false, false).is_empty());
+    }
+
+    // Ticks of the note ons of C4 over two steps of 6 ticks
+    fn note_repeat_ticks_over_two_steps(interval_ticks: u32) -> Vec<u32> {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetTriggerGenerator(Box::new(
+            ClockDivider::new(Box::new(ConstantTrigger(Trigger::On)), 6, 0),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetNoteRepeat(Some(interval_ticks)))
+            .unwrap();
+
+        let mut note_on_ticks = Vec::new();
+        for tick in 0..12 {
+            let sent = midi_output.messages().len();
+            thread.tick();
+            if midi_output.messages()[sent..].contains(&vec![NOTE_ON_MSG, 60, VELOCITY]) {
+                note_on_ticks.push(tick);
+            }
+        }
+        note_on_ticks
+    }
+
+    #[test]
+    fn note_repeat_strikes_the_note_at_the_interval_until_the_step_is_over() {
+        // 1/32 notes within 1/16 steps
+        assert_eq!(
+            note_repeat_ticks_over_two_steps(NoteLength::ThirtySecond.to_ticks(24)),
+            vec![0, 3, 6, 9]
+        );
+        // a repeat that would fall on the next step is dropped, the step starts over
+        assert_eq!(note_repeat_ticks_over_two_steps(4), vec![0, 4, 6, 10]);
+    }
+
+    #[test]
+    fn the_last_step_only_changes_on_divided_clock_steps() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetTriggerGenerator(Box::new(
+            ClockDivider::new(Box::new(ConstantTrigger(Trigger::On)), 3, 0),
+        )))
+        .unwrap();
+        let c4 = LetterOctave(Letter::C, 4);
+
+        let mut last_steps = Vec::new();
+        for _ in 0..6 {
+            thread.tick();
+            last_steps.push(thread.engine.last_step.lock().unwrap().take());
+        }
+
+        assert_eq!(
+            last_steps,
+            vec![
+                Some((c4, Trigger::On)),
+                None,
+                None,
+                Some((c4, Trigger::On)),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn midi_note_numbers_are_clamped_and_flagged_outside_the_midi_note_range() {
+        assert_eq!(midi_note_number(LetterOctave(Letter::C, 4)), (60, true));
+        assert_eq!(midi_note_number(Step(0.0).to_letter_octave()), (0, true));
+        assert_eq!(
+            midi_note_number(Step(127.0).to_letter_octave()),
+            (127, true)
+        );
+        assert_eq!(midi_note_number(Step(-5.0).to_letter_octave()), (0, false));
+        assert_eq!(
+            midi_note_number(Step(131.0).to_letter_octave()),
+            (127, false)
+        );
+    }
+
+    #[test]
+    fn the_last_step_shows_the_pitch_before_it_is_clamped() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetOctaveOffset(7)).unwrap();
+
+        thread.tick();
+
+        // C4 seven octaves up is out of range, so the top note is played instead
+        let c11 = LetterOctave(Letter::C, 11);
+        assert_eq!(
+            *thread.engine.last_step.lock().unwrap(),
+            Some((c11, Trigger::On))
+        );
+        assert_eq!(
+            midi_output.messages(),
+            vec![vec![NOTE_ON_MSG, 127, VELOCITY]]
+        );
+    }
+
+    #[test]
+    fn trigger_density_averages_the_most_recent_steps() {
+        let mut density = TriggerDensity::new(4);
+        assert_eq!(density.density(), 0.0);
+
+        density.push(Trigger::On);
+        density.push(Trigger::Off);
+        assert_eq!(density.density(), 0.5);
+        density.push(Trigger::On);
+        density.push(Trigger::On);
+        assert_eq!(density.density(), 0.75);
+
+        // the oldest steps drop out of the window, the rest last
+        density.push(Trigger::On);
+        assert_eq!(density.density(), 0.75);
+        density.push(Trigger::On);
+        assert_eq!(density.density(), 1.0);
+        for _ in 0..4 {
+            density.push(Trigger::Off);
+        }
+        assert_eq!(density.density(), 0.0);
+    }
+
+    #[test]
+    fn pitch_history_keeps_the_most_recently_played_notes() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        let pitch_history = Arc::new(Mutex::new(PitchHistory::new(3)));
+        thread.engine.pitch_history = pitch_history.clone();
+
+        let letters = [Letter::C, Letter::D, Letter::E, Letter::F, Letter::G];
+        for letter in letters {
+            tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+                ConstantPitch(LetterOctave(letter, 4)),
+            )))
+            .unwrap();
+            thread.tick();
+        }
+
+        assert_eq!(
+            pitch_history.lock().unwrap().to_vec(),
+            vec![
+                LetterOctave(Letter::E, 4),
+                LetterOctave(Letter::F, 4),
+                LetterOctave(Letter::G, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn panic_sends_all_notes_off_and_all_sound_off_even_when_stopped() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetNoteLength(4..=4)).unwrap();
+        thread.tick();
+        tx.send(SequencerCommand::Stop).unwrap();
+        tx.send(SequencerCommand::Panic).unwrap();
+
+        for _ in 0..4 {
+            thread.tick();
+        }
+
+        // the note cut by the panic is not released again afterwards
+        assert_eq!(
+            midi_output.messages(),
+            vec![
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+                vec![0xB0, 0x7B, 0x00],
+                vec![0xB0, 0x78, 0x00],
+                vec![PITCH_BEND_MSG, 0x00, 0x40],
+            ]
+        );
+    }
+
+    #[test]
+    fn octave_offset_shifts_notes_by_whole_octaves_within_midi_range() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetOctaveOffset(1)).unwrap();
+        thread.tick();
+        tx.send(SequencerCommand::SetOctaveOffset(10)).unwrap();
+        thread.tick();
+        tx.send(SequencerCommand::SetOctaveOffset(-10)).unwrap();
+        thread.tick();
+
+        let notes_on: Vec<u8> = midi_output
+            .messages()
+            .iter()
+            .filter(|message| message[0] == NOTE_ON_MSG)
+            .map(|message| message[1])
+            .collect();
+        assert_eq!(notes_on, vec![60 + 12, 127, 0]);
+    }
+
+    #[test]
+    fn notes_are_ended_with_a_note_off_status_by_default() {
+        let midi_output = MockMidiSink::default();
+        let (_tx, mut thread) = sequencer_thread(&midi_output);
+
+        thread.tick();
+        thread.engine.is_playing = false;
+        thread.tick();
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![vec![0x90, 60, VELOCITY], vec![0x80, 60, RELEASE_VELOCITY]]
+        );
+    }
+
+    #[test]
+    fn notes_are_ended_with_a_zero_velocity_note_on_in_compatibility_mode() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetNoteOffStyle(
+            NoteOffStyle::NoteOnVelocity0,
+        ))
+        .unwrap();
+
+        thread.tick();
+        thread.engine.is_playing = false;
+        thread.tick();
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![vec![0x90, 60, VELOCITY], vec![0x90, 60, 0x00]]
+        );
+    }
+
+    #[test]
+    fn the_oldest_held_note_is_stolen_beyond_the_voice_limit() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+            StepSequencePitchGenerator::new(vec![
+                LetterOctave(Letter::C, 4),
+                LetterOctave(Letter::D, 4),
+                LetterOctave(Letter::E, 4),
+            ]),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetNoteLength(10..=10)).unwrap();
+        tx.send(SequencerCommand::SetMaxVoices(2)).unwrap();
+
+        for _ in 0..3 {
+            thread.tick();
+        }
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+                vec![NOTE_ON_MSG, 62, VELOCITY],
+                vec![NOTE_OFF_MSG, 60, RELEASE_VELOCITY],
+                vec![NOTE_ON_MSG, 64, VELOCITY],
+            ]
+        );
+    }
+
+    #[test]
+    fn mono_mode_ends_the_sounding_note_before_the_next_one_starts() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+            StepSequencePitchGenerator::new(vec![
+                LetterOctave(Letter::C, 4),
+                LetterOctave(Letter::D, 4),
+            ]),
+        )))
+        .unwrap();
+        // long and tied notes would overlap the next ones
+        tx.send(SequencerCommand::SetNoteLength(10..=10)).unwrap();
+        tx.send(SequencerCommand::SetTieProbability(1.0)).unwrap();
+        tx.send(SequencerCommand::SetMono(true)).unwrap();
+
+        for _ in 0..12 {
+            thread.tick();
+        }
+
+        let messages = midi_output.messages();
+        assert_eq!(
+            messages[..4],
+            [
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+                vec![NOTE_OFF_MSG, 60, RELEASE_VELOCITY],
+                vec![NOTE_ON_MSG, 62, VELOCITY],
+                vec![NOTE_OFF_MSG, 62, RELEASE_VELOCITY],
+            ]
+        );
+        let mut sounding = 0;
+        for message in messages.iter() {
+            match message[0] {
+                NOTE_ON_MSG => {
+                    assert_eq!(sounding, 0, "{:?}", messages);
+                    sounding += 1;
+                }
+                NOTE_OFF_MSG => sounding -= 1,
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn rests_only_skip_pitches_while_the_pitch_advances_on_every_step() {
+        let played_notes = |advance_on_trigger_only: bool| {
+            let midi_output = MockMidiSink::default();
+            let (tx, mut thread) = sequencer_thread(&midi_output);
+            tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+                StepSequencePitchGenerator::new(vec![
+                    LetterOctave(Letter::C, 4),
+                    LetterOctave(Letter::D, 4),
+                    LetterOctave(Letter::E, 4),
+                    LetterOctave(Letter::F, 4),
+                ]),
+            )))
+            .unwrap();
+            // every other step is rested
+            tx.send(SequencerCommand::SetTriggerGenerator(Box::new(
+                GateRatioTriggerGenerator::new(1, 1),
+            )))
+            .unwrap();
+            tx.send(SequencerCommand::SetAdvanceOnTriggerOnly(
+                advance_on_trigger_only,
+            ))
+            .unwrap();
+            for _ in 0..8 {
+                thread.tick();
+            }
+            midi_output
+                .messages()
+                .iter()
+                .filter(|message| message[0] == NOTE_ON_MSG)
+                .map(|message| message[1])
+                .collect::<Vec<u8>>()
+        };
+
+        assert_eq!(played_notes(false), vec![60, 64, 60, 64]);
+        assert_eq!(played_notes(true), vec![60, 62, 64, 65]);
+    }
+
+    #[test]
+    fn harmonized_steps_play_the_chord_of_each_melody_note() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetPitchGenerator(Box::new(
+            StepSequencePitchGenerator::new(vec![
+                LetterOctave(Letter::C, 4),
+                LetterOctave(Letter::D, 4),
+            ]),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetNoteLength(1..=1)).unwrap();
+        tx.send(SequencerCommand::SetHarmonizer(Some(Harmonizer::new(
+            vec![4, 7],
+            MAJOR_SCALE_NOTES.to_vec(),
+        ))))
+        .unwrap();
+
+        for _ in 0..2 {
+            thread.tick();
+        }
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![
+                vec![NOTE_ON_MSG, 60, VELOCITY],
+                vec![NOTE_ON_MSG, 64, VELOCITY],
+                vec![NOTE_ON_MSG, 67, VELOCITY],
+                vec![NOTE_OFF_MSG, 60, RELEASE_VELOCITY],
+                vec![NOTE_OFF_MSG, 64, RELEASE_VELOCITY],
+                vec![NOTE_OFF_MSG, 67, RELEASE_VELOCITY],
+                vec![NOTE_ON_MSG, 62, VELOCITY],
+                vec![NOTE_ON_MSG, 65, VELOCITY],
+                vec![NOTE_ON_MSG, 69, VELOCITY],
+            ]
+        );
+    }
+
+    #[test]
+    fn modulation_values_map_pitch_steps_to_control_change_range() {
+        assert_eq!(modulation_value(Step(-5.0).to_letter_octave()), 0);
+        assert_eq!(modulation_value(Step(0.0).to_letter_octave()), 0);
+        assert_eq!(modulation_value(Step(64.0).to_letter_octave()), 64);
+        assert_eq!(modulation_value(Step(127.0).to_letter_octave()), 127);
+        assert_eq!(modulation_value(Step(200.0).to_letter_octave()), 127);
+    }
+
+    #[test]
+    fn modulation_is_sent_once_per_tick() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetTriggerGenerator(Box::new(
+            ConstantTrigger(Trigger::Off),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetModulation(Some(ModulationLane {
+            generator: Box::new(ConstantPitch(Step(64.0).to_letter_octave())),
+            target: ModulationTarget::ControlChange,
+            controller: PAN_CONTROLLER,
+        })))
+        .unwrap();
+
+        for _ in 0..3 {
+            thread.tick();
+        }
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![vec![CONTROL_CHANGE_MSG, PAN_CONTROLLER, 64]; 3]
+        );
+    }
+
+    #[test]
+    fn the_rate_limit_sends_an_unchanged_control_change_value_only_once() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        tx.send(SequencerCommand::SetTriggerGenerator(Box::new(
+            ConstantTrigger(Trigger::Off),
+        )))
+        .unwrap();
+        tx.send(SequencerCommand::SetModulation(Some(ModulationLane {
+            generator: Box::new(ConstantPitch(Step(64.0).to_letter_octave())),
+            target: ModulationTarget::ControlChange,
+            controller: PAN_CONTROLLER,
+        })))
+        .unwrap();
+        tx.send(SequencerCommand::SetMidiRateLimit(Some(16)))
+            .unwrap();
+
+        thread.tick();
+        thread.tick();
+
+        assert_eq!(
+            midi_output.messages(),
+            vec![vec![CONTROL_CHANGE_MSG, PAN_CONTROLLER, 64]]
+        );
+
+        // a panic resets the receiving synth, so the value is sent again
+        tx.send(SequencerCommand::Panic).unwrap();
+        thread.tick();
+        let pan = vec![CONTROL_CHANGE_MSG, PAN_CONTROLLER, 64];
+        let messages = midi_output.messages();
+        assert_eq!(
+            messages.iter().filter(|message| **message == pan).count(),
+            2
+        );
+        assert_eq!(messages.last(), Some(&pan));
+    }
+
+    #[test]
+    fn the_rate_limit_caps_the_messages_of_a_tick_but_not_the_note_offs_of_sent_notes() {
+        let mut rate_limiter = MidiRateLimiter::default();
+        rate_limiter.set_limit(Some(2));
+        let events = vec![
+            vec![NOTE_ON_MSG, 60, VELOCITY],
+            vec![NOTE_ON_MSG, 64, VELOCITY],
+            vec![NOTE_ON_MSG, 67, VELOCITY],
+            vec![NOTE_OFF_MSG, 55, 0],
+            vec![NOTE_ON_MSG, 52, 0],
+            vec![CONTROL_CHANGE_MSG, ALL_NOTES_OFF_CONTROLLER, 0],
+        ];
+
+        assert_eq!(
+            rate_limiter.limit(&events),
+            vec![&events[0], &events[1], &events[3], &events[4], &events[5]]
+        );
+
+        // the note off of the dropped note is dropped as well
+        let note_offs = vec![vec![NOTE_OFF_MSG, 67, 0], vec![NOTE_OFF_MSG, 60, 0]];
+        assert_eq!(rate_limiter.limit(&note_offs), vec![&note_offs[1]]);
+
+        // without a limit everything is sent
+        rate_limiter.set_limit(None);
+        assert_eq!(rate_limiter.limit(&events).len(), events.len());
+    }
+
+    #[test]
+    fn modulation_targets_send_their_own_messages() {
+        let lane = |target| ModulationLane {
+            generator: Box::new(ConstantPitch(Step(64.0).to_letter_octave())),
+            target,
+            controller: 74,
+        };
+        assert_eq!(
+            lane(ModulationTarget::ControlChange).message(64),
+            vec![CONTROL_CHANGE_MSG, 74, 64]
+        );
+        assert_eq!(
+            lane(ModulationTarget::Pan).message(64),
+            vec![CONTROL_CHANGE_MSG, PAN_CONTROLLER, 64]
+        );
+        assert_eq!(
+            lane(ModulationTarget::Aftertouch).message(64),
+            vec![0xD0, 64]
+        );
+    }
+
+    #[test]
+    fn note_lengths_map_to_tick_counts_at_24_ppqn() {
+        assert_eq!(NoteLength::ThirtySecond.to_ticks(24), 3);
+        assert_eq!(NoteLength::Sixteenth.to_ticks(24), 6);
+        assert_eq!(NoteLength::Eighth.to_ticks(24), 12);
+        assert_eq!(NoteLength::Quarter.to_ticks(24), 24);
+    }
+
+    #[test]
+    fn lane_quantizers_keep_each_lane_in_its_own_scale_before_summing() {
+        let mut config: SequencerConfiguration = SequencerModel::default().into();
+        config.melody_pitch_generator_type = PitchGeneratorType::Random;
+        config.melody_quantizer_scale = Some(MAJOR_PENTATONIC_SCALE_NOTES.to_vec());
+        // random transpositions of up to two octaves, snapped to whole octaves
+        config.transposition_pitch_generator_type = PitchGeneratorType::Random;
+        config.transposition_min_pitch = Step(0.0).to_letter_octave();
+        config.transposition_max_pitch = Step(24.0).to_letter_octave();
+        config.transposition_quantizer_scale = Some(vec![Letter::C]);
+        // the shared scale lets every note through, so only the lanes keep the sum in key
+        config.quantizer_scale = CHROMATIC_SCALE_NOTES.to_vec();
+        let mut pitch_generator = Sequencer::build_pitch_generator(&config);
+
+        for _ in 0..100 {
+            // an unquantized melody or a transposition off the octaves would leave the scale
+            let pitch = pitch_generator.tick();
+            assert!(
+                MAJOR_PENTATONIC_SCALE_NOTES.contains(&pitch.letter()),
+                "{:?}",
+                pitch
+            );
+        }
+    }
+
+    #[test]
+    fn monitor_sink_describes_midi_messages_as_text() {
+        assert_eq!(
+            format_midi_message(&[NOTE_ON_MSG, 60, VELOCITY], false),
+            "Note On C4 vel 100"
+        );
+        assert_eq!(
+            format_midi_message(&[NOTE_ON_MSG, 69, VELOCITY], true),
+            "Note On 440.00 Hz vel 100"
+        );
+        assert_eq!(
+            format_midi_message(&[NOTE_OFF_MSG | 0x01, 61, VELOCITY], false),
+            "Note Off C#4 vel 100"
+        );
+        assert_eq!(
+            format_midi_message(&[NOTE_OFF_MSG | 0x01, 60, VELOCITY], true),
+            "Note Off 261.63 Hz vel 100"
+        );
+        assert_eq!(
+            format_midi_message(&[CONTROL_CHANGE_MSG, ALL_NOTES_OFF_CONTROLLER, 0], false),
+            "Control Change 123 value 0"
+        );
+        assert_eq!(
+            format_midi_message(&[CHANNEL_PRESSURE_MSG, 90], false),
+            "Channel Pressure 90"
+        );
+        assert_eq!(
+            format_midi_message(&[PITCH_BEND_MSG, 0x00, 0x30], false),
+            "Pitch Bend -2048"
+        );
+        assert_eq!(format_midi_message(&[0xF8], false), "[F8]");
+    }
+
+    #[test]
+    fn long_notes_at_slow_tempos_are_clamped_to_the_maximum_duration() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        // a quarter note lasts 3000 ms at 20 BPM
+        tx.send(SequencerCommand::SetNoteLength(24..=24)).unwrap();
+        tx.send(SequencerCommand::SetTickDuration(tick_duration_ms(20.0)))
+            .unwrap();
+        tx.send(SequencerCommand::SetNoteDurationRange(5.0..=1000.0))
+            .unwrap();
+
+        thread.tick();
+
+        assert_eq!(thread.engine.pending_note_offs[0].remaining_ticks, 8);
+    }
+
+    #[test]
+    fn a_minimum_note_duration_above_the_maximum_is_swapped_with_it() {
+        let config = SequencerConfiguration {
+            note_min_duration_ms: 1000.0,
+            note_max_duration_ms: 5.0,
+            ..SequencerConfiguration::default()
+        };
+        assert_eq!(note_duration_range(&config), 5.0..=1000.0);
+
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        thread.engine = SequencerEngine::new(&config, true);
+        tx.send(SequencerCommand::SetNoteDurationRange(note_duration_range(
+            &config,
+        )))
+        .unwrap();
+        thread.tick();
+        assert_eq!(thread.engine.note_duration_ms, 5.0..=1000.0);
+    }
+
+    #[test]
+    fn short_notes_at_fast_tempos_are_clamped_to_the_minimum_duration() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        // a 1/32 note lasts 25 ms at 300 BPM
+        tx.send(SequencerCommand::SetNoteLength(3..=3)).unwrap();
+        tx.send(SequencerCommand::SetTickDuration(tick_duration_ms(300.0)))
+            .unwrap();
+        tx.send(SequencerCommand::SetNoteDurationRange(50.0..=1000.0))
+            .unwrap();
+
+        thread.tick();
+
+        assert_eq!(thread.engine.pending_note_offs[0].remaining_ticks, 6);
+        // notes within the range keep their length
+        tx.send(SequencerCommand::SetNoteDurationRange(5.0..=1000.0))
+            .unwrap();
+        thread.engine.pending_note_offs.clear();
+        thread.tick();
+        assert_eq!(thread.engine.pending_note_offs[0].remaining_ticks, 3);
+    }
+
+    #[test]
+    fn random_note_lengths_stay_within_the_note_length_range() {
+        let midi_output = MockMidiSink::default();
+        let (tx, mut thread) = sequencer_thread(&midi_output);
+        thread.engine.note_length_rng = SeededRng::seed_from_u64(7);
+        // between a 1/32 and a 1/8 note
+        tx.send(SequencerCommand::SetNoteLength(3..=12)).unwrap();
+        thread.tick();
+
+        let hold_ticks: Vec<u32> = (0..200)
+            .map(|_| thread.engine.note_off_ticks(VELOCITY))
+            .collect();
+        assert!(hold_ticks.iter().all(|ticks| (3..=12).contains(ticks)));
+        // the lengths actually vary, reaching both ends of the range
+        assert!(hold_ticks.contains(&3));
+        assert!(hold_ticks.contains(&12));
+    }
+
+    #[test]
+    fn louder_notes_are_held_longer_by_the_velocity_length_coupling() {
+        assert_eq!(velocity_scaled_ticks(16.0, 127, 1.0), 16.0);
+        assert_eq!(velocity_scaled_ticks(16.0, 127, 0.5), 16.0);
+        assert_eq!(velocity_scaled_ticks(16.0, 64, 0.0), 16.0);
+        // proportional to the velocity at full coupling, halfway there at half of it
+        assert!((velocity_scaled_ticks(16.0, 64, 1.0) - 8.06).abs() < 0.01);
+        assert!((velocity_scaled_ticks(16.0, 32, 1.0) - 4.03).abs() < 0.01);
+        assert!((velocity_scaled_ticks(16.0, 64, 0.5) - 12.03).abs() < 0.01);
+
+        // the velocity follows the pitch, so the higher note is louder and held longer
+        let hold_ticks = |pitch| {
+            let mut engine = SequencerEngine::with_generators(
+                Box::new(ConstantPitch(pitch)),
+                Box::new(ConstantTrigger(Trigger::On)),
+                16..=16,
+                true,
+            );
+            engine.velocity = VelocityShaper {
+                mode: VelocityMode::PitchFollow,
+                curve: VelocityCurve::Linear,
+                pitch_range: 0..=60,
+            };
+            engine.velocity_length = 1.0;
+            engine.tick();
+            engine.pending_note_offs[0].remaining_ticks
+        };
+        // C4 is played at velocity 127, C3 at 102
+        assert_eq!(hold_ticks(LetterOctave(Letter::C, 4)), 16);
+        assert_eq!(hold_ticks(LetterOctave(Letter::C, 3)), 13);
+    }
+
+    #[test]
+    fn the_timer_interval_is_at_least_one_millisecond() {
+        assert_eq!(timer_interval_us(120.0), 20_833);
+        assert_eq!(timer_interval_us(MAX_BPM), 1000);
+        // would round down to 0 ms
+        assert_eq!(timer_interval_us(5000.0), 1000);
+
+        assert_eq!(clamp_bpm(120.0), 120.0);
+        assert_eq!(clamp_bpm(5000.0), MAX_BPM);
+    }
+
+    #[test]
+    fn the_actual_bpm_follows_from_the_rounded_interval() {
+        // 20,833.33 us rounded down plays a little fast
+        assert_eq!(actual_bpm(timer_interval_us(120.0)), 120.00192);
+        // whole milliseconds would be 1% slow
+        assert_eq!(actual_bpm(21_000), 119.04762);
+        assert_eq!(actual_bpm(1000), MAX_BPM);
+    }
+
+    #[test]
+    fn microsecond_intervals_drift_far_less_than_millisecond_intervals() {
+        // a tick lasts 19.23... ms at 130 BPM
+        let bpm = 130.0;
+        let ticks = 10_000.0;
+        let exact_ms = 60_000.0 / bpm / TICKS_PER_QUARTER_NOTE as f64 * ticks;
+        // the whole milliseconds the interval used to be truncated to
+        let millisecond_drift_ms = (exact_ms - 19.0 * ticks).abs();
+        let microsecond_drift_ms =
+            (exact_ms - timer_interval_us(bpm as f32) as f64 / 1000.0 * ticks).abs();
+
+        assert!(millisecond_drift_ms > 2000.0);
+        assert!(microsecond_drift_ms < 3.0);
+    }
+
+    #[test]
+    fn tempo_automation_swings_around_the_bpm_over_the_period() {
+        let automation = TempoAutomation {
+            depth: 10.0,
+            period_bars: 16,
+        };
+        let bpm_at = |bar| automation.bpm(120.0, bar);
+        assert!((bpm_at(0) - 120.0).abs() < 0.001);
+        assert!((bpm_at(4) - 130.0).abs() < 0.001);
+        assert!((bpm_at(8) - 120.0).abs() < 0.001);
+        assert!((bpm_at(12) - 110.0).abs() < 0.001);
+        assert!((bpm_at(16) - 120.0).abs() < 0.001);
+        assert!((bpm_at(20) - 130.0).abs() < 0.001);
+
+        let disabled = TempoAutomation {
+            depth: 0.0,
+            period_bars: 16,
+        };
+        assert_eq!(disabled.bpm(120.0, 4), 120.0);
     }
 }
@@ -0,0 +1,1021 @@
+use std::{ops::RangeInclusive, str::FromStr};
+
+use log::warn;
+use nannou_conrod::widget::drop_down_list::Idx;
+use pitch_calc::{Letter, LetterOctave, Step};
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::module::{
+    self, format_step_sequence, parse_step_sequence, PitchGeneratorType, QuantizeDirection,
+    RampMode, TriggerOrder, TriggerType,
+};
+use crate::sequencer::{
+    ChannelMode, Fill, Humanize, ModulationTarget, NoteLength, NoteOffStyle, PitchFavor,
+    SequencerConfiguration, Swing, TempoAutomation,
+};
+use crate::velocity::{VelocityCurve, VelocityMode};
+
+pub const MELODY_ENABLED_DEFAULT_VALUE: bool = true;
+pub const TRANSPOSITION_ENABLED_DEFAULT_VALUE: bool = true;
+pub const DRUM_MODE_DEFAULT_VALUE: bool = false;
+// The closed hi-hat of the General MIDI drum map
+pub const DRUM_NOTE_DEFAULT_VALUE: f32 = 42.0;
+pub const MELODY_MIN_PITCH_DEFAULT_VALUE: LetterOctave = LetterOctave(Letter::C, 3);
+pub const MELODY_MAX_PITCH_DEFAULT_VALUE: LetterOctave = LetterOctave(Letter::C, 5);
+pub const MELODY_PITCH_GENERATOR_TYPE_DEFAULT_VALUE: Idx = 0;
+pub const MELODY_PITCH_GENERATOR_CYCLE_LENGTH_DEFAULT_VALUE: f32 = 64.0;
+pub const MELODY_PHASE_DEFAULT_VALUE: f32 = 0.0;
+pub const TRANSPOSITION_MIN_PITCH_DEFAULT_VALUE: Step = Step(0.0);
+pub const TRANSPOSITION_MAX_PITCH_DEFAULT_VALUE: Step = Step(12.0);
+pub const TRANSPOSITION_PITCH_GENERATOR_TYPE_DEFAULT_VALUE: Idx = 1;
+pub const TRANSPOSITION_PITCH_GENERATOR_CYCLE_LENGTH_DEFAULT_VALUE: f32 = 128.0;
+pub const TRANSPOSITION_PHASE_DEFAULT_VALUE: f32 = 0.0;
+pub const BPM_DEFAULT_VALUE: f32 = 120.0;
+pub const TEMPO_AUTOMATION_DEPTH_DEFAULT_VALUE: f32 = 0.0;
+pub const TEMPO_AUTOMATION_PERIOD_DEFAULT_VALUE: f32 = 16.0;
+pub const FILL_PHRASE_BARS_DEFAULT_VALUE: f32 = 4.0;
+pub const FILL_INTENSITY_DEFAULT_VALUE: f32 = 0.0;
+pub const PITCH_CHANCE_ENABLED_DEFAULT_VALUE: bool = false;
+pub const PITCH_FAVOR_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const PITCH_FAVOR_NAMES: &[&str] = &["Favor high", "Favor low"];
+pub const ADVANCE_ON_TRIGGER_ONLY_DEFAULT_VALUE: bool = false;
+// Humanization is off by default, the Humanize toggle switches it on with these amounts
+pub const HUMANIZE_VELOCITY_JITTER_DEFAULT_VALUE: f32 = 0.0;
+pub const HUMANIZE_TIMING_JITTER_DEFAULT_VALUE: f32 = 0.0;
+pub const HUMANIZE_LENGTH_JITTER_DEFAULT_VALUE: f32 = 0.0;
+pub const HUMANIZE_VELOCITY_JITTER_AMOUNT: f32 = 12.0;
+pub const HUMANIZE_TIMING_JITTER_AMOUNT: f32 = 1.0;
+pub const HUMANIZE_LENGTH_JITTER_AMOUNT: f32 = 0.2;
+// Straight timing, with the swing on the off-beat 8ths once it is dialed in
+pub const SWING_DEFAULT_VALUE: f32 = 0.5;
+pub const SWING_RESOLUTION_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const SWING_RESOLUTION_NAMES: &[&str] = &["Swing: 8ths", "Swing: 16ths"];
+pub const SWING_RESOLUTIONS: &[NoteLength] = &[NoteLength::Eighth, NoteLength::Sixteenth];
+pub const SEND_SONG_POSITION_DEFAULT_VALUE: bool = false;
+pub const MIDI_RATE_LIMIT_DEFAULT_VALUE: bool = false;
+// Room for the note offs, pitch bends and note ons of a full chord plus the modulation
+pub const MIDI_RATE_LIMIT_MESSAGES_PER_TICK: usize = 16;
+// Stopping pauses by default, so that playback resumes mid-pattern
+pub const STOP_RESETS_POSITION_DEFAULT_VALUE: bool = false;
+pub const LOCK_SEED_DEFAULT_VALUE: bool = false;
+pub const SEED_DEFAULT_VALUE: u64 = 0;
+pub const TRIGGER_PROBABILITY_DEFAULT_VALUE: f32 = 1.0;
+pub const TRIGGER_RESTLESSNESS_DEFAULT_VALUE: f32 = 0.0;
+pub const TRIGGER_TYPE_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const TRIGGER_TYPE_NAMES: &[&str] = &["Random", "Gate", "Gate + Random", "Gate or Random"];
+pub const TRIGGER_ORDER_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const TRIGGER_ORDER_NAMES: &[&str] = &["Divide first", "Trigger first"];
+pub const GATE_ON_DEFAULT_VALUE: f32 = 3.0;
+pub const GATE_OFF_DEFAULT_VALUE: f32 = 1.0;
+pub const CLOCK_DIVIDER_FACTOR_DEFAULT_VALUE: f32 = 16.0;
+pub const CLOCK_DIVIDER_OFFSET_DEFAULT_VALUE: f32 = 0.0;
+pub const PITCH_GENERATOR_TYPE_NAMES: &[&str] = &["Ramp", "Square", "Random"];
+// Only the melody lane can play back a step sequence or weighted scale degrees
+pub const MELODY_PITCH_GENERATOR_TYPE_NAMES: &[&str] =
+    &["Ramp", "Square", "Random", "Steps", "Degrees"];
+// Only the transposition lane can walk a chord progression
+pub const TRANSPOSITION_PITCH_GENERATOR_TYPE_NAMES: &[&str] =
+    &["Ramp", "Square", "Random", "Progression"];
+// I-V-vi-IV
+pub const TRANSPOSITION_PROGRESSION_DEFAULT_VALUE: &[i32] = &[0, 4, 5, 3];
+// The blend generator starts with a pass-through entry, followed by the melody generators
+pub const MELODY_BLEND_GENERATOR_TYPE_NAMES: &[&str] =
+    &["Off", "Ramp", "Square", "Random", "Steps", "Degrees"];
+pub const MELODY_BLEND_GENERATOR_TYPE_DEFAULT_VALUE: Idx = 0;
+pub const MELODY_BLEND_WEIGHT_DEFAULT_VALUE: f32 = 0.25;
+pub const MELODY_RAMP_MODE_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const RAMP_MODE_NAMES: &[&str] = &["Sawtooth", "Ping-pong"];
+pub const SQUARE_INTERVAL_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const SQUARE_INTERVAL_NAMES: &[&str] = &["Square: range", "Octave", "Fifth", "Fourth"];
+// Semitones of the square intervals, by the index of their name
+const SQUARE_INTERVAL_SEMITONES: &[Option<i32>] = &[None, Some(12), Some(7), Some(5)];
+pub const TRANSPOSITION_CHORD_TONES_DEFAULT_VALUE: bool = false;
+pub const MELODY_STEP_SEQUENCE_DEFAULT_VALUE: &str = "C4 E4 G4 C5 G4 E4";
+// Every degree of the largest (chromatic) scale is played equally often
+pub const MELODY_DEGREE_WEIGHTS_DEFAULT_VALUE: &[f32] = &[1.0; 12];
+pub const QUANTIZER_SCALE_INDEX_DEFAULT_VALUE: Idx = 1;
+pub const QUANTIZER_STRENGTH_DEFAULT_VALUE: f32 = 1.0;
+pub const QUANTIZER_DIRECTION_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const QUANTIZER_DIRECTION_NAMES: &[&str] = &["Up", "Down", "Nearest"];
+pub const QUANTIZER_MIN_OCTAVE_DEFAULT_VALUE: f32 = -1.0;
+pub const QUANTIZER_MAX_OCTAVE_DEFAULT_VALUE: f32 = 9.0;
+// The whole MIDI note range, where every note is quantized
+pub const QUANTIZE_BAND_MIN_PITCH_DEFAULT_VALUE: f32 = 0.0;
+pub const QUANTIZE_BAND_MAX_PITCH_DEFAULT_VALUE: f32 = 127.0;
+pub const TRANSPOSE_DEFAULT_VALUE: f32 = 0.0;
+pub const OCTAVE_SCATTER_PROBABILITY_DEFAULT_VALUE: f32 = 0.0;
+pub const OCTAVE_SCATTER_OCTAVES_DEFAULT_VALUE: f32 = 1.0;
+// Lane scales are off by default
+pub const MELODY_QUANTIZER_SCALE_INDEX_DEFAULT_VALUE: Idx = QUANTIZER_SCALES.len();
+pub const TRANSPOSITION_QUANTIZER_SCALE_INDEX_DEFAULT_VALUE: Idx = QUANTIZER_SCALES.len();
+pub const NOTE_LENGTH_MIN_INDEX_DEFAULT_VALUE: Idx = 1;
+pub const NOTE_LENGTH_MAX_INDEX_DEFAULT_VALUE: Idx = 1;
+// The default maximum is the length of a quarter note at the slowest tempo
+pub const NOTE_MIN_DURATION_DEFAULT_VALUE: f32 = 5.0;
+pub const NOTE_MAX_DURATION_DEFAULT_VALUE: f32 = 3000.0;
+pub const TIE_REPEATED_NOTES_DEFAULT_VALUE: bool = false;
+pub const TIE_PROBABILITY_DEFAULT_VALUE: f32 = 0.0;
+pub const OCTAVE_OFFSET_DEFAULT_VALUE: i32 = 0;
+pub const NOTE_OFF_AS_NOTE_ON_DEFAULT_VALUE: bool = false;
+pub const MAX_VOICES_DEFAULT_VALUE: f32 = 16.0;
+pub const RELEASE_VELOCITY_DEFAULT_VALUE: f32 = 64.0;
+pub const MICROTONAL_DEFAULT_VALUE: bool = false;
+pub const MONO_DEFAULT_VALUE: bool = false;
+pub const HARMONIZE_ENABLED_DEFAULT_VALUE: bool = false;
+// A major third and a fifth, snapped into the scale: its triads
+pub const HARMONY_INTERVALS_DEFAULT_VALUE: &[i32] = &[4, 7];
+// No channel list sends every note on channel 1
+pub const ROUND_ROBIN_CHANNELS_DEFAULT_VALUE: &[u8] = &[];
+pub const VELOCITY_MODE_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const VELOCITY_MODE_NAMES: &[&str] = &["Fixed", "Pitch follow"];
+pub const VELOCITY_CURVE_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const VELOCITY_CURVE_NAMES: &[&str] = &["Linear", "Exponential", "Logarithmic", "S-curve"];
+pub const VELOCITY_LENGTH_ENABLED_DEFAULT_VALUE: bool = false;
+pub const VELOCITY_LENGTH_AMOUNT_DEFAULT_VALUE: f32 = 0.5;
+pub const NOTE_LENGTH_NAMES: &[&str] = &["1/32", "1/16", "1/8", "1/4"];
+// The note repeat starts with an entry striking each note once, followed by the subdivisions
+pub const NOTE_REPEAT_NAMES: &[&str] = &["Repeat: off", "1/32", "1/16", "1/8"];
+pub const NOTE_REPEAT_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const MODULATION_ENABLED_DEFAULT_VALUE: bool = false;
+pub const MODULATION_GENERATOR_TYPE_DEFAULT_VALUE: Idx = 0;
+pub const MODULATION_MIN_DEFAULT_VALUE: f32 = 0.0;
+pub const MODULATION_MAX_DEFAULT_VALUE: f32 = 127.0;
+pub const MODULATION_CYCLE_LENGTH_DEFAULT_VALUE: f32 = 64.0;
+pub const MODULATION_TARGET_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const MODULATION_TARGET_NAMES: &[&str] = &["CC", "Pan", "Aftertouch"];
+pub const MODULATION_CONTROLLER_DEFAULT_VALUE: f32 = 10.0;
+pub const COUNT_IN_INDEX_DEFAULT_VALUE: Idx = 0;
+pub const COUNT_IN_NAMES: &[&str] = &["Count-in: off", "Count-in: 1 bar", "Count-in: 2 bars"];
+pub const COUNT_IN_BARS: &[u32] = &[0, 1, 2];
+pub const PITCH_HISTORY_LENGTH: usize = 32;
+pub const QUANTIZER_SCALES: &[&[Letter]] = &[
+    module::CHROMATIC_SCALE_NOTES,
+    module::MAJOR_SCALE_NOTES,
+    module::MINOR_SCALE_NOTES,
+    module::MAJOR_PENTATONIC_SCALE_NOTES,
+    module::MINOR_PENTATONIC_SCALE_NOTES,
+    module::WHOLE_TONE_SCALE_NOTES,
+];
+pub const QUANTIZER_SCALE_NAMES: &[&str] = &[
+    "Chromatic",
+    "Major",
+    "Minor",
+    "Major Pentatonic",
+    "Minor Pentatonic",
+    "Whole Tone",
+];
+// Lane scales are the quantizer scales followed by a pass-through entry. The entry comes last,
+// so that the melody scale indices of presets saved before it keep their scale.
+pub const LANE_QUANTIZER_SCALE_NAMES: &[&str] = &[
+    "Chromatic",
+    "Major",
+    "Minor",
+    "Major Pentatonic",
+    "Minor Pentatonic",
+    "Whole Tone",
+    "Off",
+];
+
+// Bounds of the settings, shared by the sliders and the variations
+pub const MELODY_PITCH_MIN_VALUE: LetterOctave = LetterOctave(Letter::C, 0);
+pub const MELODY_PITCH_MAX_VALUE: LetterOctave = LetterOctave(Letter::C, 7);
+pub const TRANSPOSITION_MIN_VALUE: Step = Step(0.0);
+pub const TRANSPOSITION_MAX_VALUE: Step = Step(24.0);
+pub const TRIGGER_PROBABILITY_MIN_VALUE: f32 = 0.0;
+pub const TRIGGER_PROBABILITY_MAX_VALUE: f32 = 1.0;
+pub const GATE_ON_MIN_VALUE: f32 = 1.0;
+pub const GATE_ON_MAX_VALUE: f32 = 16.0;
+pub const GATE_OFF_MIN_VALUE: f32 = 0.0;
+pub const GATE_OFF_MAX_VALUE: f32 = 16.0;
+pub const MELODY_BLEND_WEIGHT_MIN_VALUE: f32 = 0.0;
+pub const MELODY_BLEND_WEIGHT_MAX_VALUE: f32 = 1.0;
+pub const QUANTIZER_STRENGTH_MIN_VALUE: f32 = 0.0;
+pub const QUANTIZER_STRENGTH_MAX_VALUE: f32 = 1.0;
+// How far a variation at the full amount moves the settings
+const MUTATION_MAX_SEMITONES: f32 = 3.0;
+const MUTATION_MAX_SHARE: f32 = 0.2;
+const MUTATION_MAX_GATE_STEPS: f32 = 2.0;
+
+// Missing fields, e.g. in presets saved by older versions, fall back to the defaults
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SequencerModel {
+    pub melody_enabled: bool,
+    pub transposition_enabled: bool,
+    pub drum_mode: bool,
+    // MIDI note number, kept while drum mode is off
+    pub drum_note: f32,
+    pub melody_min_pitch: f32,
+    pub melody_max_pitch: f32,
+    pub melody_pitch_generator_type_index: Option<Idx>,
+    pub melody_cycle_length: f32,
+    pub melody_phase: f32,
+    #[serde(deserialize_with = "step_sequence_or_default")]
+    pub melody_step_sequence: String,
+    pub melody_degree_weights: Vec<f32>,
+    pub melody_ramp_mode_index: Option<Idx>,
+    pub square_interval_index: Option<Idx>,
+    pub melody_quantizer_scale_index: Option<Idx>,
+    pub melody_blend_generator_type_index: Option<Idx>,
+    pub melody_blend_weight: f32,
+    pub transposition_min_pitch: f32,
+    pub transposition_max_pitch: f32,
+    pub transposition_pitch_generator_type_index: Option<Idx>,
+    pub transposition_cycle_length: f32,
+    pub transposition_phase: f32,
+    pub transposition_quantizer_scale_index: Option<Idx>,
+    pub transposition_progression: Vec<i32>,
+    pub transposition_chord_tones: bool,
+    pub trigger_probability: f32,
+    pub trigger_restlessness: f32,
+    pub trigger_type_index: Option<Idx>,
+    pub trigger_order_index: Option<Idx>,
+    pub gate_on: f32,
+    pub gate_off: f32,
+    pub clock_divider_factor: f32,
+    pub clock_divider_offset: f32,
+    pub quantizer_scale_index: Option<Idx>,
+    pub quantizer_strength: f32,
+    pub quantizer_direction_index: Option<Idx>,
+    pub quantizer_min_octave: f32,
+    pub quantizer_max_octave: f32,
+    pub quantize_band_min_pitch: f32,
+    pub quantize_band_max_pitch: f32,
+    pub transpose: f32,
+    pub octave_scatter_probability: f32,
+    pub octave_scatter_octaves: f32,
+    pub note_length_min_index: Option<Idx>,
+    pub note_length_max_index: Option<Idx>,
+    pub note_repeat_index: Option<Idx>,
+    pub note_min_duration: f32,
+    pub note_max_duration: f32,
+    pub tie_repeated_notes: bool,
+    pub tie_probability: f32,
+    pub octave_offset: i32,
+    pub note_off_as_note_on: bool,
+    pub release_velocity: f32,
+    pub microtonal: bool,
+    // MIDI channels from 1 to 16, as shown to the user
+    pub round_robin_channels: Vec<u8>,
+    pub max_voices: f32,
+    pub mono: bool,
+    pub harmonize_enabled: bool,
+    // Semitones from the melody note, kept while harmonizing is off
+    pub harmony_intervals: Vec<i32>,
+    pub velocity_mode_index: Option<Idx>,
+    pub velocity_curve_index: Option<Idx>,
+    // Louder notes are held longer by the amount while enabled
+    pub velocity_length_enabled: bool,
+    pub velocity_length_amount: f32,
+    pub modulation_enabled: bool,
+    pub modulation_generator_type_index: Option<Idx>,
+    pub modulation_min: f32,
+    pub modulation_max: f32,
+    pub modulation_cycle_length: f32,
+    pub modulation_target_index: Option<Idx>,
+    pub modulation_controller: f32,
+    pub bpm: f32,
+    pub tempo_automation_depth: f32,
+    pub tempo_automation_period: f32,
+    pub fill_phrase_bars: f32,
+    pub fill_intensity: f32,
+    pub pitch_chance_enabled: bool,
+    pub pitch_favor_index: Option<Idx>,
+    pub advance_on_trigger_only: bool,
+    // Velocity steps, clock ticks, and share of the note length
+    pub humanize_velocity_jitter: f32,
+    pub humanize_timing_jitter: f32,
+    pub humanize_length_jitter: f32,
+    // Share of each pair of steps the first step lasts
+    pub swing: f32,
+    pub swing_resolution_index: Option<Idx>,
+    pub send_song_position: bool,
+    pub stop_resets_position: bool,
+    pub midi_rate_limit: bool,
+    pub lock_seed: bool,
+    // Kept while the seed is unlocked, to lock it again later
+    pub seed: u64,
+    // Seeds of the lanes of their own, locked independently of the seed above
+    pub melody_lock_seed: bool,
+    pub melody_seed: u64,
+    pub transposition_lock_seed: bool,
+    pub transposition_seed: u64,
+    pub trigger_lock_seed: bool,
+    pub trigger_seed: u64,
+    pub count_in_index: Option<Idx>,
+}
+
+impl Default for SequencerModel {
+    fn default() -> Self {
+        SequencerModel {
+            melody_enabled: MELODY_ENABLED_DEFAULT_VALUE,
+            transposition_enabled: TRANSPOSITION_ENABLED_DEFAULT_VALUE,
+            drum_mode: DRUM_MODE_DEFAULT_VALUE,
+            drum_note: DRUM_NOTE_DEFAULT_VALUE,
+            melody_min_pitch: MELODY_MIN_PITCH_DEFAULT_VALUE.step(),
+            melody_max_pitch: MELODY_MAX_PITCH_DEFAULT_VALUE.step(),
+            melody_pitch_generator_type_index: Some(MELODY_PITCH_GENERATOR_TYPE_DEFAULT_VALUE),
+            melody_cycle_length: MELODY_PITCH_GENERATOR_CYCLE_LENGTH_DEFAULT_VALUE,
+            melody_phase: MELODY_PHASE_DEFAULT_VALUE,
+            melody_step_sequence: MELODY_STEP_SEQUENCE_DEFAULT_VALUE.to_string(),
+            melody_degree_weights: MELODY_DEGREE_WEIGHTS_DEFAULT_VALUE.to_vec(),
+            melody_ramp_mode_index: Some(MELODY_RAMP_MODE_INDEX_DEFAULT_VALUE),
+            square_interval_index: Some(SQUARE_INTERVAL_INDEX_DEFAULT_VALUE),
+            melody_quantizer_scale_index: Some(MELODY_QUANTIZER_SCALE_INDEX_DEFAULT_VALUE),
+            melody_blend_generator_type_index: Some(MELODY_BLEND_GENERATOR_TYPE_DEFAULT_VALUE),
+            melody_blend_weight: MELODY_BLEND_WEIGHT_DEFAULT_VALUE,
+            transposition_min_pitch: TRANSPOSITION_MIN_PITCH_DEFAULT_VALUE.step(),
+            transposition_max_pitch: TRANSPOSITION_MAX_PITCH_DEFAULT_VALUE.step(),
+            transposition_pitch_generator_type_index: Some(
+                TRANSPOSITION_PITCH_GENERATOR_TYPE_DEFAULT_VALUE,
+            ),
+            transposition_cycle_length: TRANSPOSITION_PITCH_GENERATOR_CYCLE_LENGTH_DEFAULT_VALUE,
+            transposition_phase: TRANSPOSITION_PHASE_DEFAULT_VALUE,
+            transposition_quantizer_scale_index: Some(
+                TRANSPOSITION_QUANTIZER_SCALE_INDEX_DEFAULT_VALUE,
+            ),
+            transposition_progression: TRANSPOSITION_PROGRESSION_DEFAULT_VALUE.to_vec(),
+            transposition_chord_tones: TRANSPOSITION_CHORD_TONES_DEFAULT_VALUE,
+            trigger_probability: TRIGGER_PROBABILITY_DEFAULT_VALUE,
+            trigger_restlessness: TRIGGER_RESTLESSNESS_DEFAULT_VALUE,
+            trigger_type_index: Some(TRIGGER_TYPE_INDEX_DEFAULT_VALUE),
+            trigger_order_index: Some(TRIGGER_ORDER_INDEX_DEFAULT_VALUE),
+            gate_on: GATE_ON_DEFAULT_VALUE,
+            gate_off: GATE_OFF_DEFAULT_VALUE,
+            clock_divider_factor: CLOCK_DIVIDER_FACTOR_DEFAULT_VALUE,
+            clock_divider_offset: CLOCK_DIVIDER_OFFSET_DEFAULT_VALUE,
+            quantizer_scale_index: Some(QUANTIZER_SCALE_INDEX_DEFAULT_VALUE),
+            quantizer_strength: QUANTIZER_STRENGTH_DEFAULT_VALUE,
+            quantizer_direction_index: Some(QUANTIZER_DIRECTION_INDEX_DEFAULT_VALUE),
+            quantizer_min_octave: QUANTIZER_MIN_OCTAVE_DEFAULT_VALUE,
+            quantizer_max_octave: QUANTIZER_MAX_OCTAVE_DEFAULT_VALUE,
+            quantize_band_min_pitch: QUANTIZE_BAND_MIN_PITCH_DEFAULT_VALUE,
+            quantize_band_max_pitch: QUANTIZE_BAND_MAX_PITCH_DEFAULT_VALUE,
+            transpose: TRANSPOSE_DEFAULT_VALUE,
+            octave_scatter_probability: OCTAVE_SCATTER_PROBABILITY_DEFAULT_VALUE,
+            octave_scatter_octaves: OCTAVE_SCATTER_OCTAVES_DEFAULT_VALUE,
+            note_length_min_index: Some(NOTE_LENGTH_MIN_INDEX_DEFAULT_VALUE),
+            note_length_max_index: Some(NOTE_LENGTH_MAX_INDEX_DEFAULT_VALUE),
+            note_repeat_index: Some(NOTE_REPEAT_INDEX_DEFAULT_VALUE),
+            note_min_duration: NOTE_MIN_DURATION_DEFAULT_VALUE,
+            note_max_duration: NOTE_MAX_DURATION_DEFAULT_VALUE,
+            tie_repeated_notes: TIE_REPEATED_NOTES_DEFAULT_VALUE,
+            tie_probability: TIE_PROBABILITY_DEFAULT_VALUE,
+            octave_offset: OCTAVE_OFFSET_DEFAULT_VALUE,
+            note_off_as_note_on: NOTE_OFF_AS_NOTE_ON_DEFAULT_VALUE,
+            release_velocity: RELEASE_VELOCITY_DEFAULT_VALUE,
+            microtonal: MICROTONAL_DEFAULT_VALUE,
+            round_robin_channels: ROUND_ROBIN_CHANNELS_DEFAULT_VALUE.to_vec(),
+            max_voices: MAX_VOICES_DEFAULT_VALUE,
+            mono: MONO_DEFAULT_VALUE,
+            harmonize_enabled: HARMONIZE_ENABLED_DEFAULT_VALUE,
+            harmony_intervals: HARMONY_INTERVALS_DEFAULT_VALUE.to_vec(),
+            velocity_mode_index: Some(VELOCITY_MODE_INDEX_DEFAULT_VALUE),
+            velocity_curve_index: Some(VELOCITY_CURVE_INDEX_DEFAULT_VALUE),
+            velocity_length_enabled: VELOCITY_LENGTH_ENABLED_DEFAULT_VALUE,
+            velocity_length_amount: VELOCITY_LENGTH_AMOUNT_DEFAULT_VALUE,
+            modulation_enabled: MODULATION_ENABLED_DEFAULT_VALUE,
+            modulation_generator_type_index: Some(MODULATION_GENERATOR_TYPE_DEFAULT_VALUE),
+            modulation_min: MODULATION_MIN_DEFAULT_VALUE,
+            modulation_max: MODULATION_MAX_DEFAULT_VALUE,
+            modulation_cycle_length: MODULATION_CYCLE_LENGTH_DEFAULT_VALUE,
+            modulation_target_index: Some(MODULATION_TARGET_INDEX_DEFAULT_VALUE),
+            modulation_controller: MODULATION_CONTROLLER_DEFAULT_VALUE,
+            bpm: BPM_DEFAULT_VALUE,
+            tempo_automation_depth: TEMPO_AUTOMATION_DEPTH_DEFAULT_VALUE,
+            tempo_automation_period: TEMPO_AUTOMATION_PERIOD_DEFAULT_VALUE,
+            fill_phrase_bars: FILL_PHRASE_BARS_DEFAULT_VALUE,
+            fill_intensity: FILL_INTENSITY_DEFAULT_VALUE,
+            pitch_chance_enabled: PITCH_CHANCE_ENABLED_DEFAULT_VALUE,
+            pitch_favor_index: Some(PITCH_FAVOR_INDEX_DEFAULT_VALUE),
+            advance_on_trigger_only: ADVANCE_ON_TRIGGER_ONLY_DEFAULT_VALUE,
+            humanize_velocity_jitter: HUMANIZE_VELOCITY_JITTER_DEFAULT_VALUE,
+            humanize_timing_jitter: HUMANIZE_TIMING_JITTER_DEFAULT_VALUE,
+            humanize_length_jitter: HUMANIZE_LENGTH_JITTER_DEFAULT_VALUE,
+            swing: SWING_DEFAULT_VALUE,
+            swing_resolution_index: Some(SWING_RESOLUTION_INDEX_DEFAULT_VALUE),
+            send_song_position: SEND_SONG_POSITION_DEFAULT_VALUE,
+            stop_resets_position: STOP_RESETS_POSITION_DEFAULT_VALUE,
+            midi_rate_limit: MIDI_RATE_LIMIT_DEFAULT_VALUE,
+            lock_seed: LOCK_SEED_DEFAULT_VALUE,
+            seed: SEED_DEFAULT_VALUE,
+            melody_lock_seed: LOCK_SEED_DEFAULT_VALUE,
+            melody_seed: SEED_DEFAULT_VALUE,
+            transposition_lock_seed: LOCK_SEED_DEFAULT_VALUE,
+            transposition_seed: SEED_DEFAULT_VALUE,
+            trigger_lock_seed: LOCK_SEED_DEFAULT_VALUE,
+            trigger_seed: SEED_DEFAULT_VALUE,
+            count_in_index: Some(COUNT_IN_INDEX_DEFAULT_VALUE),
+        }
+    }
+}
+
+impl SequencerModel {
+    // A subtle variation of the musical settings, e.g. to evolve a patch gradually: ranges move
+    // by a few semitones, shares by a few percent, and a note of the step sequence may move.
+    // The amount from 0 to 1 scales how far they move. Everything else is kept as it is.
+    pub fn mutate<R: Rng>(&self, rng: &mut R, amount: f32) -> SequencerModel {
+        let semitones = (amount * MUTATION_MAX_SEMITONES).round();
+        let share = amount * MUTATION_MAX_SHARE;
+        let gate_steps = (amount * MUTATION_MAX_GATE_STEPS).round();
+        let (melody_min_pitch, melody_max_pitch) = nudge_ordered_pair(
+            rng,
+            (self.melody_min_pitch, self.melody_max_pitch),
+            semitones,
+            MELODY_PITCH_MIN_VALUE.step(),
+            MELODY_PITCH_MAX_VALUE.step(),
+        );
+        let (transposition_min_pitch, transposition_max_pitch) = nudge_ordered_pair(
+            rng,
+            (self.transposition_min_pitch, self.transposition_max_pitch),
+            semitones,
+            TRANSPOSITION_MIN_VALUE.step(),
+            TRANSPOSITION_MAX_VALUE.step(),
+        );
+        SequencerModel {
+            melody_min_pitch,
+            melody_max_pitch,
+            transposition_min_pitch,
+            transposition_max_pitch,
+            melody_step_sequence: mutate_step_sequence(rng, &self.melody_step_sequence, amount),
+            melody_blend_weight: nudge(
+                rng,
+                self.melody_blend_weight,
+                share,
+                MELODY_BLEND_WEIGHT_MIN_VALUE,
+                MELODY_BLEND_WEIGHT_MAX_VALUE,
+            ),
+            trigger_probability: (nudge(
+                rng,
+                self.trigger_probability,
+                share,
+                TRIGGER_PROBABILITY_MIN_VALUE,
+                TRIGGER_PROBABILITY_MAX_VALUE,
+            ) * 100.0)
+                .round()
+                / 100.0,
+            gate_on: nudge(
+                rng,
+                self.gate_on,
+                gate_steps,
+                GATE_ON_MIN_VALUE,
+                GATE_ON_MAX_VALUE,
+            )
+            .round(),
+            gate_off: nudge(
+                rng,
+                self.gate_off,
+                gate_steps,
+                GATE_OFF_MIN_VALUE,
+                GATE_OFF_MAX_VALUE,
+            )
+            .round(),
+            quantizer_strength: nudge(
+                rng,
+                self.quantizer_strength,
+                share,
+                QUANTIZER_STRENGTH_MIN_VALUE,
+                QUANTIZER_STRENGTH_MAX_VALUE,
+            ),
+            ..self.clone()
+        }
+    }
+}
+
+// The value moved by up to the delta either way, within the bounds
+fn nudge<R: Rng>(rng: &mut R, value: f32, delta: f32, min: f32, max: f32) -> f32 {
+    (value + rng.gen_range(-delta..=delta)).clamp(min, max)
+}
+
+// Both values moved by up to the delta to whole numbers within the bounds, the lower one first
+fn nudge_ordered_pair<R: Rng>(
+    rng: &mut R,
+    (a, b): (f32, f32),
+    delta: f32,
+    min: f32,
+    max: f32,
+) -> (f32, f32) {
+    let a = nudge(rng, a, delta, min, max).round();
+    let b = nudge(rng, b, delta, min, max).round();
+    (a.min(b), a.max(b))
+}
+
+// With a chance of the amount, one note of the step sequence moves by a semitone or two
+fn mutate_step_sequence<R: Rng>(rng: &mut R, step_sequence: &str, amount: f32) -> String {
+    let mut notes = match parse_step_sequence(step_sequence) {
+        Ok(notes) if !notes.is_empty() => notes,
+        _ => return step_sequence.to_string(),
+    };
+    if rng.gen::<f32>() < amount {
+        let index = rng.gen_range(0..notes.len());
+        let delta = [-2.0, -1.0, 1.0, 2.0][rng.gen_range(0..4)];
+        let step = (notes[index].step() + delta).clamp(0.0, 127.0);
+        notes[index] = Step(step).to_letter_octave();
+    }
+    format_step_sequence(&notes)
+}
+
+impl From<SequencerModel> for SequencerConfiguration {
+    fn from(model: SequencerModel) -> Self {
+        SequencerConfiguration {
+            melody_enabled: model.melody_enabled,
+            transposition_enabled: model.transposition_enabled,
+            drum_note: model
+                .drum_mode
+                .then(|| Step(model.drum_note).to_letter_octave()),
+            melody_min_pitch: Step(model.melody_min_pitch).to_letter_octave(),
+            melody_max_pitch: Step(model.melody_max_pitch).to_letter_octave(),
+            melody_pitch_generator_type: melody_pitch_generator_type_from_index(
+                model.melody_pitch_generator_type_index,
+            ),
+            melody_cycle_length: model.melody_cycle_length as u32,
+            melody_phase: model.melody_phase as u32,
+            melody_step_sequence: parse_step_sequence(&model.melody_step_sequence)
+                .or_else(|_| parse_step_sequence(MELODY_STEP_SEQUENCE_DEFAULT_VALUE))
+                .unwrap(),
+            melody_degree_weights: model.melody_degree_weights,
+            melody_ramp_mode: ramp_mode_from_index(model.melody_ramp_mode_index),
+            square_interval: square_interval_from_index(model.square_interval_index),
+            melody_quantizer_scale: lane_quantizer_scale_from_index(
+                model.melody_quantizer_scale_index,
+            ),
+            melody_blend_generator_type: melody_blend_generator_type_from_index(
+                model.melody_blend_generator_type_index,
+            ),
+            melody_blend_weight: model.melody_blend_weight,
+            transposition_min_pitch: Step(model.transposition_min_pitch).to_letter_octave(),
+            transposition_max_pitch: Step(model.transposition_max_pitch).to_letter_octave(),
+            transposition_pitch_generator_type: transposition_pitch_generator_type_from_index(
+                model.transposition_pitch_generator_type_index,
+            ),
+            transposition_cycle_length: model.transposition_cycle_length as u32,
+            transposition_phase: model.transposition_phase as u32,
+            transposition_quantizer_scale: lane_quantizer_scale_from_index(
+                model.transposition_quantizer_scale_index,
+            ),
+            transposition_progression: model.transposition_progression,
+            transposition_chord_tones: model.transposition_chord_tones,
+            trigger_probablilty: model.trigger_probability,
+            trigger_restlessness: model.trigger_restlessness,
+            trigger_type: trigger_type_from_index(model.trigger_type_index),
+            gate_on: model.gate_on as u32,
+            gate_off: model.gate_off as u32,
+            clock_divider_factor: model.clock_divider_factor as u32,
+            clock_divider_offset: model.clock_divider_offset as u32,
+            trigger_order: trigger_order_from_index(model.trigger_order_index),
+            quantizer_scale: QUANTIZER_SCALES[model.quantizer_scale_index.unwrap()].to_vec(),
+            quantizer_strength: model.quantizer_strength,
+            quantizer_direction: quantizer_direction_from_index(model.quantizer_direction_index),
+            allowed_octaves: allowed_octaves_from_bounds(
+                model.quantizer_min_octave,
+                model.quantizer_max_octave,
+            ),
+            quantize_range: quantize_range_from_band(
+                model.quantize_band_min_pitch,
+                model.quantize_band_max_pitch,
+            ),
+            transpose: model.transpose as i32,
+            octave_scatter_probability: model.octave_scatter_probability,
+            octave_scatter_octaves: model.octave_scatter_octaves as u32,
+            note_length_min: note_length_from_index(model.note_length_min_index),
+            note_length_max: note_length_from_index(model.note_length_max_index),
+            note_repeat: note_repeat_from_index(model.note_repeat_index),
+            note_min_duration_ms: model.note_min_duration,
+            note_max_duration_ms: model.note_max_duration,
+            tie_repeated_notes: model.tie_repeated_notes,
+            tie_probability: model.tie_probability,
+            // the drum note is played as it is
+            octave_offset: if model.drum_mode {
+                0
+            } else {
+                model.octave_offset
+            },
+            note_off_style: if model.note_off_as_note_on {
+                NoteOffStyle::NoteOnVelocity0
+            } else {
+                NoteOffStyle::Status80
+            },
+            release_velocity: model.release_velocity.clamp(0.0, 127.0) as u8,
+            channel_mode: channel_mode_from_channels(&model.round_robin_channels),
+            microtonal: model.microtonal,
+            max_voices: model.max_voices as usize,
+            mono: model.mono,
+            harmony_intervals: if model.harmonize_enabled && !model.drum_mode {
+                Some(model.harmony_intervals.clone())
+            } else {
+                None
+            },
+            pitch_history_length: PITCH_HISTORY_LENGTH,
+            velocity_mode: velocity_mode_from_index(model.velocity_mode_index),
+            velocity_curve: velocity_curve_from_index(model.velocity_curve_index),
+            velocity_length: if model.velocity_length_enabled {
+                model.velocity_length_amount
+            } else {
+                0.0
+            },
+            modulation_enabled: model.modulation_enabled,
+            modulation_generator_type: pitch_generator_type_from_index(
+                model.modulation_generator_type_index,
+            ),
+            modulation_min: model.modulation_min as u8,
+            modulation_max: model.modulation_max as u8,
+            modulation_cycle_length: model.modulation_cycle_length as u32,
+            modulation_target: modulation_target_from_index(model.modulation_target_index),
+            modulation_controller: model.modulation_controller as u8,
+            bpm: model.bpm,
+            tempo_automation: TempoAutomation {
+                depth: model.tempo_automation_depth,
+                period_bars: model.tempo_automation_period as u32,
+            },
+            count_in_bars: COUNT_IN_BARS[model.count_in_index.unwrap()],
+            fill: Fill {
+                phrase_bars: model.fill_phrase_bars as u32,
+                intensity: model.fill_intensity,
+            },
+            pitch_chance: model
+                .pitch_chance_enabled
+                .then(|| pitch_favor_from_index(model.pitch_favor_index)),
+            advance_on_trigger_only: model.advance_on_trigger_only,
+            humanize: Humanize {
+                velocity_jitter: model.humanize_velocity_jitter as u8,
+                timing_jitter_ticks: model.humanize_timing_jitter as u32,
+                length_jitter: model.humanize_length_jitter,
+            },
+            swing: Swing {
+                amount: model.swing,
+                resolution: SWING_RESOLUTIONS[model.swing_resolution_index.unwrap()],
+            },
+            send_song_position: model.send_song_position,
+            stop_resets_position: model.stop_resets_position,
+            midi_rate_limit: model
+                .midi_rate_limit
+                .then_some(MIDI_RATE_LIMIT_MESSAGES_PER_TICK),
+            seed: model.lock_seed.then_some(model.seed),
+            melody_seed: model.melody_lock_seed.then_some(model.melody_seed),
+            transposition_seed: model
+                .transposition_lock_seed
+                .then_some(model.transposition_seed),
+            trigger_seed: model.trigger_lock_seed.then_some(model.trigger_seed),
+        }
+    }
+}
+
+impl Default for SequencerConfiguration {
+    fn default() -> Self {
+        SequencerModel::default().into()
+    }
+}
+
+// Switch velocity, timing and note length humanization on together with the default
+// amounts, or off together
+// A step sequence of a preset that doesn't parse, e.g. an empty one, is replaced by the
+// default one when the preset is loaded
+fn step_sequence_or_default<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let step_sequence = String::deserialize(deserializer)?;
+    match parse_step_sequence(&step_sequence) {
+        Ok(_) => Ok(step_sequence),
+        Err(e) => {
+            warn!(
+                "Invalid melody step sequence {:?} ({}), using the default one",
+                step_sequence, e
+            );
+            Ok(MELODY_STEP_SEQUENCE_DEFAULT_VALUE.to_string())
+        }
+    }
+}
+
+pub fn set_humanize(model: &mut SequencerModel, is_on: bool) {
+    let amount = |value: f32| if is_on { value } else { 0.0 };
+    model.humanize_velocity_jitter = amount(HUMANIZE_VELOCITY_JITTER_AMOUNT);
+    model.humanize_timing_jitter = amount(HUMANIZE_TIMING_JITTER_AMOUNT);
+    model.humanize_length_jitter = amount(HUMANIZE_LENGTH_JITTER_AMOUNT);
+}
+
+// Whether any humanization is on, e.g. set in a preset by hand
+pub fn is_humanized(model: &SequencerModel) -> bool {
+    model.humanize_velocity_jitter > 0.0
+        || model.humanize_timing_jitter > 0.0
+        || model.humanize_length_jitter > 0.0
+}
+
+// The model a share t of the way from a to b, e.g. to move from one preset to another over
+// a number of bars. Numeric settings are crossfaded, the ones set in whole steps rounded to
+// them, and the other settings switch from a to b half way.
+pub fn morph(a: &SequencerModel, b: &SequencerModel, t: f32) -> SequencerModel {
+    let t = t.clamp(0.0, 1.0);
+    let fade = |a: f32, b: f32| a + (b - a) * t;
+    let step = |a: f32, b: f32| fade(a, b).round();
+    SequencerModel {
+        melody_min_pitch: step(a.melody_min_pitch, b.melody_min_pitch),
+        melody_max_pitch: step(a.melody_max_pitch, b.melody_max_pitch),
+        melody_cycle_length: step(a.melody_cycle_length, b.melody_cycle_length),
+        melody_phase: step(a.melody_phase, b.melody_phase),
+        melody_blend_weight: fade(a.melody_blend_weight, b.melody_blend_weight),
+        transposition_min_pitch: step(a.transposition_min_pitch, b.transposition_min_pitch),
+        transposition_max_pitch: step(a.transposition_max_pitch, b.transposition_max_pitch),
+        transposition_cycle_length: step(
+            a.transposition_cycle_length,
+            b.transposition_cycle_length,
+        ),
+        transposition_phase: step(a.transposition_phase, b.transposition_phase),
+        trigger_probability: fade(a.trigger_probability, b.trigger_probability),
+        trigger_restlessness: fade(a.trigger_restlessness, b.trigger_restlessness),
+        gate_on: step(a.gate_on, b.gate_on),
+        gate_off: step(a.gate_off, b.gate_off),
+        clock_divider_factor: step(a.clock_divider_factor, b.clock_divider_factor),
+        clock_divider_offset: step(a.clock_divider_offset, b.clock_divider_offset),
+        quantizer_strength: fade(a.quantizer_strength, b.quantizer_strength),
+        quantizer_min_octave: step(a.quantizer_min_octave, b.quantizer_min_octave),
+        quantizer_max_octave: step(a.quantizer_max_octave, b.quantizer_max_octave),
+        quantize_band_min_pitch: step(a.quantize_band_min_pitch, b.quantize_band_min_pitch),
+        quantize_band_max_pitch: step(a.quantize_band_max_pitch, b.quantize_band_max_pitch),
+        transpose: step(a.transpose, b.transpose),
+        octave_scatter_probability: fade(
+            a.octave_scatter_probability,
+            b.octave_scatter_probability,
+        ),
+        octave_scatter_octaves: step(a.octave_scatter_octaves, b.octave_scatter_octaves),
+        note_min_duration: step(a.note_min_duration, b.note_min_duration),
+        note_max_duration: step(a.note_max_duration, b.note_max_duration),
+        tie_probability: fade(a.tie_probability, b.tie_probability),
+        octave_offset: step(a.octave_offset as f32, b.octave_offset as f32) as i32,
+        release_velocity: step(a.release_velocity, b.release_velocity),
+        max_voices: step(a.max_voices, b.max_voices),
+        velocity_length_amount: fade(a.velocity_length_amount, b.velocity_length_amount),
+        modulation_min: step(a.modulation_min, b.modulation_min),
+        modulation_max: step(a.modulation_max, b.modulation_max),
+        modulation_cycle_length: step(a.modulation_cycle_length, b.modulation_cycle_length),
+        modulation_controller: step(a.modulation_controller, b.modulation_controller),
+        bpm: fade(a.bpm, b.bpm),
+        tempo_automation_depth: step(a.tempo_automation_depth, b.tempo_automation_depth),
+        tempo_automation_period: step(a.tempo_automation_period, b.tempo_automation_period),
+        fill_phrase_bars: step(a.fill_phrase_bars, b.fill_phrase_bars),
+        fill_intensity: fade(a.fill_intensity, b.fill_intensity),
+        humanize_velocity_jitter: fade(a.humanize_velocity_jitter, b.humanize_velocity_jitter),
+        humanize_timing_jitter: fade(a.humanize_timing_jitter, b.humanize_timing_jitter),
+        humanize_length_jitter: fade(a.humanize_length_jitter, b.humanize_length_jitter),
+        swing: fade(a.swing, b.swing),
+        ..if t < 0.5 { a.clone() } else { b.clone() }
+    }
+}
+
+// The full MIDI note range leaves no note chromatic, so every note is quantized
+pub fn quantize_range_from_band(min_pitch: f32, max_pitch: f32) -> Option<RangeInclusive<Step>> {
+    if min_pitch <= QUANTIZE_BAND_MIN_PITCH_DEFAULT_VALUE
+        && max_pitch >= QUANTIZE_BAND_MAX_PITCH_DEFAULT_VALUE
+    {
+        None
+    } else {
+        Some(Step(min_pitch)..=Step(max_pitch))
+    }
+}
+
+// A preset can have the minimum octave above the maximum, which are put in order
+pub fn allowed_octaves_from_bounds(min_octave: f32, max_octave: f32) -> RangeInclusive<i32> {
+    let (min_octave, max_octave) = (min_octave as i32, max_octave as i32);
+    min_octave.min(max_octave)..=min_octave.max(max_octave)
+}
+
+pub fn channel_mode_from_channels(channels: &[u8]) -> ChannelMode {
+    if channels.is_empty() {
+        ChannelMode::Fixed
+    } else {
+        ChannelMode::RoundRobin(
+            channels
+                .iter()
+                .map(|channel| channel.clamp(&1, &16) - 1)
+                .collect(),
+        )
+    }
+}
+
+pub fn pitch_generator_type_from_index(idx: Option<Idx>) -> PitchGeneratorType {
+    PitchGeneratorType::from_str(PITCH_GENERATOR_TYPE_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn melody_pitch_generator_type_from_index(idx: Option<Idx>) -> PitchGeneratorType {
+    PitchGeneratorType::from_str(MELODY_PITCH_GENERATOR_TYPE_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn melody_blend_generator_type_from_index(idx: Option<Idx>) -> Option<PitchGeneratorType> {
+    match idx.unwrap() {
+        0 => None,
+        idx => Some(PitchGeneratorType::from_str(MELODY_BLEND_GENERATOR_TYPE_NAMES[idx]).unwrap()),
+    }
+}
+
+pub fn transposition_pitch_generator_type_from_index(idx: Option<Idx>) -> PitchGeneratorType {
+    PitchGeneratorType::from_str(TRANSPOSITION_PITCH_GENERATOR_TYPE_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn trigger_type_from_index(idx: Option<Idx>) -> TriggerType {
+    TriggerType::from_str(TRIGGER_TYPE_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn pitch_favor_from_index(idx: Option<Idx>) -> PitchFavor {
+    PitchFavor::from_str(PITCH_FAVOR_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn trigger_order_from_index(idx: Option<Idx>) -> TriggerOrder {
+    TriggerOrder::from_str(TRIGGER_ORDER_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn note_length_from_index(idx: Option<Idx>) -> NoteLength {
+    NoteLength::from_str(NOTE_LENGTH_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn note_repeat_from_index(idx: Option<Idx>) -> Option<NoteLength> {
+    NoteLength::from_str(NOTE_REPEAT_NAMES[idx.unwrap()]).ok()
+}
+
+// The entry after the quantizer scales leaves the lane unquantized
+pub fn lane_quantizer_scale_from_index(idx: Option<Idx>) -> Option<Vec<Letter>> {
+    QUANTIZER_SCALES
+        .get(idx.unwrap())
+        .map(|scale| scale.to_vec())
+}
+
+// The scale whose degrees the degrees generator plays: the melody scale, or the shared
+// scale without one
+pub fn melody_degree_scale(model: &SequencerModel) -> Vec<Letter> {
+    lane_quantizer_scale_from_index(model.melody_quantizer_scale_index)
+        .unwrap_or_else(|| QUANTIZER_SCALES[model.quantizer_scale_index.unwrap()].to_vec())
+}
+
+pub fn ramp_mode_from_index(idx: Option<Idx>) -> RampMode {
+    RampMode::from_str(RAMP_MODE_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn square_interval_from_index(idx: Option<Idx>) -> Option<i32> {
+    SQUARE_INTERVAL_SEMITONES[idx.unwrap()]
+}
+
+pub fn quantizer_direction_from_index(idx: Option<Idx>) -> QuantizeDirection {
+    QuantizeDirection::from_str(QUANTIZER_DIRECTION_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn modulation_target_from_index(idx: Option<Idx>) -> ModulationTarget {
+    ModulationTarget::from_str(MODULATION_TARGET_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn velocity_mode_from_index(idx: Option<Idx>) -> VelocityMode {
+    VelocityMode::from_str(VELOCITY_MODE_NAMES[idx.unwrap()]).unwrap()
+}
+
+pub fn velocity_curve_from_index(idx: Option<Idx>) -> VelocityCurve {
+    VelocityCurve::from_str(VELOCITY_CURVE_NAMES[idx.unwrap()]).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::MAJOR_SCALE_NOTES;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    #[test]
+    fn default_configuration_matches_the_documented_defaults() {
+        let config = SequencerConfiguration::default();
+        assert_eq!(config.bpm, 120.0);
+        assert_eq!(config.quantizer_scale, MAJOR_SCALE_NOTES.to_vec());
+        assert_eq!(config.melody_quantizer_scale, None);
+        assert_eq!(config.transposition_quantizer_scale, None);
+        assert!(config.melody_blend_generator_type.is_none());
+        assert!(config.melody_pitch_generator_type == PitchGeneratorType::RampUp);
+        assert!(config.transposition_pitch_generator_type == PitchGeneratorType::Square);
+        assert!(config.modulation_generator_type == PitchGeneratorType::RampUp);
+        assert_eq!(config.note_length_min, NoteLength::Sixteenth);
+        assert_eq!(config.note_length_max, NoteLength::Sixteenth);
+        assert_eq!(config.note_off_style, NoteOffStyle::Status80);
+        assert!(config.quantize_range.is_none());
+        assert!(!config.humanize.is_enabled());
+    }
+
+    #[test]
+    fn the_humanize_toggle_sets_and_clears_all_three_humanize_amounts() {
+        let mut model = SequencerModel::default();
+        assert!(!is_humanized(&model));
+
+        set_humanize(&mut model, true);
+        assert!(is_humanized(&model));
+        let config = SequencerConfiguration::from(model.clone());
+        assert_eq!(
+            config.humanize,
+            Humanize {
+                velocity_jitter: HUMANIZE_VELOCITY_JITTER_AMOUNT as u8,
+                timing_jitter_ticks: HUMANIZE_TIMING_JITTER_AMOUNT as u32,
+                length_jitter: HUMANIZE_LENGTH_JITTER_AMOUNT,
+            }
+        );
+
+        set_humanize(&mut model, false);
+        assert!(!is_humanized(&model));
+        let config = SequencerConfiguration::from(model);
+        assert_eq!(config.humanize.velocity_jitter, 0);
+        assert_eq!(config.humanize.timing_jitter_ticks, 0);
+        assert_eq!(config.humanize.length_jitter, 0.0);
+    }
+
+    #[test]
+    fn a_morph_crossfades_numbers_and_switches_the_other_settings_half_way() {
+        let a = SequencerModel {
+            bpm: 100.0,
+            melody_max_pitch: 72.0,
+            melody_cycle_length: 8.0,
+            trigger_probability: 0.2,
+            quantizer_scale_index: Some(0),
+            ..SequencerModel::default()
+        };
+        let b = SequencerModel {
+            bpm: 140.0,
+            melody_max_pitch: 81.0,
+            melody_cycle_length: 16.0,
+            trigger_probability: 0.8,
+            quantizer_scale_index: Some(2),
+            ..SequencerModel::default()
+        };
+
+        assert!(morph(&a, &b, 0.0) == a);
+        assert!(morph(&a, &b, 1.0) == b);
+
+        let half_way = morph(&a, &b, 0.5);
+        assert_eq!(half_way.bpm, 120.0);
+        assert!((half_way.trigger_probability - 0.5).abs() < 1e-6);
+        assert_eq!(half_way.melody_cycle_length, 12.0);
+        // set in whole semitones
+        assert_eq!(half_way.melody_max_pitch, 77.0);
+        assert_eq!(half_way.quantizer_scale_index, Some(2));
+        assert_eq!(morph(&a, &b, 0.4).quantizer_scale_index, Some(0));
+    }
+
+    #[test]
+    fn a_preset_with_an_invalid_step_sequence_loads_the_default_one() {
+        for step_sequence in ["", "C4 H4"] {
+            let preset = format!("{{\"melody_step_sequence\": {:?}}}", step_sequence);
+            let model: SequencerModel = serde_json::from_str(&preset).unwrap();
+            assert_eq!(
+                model.melody_step_sequence,
+                MELODY_STEP_SEQUENCE_DEFAULT_VALUE
+            );
+            let config = SequencerConfiguration::from(model);
+            assert_eq!(
+                config.melody_step_sequence,
+                parse_step_sequence(MELODY_STEP_SEQUENCE_DEFAULT_VALUE).unwrap()
+            );
+        }
+        let model: SequencerModel =
+            serde_json::from_str("{\"melody_step_sequence\": \"D3 F3\"}").unwrap();
+        assert_eq!(model.melody_step_sequence, "D3 F3");
+    }
+
+    #[test]
+    fn a_stored_melody_scale_index_keeps_its_scale() {
+        // presets from before the lane scales could be switched off store a quantizer scale
+        let model: SequencerModel =
+            serde_json::from_str("{\"melody_quantizer_scale_index\": 1}").unwrap();
+        let config = SequencerConfiguration::from(model);
+        assert_eq!(
+            config.melody_quantizer_scale,
+            Some(module::MAJOR_SCALE_NOTES.to_vec())
+        );
+        assert_eq!(config.transposition_quantizer_scale, None);
+        assert_eq!(
+            LANE_QUANTIZER_SCALE_NAMES[..QUANTIZER_SCALE_NAMES.len()],
+            *QUANTIZER_SCALE_NAMES
+        );
+    }
+
+    #[test]
+    fn mutated_settings_stay_within_their_bounds() {
+        let mut rng = Pcg32::seed_from_u64(0);
+        let mut sequencer_model = SequencerModel::default();
+        for _ in 0..1000 {
+            sequencer_model = sequencer_model.mutate(&mut rng, 1.0);
+            assert!(sequencer_model.melody_min_pitch <= sequencer_model.melody_max_pitch);
+            assert!(MELODY_PITCH_MIN_VALUE.step() <= sequencer_model.melody_min_pitch);
+            assert!(sequencer_model.melody_max_pitch <= MELODY_PITCH_MAX_VALUE.step());
+            assert!(
+                sequencer_model.transposition_min_pitch <= sequencer_model.transposition_max_pitch
+            );
+            assert!(TRANSPOSITION_MIN_VALUE.step() <= sequencer_model.transposition_min_pitch);
+            assert!(sequencer_model.transposition_max_pitch <= TRANSPOSITION_MAX_VALUE.step());
+            assert!((0.0..=1.0).contains(&sequencer_model.trigger_probability));
+            assert!((0.0..=1.0).contains(&sequencer_model.melody_blend_weight));
+            assert!((0.0..=1.0).contains(&sequencer_model.quantizer_strength));
+            assert!((GATE_ON_MIN_VALUE..=GATE_ON_MAX_VALUE).contains(&sequencer_model.gate_on));
+            assert!((GATE_OFF_MIN_VALUE..=GATE_OFF_MAX_VALUE).contains(&sequencer_model.gate_off));
+            // the step sequence must still parse, which the conversion checks
+            let _: SequencerConfiguration = sequencer_model.clone().into();
+        }
+    }
+
+    #[test]
+    fn no_mutation_amount_keeps_the_settings() {
+        let mut rng = Pcg32::seed_from_u64(0);
+        let sequencer_model = SequencerModel::default();
+        assert!(sequencer_model.mutate(&mut rng, 0.0) == sequencer_model);
+    }
+}
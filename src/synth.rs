@@ -0,0 +1,353 @@
+use std::f32::consts::PI;
+use std::sync::mpsc;
+
+use log::{info, warn};
+use midir::SendError;
+use nannou_audio::{Buffer, Host, Stream};
+use pitch_calc::Step;
+
+use crate::module::letter_octave_to_hz;
+use crate::sequencer::MidiSink;
+
+const NOTE_ON_MSG: u8 = 0x90;
+const NOTE_OFF_MSG: u8 = 0x80;
+const CONTROL_CHANGE_MSG: u8 = 0xB0;
+const ALL_SOUND_OFF_CONTROLLER: u8 = 0x78;
+const ALL_NOTES_OFF_CONTROLLER: u8 = 0x7B;
+// The metronome clicks on the drum channel, which has no pitch to play
+const DRUM_CHANNEL: u8 = 9;
+const VOICES: usize = 8;
+// Level of a voice at full velocity, low enough for all voices to sound without clipping
+const VOICE_GAIN: f32 = 0.12;
+const ATTACK_SECONDS: f32 = 0.005;
+const DECAY_SECONDS: f32 = 0.1;
+const SUSTAIN_LEVEL: f32 = 0.6;
+// Time the release takes from the full level down to silence
+const RELEASE_SECONDS: f32 = 0.2;
+
+pub const WAVEFORM_NAMES: &[&str] = &["Synth: sine", "Synth: saw"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Saw,
+}
+
+impl Waveform {
+    pub fn from_index(idx: usize) -> Waveform {
+        match idx {
+            0 => Waveform::Sine,
+            _ => Waveform::Saw,
+        }
+    }
+
+    // The level of the wave at the phase, from 0 to 1 over a period
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Saw => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+enum SynthEvent {
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8 },
+    AllNotesOff,
+    SetWaveform(Waveform),
+}
+
+// The event the synth plays for a MIDI message on any channel but the drum channel
+fn synth_event(message: &[u8]) -> Option<SynthEvent> {
+    match message {
+        [status, ..] if status & 0x0F == DRUM_CHANNEL => None,
+        [status, note, velocity] if status & 0xF0 == NOTE_ON_MSG && *velocity > 0 => {
+            Some(SynthEvent::NoteOn {
+                note: *note,
+                velocity: *velocity,
+            })
+        }
+        // A note on with velocity 0 ends a note
+        [status, note, _] if status & 0xF0 == NOTE_ON_MSG || status & 0xF0 == NOTE_OFF_MSG => {
+            Some(SynthEvent::NoteOff { note: *note })
+        }
+        [status, controller, _]
+            if status & 0xF0 == CONTROL_CHANGE_MSG
+                && (*controller == ALL_NOTES_OFF_CONTROLLER
+                    || *controller == ALL_SOUND_OFF_CONTROLLER) =>
+        {
+            Some(SynthEvent::AllNotesOff)
+        }
+        _ => None,
+    }
+}
+
+// The voices play frequencies, so that they do not depend on the MIDI note numbering
+fn note_frequency(note: u8) -> f32 {
+    letter_octave_to_hz(Step(note as f32).to_letter_octave())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Clone, Copy)]
+struct Voice {
+    note: u8,
+    frequency: f32,
+    gain: f32,
+    phase: f32,
+    level: f32,
+    stage: Stage,
+    // Order the voices were started in, to find the oldest one
+    started: u64,
+}
+
+impl Voice {
+    fn idle() -> Voice {
+        Voice {
+            note: 0,
+            frequency: 0.0,
+            gain: 0.0,
+            phase: 0.0,
+            level: 0.0,
+            stage: Stage::Idle,
+            started: 0,
+        }
+    }
+
+    // The next sample of the voice, moving its envelope on by one sample
+    fn next_sample(&mut self, waveform: Waveform, sample_rate: f32) -> f32 {
+        match self.stage {
+            Stage::Idle => return 0.0,
+            Stage::Attack => {
+                self.level += 1.0 / (ATTACK_SECONDS * sample_rate);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= (1.0 - SUSTAIN_LEVEL) / (DECAY_SECONDS * sample_rate);
+                if self.level <= SUSTAIN_LEVEL {
+                    self.level = SUSTAIN_LEVEL;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => (),
+            Stage::Release => {
+                self.level -= 1.0 / (RELEASE_SECONDS * sample_rate);
+                if self.level <= 0.0 {
+                    *self = Voice::idle();
+                    return 0.0;
+                }
+            }
+        }
+        let sample = waveform.sample(self.phase) * self.level * self.gain;
+        self.phase = (self.phase + self.frequency / sample_rate).fract();
+        sample
+    }
+}
+
+// The state of the audio thread. Voices are allocated as the note ons come in: a note that
+// is already sounding is struck again on its own voice, otherwise an idle voice is taken.
+// With all voices busy, the quietest releasing voice is stolen, or the oldest held one
+// without any. A note off releases the voices of its note, which go idle once their release
+// has faded out, so a note off never cuts a note short.
+struct SynthAudio {
+    voices: [Voice; VOICES],
+    waveform: Waveform,
+    events: mpsc::Receiver<SynthEvent>,
+    notes_started: u64,
+}
+
+impl SynthAudio {
+    fn note_on(&mut self, note: u8, velocity: u8) {
+        let index = self
+            .voices
+            .iter()
+            .position(|voice| voice.stage != Stage::Idle && voice.note == note)
+            .or_else(|| {
+                self.voices
+                    .iter()
+                    .position(|voice| voice.stage == Stage::Idle)
+            })
+            .unwrap_or_else(|| self.voice_to_steal());
+        self.notes_started += 1;
+        let voice = &mut self.voices[index];
+        voice.note = note;
+        voice.frequency = note_frequency(note);
+        voice.gain = VOICE_GAIN * velocity as f32 / 127.0;
+        voice.stage = Stage::Attack;
+        voice.started = self.notes_started;
+    }
+
+    fn voice_to_steal(&self) -> usize {
+        let releasing = self
+            .voices
+            .iter()
+            .enumerate()
+            .filter(|(_, voice)| voice.stage == Stage::Release)
+            .min_by(|(_, a), (_, b)| a.level.total_cmp(&b.level))
+            .map(|(index, _)| index);
+        releasing.unwrap_or_else(|| {
+            self.voices
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, voice)| voice.started)
+                .map(|(index, _)| index)
+                .unwrap()
+        })
+    }
+
+    fn note_off(&mut self, note: u8) {
+        for voice in self.voices.iter_mut() {
+            if voice.stage != Stage::Idle && voice.note == note {
+                voice.stage = Stage::Release;
+            }
+        }
+    }
+
+    fn all_notes_off(&mut self) {
+        for voice in self.voices.iter_mut() {
+            if voice.stage != Stage::Idle {
+                voice.stage = Stage::Release;
+            }
+        }
+    }
+
+    fn handle(&mut self, event: SynthEvent) {
+        match event {
+            SynthEvent::NoteOn { note, velocity } => self.note_on(note, velocity),
+            SynthEvent::NoteOff { note } => self.note_off(note),
+            SynthEvent::AllNotesOff => self.all_notes_off(),
+            SynthEvent::SetWaveform(waveform) => self.waveform = waveform,
+        }
+    }
+}
+
+// Renders a buffer on the audio thread, after taking in the events sent since the last one
+fn render(audio: &mut SynthAudio, buffer: &mut Buffer) {
+    while let Ok(event) = audio.events.try_recv() {
+        audio.handle(event);
+    }
+    let sample_rate = buffer.sample_rate() as f32;
+    let waveform = audio.waveform;
+    for frame in buffer.frames_mut() {
+        let sample: f32 = audio
+            .voices
+            .iter_mut()
+            .map(|voice| voice.next_sample(waveform, sample_rate))
+            .sum();
+        for channel in frame {
+            *channel = sample;
+        }
+    }
+}
+
+// A simple polyphonic synth on the default audio output, playing the notes sent to the
+// MIDI output along with it, so that the app can be heard without an external synth
+pub struct Synth {
+    // Playing as long as it is kept
+    _stream: Stream<SynthAudio>,
+    events: mpsc::Sender<SynthEvent>,
+}
+
+impl Synth {
+    pub fn open(waveform: Waveform) -> Option<Synth> {
+        let (events, receiver) = mpsc::channel();
+        let audio = SynthAudio {
+            voices: [Voice::idle(); VOICES],
+            waveform,
+            events: receiver,
+            notes_started: 0,
+        };
+        let stream = Host::new()
+            .new_output_stream(audio)
+            .render(render)
+            .build()
+            .map_err(|e| warn!("Failed to open the audio output for the synth: {}", e))
+            .ok()?;
+        if let Err(e) = stream.play() {
+            warn!("Failed to start the synth: {}", e);
+            return None;
+        }
+        info!("Playing the notes on the built-in synth");
+        Some(Synth {
+            _stream: stream,
+            events,
+        })
+    }
+
+    pub fn set_waveform(&self, waveform: Waveform) {
+        self.events.send(SynthEvent::SetWaveform(waveform)).ok();
+    }
+
+    // A MIDI sink sending the messages on to the output, and playing their notes on the synth
+    pub fn midi_sink(&self, output: Box<dyn MidiSink>) -> Box<dyn MidiSink> {
+        Box::new(SynthMidiSink {
+            output,
+            events: self.events.clone(),
+        })
+    }
+}
+
+struct SynthMidiSink {
+    output: Box<dyn MidiSink>,
+    events: mpsc::Sender<SynthEvent>,
+}
+
+impl MidiSink for SynthMidiSink {
+    fn send(&mut self, message: &[u8]) -> Result<(), SendError> {
+        if let Some(event) = synth_event(message) {
+            // the synth is gone once the app shuts down, the MIDI output carries on
+            self.events.send(event).ok();
+        }
+        self.output.send(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pitch_calc::{Letter, LetterOctave};
+
+    #[test]
+    fn notes_are_played_at_the_frequency_of_their_pitch() {
+        assert_eq!(LetterOctave(Letter::A, 4).hz(), 440.0);
+        assert_eq!(note_frequency(69), 440.0);
+        assert!((note_frequency(81) - 880.0).abs() < 0.01);
+        assert!((note_frequency(60) - 261.63).abs() < 0.01);
+    }
+
+    #[test]
+    fn busy_voices_are_stolen_from_the_oldest_note() {
+        let (_events, receiver) = mpsc::channel();
+        let mut audio = SynthAudio {
+            voices: [Voice::idle(); VOICES],
+            waveform: Waveform::Sine,
+            events: receiver,
+            notes_started: 0,
+        };
+        for note in 60..60 + VOICES as u8 {
+            audio.handle(synth_event(&[NOTE_ON_MSG, note, 100]).unwrap());
+        }
+        // the same note is struck again on its own voice
+        audio.handle(synth_event(&[NOTE_ON_MSG, 61, 100]).unwrap());
+        audio.handle(synth_event(&[NOTE_ON_MSG, 72, 100]).unwrap());
+
+        let notes: Vec<u8> = audio.voices.iter().map(|voice| voice.note).collect();
+        assert_eq!(notes, vec![72, 61, 62, 63, 64, 65, 66, 67]);
+
+        // released voices are stolen first
+        audio.handle(synth_event(&[NOTE_OFF_MSG, 65, 0]).unwrap());
+        audio.handle(synth_event(&[NOTE_ON_MSG, 73, 100]).unwrap());
+        assert_eq!(audio.voices[5].note, 73);
+        assert_eq!(audio.voices[5].stage, Stage::Attack);
+    }
+}
@@ -0,0 +1,175 @@
+use std::{fmt::Display, ops::RangeInclusive, str::FromStr};
+
+pub const FIXED_VELOCITY: u8 = 0x64;
+const MIN_VELOCITY: f32 = 1.0;
+const MAX_VELOCITY: f32 = 127.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum VelocityMode {
+    Fixed,
+    PitchFollow,
+}
+
+impl Display for VelocityMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            VelocityMode::Fixed => write!(f, "Fixed"),
+            VelocityMode::PitchFollow => write!(f, "Pitch follow"),
+        }
+    }
+}
+
+impl FromStr for VelocityMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Fixed" => Ok(VelocityMode::Fixed),
+            "Pitch follow" => Ok(VelocityMode::PitchFollow),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum VelocityCurve {
+    Linear,
+    Exponential,
+    Logarithmic,
+    SCurve,
+}
+
+impl VelocityCurve {
+    // Map a normalized value onto a velocity of 1-127. Velocity 0 is left out, because
+    // a note on with velocity 0 is treated as a note off.
+    pub fn apply(&self, norm: f32) -> u8 {
+        let x = norm.clamp(0.0, 1.0);
+        let y = match *self {
+            VelocityCurve::Linear => x,
+            VelocityCurve::Exponential => (10f32.powf(x) - 1.0) / 9.0,
+            VelocityCurve::Logarithmic => (1.0 + 9.0 * x).log10(),
+            VelocityCurve::SCurve => x * x * (3.0 - 2.0 * x),
+        };
+        (MIN_VELOCITY + y * (MAX_VELOCITY - MIN_VELOCITY)).round() as u8
+    }
+}
+
+impl Display for VelocityCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            VelocityCurve::Linear => write!(f, "Linear"),
+            VelocityCurve::Exponential => write!(f, "Exponential"),
+            VelocityCurve::Logarithmic => write!(f, "Logarithmic"),
+            VelocityCurve::SCurve => write!(f, "S-curve"),
+        }
+    }
+}
+
+impl FromStr for VelocityCurve {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Linear" => Ok(VelocityCurve::Linear),
+            "Exponential" => Ok(VelocityCurve::Exponential),
+            "Logarithmic" => Ok(VelocityCurve::Logarithmic),
+            "S-curve" => Ok(VelocityCurve::SCurve),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct VelocityShaper {
+    pub mode: VelocityMode,
+    pub curve: VelocityCurve,
+    // Notes that map to the lowest and highest velocity in pitch follow mode
+    pub pitch_range: RangeInclusive<u8>,
+}
+
+impl VelocityShaper {
+    pub fn velocity(&self, note: u8) -> u8 {
+        match self.mode {
+            VelocityMode::Fixed => FIXED_VELOCITY,
+            VelocityMode::PitchFollow => {
+                let low = *self.pitch_range.start() as f32;
+                let high = *self.pitch_range.end() as f32;
+                let norm = if high > low {
+                    (note as f32 - low) / (high - low)
+                } else {
+                    1.0
+                };
+                self.curve.apply(norm)
+            }
+        }
+    }
+}
+
+impl Default for VelocityShaper {
+    fn default() -> Self {
+        VelocityShaper {
+            mode: VelocityMode::Fixed,
+            curve: VelocityCurve::Linear,
+            pitch_range: 0..=127,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: &[VelocityCurve] = &[
+        VelocityCurve::Linear,
+        VelocityCurve::Exponential,
+        VelocityCurve::Logarithmic,
+        VelocityCurve::SCurve,
+    ];
+
+    #[test]
+    fn velocity_curves_map_the_endpoints_to_the_lowest_and_highest_velocity() {
+        for curve in CURVES {
+            assert_eq!(curve.apply(0.0), 1, "{}", curve);
+            assert_eq!(curve.apply(1.0), 127, "{}", curve);
+            assert_eq!(curve.apply(-1.0), 1, "{}", curve);
+            assert_eq!(curve.apply(2.0), 127, "{}", curve);
+        }
+    }
+
+    #[test]
+    fn velocity_curves_are_monotonic() {
+        for curve in CURVES {
+            let velocities: Vec<u8> = (0..=100).map(|i| curve.apply(i as f32 / 100.0)).collect();
+            assert!(
+                velocities.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{}",
+                curve
+            );
+        }
+    }
+
+    #[test]
+    fn velocity_curves_have_the_expected_shape_at_the_midpoint() {
+        assert_eq!(VelocityCurve::Linear.apply(0.5), 64);
+        assert_eq!(VelocityCurve::SCurve.apply(0.5), 64);
+        assert!(VelocityCurve::Exponential.apply(0.5) < 40);
+        assert!(VelocityCurve::Logarithmic.apply(0.5) > 88);
+        // the S-curve is flatter than linear near the ends
+        assert!(VelocityCurve::SCurve.apply(0.1) < VelocityCurve::Linear.apply(0.1));
+        assert!(VelocityCurve::SCurve.apply(0.9) > VelocityCurve::Linear.apply(0.9));
+    }
+
+    #[test]
+    fn pitch_follow_velocity_rises_across_the_pitch_range() {
+        let shaper = VelocityShaper {
+            mode: VelocityMode::PitchFollow,
+            curve: VelocityCurve::Linear,
+            pitch_range: 48..=72,
+        };
+        assert_eq!(shaper.velocity(40), 1);
+        assert_eq!(shaper.velocity(48), 1);
+        assert_eq!(shaper.velocity(60), 64);
+        assert_eq!(shaper.velocity(72), 127);
+        assert_eq!(VelocityShaper::default().velocity(60), FIXED_VELOCITY);
+    }
+}